@@ -10,7 +10,6 @@ fn main() -> Result<(), EncodeError> {
     println!("--===~ XmRs SID Module Info Example ~===--");
     println!("(c) 2024 Sébastien Béchet\n");
 
-    //TODO: SOUNDFX
     println!("Warning: it's just a game to extract some data. Don't expect anything beautiful.");
 
     // SidModule::get_sid_commando();
@@ -29,6 +28,7 @@ fn main() -> Result<(), EncodeError> {
     let sid = SidModule::get_sid_spellbound();
 
     // println!("{:?}", sid);
+    println!("{} sound effects", sid.soundfx.len());
 
     Ok(())
 }
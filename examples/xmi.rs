@@ -1,11 +1,11 @@
 #![forbid(unsafe_code)]
 
-use bincode::error::DecodeError;
 use xmrs::import::xm::xi_instrument::XiInstrument;
+use xmrs::Error;
 
 const XI: &[u8] = include_bytes!("instr.xi");
 
-fn main() -> Result<(), DecodeError> {
+fn main() -> Result<(), Error> {
     let xmi = XiInstrument::load(XI)?;
     println!("Load XMI: {:#x?}", xmi);
     let instr = xmi.to_instrument();
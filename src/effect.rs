@@ -153,9 +153,13 @@ pub enum TrackEffect {
         fine: bool,
     },
 
-    /// `speed`
+    /// Slides the period by `speed` a tick, a negative speed slides up
     /// Pitch effect
-    Portamento(f32),
+    Portamento {
+        speed: f32,
+        /// if true, only at tick0, otherwise from tick1
+        fine: bool,
+    },
 
     /// `speed`, portamento to note at speed.
     /// see `::Glissando` to round to the nearest halftone
@@ -222,8 +226,11 @@ pub enum TrackEffect {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum MidiMacroType {
+    /// `index` of the parametric macro used by `ParametricValue`
     // None: use the last index
     Parametric(Option<usize>),
+    /// `value` [0..127], send the selected parametric macro with this value
+    ParametricValue(usize),
     Fixed(usize),
 }
 
@@ -415,9 +422,19 @@ impl TrackEffect {
                 speed: h1 + o1,
                 fine: *tick2,
             }),
-            (TrackEffect::Portamento(h1), TrackEffect::Portamento(o1)) => {
-                Some(TrackEffect::Portamento(h1 + o1))
-            }
+            (
+                TrackEffect::Portamento {
+                    speed: h1,
+                    fine: fine1,
+                },
+                TrackEffect::Portamento {
+                    speed: o1,
+                    fine: fine2,
+                },
+            ) if fine1 == fine2 => Some(TrackEffect::Portamento {
+                speed: h1 + o1,
+                fine: *fine1,
+            }),
             (TrackEffect::TonePortamento(h1), TrackEffect::TonePortamento(o1)) => {
                 Some(TrackEffect::TonePortamento(h1 + o1))
             }
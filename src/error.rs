@@ -0,0 +1,191 @@
+use bincode::error::DecodeError;
+use core::fmt::{Display, Formatter, Result};
+
+use crate::format::Format;
+
+/// Loader errors, with the format being tried and the byte offset in the file
+#[derive(Debug)]
+pub enum Error {
+    /// No magic, signature or sane header for this format
+    BadMagic { format: Format, offset: usize },
+    /// The data ends inside `structure`
+    Truncated {
+        format: Format,
+        structure: &'static str,
+        offset: usize,
+    },
+    /// Known format, unknown version
+    UnsupportedVersion {
+        format: Format,
+        version: u32,
+        offset: usize,
+    },
+    /// Compressed sample data can not be unpacked
+    InvalidSampleCompression { format: Format, offset: usize },
+    /// `field` holds a value out of its range
+    OutOfRange {
+        format: Format,
+        field: &'static str,
+        value: i64,
+        offset: usize,
+    },
+    /// Valid file, but content this loader does not handle
+    Unsupported {
+        format: Format,
+        what: &'static str,
+        offset: usize,
+    },
+    /// Other `structure` decoding error
+    Decode {
+        format: Format,
+        structure: &'static str,
+        offset: usize,
+        error: DecodeError,
+    },
+}
+
+impl Error {
+    /// From a decoding error of `structure` at `offset`
+    pub(crate) fn decode(
+        format: Format,
+        structure: &'static str,
+        offset: usize,
+        error: DecodeError,
+    ) -> Self {
+        match error {
+            DecodeError::UnexpectedEnd { .. }
+            | DecodeError::LimitExceeded
+            | DecodeError::ArrayLengthMismatch { .. } => Error::Truncated {
+                format,
+                structure,
+                offset,
+            },
+            error => Error::Decode {
+                format,
+                structure,
+                offset,
+                error,
+            },
+        }
+    }
+
+    /// Format being tried
+    pub fn format(&self) -> Format {
+        match self {
+            Error::BadMagic { format, .. }
+            | Error::Truncated { format, .. }
+            | Error::UnsupportedVersion { format, .. }
+            | Error::InvalidSampleCompression { format, .. }
+            | Error::OutOfRange { format, .. }
+            | Error::Unsupported { format, .. }
+            | Error::Decode { format, .. } => *format,
+        }
+    }
+
+    /// Byte offset of the error
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::BadMagic { offset, .. }
+            | Error::Truncated { offset, .. }
+            | Error::UnsupportedVersion { offset, .. }
+            | Error::InvalidSampleCompression { offset, .. }
+            | Error::OutOfRange { offset, .. }
+            | Error::Unsupported { offset, .. }
+            | Error::Decode { offset, .. } => *offset,
+        }
+    }
+
+    /// Same error, `base` bytes further: a structure loaded from a subslice
+    pub(crate) fn at(mut self, base: usize) -> Self {
+        match &mut self {
+            Error::BadMagic { offset, .. }
+            | Error::Truncated { offset, .. }
+            | Error::UnsupportedVersion { offset, .. }
+            | Error::InvalidSampleCompression { offset, .. }
+            | Error::OutOfRange { offset, .. }
+            | Error::Unsupported { offset, .. }
+            | Error::Decode { offset, .. } => *offset += base,
+        }
+        self
+    }
+
+    /// Same error, for another format: a structure shared by several formats
+    pub(crate) fn with_format(mut self, other: Format) -> Self {
+        match &mut self {
+            Error::BadMagic { format, .. }
+            | Error::Truncated { format, .. }
+            | Error::UnsupportedVersion { format, .. }
+            | Error::InvalidSampleCompression { format, .. }
+            | Error::OutOfRange { format, .. }
+            | Error::Unsupported { format, .. }
+            | Error::Decode { format, .. } => *format = other,
+        }
+        self
+    }
+
+    /// The data is not of this format at all
+    pub fn is_bad_magic(&self) -> bool {
+        matches!(self, Error::BadMagic { .. })
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Error::BadMagic { format, offset } => {
+                write!(f, "{}: bad magic at offset {}", format, offset)
+            }
+            Error::Truncated {
+                format,
+                structure,
+                offset,
+            } => write!(
+                f,
+                "{}: truncated {} at offset {}",
+                format, structure, offset
+            ),
+            Error::UnsupportedVersion {
+                format,
+                version,
+                offset,
+            } => write!(
+                f,
+                "{}: unsupported version {:#x} at offset {}",
+                format, version, offset
+            ),
+            Error::InvalidSampleCompression { format, offset } => write!(
+                f,
+                "{}: invalid sample compression at offset {}",
+                format, offset
+            ),
+            Error::OutOfRange {
+                format,
+                field,
+                value,
+                offset,
+            } => write!(
+                f,
+                "{}: {} out of range ({}) at offset {}",
+                format, field, value, offset
+            ),
+            Error::Unsupported {
+                format,
+                what,
+                offset,
+            } => write!(f, "{}: unsupported {} at offset {}", format, what, offset),
+            Error::Decode {
+                format,
+                structure,
+                offset,
+                error,
+            } => write!(
+                f,
+                "{}: {} at offset {}: {}",
+                format, structure, offset, error
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
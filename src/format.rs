@@ -0,0 +1,55 @@
+use core::fmt::{Display, Formatter, Result};
+
+/// Historical file formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Amiga ProTracker and friends Module
+    Mod,
+    /// Fast Tracker II Module
+    Xm,
+    /// Fast Tracker II Instrument
+    Xi,
+    /// Fast Tracker II Pattern
+    Xp,
+    /// Fast Tracker II Track
+    Xt,
+    /// Scream Tracker 3 Module
+    S3m,
+    /// Impulse Tracker Module
+    It,
+    /// Impulse Tracker Instrument
+    Iti,
+    /// Impulse Tracker Sample
+    Its,
+    /// Commodore 64 PSID or RSID tune
+    Sid,
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let name = match self {
+            Format::Mod => "MOD",
+            Format::Xm => "XM",
+            Format::Xi => "XI",
+            Format::Xp => "XP",
+            Format::Xt => "XT",
+            Format::S3m => "S3M",
+            Format::It => "IT",
+            Format::Iti => "ITI",
+            Format::Its => "ITS",
+            Format::Sid => "SID",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How sure a header sniffing is, from a weak hint to a consistent header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Confidence {
+    /// Only a hint, like a magic deep in the data with an odd header around it
+    Low,
+    /// A magic, without more checks
+    Medium,
+    /// A magic and a consistent header
+    High,
+}
@@ -1,11 +1,15 @@
 use super::amiga_sample::AmigaSample;
 use super::patternslot::PatternSlot;
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 
+use crate::error::Error;
+use crate::format::{Confidence, Format};
 use crate::import::import_memory::ImportMemory;
 use crate::import::import_memory::MemoryType;
+use crate::import::xm::mod_xm_effect::{ModXmEffect, ModXmLost, ModXmMemory};
 use crate::prelude::*;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::{vec, vec::Vec};
@@ -23,6 +27,24 @@ pub struct AmigaModule {
 }
 
 impl AmigaModule {
+    /// Header sniffing of tagged modules, the module is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        let tag = data.get(0x438..0x438 + 4)?;
+        let amiga = AmigaModule {
+            tag: String::from_utf8_lossy(tag).to_string(),
+            ..Default::default()
+        };
+        amiga.get_number_of_tracks()?;
+        // a tag is only 4 bytes far in the data, the pattern order has to look sane
+        let song_length = data[0x3B6];
+        let positions = &data[0x3B8..0x438];
+        if (1..=128).contains(&song_length) && positions.iter().all(|&p| p < 128) {
+            Some(Confidence::Medium)
+        } else {
+            Some(Confidence::Low)
+        }
+    }
+
     fn get_number_of_tracks(&self) -> Option<u8> {
         match self.tag.as_str() {
             "TDZ1" => Some(1),
@@ -45,6 +67,21 @@ impl AmigaModule {
         }
     }
 
+    /// Inverse of `get_number_of_tracks`
+    fn get_tag(number_of_tracks: usize, number_of_patterns: usize) -> Option<String> {
+        match number_of_tracks {
+            1 => Some("TDZ1".to_string()),
+            2 => Some("2CHN".to_string()),
+            3 => Some("TDZ3".to_string()),
+            // ProTracker uses M!K! for more than 64 patterns
+            4 if number_of_patterns > 64 => Some("M!K!".to_string()),
+            4 => Some("M.K.".to_string()),
+            5..=9 => Some(format!("{}CHN", number_of_tracks)),
+            10..=32 => Some(format!("{}CH", number_of_tracks)),
+            _ => None,
+        }
+    }
+
     fn get_number_of_samples(&self) -> usize {
         match self.get_number_of_tracks() {
             None => 15,
@@ -56,11 +93,39 @@ impl AmigaModule {
         1 + *self.positions.iter().max().unwrap_or(&0) as usize
     }
 
-    pub fn load(ser_amiga_module: &[u8]) -> Result<AmigaModule, DecodeError> {
+    pub fn load(ser_amiga_module: &[u8]) -> Result<AmigaModule, Error> {
         let mut amiga = AmigaModule {
             ..Default::default()
         };
 
+        // get tag if any?
+        if let Some(tag) = ser_amiga_module.get(0x438..0x438 + 4) {
+            amiga.tag = String::from_utf8_lossy(tag).to_string();
+        }
+        // without tag, nothing tells this is an amiga module
+        let tagged = amiga.get_number_of_tracks().is_some();
+        let fail = |error: Error| {
+            if tagged {
+                error
+            } else {
+                Error::BadMagic {
+                    format: Format::Mod,
+                    offset: 0x438,
+                }
+            }
+        };
+        let truncated = |structure, offset| {
+            fail(Error::Truncated {
+                format: Format::Mod,
+                structure,
+                offset,
+            })
+        };
+        let header_len = 20 + 30 * amiga.get_number_of_samples() + 2 + 128;
+        if ser_amiga_module.len() < header_len {
+            return Err(truncated("header", 0));
+        }
+
         amiga.title = String::from_utf8_lossy(&ser_amiga_module[0..22]).to_string();
         amiga.title = amiga
             .title
@@ -70,20 +135,26 @@ impl AmigaModule {
             .trim()
             .to_string();
 
-        // get tag if any?
-        amiga.tag = String::from_utf8_lossy(&ser_amiga_module[0x438..0x438 + 4]).to_string();
-
         let mut data = &ser_amiga_module[0x14..];
 
         // samples struct
         for _i in 0..amiga.get_number_of_samples() {
-            let (d2, sample) = AmigaSample::load(data)?;
+            let (d2, sample) = AmigaSample::load(data)
+                .map_err(|e| fail(e.at(ser_amiga_module.len() - data.len())))?;
             data = d2;
             amiga.samples.push(sample);
         }
 
         amiga.song_length = data[0];
         amiga.restart_position = data[1];
+        if amiga.song_length > 128 {
+            return Err(fail(Error::OutOfRange {
+                format: Format::Mod,
+                field: "song length",
+                value: amiga.song_length as i64,
+                offset: ser_amiga_module.len() - data.len(),
+            }));
+        }
         data = &data[2..];
 
         // positions
@@ -104,6 +175,9 @@ impl AmigaModule {
         };
 
         let number_of_patterns = amiga.get_number_of_patterns();
+        if data.len() < number_of_patterns * 64 * number_of_tracks * 4 {
+            return Err(truncated("patterns", ser_amiga_module.len() - data.len()));
+        }
         for _p in 0..number_of_patterns {
             let mut pattern: Vec<Vec<PatternSlot>> = vec![];
             for _row in 0..64 {
@@ -194,4 +268,320 @@ impl AmigaModule {
 
         module
     }
+
+    fn from_pattern(
+        index: usize,
+        pattern: &Pattern,
+        number_of_tracks: usize,
+    ) -> Result<(Vec<Vec<PatternSlot>>, Vec<ModXmLost>), EncodeError> {
+        if pattern.len() > 64 {
+            return Err(EncodeError::OtherString(format!(
+                "AmigaModule: pattern {} has more than 64 rows",
+                index
+            )));
+        }
+
+        let mut slots: Vec<Vec<PatternSlot>> = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        let channels = pattern.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut memory = vec![ModXmMemory::default(); channels];
+        for (r, row) in pattern.iter().enumerate() {
+            let mut line = vec![PatternSlot::default(); number_of_tracks];
+            for (c, tu) in row.iter().enumerate() {
+                let (ps, mut lost) = ModXmEffect::mod_pack(&mut memory[c], tu);
+                if c < number_of_tracks {
+                    if ps.serialize().is_none() {
+                        return Err(EncodeError::OtherString(format!(
+                            "AmigaModule: pattern {}, row {}, channel {}: note {:?} has no Amiga period",
+                            index, r, c, tu.note
+                        )));
+                    }
+                    line[c] = ps;
+                } else {
+                    lost.note = tu.note;
+                    lost.instrument = tu.instrument;
+                    lost.effects = tu.effects.clone();
+                    lost.global_effects = tu.global_effects.clone();
+                }
+                if !lost.is_empty() {
+                    lost.pattern = index;
+                    lost.row = r;
+                    lost.channel = c;
+                    all_lost.push(lost);
+                }
+            }
+            slots.push(line);
+        }
+
+        // MOD patterns have 64 rows, add a pattern break to shorter ones
+        if slots.len() < 64 {
+            if slots.is_empty() {
+                slots.push(vec![PatternSlot::default(); number_of_tracks]);
+            }
+            let rows = slots.len();
+            match slots[rows - 1]
+                .iter_mut()
+                .find(|ps| ps.effect_type == 0 && ps.effect_parameter == 0)
+            {
+                Some(ps) => ps.effect_type = 0x0D,
+                None => {
+                    return Err(EncodeError::OtherString(format!(
+                        "AmigaModule: pattern {}, row {}: no room for a pattern break",
+                        index,
+                        rows - 1
+                    )))
+                }
+            }
+            slots.resize(64, vec![PatternSlot::default(); number_of_tracks]);
+        }
+        Ok((slots, all_lost))
+    }
+
+    /// Inverse of `to_instr`
+    fn from_instr(index: usize, instr: &Instrument) -> Result<(AmigaSample, Vec<i8>), EncodeError> {
+        let sample = match &instr.instr_type {
+            InstrumentType::Empty => None,
+            InstrumentType::Default(id) => {
+                let mut samples = id.sample.iter().flatten();
+                let sample = samples.next();
+                if samples.next().is_some() {
+                    return Err(EncodeError::OtherString(format!(
+                        "AmigaModule: instrument {} has more than one sample",
+                        index
+                    )));
+                }
+                sample
+            }
+            _ => {
+                return Err(EncodeError::OtherString(format!(
+                    "AmigaModule: instrument {} is not a sample instrument",
+                    index
+                )))
+            }
+        };
+
+        let Some(sample) = sample else {
+            return Ok((
+                AmigaSample {
+                    name: instr.name.clone(),
+                    repeat_length_div2: 1,
+                    ..Default::default()
+                },
+                vec![],
+            ));
+        };
+
+        if sample.relative_pitch != 0 {
+            return Err(EncodeError::OtherString(format!(
+                "AmigaModule: instrument {} has a relative pitch",
+                index
+            )));
+        }
+        let mut audio = match &sample.data {
+            None => vec![],
+            Some(SampleDataType::Mono8(d)) => d.clone(),
+            Some(_) => {
+                return Err(EncodeError::OtherString(format!(
+                    "AmigaModule: instrument {} is not a mono 8-bit sample",
+                    index
+                )))
+            }
+        };
+        if audio.len() % 2 != 0 {
+            audio.push(0);
+        }
+        if audio.len() > 2 * u16::MAX as usize {
+            return Err(EncodeError::OtherString(format!(
+                "AmigaModule: instrument {} sample is too long",
+                index
+            )));
+        }
+        Ok((AmigaSample::from_sample(&instr.name, sample), audio))
+    }
+
+    /// Inverse of `to_module`.
+    /// Fails on multi-sample instruments, 16-bit samples and notes without Amiga period,
+    /// effects which can't be saved in patterns are returned.
+    pub fn from_module(module: &Module) -> Result<(Self, Vec<ModXmLost>), EncodeError> {
+        if module.instrument.len() > 31 {
+            return Err(EncodeError::Other("AmigaModule: more than 31 instruments"));
+        }
+
+        let positions: Vec<u8> = module
+            .pattern_order
+            .iter()
+            .flatten()
+            .map(|&o| o as u8)
+            .collect();
+        if positions.len() > 128 {
+            return Err(EncodeError::Other("AmigaModule: more than 128 positions"));
+        }
+        if module
+            .pattern_order
+            .iter()
+            .flatten()
+            .any(|&o| o >= module.pattern.len() || o >= 100)
+        {
+            return Err(EncodeError::Other("AmigaModule: bad pattern order index"));
+        }
+        // no song separator in MOD
+        if module.pattern_order.len() > 1 {
+            return Err(EncodeError::Other("AmigaModule: more than one song"));
+        }
+        // only patterns played are saved
+        let number_of_patterns = positions.iter().max().map_or(1, |&o| o as usize + 1);
+
+        let number_of_tracks = module
+            .pattern
+            .iter()
+            .flatten()
+            .filter_map(|row| row.iter().rposition(|tu| !tu.is_empty()))
+            .max()
+            .map_or(4, |c| c + 1);
+        let tag = Self::get_tag(number_of_tracks, number_of_patterns)
+            .ok_or(EncodeError::Other("AmigaModule: more than 32 channels"))?;
+
+        let mut patterns = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        for (i, p) in module.pattern.iter().take(number_of_patterns).enumerate() {
+            let (slots, mut lost) = Self::from_pattern(i, p, number_of_tracks)?;
+            patterns.push(slots);
+            all_lost.append(&mut lost);
+        }
+        if patterns.is_empty() {
+            patterns.push(Self::from_pattern(0, &vec![], number_of_tracks)?.0);
+        }
+
+        let mut samples = vec![];
+        let mut audio = vec![];
+        for (i, instr) in module.instrument.iter().enumerate() {
+            let (sample, data) = Self::from_instr(i, instr)?;
+            samples.push(sample);
+            audio.push(data);
+        }
+        while samples.len() < 31 {
+            samples.push(AmigaSample {
+                repeat_length_div2: 1,
+                ..Default::default()
+            });
+            audio.push(vec![]);
+        }
+
+        let song_length = positions.len().max(1) as u8;
+        let mut positions = positions;
+        positions.resize(128, 0);
+
+        Ok((
+            Self {
+                title: module.name.clone(),
+                samples,
+                song_length,
+                restart_position: module.restart_position.min(127) as u8,
+                positions,
+                tag,
+                patterns,
+                audio,
+            },
+            all_lost,
+        ))
+    }
+
+    /// Title, 31 samples, positions, tag, patterns then samples data
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut output: Vec<u8> = vec![0; 20];
+        let title = self.title.as_bytes();
+        let len = title.len().min(20);
+        output[..len].copy_from_slice(&title[..len]);
+
+        for sample in &self.samples {
+            output.extend(sample.save()?);
+        }
+        output.push(self.song_length);
+        output.push(self.restart_position);
+        output.extend(&self.positions);
+        output.extend(self.tag.as_bytes());
+
+        for pattern in &self.patterns {
+            for row in pattern {
+                for slot in row {
+                    let e = slot
+                        .serialize()
+                        .ok_or(EncodeError::Other("AmigaModule: bad pattern slot"))?;
+                    output.extend(e.to_be_bytes());
+                }
+            }
+        }
+
+        for data in &self.audio {
+            output.extend(data.iter().map(|&x| x as u8));
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn mod_save_load() {
+        use crate::prelude::*;
+
+        let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        for instr in module.instrument.iter_mut() {
+            if let InstrumentType::Default(id) = &mut instr.instr_type {
+                for s in id.sample.iter_mut().flatten() {
+                    s.relative_pitch = 0;
+                    s.data = Some(SampleDataType::Mono8((0..100).map(|i| i as i8).collect()));
+                }
+            }
+        }
+        let tu = &mut module.pattern[0][0][0];
+        tu.effects = vec![TrackEffect::Volume {
+            value: 0.5,
+            tick: 0,
+        }];
+        tu.global_effects = vec![];
+        let tu = tu.clone();
+
+        let (data, lost) = module.save_mod_with_losses().unwrap();
+        assert!(lost.is_empty());
+        let module2 = Module::load_mod(&data).unwrap();
+        assert_eq!(data, module2.save_mod().unwrap());
+        assert_eq!(module.pattern_order, module2.pattern_order);
+        let tu2 = &module2.pattern[0][0][0];
+        assert_eq!(tu2.note, tu.note);
+        assert_eq!(tu2.effects, tu.effects);
+
+        // less than 4 channels have their own tag
+        assert_eq!(&data[0x438..0x43C], b"TDZ1");
+        assert_eq!(module2.pattern[0][0].len(), 1);
+
+        // MOD has a single order table
+        module.pattern_order.push(vec![0]);
+        assert!(module.save_mod().is_err());
+        module.pattern_order.pop();
+
+        // panning slide has no MOD effect
+        let panning = TrackEffect::PanningSlide {
+            speed: 0.25,
+            fine: false,
+        };
+        module.pattern[0][0][0].effects.push(panning.clone());
+        let (_, lost) = module.save_mod_with_losses().unwrap();
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].effects, vec![panning]);
+        module.pattern[0][0][0].effects.pop();
+
+        // MOD limits
+        module.pattern[0][0][0].note = Pitch::C9;
+        assert!(module.save_mod().is_err());
+        module.pattern[0][0][0].note = Pitch::C4;
+        if let InstrumentType::Default(id) = &mut module.instrument[0].instr_type {
+            if let Some(s) = id.sample.iter_mut().flatten().next() {
+                s.data = Some(SampleDataType::Mono16(vec![0; 100]));
+            }
+        }
+        assert!(module.save_mod().is_err());
+    }
 }
@@ -1,15 +1,28 @@
-use super::serde_helper::deserialize_string_22;
-use bincode::error::DecodeError;
-use serde::Deserialize;
+use super::serde_helper::{deserialize_string_22, serialize_string_22};
+use bincode::error::EncodeError;
+use serde::{Deserialize, Serialize};
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt;
 
+use crate::error::Error;
+use crate::format::Format;
 use crate::prelude::*;
 
-#[derive(Default, Deserialize)]
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct AmigaSample {
-    #[serde(deserialize_with = "deserialize_string_22")]
+    #[serde(
+        deserialize_with = "deserialize_string_22",
+        serialize_with = "serialize_string_22"
+    )]
     pub name: String,
     pub length_div2: u16,
     pub finetune: u8,
@@ -34,7 +47,7 @@ impl fmt::Debug for AmigaSample {
 }
 
 impl AmigaSample {
-    pub fn load(ser_sample: &[u8]) -> Result<(&[u8], Self), DecodeError> {
+    pub fn load(ser_sample: &[u8]) -> Result<(&[u8], Self), Error> {
         match bincode::serde::decode_from_slice::<AmigaSample, _>(
             &ser_sample,
             bincode::config::legacy(),
@@ -45,7 +58,7 @@ impl AmigaSample {
                 aspl.repeat_length_div2 = aspl.repeat_length_div2.rotate_right(8);
                 Ok((&ser_sample[30..], aspl))
             }
-            Err(e) => Err(e),
+            Err(e) => Err(Error::decode(Format::Mod, "sample header", 0, e)),
         }
     }
 
@@ -82,4 +95,36 @@ impl AmigaSample {
             data: None,
         }
     }
+
+    /// Inverse of `to_sample`, odd sample length is rounded up
+    pub fn from_sample(name: &str, sample: &Sample) -> Self {
+        let length = sample.len();
+        let (repeat_offset, repeat_length) = match sample.loop_flag {
+            LoopType::No => (0, 2),
+            // no ping-pong loop in MOD
+            _ => (sample.loop_start as usize, sample.loop_length as usize),
+        };
+        let finetune = (sample.finetune * 127.0 / 16.0).round().clamp(-8.0, 7.0) as i8;
+
+        Self {
+            name: name.into(),
+            length_div2: length.div_ceil(2) as u16,
+            finetune: finetune as u8 & 0x0F,
+            volume: (sample.volume * 64.0).round().clamp(0.0, 64.0) as u8,
+            repeat_offset_div2: (repeat_offset / 2) as u16,
+            repeat_length_div2: (repeat_length / 2).max(1) as u16,
+        }
+    }
+
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        let aspl = Self {
+            name: self.name.clone(),
+            length_div2: self.length_div2.rotate_left(8),
+            finetune: self.finetune,
+            volume: self.volume,
+            repeat_offset_div2: self.repeat_offset_div2.rotate_left(8),
+            repeat_length_div2: self.repeat_length_div2.rotate_left(8),
+        };
+        bincode::serde::encode_to_vec(&aspl, bincode::config::legacy())
+    }
 }
@@ -93,6 +93,39 @@ impl PatternSlot {
         }
     }
 
+    /// Inverse of `amiga_pitch`
+    fn amiga_period(pitch: u8) -> Option<u16> {
+        const PERIODS: [u16; 84] = [
+            6848, 6464, 6096, 5760, 5424, 5120, 4832, 4560, 4304, 4064, 3840, 3624, // 0
+            3424, 3232, 3048, 2880, 2712, 2560, 2416, 2280, 2152, 2032, 1920, 1812, // 1
+            1712, 1616, 1524, 1440, 1356, 1280, 1208, 1140, 1076, 1016, 960, 906, // 2
+            856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453, // 3
+            428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226, // 4
+            214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113, // 5
+            107, 101, 95, 90, 85, 80, 75, 71, 67, 63, 60, 56, // 6
+        ];
+        PERIODS.get(pitch as usize).copied()
+    }
+
+    /// Inverse of `deserialize`, fails if the note has no Amiga period
+    pub fn serialize(&self) -> Option<u32> {
+        let period = match self.note {
+            Pitch::None => 0,
+            note => Self::amiga_period(note as u8)?,
+        };
+        let instrument = self.instrument.map_or(0, |i| i as u32 + 1);
+        if instrument > 0xFF {
+            return None;
+        }
+        Some(
+            (instrument & 0xF0) << 24
+                | (period as u32) << 16
+                | (instrument & 0x0F) << 12
+                | ((self.effect_type & 0x0F) as u32) << 8
+                | self.effect_parameter as u32,
+        )
+    }
+
     /*
         0bIIII_PPPPPPPPPPPP_IIII_EEEE_DDDDDDDD
         P: period
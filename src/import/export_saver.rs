@@ -0,0 +1,118 @@
+use crate::prelude::*;
+#[cfg(any(
+    feature = "import_amiga",
+    feature = "import_it",
+    feature = "import_s3m",
+    feature = "import_xm"
+))]
+use bincode::error::EncodeError;
+
+#[cfg(any(
+    feature = "import_amiga",
+    feature = "import_it",
+    feature = "import_s3m",
+    feature = "import_xm"
+))]
+use alloc::vec::Vec;
+
+#[cfg(any(feature = "import_amiga", feature = "import_it", feature = "import_xm"))]
+use super::xm::mod_xm_effect::ModXmLost;
+
+impl Module {
+    /// Try to export to Fast Tracker II Module file,
+    /// see `save_xm_with_losses` to know what can't be saved
+    #[cfg(feature = "import_xm")]
+    pub fn save_xm(&self) -> Result<Vec<u8>, EncodeError> {
+        self.save_xm_with_losses().map(|(data, _)| data)
+    }
+
+    /// Try to export to Fast Tracker II Module file,
+    /// also returns everything which can't be saved in patterns
+    #[cfg(feature = "import_xm")]
+    pub fn save_xm_with_losses(&self) -> Result<(Vec<u8>, Vec<ModXmLost>), EncodeError> {
+        use super::xm::xmmodule::XmModule;
+
+        let (mut xm, lost) = XmModule::from_module(self)?;
+        Ok((xm.save()?, lost))
+    }
+
+    /// Try to export to Impulse Tracker Module file,
+    /// see `save_it_with_losses` to know what can't be saved
+    #[cfg(feature = "import_it")]
+    pub fn save_it(&self) -> Result<Vec<u8>, EncodeError> {
+        self.save_it_with_losses().map(|(data, _)| data)
+    }
+
+    /// Try to export to Impulse Tracker Module file,
+    /// also returns everything which can't be saved in patterns
+    #[cfg(feature = "import_it")]
+    pub fn save_it_with_losses(&self) -> Result<(Vec<u8>, Vec<ModXmLost>), EncodeError> {
+        use super::it::it_module::ItModule;
+
+        let (mut it, lost) = ItModule::from_module(self)?;
+        Ok((it.save()?, lost))
+    }
+
+    /// Try to export to Impulse Tracker Module file, mono samples are compressed
+    /// using IT214 (or IT215 if `it215`)
+    #[cfg(feature = "import_it")]
+    pub fn save_it_compressed(&self, it215: bool) -> Result<Vec<u8>, EncodeError> {
+        use super::it::it_module::ItModule;
+
+        ItModule::from_module(self)?.0.save_compressed(it215)
+    }
+
+    /// Try to export to Scream Tracker 3 Module file
+    #[cfg(feature = "import_s3m")]
+    pub fn save_s3m(&self) -> Result<Vec<u8>, EncodeError> {
+        use super::s3m::s3m_module::S3mModule;
+
+        S3mModule::from_module(self)?.save()
+    }
+
+    /// Try to export to Amiga Module file,
+    /// see `save_mod_with_losses` to know what can't be saved
+    #[cfg(feature = "import_amiga")]
+    pub fn save_mod(&self) -> Result<Vec<u8>, EncodeError> {
+        self.save_mod_with_losses().map(|(data, _)| data)
+    }
+
+    /// Try to export to Amiga Module file,
+    /// also returns everything which can't be saved in patterns
+    #[cfg(feature = "import_amiga")]
+    pub fn save_mod_with_losses(&self) -> Result<(Vec<u8>, Vec<ModXmLost>), EncodeError> {
+        use super::amiga::amiga_module::AmigaModule;
+
+        let (module, lost) = AmigaModule::from_module(self)?;
+        Ok((module.save()?, lost))
+    }
+}
+
+impl Instrument {
+    /// Try to export to Fast Tracker II Extended Instrument file
+    #[cfg(feature = "import_xm")]
+    pub fn save_xi(&self) -> Result<Vec<u8>, EncodeError> {
+        use super::xm::xi_instrument::XiInstrument;
+        use super::xm::xminstrument::XmInstrument;
+
+        XiInstrument::save(&mut XmInstrument::from_instr(self))
+    }
+
+    /// Try to export to Impulse Tracker Instrument file
+    #[cfg(feature = "import_it")]
+    pub fn save_iti(&self) -> Result<Vec<u8>, EncodeError> {
+        use super::it::iti_instrument::ItiInstrument;
+
+        ItiInstrument::save(self)
+    }
+}
+
+impl Sample {
+    /// Try to export to Impulse Tracker Sample file
+    #[cfg(feature = "import_it")]
+    pub fn save_its(&self) -> Result<Vec<u8>, EncodeError> {
+        use super::it::its_sample::ItsSample;
+
+        ItsSample::save(self)
+    }
+}
@@ -1,10 +1,12 @@
+use crate::error::Error;
+use crate::format::{Confidence, Format};
 use crate::prelude::*;
-use bincode::error::DecodeError;
+use alloc::vec::Vec;
 
 impl Module {
     /// Try to import Amiga Module file
     #[cfg(feature = "import_amiga")]
-    pub fn load_mod(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load_mod(source: &[u8]) -> Result<Self, Error> {
         use super::amiga::amiga_module::AmigaModule;
 
         match AmigaModule::load(source) {
@@ -15,7 +17,7 @@ impl Module {
 
     /// Try to import Fast Tracker II Module file
     #[cfg(feature = "import_xm")]
-    pub fn load_xm(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load_xm(source: &[u8]) -> Result<Self, Error> {
         use super::xm::xmmodule::XmModule;
 
         match XmModule::load(source) {
@@ -26,7 +28,7 @@ impl Module {
 
     /// Try to import Scream Tracker 3 Module file
     #[cfg(feature = "import_s3m")]
-    pub fn load_s3m(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load_s3m(source: &[u8]) -> Result<Self, Error> {
         use super::s3m::s3m_module::S3mModule;
 
         match S3mModule::load(source) {
@@ -37,7 +39,7 @@ impl Module {
 
     /// Try to import Impulse Tracker Module file
     #[cfg(feature = "import_it")]
-    pub fn load_it(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load_it(source: &[u8]) -> Result<Self, Error> {
         use super::it::it_module::ItModule;
 
         match ItModule::load(source) {
@@ -46,29 +48,198 @@ impl Module {
         }
     }
 
+    /// Guess the formats of the data from their headers only, the most likely first
+    pub fn detect_format(source: &[u8]) -> Vec<(Format, Confidence)> {
+        #[allow(unused_mut)]
+        let mut detected: Vec<(Format, Option<Confidence>)> = Vec::new();
+
+        #[cfg(feature = "import_xm")]
+        {
+            use super::xm::xi_instrument::XiInstrument;
+            use super::xm::xmheader::XmHeader;
+            detected.push((Format::Xm, XmHeader::detect(source)));
+            detected.push((Format::Xi, XiInstrument::detect(source)));
+        }
+
+        #[cfg(feature = "import_s3m")]
+        {
+            use super::s3m::s3m_module::S3mModule;
+            detected.push((Format::S3m, S3mModule::detect(source)));
+        }
+
+        #[cfg(feature = "import_it")]
+        {
+            use super::it::it_module::ItModule;
+            use super::it::iti_instrument::ItiInstrument;
+            detected.push((Format::It, ItModule::detect(source)));
+            detected.push((Format::Iti, ItiInstrument::detect(source)));
+        }
+
+        #[cfg(feature = "import_amiga")]
+        {
+            use super::amiga::amiga_module::AmigaModule;
+            detected.push((Format::Mod, AmigaModule::detect(source)));
+        }
+
+        #[cfg(feature = "import_sid")]
+        {
+            use super::sid::psid_header::PsidHeader;
+            detected.push((Format::Sid, PsidHeader::detect(source)));
+        }
+
+        let mut formats: Vec<(Format, Confidence)> = detected
+            .into_iter()
+            .filter_map(|(format, confidence)| Some((format, confidence?)))
+            .collect();
+        formats.sort_by_key(|&(_, confidence)| core::cmp::Reverse(confidence));
+        formats
+    }
+
     /// Try to import any historical Module file
-    pub fn load(source: &[u8]) -> Result<Self, DecodeError> {
+    ///
+    /// If no format fits, the error is the one of the format going the furthest
+    /// in the data, a bad magic being the least telling
+    pub fn load(source: &[u8]) -> Result<Self, Error> {
+        #[allow(unused_mut)]
+        let mut errors: Vec<Error> = Vec::new();
+
         #[cfg(feature = "import_xm")]
-        if let Ok(m) = Self::load_xm(source) {
-            return Ok(m);
+        match Self::load_xm(source) {
+            Ok(m) => return Ok(m),
+            Err(e) => errors.push(e),
         };
 
         #[cfg(feature = "import_s3m")]
-        if let Ok(m) = Self::load_s3m(source) {
-            return Ok(m);
+        match Self::load_s3m(source) {
+            Ok(m) => return Ok(m),
+            Err(e) => errors.push(e),
         };
 
         #[cfg(feature = "import_it")]
-        if let Ok(m) = Self::load_it(source) {
-            return Ok(m);
+        match Self::load_it(source) {
+            Ok(m) => return Ok(m),
+            Err(e) => errors.push(e),
         };
 
         // The amiga format is the last one because it is the least well specified for format detection
         #[cfg(feature = "import_amiga")]
-        if let Ok(m) = Self::load_mod(source) {
-            return Ok(m);
+        match Self::load_mod(source) {
+            Ok(m) => return Ok(m),
+            Err(e) => errors.push(e),
         };
 
-        return Err(DecodeError::Other("Unknown data?"));
+        let rank = |e: &Error| (!e.is_bad_magic(), e.offset());
+        let best = errors
+            .into_iter()
+            .reduce(|best, e| if rank(&e) > rank(&best) { e } else { best });
+        Err(best.unwrap_or(Error::BadMagic {
+            format: Format::Mod,
+            offset: 0,
+        }))
+    }
+}
+
+impl Instrument {
+    /// Try to import Impulse Tracker Instrument file
+    #[cfg(feature = "import_it")]
+    pub fn load_iti(source: &[u8]) -> Result<Self, Error> {
+        use super::it::iti_instrument::ItiInstrument;
+
+        ItiInstrument::load(source)
+    }
+}
+
+impl Sample {
+    /// Try to import Impulse Tracker Sample file
+    #[cfg(feature = "import_it")]
+    pub fn load_its(source: &[u8]) -> Result<Self, Error> {
+        use super::it::its_sample::ItsSample;
+
+        ItsSample::load(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[cfg(feature = "import")]
+    #[test]
+    fn load_errors() {
+        use crate::format::Format;
+        use crate::prelude::*;
+        use crate::Error;
+
+        let data = include_bytes!("../../examples/note.xm");
+        let truncated = &data[..data.len() - 10];
+        match Module::load_xm(truncated) {
+            Err(Error::Truncated {
+                format: Format::Xm,
+                offset,
+                ..
+            }) => assert!(offset > 0 && offset < data.len()),
+            e => panic!("{:?}", e.err()),
+        }
+
+        // the XM loader goes the furthest
+        let e = Module::load(truncated).err().unwrap();
+        assert_eq!(e.format(), Format::Xm);
+        assert!(!e.is_bad_magic());
+
+        let e = Module::load(&[0x55; 2048]).err().unwrap();
+        assert!(e.is_bad_magic());
+        assert!(Module::load(&[]).err().unwrap().is_bad_magic());
+    }
+
+    #[cfg(feature = "import")]
+    #[test]
+    fn detect_format() {
+        use crate::format::{Confidence, Format};
+        use crate::prelude::*;
+
+        let data = include_bytes!("../../examples/note.xm");
+        let module = Module::load_xm(data).unwrap();
+        let first = |data: &[u8]| Module::detect_format(data).first().copied();
+        assert_eq!(first(data), Some((Format::Xm, Confidence::High)));
+        // the header is enough
+        assert_eq!(first(&data[..80]), Some((Format::Xm, Confidence::High)));
+        assert_eq!(
+            first(&module.save_s3m().unwrap()),
+            Some((Format::S3m, Confidence::High))
+        );
+        assert_eq!(
+            first(&module.save_it().unwrap()),
+            Some((Format::It, Confidence::High))
+        );
+        assert_eq!(
+            first(include_bytes!("../../examples/instr.xi")),
+            Some((Format::Xi, Confidence::High))
+        );
+        assert_eq!(
+            first(&module.instrument[0].save_iti().unwrap()),
+            Some((Format::Iti, Confidence::High))
+        );
+
+        let mut data = vec![0u8; 0x43C];
+        data[0x3B6] = 1;
+        data[0x438..].copy_from_slice(b"M.K.");
+        assert_eq!(
+            Module::detect_format(&data),
+            vec![(Format::Mod, Confidence::Medium)]
+        );
+        data[0x3B8] = 0xFF;
+        assert_eq!(
+            Module::detect_format(&data),
+            vec![(Format::Mod, Confidence::Low)]
+        );
+
+        #[cfg(feature = "import_sid")]
+        assert_eq!(
+            first(&crate::import::sid::one_sid::OneSid::get_sid_commando().song),
+            Some((Format::Sid, Confidence::High))
+        );
+
+        assert!(Module::detect_format(&[0x55; 2048]).is_empty());
+        assert!(Module::detect_format(&[]).is_empty());
     }
 }
@@ -0,0 +1,32 @@
+use alloc::vec::Vec;
+
+/// Inverse of `BitReader`: bits are written LSB first
+#[derive(Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    databit_index: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_bits(&mut self, n: u8, value: u32) {
+        for i in 0..n {
+            if self.databit_index == 0 {
+                self.data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            if let Some(last) = self.data.last_mut() {
+                *last |= bit << self.databit_index;
+            }
+            self.databit_index = (self.databit_index + 1) % 8;
+        }
+    }
+
+    /// Unused bits of the last byte are zero
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
@@ -1,9 +1,10 @@
 use core::time::Duration;
 
+use crate::error::Error;
+use crate::format::Format;
 use alloc::format;
 use alloc::vec;
 use alloc::vec::Vec;
-use bincode::error::DecodeError;
 use bincode::Decode;
 use core::fmt;
 use serde::Deserialize;
@@ -76,11 +77,16 @@ impl ItEditHistoryEntry {
 pub struct ItEditHistory;
 
 impl ItEditHistory {
-    pub fn load(source: &[u8]) -> Result<(Option<Vec<ItEditHistoryEntry>>, usize), DecodeError> {
+    pub fn load(source: &[u8]) -> Result<(Option<Vec<ItEditHistoryEntry>>, usize), Error> {
         let data = source;
+        let truncated = Error::Truncated {
+            format: Format::It,
+            structure: "edit history",
+            offset: 0,
+        };
 
         if data.len() < 2 {
-            return Err(DecodeError::LimitExceeded);
+            return Err(truncated);
         }
 
         let edit_history_number: u16 = u16::from_le_bytes(data[0..2].try_into().unwrap());
@@ -90,11 +96,9 @@ impl ItEditHistory {
 
         let struct_size = core::mem::size_of::<ItEditHistoryEntry>();
         let total_size = struct_size * edit_history_number as usize;
-        let data = &data[2..2 + total_size];
-
-        if data.len() < total_size {
-            return Err(DecodeError::LimitExceeded);
-        }
+        let Some(data) = data.get(2..2 + total_size) else {
+            return Err(truncated);
+        };
 
         let mut edit_histories: Vec<ItEditHistoryEntry> = vec![];
         for i in 0..edit_history_number {
@@ -103,10 +103,21 @@ use crate::effect::MidiMacroType;
 use crate::import::patternslot::PatternSlot;
 use crate::import::track_import_effect::TrackImportEffect;
 use crate::import::track_import_unit::TrackImportUnit;
+use crate::import::xm::mod_xm_effect::{ModXmEffect, ModXmLost, ModXmMemory};
 use crate::prelude::*;
 use alloc::vec;
 use alloc::vec::Vec;
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Tone portamento speeds of the volume column `g0x`
+const VOLUME_TONE_PORTAMENTO: [u8; 10] = [0, 1, 4, 8, 16, 32, 64, 96, 128, 255];
+
 pub struct ItEffect;
 
 /// The idea here is to achieve a first level of abstraction
@@ -119,7 +130,7 @@ impl ItEffect {
     // DFx Fine volume slide down
     // DxF Fine volume slide up
     fn it_volume_slide(fx: u8) -> Option<TrackImportEffect> {
-        let nibble_high = fx & 0xF0 >> 4;
+        let nibble_high = (fx & 0xF0) >> 4;
         let nibble_low = fx & 0x0F;
 
         match (nibble_high, nibble_low) {
@@ -259,7 +270,7 @@ impl ItEffect {
 
             // Set Channel Volume (Mxx)
             0x0D => {
-                let volume = current.effect_parameter.max(64) as f32 / 64.0;
+                let volume = current.effect_parameter.min(64) as f32 / 64.0;
                 return Some(vec![TrackImportEffect::ChannelVolume(volume)]);
             }
 
@@ -312,17 +323,13 @@ impl ItEffect {
             // PxF Fine pan slide up (right)
             0x10 => {
                 let param = current.effect_parameter;
-                let upper_nibble = param & 0xF0;
+                let upper_nibble = (param & 0xF0) >> 4;
                 let lower_nibble = param & 0x0F;
 
                 return match (upper_nibble, lower_nibble) {
-                    (f, 0) => Some(vec![TrackImportEffect::PanningSlideN(
-                        (f >> 4) as f32 / 16.0,
-                    )]), // Slide up (right)
+                    (f, 0) => Some(vec![TrackImportEffect::PanningSlideN(f as f32 / 16.0)]), // Slide up (right)
                     (0, f) => Some(vec![TrackImportEffect::PanningSlideN(-(f as f32) / 16.0)]), // Slide down (left)
-                    (f, 0xF) => Some(vec![TrackImportEffect::PanningSlide0(
-                        (f >> 4) as f32 / 16.0,
-                    )]), // Slide up (right)
+                    (f, 0xF) => Some(vec![TrackImportEffect::PanningSlide0(f as f32 / 16.0)]), // Slide up (right)
                     (0xF, f) => Some(vec![TrackImportEffect::PanningSlide0(-(f as f32) / 16.0)]), // Slide down (left)
                     _ => None,
                 };
@@ -512,13 +519,11 @@ impl ItEffect {
                     current.effect_parameter as usize,
                 ));
             }
-            // Effect Break to Row (Cxx) -> Pas d'Effect direct à lier ici, ajouter un commentaire si nécessaire.
+            // Effect Break to Row (Cxx), xx is not BCD encoded in IT
             0x03 => {
-                let param = current.effect_parameter;
-                let ten = (param >> 4) as usize * 10;
-                let unit = (param & 0x0F) as usize;
-                let total = ten + unit;
-                return Some(GlobalEffect::PatternBreak(total));
+                return Some(GlobalEffect::PatternBreak(
+                    current.effect_parameter as usize,
+                ));
             }
 
             // Effect Special Commands (Sxx)
@@ -562,7 +567,7 @@ impl ItEffect {
             // T1x Tempo slide up
             0x14 => {
                 let param = current.effect_parameter;
-                let upper_nibble = param & 0xF0;
+                let upper_nibble = (param & 0xF0) >> 4;
                 let lower_nibble = param & 0x0F;
 
                 return match (upper_nibble, lower_nibble) {
@@ -575,7 +580,7 @@ impl ItEffect {
             // Effect Set Global Volume (Vxx)
             0x16 => {
                 return Some(GlobalEffect::Volume(
-                    (current.effect_parameter.max(64) as f32) / 64.0,
+                    (current.effect_parameter.min(128) as f32) / 128.0,
                 ))
             }
 
@@ -586,7 +591,7 @@ impl ItEffect {
             // WxF Fine global volume slide up
             0x17 => {
                 let param = current.effect_parameter;
-                let upper_nibble = param & 0xF0;
+                let upper_nibble = (param & 0xF0) >> 4;
                 let lower_nibble = param & 0x0F;
 
                 return match (upper_nibble, lower_nibble) {
@@ -595,7 +600,7 @@ impl ItEffect {
                         fine: false,
                     }), // Slide down
                     (f, 0) => Some(GlobalEffect::VolumeSlide {
-                        speed: (f >> 4) as f32 / 64.0,
+                        speed: f as f32 / 64.0,
                         fine: false,
                     }), // Slide up
                     (0xF, f) => Some(GlobalEffect::VolumeSlide {
@@ -603,7 +608,7 @@ impl ItEffect {
                         fine: true,
                     }), // Fine Slide down
                     (f, 0xF) => Some(GlobalEffect::VolumeSlide {
-                        speed: (f >> 4) as f32 / 64.0,
+                        speed: f as f32 / 64.0,
                         fine: true,
                     }), // Fine Slide up
                     _ => None,
@@ -614,7 +619,9 @@ impl ItEffect {
             0x1A => {
                 let param = current.effect_parameter;
                 if param & 0x80 == 0 {
-                    return Some(GlobalEffect::MidiMacro(MidiMacroType::Parametric(None)));
+                    return Some(GlobalEffect::MidiMacro(MidiMacroType::ParametricValue(
+                        param as usize,
+                    )));
                 } else {
                     return Some(GlobalEffect::MidiMacro(MidiMacroType::Fixed(
                         param as usize - 0x80,
@@ -629,7 +636,7 @@ impl ItEffect {
     fn parse_it_volume(freq_type: FrequencyType, slot: &PatternSlot) -> Option<TrackImportEffect> {
         // Set Volume (vxx)
         if slot.volume <= 64 {
-            return Some(TrackImportEffect::ChannelVolume(slot.volume as f32 / 64.0));
+            return Some(TrackImportEffect::Volume(slot.volume as f32 / 64.0, 0));
         }
 
         // Set Panning (pxx)
@@ -651,17 +658,17 @@ impl ItEffect {
 
         match fx {
             // Fine Volume Slide Up (a0x)
-            0x0 => return Some(TrackImportEffect::VolumeSlide0(param as f32 / 16.0)),
+            0x0 => return Some(TrackImportEffect::VolumeSlide0(param as f32 / 64.0)),
             // Fine Volume Slide Down (b0x)
-            0x1 => return Some(TrackImportEffect::VolumeSlide0(-(param as f32) / 16.0)),
+            0x1 => return Some(TrackImportEffect::VolumeSlide0(-(param as f32) / 64.0)),
             // Volume Slide Up (c0x)
-            0x2 => return Some(TrackImportEffect::VolumeSlideN(param as f32 / 16.0)),
+            0x2 => return Some(TrackImportEffect::VolumeSlideN(param as f32 / 64.0)),
             // Volume Slide Down (d0x)
-            0x3 => return Some(TrackImportEffect::VolumeSlideN(-(param as f32) / 16.0)),
-            // Portamento Down (e0x)
-            0x4 => return Some(TrackImportEffect::PortamentoDown(-(param as f32) / 16.0)),
-            // Portamento Up (f0x)
-            0x5 => return Some(TrackImportEffect::PortamentoUp(param as f32 / 16.0)),
+            0x3 => return Some(TrackImportEffect::VolumeSlideN(-(param as f32) / 64.0)),
+            // Portamento Down (e0x), same as Exx with xx = 4*x
+            0x4 => return Some(TrackImportEffect::PortamentoDown(16.0 * param as f32)),
+            // Portamento Up (f0x), same as Fxx with xx = 4*x
+            0x5 => return Some(TrackImportEffect::PortamentoUp(-16.0 * param as f32)),
             // Tone Portamento (g0x)
             0x6 => {
                 let param = match param {
@@ -724,4 +731,406 @@ impl ItEffect {
             })
             .collect::<Vec<Vec<TrackImportUnit>>>() // Convertir tous les canaux
     }
+
+    //--- pack: TrackUnit to PatternSlot --------------------------------------
+
+    /// Dxy, Nxy, Pxy and Wxy parameter, `unit` is the value of one step
+    fn it_pack_slide(speed: f32, fine: bool, unit: f32) -> Option<u8> {
+        let x = ModXmEffect::nibble(speed.abs() * unit)?;
+        match (fine, speed >= 0.0) {
+            (false, true) => Some(x << 4),
+            (false, false) => Some(x),
+            // xF and Fx are ambiguous with 0 and F
+            (true, _) if x == 0 || x == 0xF => None,
+            (true, true) => Some(x << 4 | 0x0F),
+            (true, false) => Some(0xF0 | x),
+        }
+    }
+
+    fn it_pack_waveform(waveform: &Waveform, retrig: bool) -> Option<u8> {
+        if !retrig {
+            return None;
+        }
+        match waveform {
+            Waveform::Sine => Some(0),
+            Waveform::RampDown => Some(1),
+            Waveform::Square => Some(2),
+            Waveform::Random => Some(3),
+            _ => None,
+        }
+    }
+
+    fn it_pack_tone_portamento(freq_type: FrequencyType, speed: f32) -> f32 {
+        match freq_type {
+            FrequencyType::LinearFrequencies => speed / 4.0,
+            FrequencyType::AmigaFrequencies => speed,
+        }
+    }
+
+    /// Effect column (type, parameter)
+    fn it_pack_effect(freq_type: FrequencyType, effect: &TrackEffect) -> Option<(u8, u8)> {
+        match effect {
+            TrackEffect::VolumeSlide { speed, fine } => {
+                Some((0x04, Self::it_pack_slide(*speed, *fine, 64.0)?))
+            }
+            TrackEffect::Portamento { speed, fine } => {
+                let (fx, speed) = if *speed < 0.0 {
+                    (0x06, -speed)
+                } else {
+                    (0x05, *speed)
+                };
+                let param = speed / 4.0;
+                if param.fract() != 0.0 {
+                    if !fine {
+                        return None;
+                    }
+                    // Extra fine portamento
+                    return Some((fx, 0xE0 | ModXmEffect::nibble(speed)?));
+                }
+                if *fine {
+                    return Some((fx, 0xF0 | ModXmEffect::nibble(param)?));
+                }
+                let param = ModXmEffect::byte(param)?;
+                if param >= 0xE0 {
+                    return None;
+                }
+                Some((fx, param))
+            }
+            TrackEffect::TonePortamento(speed) => {
+                let param = Self::it_pack_tone_portamento(freq_type, *speed);
+                Some((0x07, ModXmEffect::byte(param)?))
+            }
+            TrackEffect::Vibrato { speed, depth } => {
+                let x = ModXmEffect::nibble(speed * 256.0)?;
+                let depth = depth * 4.0;
+                if depth.fract() == 0.0 {
+                    Some((0x08, x << 4 | ModXmEffect::nibble(depth)?))
+                } else {
+                    // Fine vibrato
+                    Some((0x15, x << 4 | ModXmEffect::nibble(depth * 4.0)?))
+                }
+            }
+            TrackEffect::Tremor { on_time, off_time } => {
+                let x = ModXmEffect::nibble(*on_time as f32)?;
+                let y = ModXmEffect::nibble(*off_time as f32)?;
+                Some((0x09, x << 4 | y))
+            }
+            TrackEffect::Arpeggio { half1, half2 } => {
+                let x = ModXmEffect::nibble(*half1 as f32)?;
+                let y = ModXmEffect::nibble(*half2 as f32)?;
+                Some((0x0A, x << 4 | y))
+            }
+            TrackEffect::ChannelVolume(value) => {
+                Some((0x0D, ModXmEffect::byte(value * 64.0)?.min(64)))
+            }
+            TrackEffect::ChannelVolumeSlide { speed, fine } => {
+                Some((0x0E, Self::it_pack_slide(*speed, *fine, 64.0)?))
+            }
+            TrackEffect::InstrumentSampleOffset(offset) => {
+                if offset % 256 != 0 || *offset > 0xFF00 {
+                    return None;
+                }
+                Some((0x0F, (offset / 256) as u8))
+            }
+            TrackEffect::PanningSlide { speed, fine } => {
+                Some((0x10, Self::it_pack_slide(*speed, *fine, 16.0)?))
+            }
+            TrackEffect::NoteRetrig {
+                speed,
+                volume_modifier,
+            } => {
+                let x = ModXmEffect::mod_xm_pack_retrig_operator(volume_modifier)?;
+                Some((0x11, x << 4 | ModXmEffect::nibble(*speed as f32)?))
+            }
+            TrackEffect::Tremolo { speed, depth } => {
+                let x = ModXmEffect::nibble(speed * 64.0)?;
+                let y = ModXmEffect::nibble(depth * 16.0)?;
+                Some((0x12, x << 4 | y))
+            }
+            TrackEffect::VibratoWaveform { waveform, retrig } => {
+                Some((0x13, 0x30 | Self::it_pack_waveform(waveform, *retrig)?))
+            }
+            TrackEffect::TremoloWaveform { waveform, retrig } => {
+                Some((0x13, 0x40 | Self::it_pack_waveform(waveform, *retrig)?))
+            }
+            TrackEffect::PanbrelloWaveform { waveform, retrig } => {
+                Some((0x13, 0x50 | Self::it_pack_waveform(waveform, *retrig)?))
+            }
+            TrackEffect::NoteCut {
+                tick: 0,
+                past: true,
+            } => Some((0x13, 0x70)),
+            TrackEffect::NoteOff {
+                tick: 0,
+                past: true,
+            } => Some((0x13, 0x71)),
+            TrackEffect::NoteFadeOut {
+                tick: 0,
+                past: true,
+            } => Some((0x13, 0x72)),
+            TrackEffect::InstrumentNewNoteAction(nna) => match nna {
+                NewNoteAction::NoteCut => Some((0x13, 0x73)),
+                NewNoteAction::Continue => Some((0x13, 0x74)),
+                NewNoteAction::NoteOff => Some((0x13, 0x75)),
+                NewNoteAction::NoteFadeOut => Some((0x13, 0x76)),
+            },
+            TrackEffect::InstrumentVolumeEnvelope(on) => {
+                Some((0x13, if *on { 0x77 } else { 0x78 }))
+            }
+            TrackEffect::InstrumentPanningEnvelope(on) => {
+                Some((0x13, if *on { 0x79 } else { 0x7A }))
+            }
+            TrackEffect::InstrumentPitchEnvelope(on) => Some((0x13, if *on { 0x7B } else { 0x7C })),
+            TrackEffect::InstrumentSurround(on) => Some((0x13, 0x90 | *on as u8)),
+            TrackEffect::NoteCut { tick, past: false } => {
+                Some((0x13, 0xC0 | ModXmEffect::nibble(*tick as f32)?))
+            }
+            TrackEffect::NoteDelay(tick) => Some((0x13, 0xD0 | ModXmEffect::nibble(*tick as f32)?)),
+            TrackEffect::Panning(pos) => Some((0x18, ModXmEffect::byte((pos * 256.0).min(255.0))?)),
+            TrackEffect::Panbrello { speed, depth } => {
+                let x = ModXmEffect::nibble(speed * 256.0)?;
+                let y = ModXmEffect::nibble(depth * 4.0)?;
+                Some((0x19, x << 4 | y))
+            }
+            _ => None,
+        }
+    }
+
+    /// Volume column byte
+    fn it_pack_volume(freq_type: FrequencyType, effect: &TrackEffect) -> Option<u8> {
+        let param = |v: f32| {
+            let v = v.round();
+            if (0.0..=9.0).contains(&v) {
+                Some(v as u8)
+            } else {
+                None
+            }
+        };
+        match effect {
+            TrackEffect::Volume { value, tick: 0 } => {
+                Some(ModXmEffect::byte(value * 64.0)?.min(64))
+            }
+            TrackEffect::VolumeSlide { speed, fine } => {
+                let base = match (fine, *speed >= 0.0) {
+                    (true, true) => 65,
+                    (true, false) => 75,
+                    (false, true) => 85,
+                    (false, false) => 95,
+                };
+                Some(base + param(speed.abs() * 64.0)?)
+            }
+            TrackEffect::Portamento { speed, fine: false } => {
+                let base = if *speed < 0.0 { 115 } else { 105 };
+                let speed = speed.abs() / 16.0;
+                if speed.fract() != 0.0 {
+                    return None;
+                }
+                Some(base + param(speed)?)
+            }
+            TrackEffect::Panning(pos) => Some(128 + ModXmEffect::byte(pos * 64.0)?.min(64)),
+            TrackEffect::TonePortamento(speed) => {
+                let speed = Self::it_pack_tone_portamento(freq_type, *speed);
+                let index = VOLUME_TONE_PORTAMENTO
+                    .iter()
+                    .position(|&s| s as f32 == speed)?;
+                Some(193 + index as u8)
+            }
+            _ => None,
+        }
+    }
+
+    fn it_pack_global_effect(effect: &GlobalEffect) -> Option<(u8, u8)> {
+        match effect {
+            GlobalEffect::Speed(speed) => {
+                if *speed == 0 {
+                    return None;
+                }
+                Some((0x01, ModXmEffect::byte(*speed as f32)?))
+            }
+            GlobalEffect::PositionJump(pos) => Some((0x02, ModXmEffect::byte(*pos as f32)?)),
+            GlobalEffect::PatternBreak(row) => Some((0x03, ModXmEffect::byte(*row as f32)?)),
+            GlobalEffect::PatternDelay {
+                quantity,
+                tempo: false,
+            } => Some((0x13, 0x60 | ModXmEffect::nibble(*quantity as f32)?)),
+            GlobalEffect::PatternLoop(count) => {
+                Some((0x13, 0xB0 | ModXmEffect::nibble(*count as f32)?))
+            }
+            GlobalEffect::PatternDelay {
+                quantity,
+                tempo: true,
+            } => Some((0x13, 0xE0 | ModXmEffect::nibble(*quantity as f32)?)),
+            GlobalEffect::MidiMacro(MidiMacroType::Parametric(Some(index))) => {
+                Some((0x13, 0xF0 | ModXmEffect::nibble(*index as f32)?))
+            }
+            GlobalEffect::BpmSlide(speed) => {
+                let x = ModXmEffect::nibble(speed.unsigned_abs() as f32)?;
+                if x == 0 {
+                    return None;
+                }
+                Some((0x14, if *speed < 0 { x } else { 0x10 | x }))
+            }
+            GlobalEffect::Bpm(bpm) => {
+                if *bpm < 0x20 || *bpm > 255 {
+                    return None;
+                }
+                Some((0x14, *bpm as u8))
+            }
+            GlobalEffect::Volume(value) => Some((0x16, ModXmEffect::byte(value * 128.0)?.min(128))),
+            GlobalEffect::VolumeSlide { speed, fine } => {
+                Some((0x17, Self::it_pack_slide(*speed, *fine, 64.0)?))
+            }
+            // Z00 would be read back as a parametric value
+            GlobalEffect::MidiMacro(MidiMacroType::Parametric(None)) => None,
+            GlobalEffect::MidiMacro(MidiMacroType::ParametricValue(value)) => {
+                if *value > 0x7F {
+                    return None;
+                }
+                Some((0x1A, *value as u8))
+            }
+            GlobalEffect::MidiMacro(MidiMacroType::Fixed(index)) => {
+                if *index > 0x7F {
+                    return None;
+                }
+                Some((0x1A, 0x80 | *index as u8))
+            }
+        }
+    }
+
+    /// Try to merge a volume slide with the effect column using `Kxy` or `Lxy`.
+    /// Tone portamento and vibrato parameters are then taken from tracker memory,
+    /// so only if `memory` already holds them.
+    fn it_pack_merge(
+        freq_type: FrequencyType,
+        memory: &ModXmMemory,
+        fx: (u8, u8),
+        effect: &TrackEffect,
+    ) -> Option<(u8, u8)> {
+        match (fx.0, effect) {
+            (0x07, TrackEffect::VolumeSlide { speed, fine })
+                if memory.has_tone_portamento(fx.1) =>
+            {
+                Some((0x0C, Self::it_pack_slide(*speed, *fine, 64.0)?))
+            }
+            (0x08, TrackEffect::VolumeSlide { speed, fine }) if memory.has_vibrato(fx.1) => {
+                Some((0x0B, Self::it_pack_slide(*speed, *fine, 64.0)?))
+            }
+            (0x04, TrackEffect::TonePortamento(_)) => {
+                let (_, param) = Self::it_pack_effect(freq_type, effect)?;
+                if !memory.has_tone_portamento(param) {
+                    return None;
+                }
+                Some((0x0C, fx.1))
+            }
+            (0x04, TrackEffect::Vibrato { .. }) => {
+                match Self::it_pack_effect(freq_type, effect)? {
+                    (0x08, param) if memory.has_vibrato(param) => Some((0x0B, fx.1)),
+                    // fine vibrato has no Kxy
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Remember the parameters of an effect column and volume column
+    fn it_pack_memory(memory: &mut ModXmMemory, fx: Option<(u8, u8)>, vol: Option<u8>) {
+        match fx {
+            Some((0x07, param)) => memory.set_tone_portamento(param),
+            Some((0x08, param)) => memory.set_vibrato(param),
+            // fine vibrato depth is remembered with another unit
+            Some((0x15, _)) => memory.forget_vibrato(),
+            _ => {}
+        }
+        if let Some(v @ 193..=202) = vol {
+            memory.set_tone_portamento(VOLUME_TONE_PORTAMENTO[v as usize - 193]);
+        }
+    }
+
+    /// Best effort to save a `TrackUnit` into one volume column and one effect column.
+    /// Global effects have priority on the effect column.
+    /// All effects which can't be saved are returned.
+    /// `memory` is the channel memory from the previous rows of the pattern.
+    pub fn it_pack(
+        freq_type: FrequencyType,
+        memory: &mut ModXmMemory,
+        current: &TrackUnit,
+    ) -> (PatternSlot, ModXmLost) {
+        let mut ps = PatternSlot {
+            note: current.note,
+            instrument: current.instrument,
+            volume: 255, // no volume
+            ..Default::default()
+        };
+        let mut lost = ModXmLost::default();
+        let mut fx: Option<(u8, u8)> = None;
+        let mut vol: Option<u8> = None;
+
+        for ge in &current.global_effects {
+            match (fx, Self::it_pack_global_effect(ge)) {
+                (None, Some(e)) => fx = Some(e),
+                _ => lost.global_effects.push(ge.clone()),
+            }
+        }
+
+        for te in &current.effects {
+            let e = Self::it_pack_effect(freq_type, te);
+            let v = Self::it_pack_volume(freq_type, te);
+            let volume_first = matches!(te, TrackEffect::Volume { .. });
+            if volume_first && vol.is_none() && v.is_some() {
+                vol = v;
+            } else if fx.is_none() && e.is_some() {
+                fx = e;
+            } else if vol.is_none() && v.is_some() {
+                vol = v;
+            } else if let Some(m) = fx.and_then(|f| Self::it_pack_merge(freq_type, memory, f, te)) {
+                fx = Some(m);
+            } else {
+                lost.effects.push(te.clone());
+            }
+        }
+
+        Self::it_pack_memory(memory, fx, vol);
+
+        if let Some((t, p)) = fx {
+            ps.effect_type = t;
+            ps.effect_parameter = p;
+        }
+        if let Some(v) = vol {
+            ps.volume = v;
+        }
+        (ps, lost)
+    }
+
+    pub fn it_pack_pattern(
+        freq_type: FrequencyType,
+        pattern: &Pattern,
+        number_of_channels: usize,
+    ) -> (Vec<Vec<PatternSlot>>, Vec<ModXmLost>) {
+        let mut slots: Vec<Vec<PatternSlot>> = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        let channels = pattern.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut memory = vec![ModXmMemory::default(); channels];
+        for (r, row) in pattern.iter().enumerate() {
+            let mut line: Vec<PatternSlot> = vec![];
+            for (c, tu) in row.iter().enumerate() {
+                let (ps, mut lost) = Self::it_pack(freq_type, &mut memory[c], tu);
+                if c < number_of_channels {
+                    line.push(ps);
+                } else {
+                    lost.note = tu.note;
+                    lost.instrument = tu.instrument;
+                    lost.effects = tu.effects.clone();
+                    lost.global_effects = tu.global_effects.clone();
+                }
+                if !lost.is_empty() {
+                    lost.row = r;
+                    lost.channel = c;
+                    all_lost.push(lost);
+                }
+            }
+            slots.push(line);
+        }
+        (slots, all_lost)
+    }
 }
@@ -1,22 +1,32 @@
 use alloc::string::String;
 use alloc::string::ToString;
-use bincode::error::DecodeError;
-use serde::Deserialize;
+use alloc::vec::Vec;
+use bincode::error::EncodeError;
+use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
-use super::serde_helper::deserialize_string_26;
-use super::serde_helper::deserialize_string_4;
+use crate::error::Error;
+use crate::format::Format;
+
+use super::serde_helper::{deserialize_string_26, deserialize_string_4};
+use super::serde_helper::{serialize_string_26, serialize_string_4};
 
 /// IT file header.
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[repr(C)]
 pub struct ItHeader {
     /// Identifier ("IMPM").
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     id: String,
 
     /// Song name
-    #[serde(deserialize_with = "deserialize_string_26")]
+    #[serde(
+        deserialize_with = "deserialize_string_26",
+        serialize_with = "serialize_string_26"
+    )]
     pub song_name: String,
 
     /// Pattern information: Represents the number of rows per beat.
@@ -88,7 +98,10 @@ pub struct ItHeader {
     pub message_offset: u32,
 
     /// Reserved field ("OMPT" for interpreted Mod Plug files).
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     pub reserved: String,
 
     /// Initial pan of the channels
@@ -102,25 +115,55 @@ pub struct ItHeader {
     pub initial_channel_volume: [u8; 64],
 }
 
+impl Default for ItHeader {
+    fn default() -> Self {
+        Self {
+            id: "IMPM".to_string(),
+            song_name: String::new(),
+            rows_per_beat: 4,
+            rows_per_measure: 16,
+            order_number: 0,
+            instrument_number: 0,
+            sample_number: 0,
+            pattern_number: 0,
+            created_with_tracker: 0x0214,
+            compatible_with_tracker: 0x0200,
+            flags: 0b0000_0101, // stereo, instruments
+            special_flags: 0,
+            global_volume: 128,
+            mix_volume: 48,
+            initial_speed: 6,
+            initial_bpm: 125,
+            pan_separation: 128,
+            pitch_wheel_depth: 0,
+            message_length: 0,
+            message_offset: 0,
+            reserved: String::new(),
+            initial_channel_pan: [32; 64],
+            initial_channel_volume: [64; 64],
+        }
+    }
+}
+
 impl ItHeader {
-    pub fn load(data: &[u8]) -> Result<(Self, usize), DecodeError> {
-        let header_de =
-            bincode::serde::decode_from_slice::<ItHeader, _>(data, bincode::config::legacy());
-
-        match header_de {
-            Ok(header_de_ok) => {
-                if header_de_ok.0.is_it_header() {
-                    return Ok(header_de_ok);
-                } else {
-                    return Err(DecodeError::OtherString("Not an IT Module?".to_string()));
-                }
-            }
-            Err(e) => return Err(e),
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        bincode::serde::encode_to_vec(self, bincode::config::legacy())
+    }
+
+    pub fn load(data: &[u8]) -> Result<(Self, usize), Error> {
+        if !data.starts_with(b"IMPM") {
+            return Err(Error::BadMagic {
+                format: Format::It,
+                offset: 0,
+            });
         }
+        bincode::serde::decode_from_slice::<ItHeader, _>(data, bincode::config::legacy())
+            .map_err(|e| Error::decode(Format::It, "header", 0, e))
     }
 
+    /// Serialized size, strings are fixed size arrays in the file
     pub fn get_size() -> usize {
-        core::mem::size_of::<ItHeader>()
+        192
     }
 
     pub fn is_it_header(&self) -> bool {
@@ -1,15 +1,25 @@
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
-use bincode::error::DecodeError;
-use serde::Deserialize;
+use bincode::error::{DecodeError, EncodeError};
+use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
+use crate::error::Error;
+use crate::format::Format;
 use crate::prelude::*;
 
 use super::serde_helper::deserialize_string_12;
 use super::serde_helper::deserialize_string_26;
 use super::serde_helper::deserialize_string_4;
+use super::serde_helper::{serialize_string_12, serialize_string_26, serialize_string_4};
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
 
 #[derive(Deserialize, Debug)]
 #[repr(C)]
@@ -120,15 +130,21 @@ impl ItEnvelopePre2 {
 
 // --------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[repr(C)]
 pub struct ItInstrumentHeaderPost2 {
     /// Instrument identifier - must be "IMPI"
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     pub id: String,
 
     /// DOS filename
-    #[serde(deserialize_with = "deserialize_string_12")]
+    #[serde(
+        deserialize_with = "deserialize_string_12",
+        serialize_with = "serialize_string_12"
+    )]
     pub dos_filename: String,
 
     /// Reserved
@@ -185,7 +201,10 @@ pub struct ItInstrumentHeaderPost2 {
     pub reserved2: u8,
 
     /// Instrument name
-    #[serde(deserialize_with = "deserialize_string_26")]
+    #[serde(
+        deserialize_with = "deserialize_string_26",
+        serialize_with = "serialize_string_26"
+    )]
     pub instrument_name: String,
 
     /// Initial filter cutoff frequency (0-127)
@@ -218,7 +237,7 @@ impl ItInstrumentHeaderPost2 {
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[repr(C)]
 pub struct ItEnvelopePost2 {
     /// Envelope flags
@@ -253,23 +272,30 @@ pub struct ItEnvelopePost2 {
 }
 
 impl ItEnvelopePost2 {
-    pub fn to_envelope(&self) -> Vec<EnvelopePoint> {
+    /// `signed` is true for panning and pitch envelopes (-32..32), false for volume (0..64)
+    pub fn to_envelope(&self, signed: bool) -> Vec<EnvelopePoint> {
         let mut points = Vec::new();
 
-        for (tick, magnitude) in self.node_points.iter() {
+        let count = (self.node_count as usize).min(self.node_points.len());
+        for (value, tick) in self.node_points[..count].iter() {
+            let value = if signed {
+                (*value as i8 as f32 + 32.0) / 64.0
+            } else {
+                (*value as f32) / 64.0
+            };
             points.push(EnvelopePoint {
                 frame: *tick as usize,
-                value: (*magnitude as f32) / 64.0,
+                value,
             });
         }
         points.sort_by(|a, b| a.frame.cmp(&b.frame));
         points
     }
 
-    pub fn to_envelope_struct(&self) -> Envelope {
+    pub fn to_envelope_struct(&self, signed: bool) -> Envelope {
         Envelope {
             enabled: self.flags & 0b0000_0001 != 0,
-            point: self.to_envelope(),
+            point: self.to_envelope(signed),
             sustain_enabled: self.flags & 0b0000_0100 != 0,
             sustain_start_point: self.sustain_loop_start as usize,
             sustain_end_point: self.sustain_loop_end as usize,
@@ -278,6 +304,39 @@ impl ItEnvelopePost2 {
             loop_end_point: self.loop_end as usize,
         }
     }
+
+    /// Inverse of `to_envelope_struct`, only the first 25 points are kept
+    pub fn from_envelope(env: &Envelope, signed: bool) -> Self {
+        let mut it_env = Self::default();
+        let count = env.point.len().min(it_env.node_points.len());
+
+        for (i, p) in env.point[..count].iter().enumerate() {
+            let value = if signed {
+                ((p.value * 64.0).round() - 32.0).clamp(-32.0, 32.0) as i8 as u8
+            } else {
+                (p.value * 64.0).round().clamp(0.0, 64.0) as u8
+            };
+            it_env.node_points[i] = (value, p.frame.min(9999) as u16);
+        }
+
+        let last = count.saturating_sub(1);
+        it_env.node_count = count as u8;
+        it_env.loop_start = env.loop_start_point.min(last) as u8;
+        it_env.loop_end = env.loop_end_point.min(last) as u8;
+        it_env.sustain_loop_start = env.sustain_start_point.min(last) as u8;
+        it_env.sustain_loop_end = env.sustain_end_point.min(last) as u8;
+
+        if env.enabled && count != 0 {
+            it_env.flags |= 0b0000_0001;
+        }
+        if env.loop_enabled {
+            it_env.flags |= 0b0000_0010;
+        }
+        if env.sustain_enabled {
+            it_env.flags |= 0b0000_0100;
+        }
+        it_env
+    }
 }
 
 // --------------------------------------------------------------------------
@@ -306,6 +365,130 @@ impl ItInstrumentPost2 {
     pub fn is_it_instrument(&self) -> bool {
         self.instr.is_it_instrument()
     }
+
+    /// `sample_index` gives the global IT sample number of each `InstrDefault.sample`
+    pub fn from_instrument(instrument: &Instrument, sample_index: &[Option<usize>]) -> Self {
+        let mut instr = ItInstrumentHeaderPost2 {
+            id: "IMPI".to_string(),
+            dos_filename: String::new(),
+            reserved1: 0,
+            nna: 0,
+            duplicate_check_type: 0,
+            duplicate_check_action: 0,
+            fadeout: 0,
+            pitch_pan_separation: 0,
+            pitch_pan_center: Pitch::C4 as u8,
+            global_volume: 128,
+            default_pan: 32 | 0b1000_0000,
+            random_volume_variation: 0,
+            random_pan_variation: 0,
+            tracker_version: 0,
+            num_samples: 0,
+            reserved2: 0,
+            instrument_name: instrument.name.clone(),
+            initial_filter_cutoff: 0,
+            initial_filter_resonance: 0,
+            midi_channel: 0,
+            midi_program: 0,
+            midi_bank: 0,
+            note_sample_keyboard_table: [(0, 0); 120],
+        };
+        for (note, entry) in instr.note_sample_keyboard_table.iter_mut().enumerate() {
+            entry.0 = note as u8;
+        }
+
+        let mut it = Self {
+            instr,
+            volume_envelope: ItEnvelopePost2::default(),
+            panning_envelope: ItEnvelopePost2::default(),
+            pitch_envelope: ItEnvelopePost2::default(),
+        };
+
+        let id = match &instrument.instr_type {
+            InstrumentType::Default(id) => id,
+            _ => return it,
+        };
+
+        let nna_value = |nna: &NewNoteAction| match nna {
+            NewNoteAction::NoteCut => 0,
+            NewNoteAction::Continue => 1,
+            NewNoteAction::NoteOff => 2,
+            NewNoteAction::NoteFadeOut => 3,
+        };
+        let dca_value = |dca: &DuplicateCheckAction| match dca {
+            DuplicateCheckAction::NoteCut(nna) => (0, nna_value(nna)),
+            DuplicateCheckAction::NoteOff(nna) => (1, nna_value(nna)),
+            DuplicateCheckAction::NoteFadeOut(nna) => (2, nna_value(nna)),
+        };
+        let (dct, (dca, nna)) = match &id.duplicate_check {
+            DuplicateCheckType::Off(nna) => (0, (0, nna_value(nna))),
+            DuplicateCheckType::Note(dca) => (1, dca_value(dca)),
+            DuplicateCheckType::Sample(dca) => (2, dca_value(dca)),
+            DuplicateCheckType::Instrument(dca) => (3, dca_value(dca)),
+        };
+        it.instr.nna = nna;
+        it.instr.duplicate_check_type = dct;
+        it.instr.duplicate_check_action = dca;
+
+        it.instr.fadeout = (id.volume_fadeout * 64.0 * 512.0)
+            .round()
+            .clamp(0.0, 1024.0) as i16;
+        it.instr.pitch_pan_separation =
+            (id.pitch_pan_separation * 32.0).round().clamp(-32.0, 32.0) as i8;
+        it.instr.pitch_pan_center = id.pitch_pan_center as u8;
+        it.instr.global_volume = (id.global_volume * 128.0).round().clamp(0.0, 128.0) as u8;
+        // centered panning is saved as "don't use default pan"
+        it.instr.default_pan = match (id.default_pan * 64.0).round().clamp(0.0, 64.0) as u8 {
+            32 => 32 | 0b1000_0000,
+            pan => pan,
+        };
+        it.instr.random_volume_variation = (id.random_volume_variation * 100.0)
+            .round()
+            .clamp(0.0, 100.0) as u8;
+        it.instr.random_pan_variation =
+            (id.random_pan_variation * 100.0).round().clamp(0.0, 100.0) as u8;
+        it.instr.num_samples = sample_index.iter().flatten().count().min(255) as u8;
+
+        it.instr.initial_filter_cutoff = id.initial_filter_cutoff;
+        it.instr.initial_filter_resonance = id.initial_filter_resonance;
+        it.instr.midi_channel = id.midi.channel;
+        it.instr.midi_program = id.midi.program.min(255) as u8;
+        it.instr.midi_bank = id.midi.bank;
+
+        for (note, s) in id.sample_for_pitch.iter().enumerate() {
+            if let Some(Some(global)) = s.and_then(|s| sample_index.get(s)) {
+                if *global < 255 {
+                    it.instr.note_sample_keyboard_table[note].1 = *global as u8 + 1;
+                }
+            }
+        }
+
+        it.volume_envelope = ItEnvelopePost2::from_envelope(&id.volume_envelope, false);
+        it.panning_envelope = ItEnvelopePost2::from_envelope(&id.pan_envelope, true);
+        it.pitch_envelope = ItEnvelopePost2::from_envelope(&id.pitch_envelope, true);
+        if id.pitch_envelope_as_low_pass_filter {
+            it.pitch_envelope.flags |= 0b1000_0000;
+        }
+        it
+    }
+
+    /// Instrument header followed by volume, panning and pitch envelopes (554 bytes)
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut output = bincode::serde::encode_to_vec(&self.instr, bincode::config::legacy())?;
+        for env in [
+            &self.volume_envelope,
+            &self.panning_envelope,
+            &self.pitch_envelope,
+        ] {
+            output.extend(bincode::serde::encode_to_vec(
+                env,
+                bincode::config::legacy(),
+            )?);
+            output.push(0);
+        }
+        output.extend([0u8; 4]);
+        Ok(output)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -322,25 +505,39 @@ impl ItInstrument {
         }
     }
 
-    pub fn load_post2(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            ItInstrument::Pre2(_) => Err(EncodeError::Other(
+                "ItInstrument: pre-2.0 instruments can't be saved",
+            )),
+            ItInstrument::Post2(i) => i.save(),
+        }
+    }
+
+    /// Error for the instrument `structure` at `data`
+    fn decode_error(source: &[u8], structure: &'static str, data: &[u8], e: DecodeError) -> Error {
+        Error::decode(Format::It, structure, source.len() - data.len(), e)
+    }
+
+    pub fn load_post2(source: &[u8]) -> Result<Self, Error> {
         let mut data = source;
+        if !source.starts_with(b"IMPI") {
+            return Err(Error::BadMagic {
+                format: Format::It,
+                offset: 0,
+            });
+        }
 
         let instr_h = bincode::serde::decode_from_slice::<ItInstrumentHeaderPre2, _>(
             data,
             bincode::config::legacy(),
-        )?;
-
-        if !instr_h.0.is_it_instrument() {
-            return Err(DecodeError::OtherString(
-                "Not an IT Instrument?".to_string(),
-            ));
-        }
+        )
+        .map_err(|e| Self::decode_error(source, "instrument", data, e))?;
 
         data = &data[instr_h.1..];
-        let vol = bincode::serde::decode_from_slice::<ItEnvelopePre2, _>(
-            data,
-            bincode::config::legacy(),
-        )?;
+        let vol =
+            bincode::serde::decode_from_slice::<ItEnvelopePre2, _>(data, bincode::config::legacy())
+                .map_err(|e| Self::decode_error(source, "volume envelope", data, e))?;
         let instr = ItInstrumentPre2 {
             instr: instr_h.0,
             volume_envelope: vol.0,
@@ -348,35 +545,39 @@ impl ItInstrument {
         return Ok(ItInstrument::Pre2(instr));
     }
 
-    pub fn load_pre2(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load_pre2(source: &[u8]) -> Result<Self, Error> {
         let mut data = source;
+        if !source.starts_with(b"IMPI") {
+            return Err(Error::BadMagic {
+                format: Format::It,
+                offset: 0,
+            });
+        }
 
         let instr_h = bincode::serde::decode_from_slice::<ItInstrumentHeaderPost2, _>(
             data,
             bincode::config::legacy(),
-        )?;
-
-        if !instr_h.0.is_it_instrument() {
-            return Err(DecodeError::OtherString(
-                "Not an IT Instrument?".to_string(),
-            ));
-        }
+        )
+        .map_err(|e| Self::decode_error(source, "instrument", data, e))?;
 
         data = &data[instr_h.1..];
         let vol = bincode::serde::decode_from_slice::<ItEnvelopePost2, _>(
             data,
             bincode::config::legacy(),
-        )?;
-        data = &data[1 + vol.1..];
+        )
+        .map_err(|e| Self::decode_error(source, "volume envelope", data, e))?;
+        data = data.get(1 + vol.1..).unwrap_or_default();
         let pan = bincode::serde::decode_from_slice::<ItEnvelopePost2, _>(
             data,
             bincode::config::legacy(),
-        )?;
-        data = &data[1 + pan.1..];
+        )
+        .map_err(|e| Self::decode_error(source, "panning envelope", data, e))?;
+        data = data.get(1 + pan.1..).unwrap_or_default();
         let pitch = bincode::serde::decode_from_slice::<ItEnvelopePost2, _>(
             data,
             bincode::config::legacy(),
-        )?;
+        )
+        .map_err(|e| Self::decode_error(source, "pitch envelope", data, e))?;
         let instr = ItInstrumentPost2 {
             instr: instr_h.0,
             volume_envelope: vol.0,
@@ -442,9 +643,9 @@ impl ItInstrument {
                     }
                 }
 
-                instr.volume_envelope = source.volume_envelope.to_envelope_struct();
-                instr.pan_envelope = source.panning_envelope.to_envelope_struct();
-                instr.pitch_envelope = source.pitch_envelope.to_envelope_struct();
+                instr.volume_envelope = source.volume_envelope.to_envelope_struct(false);
+                instr.pan_envelope = source.panning_envelope.to_envelope_struct(true);
+                instr.pitch_envelope = source.pitch_envelope.to_envelope_struct(true);
                 instr.pitch_envelope_as_low_pass_filter =
                     source.pitch_envelope.flags & 0b1000_0000 != 0;
 
@@ -1,18 +1,22 @@
+use crate::error::Error;
+use crate::format::{Confidence, Format};
 use crate::import::import_memory::{ImportMemory, MemoryType};
 use crate::import::orders_helper;
 use crate::import::patternslot::PatternSlot;
+use crate::import::xm::mod_xm_effect::ModXmLost;
 use crate::prelude::*;
 
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 
 use alloc::format;
-use alloc::string::{String, ToString};
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
 use super::it_edit_history::{ItEditHistory, ItEditHistoryEntry};
+use super::it_effect::ItEffect;
 use super::it_header::ItHeader;
-use super::it_instrument::ItInstrument;
+use super::it_instrument::{ItInstrument, ItInstrumentPost2};
 use super::it_midi_macros::ItMidiMacros;
 use super::it_pattern::ItPattern;
 use super::it_plugins::Plugins;
@@ -37,28 +41,33 @@ pub struct ItModule {
 }
 
 impl ItModule {
-    pub fn load(ser_it_module: &[u8]) -> Result<Self, DecodeError> {
+    /// Header sniffing, the module is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        match ItHeader::load(data) {
+            Ok((header, _)) if header.is_it_header() => Some(Confidence::High),
+            Err(e) if e.is_bad_magic() => None,
+            _ => Some(Confidence::Medium),
+        }
+    }
+
+    pub fn load(ser_it_module: &[u8]) -> Result<Self, Error> {
         let data = ser_it_module;
+        let truncated = |structure, offset| Error::Truncated {
+            format: Format::It,
+            structure,
+            offset,
+        };
+        let offset = |data: &[u8]| ser_it_module.len() - data.len();
 
         // === ItHeader =====================================================
 
-        if data.len() < ItHeader::get_size() {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: ItHeader::get_size(),
-                found: data.len(),
-            });
-        }
-
         let header = ItHeader::load(data)?;
         let data = &data[header.1..];
 
         // === Orders =======================================================
 
         if data.len() < header.0.order_number as usize {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: header.0.order_number as usize,
-                found: data.len(),
-            });
+            return Err(truncated("orders", offset(data)));
         }
 
         let orders = &data[..header.0.order_number as usize];
@@ -67,10 +76,7 @@ impl ItModule {
         // === Instruments Offsets ==========================================
 
         if data.len() < 4 * header.0.instrument_number as usize {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: 4 * header.0.instrument_number as usize,
-                found: data.len(),
-            });
+            return Err(truncated("instrument offsets", offset(data)));
         }
 
         let instrument_offsets_u8 = &data[..4 * header.0.instrument_number as usize];
@@ -85,10 +91,7 @@ impl ItModule {
         // === Samples Header Offsets =======================================
 
         if data.len() < 4 * header.0.sample_number as usize {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: 4 * header.0.sample_number as usize,
-                found: data.len(),
-            });
+            return Err(truncated("sample offsets", offset(data)));
         }
 
         let sample_header_offsets_u8 = &data[..4 * header.0.sample_number as usize];
@@ -103,10 +106,7 @@ impl ItModule {
         // === Patterns Offsets ==========================================
 
         if data.len() < 4 * header.0.pattern_number as usize {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: 4 * header.0.pattern_number as usize,
-                found: data.len(),
-            });
+            return Err(truncated("pattern offsets", offset(data)));
         }
 
         let pattern_offsets_u8 = &data[..4 * header.0.pattern_number as usize];
@@ -121,7 +121,7 @@ impl ItModule {
         // === Edit History =================================================
 
         let edit_history = if header.0.is_edit_history_embedded() {
-            let (edit_history, l) = ItEditHistory::load(data)?;
+            let (edit_history, l) = ItEditHistory::load(data).map_err(|e| e.at(offset(data)))?;
             data = &data[l..];
             edit_history
         } else {
@@ -135,7 +135,8 @@ impl ItModule {
             let r = bincode::serde::decode_from_slice::<ItMidiMacros, _>(
                 data,
                 bincode::config::legacy(),
-            )?;
+            )
+            .map_err(|e| Error::decode(Format::It, "midi macros", offset(data), e))?;
             data = &data[r.1..];
             Some(r.0)
         } else {
@@ -146,7 +147,7 @@ impl ItModule {
 
         let pattern_names = if ItXNames::is_pnam(data) {
             data = &data[4..];
-            let (pattern_names, l) = ItXNames::load(data, 32)?;
+            let (pattern_names, l) = ItXNames::load(data, 32).map_err(|e| e.at(offset(data)))?;
             data = &data[l..];
             pattern_names
         } else {
@@ -157,7 +158,7 @@ impl ItModule {
 
         let channel_names = if ItXNames::is_cnam(data) {
             data = &data[4..];
-            let (channel_names, l) = ItXNames::load(data, 20)?;
+            let (channel_names, l) = ItXNames::load(data, 20).map_err(|e| e.at(offset(data)))?;
             data = &data[l..];
             channel_names
         } else {
@@ -178,14 +179,10 @@ impl ItModule {
         let message = if header.0.is_song_message_attached() && header.0.message_length != 0 {
             let start = header.0.message_offset as usize;
             let end = start + header.0.message_length as usize;
-            if (&ser_it_module[start..]).len() < header.0.message_length as usize {
-                return Err(DecodeError::ArrayLengthMismatch {
-                    required: header.0.message_length as usize,
-                    found: (&ser_it_module[start..]).len(),
-                });
-            }
-            let src = &ser_it_module[start..end];
-            String::from_utf8_lossy(src).trim().to_string()
+            let Some(src) = ser_it_module.get(start..end) else {
+                return Err(truncated("message", start));
+            };
+            String::from_utf8_lossy(src).trim().replace('\r', "\n")
         } else {
             String::new()
         };
@@ -197,15 +194,16 @@ impl ItModule {
             let i_seek = instrument_offsets[i as usize] as usize;
 
             if ser_it_module.len() < i_seek {
-                return Err(DecodeError::LimitExceeded);
+                return Err(truncated("instrument", i_seek));
             }
 
             let data = &ser_it_module[i_seek..];
-            if !header.0.is_post20() {
-                instruments.push(ItInstrument::load_post2(data)?);
+            let instrument = if !header.0.is_post20() {
+                ItInstrument::load_post2(data)
             } else {
-                instruments.push(ItInstrument::load_pre2(data)?);
-            }
+                ItInstrument::load_pre2(data)
+            };
+            instruments.push(instrument.map_err(|e| e.at(i_seek))?);
         }
 
         // === Samples Header ===============================================
@@ -215,14 +213,15 @@ impl ItModule {
             let i_seek = sample_header_offsets[i as usize] as usize;
 
             if ser_it_module.len() < i_seek {
-                return Err(DecodeError::LimitExceeded);
+                return Err(truncated("sample header", i_seek));
             }
 
             let data = &ser_it_module[i_seek..];
             let sample_h = bincode::serde::decode_from_slice::<ItSampleHeader, _>(
                 data,
                 bincode::config::legacy(),
-            )?;
+            )
+            .map_err(|e| Error::decode(Format::It, "sample header", i_seek, e))?;
             samples_header.push(sample_h.0);
         }
 
@@ -230,14 +229,16 @@ impl ItModule {
 
         let mut patterns: Vec<Vec<Vec<PatternSlot>>> = vec![];
         for pattern_seek in &pattern_offsets {
-            if ser_it_module.len() < *pattern_seek as usize {
-                return Err(DecodeError::LimitExceeded);
+            let seek = *pattern_seek as usize;
+            if ser_it_module.len() < seek {
+                return Err(truncated("pattern", seek));
             }
 
-            if *pattern_seek != 0 {
-                let data = &ser_it_module[*pattern_seek as usize..];
-                let itpattern = ItPattern::load(data)?;
-                let pattern = itpattern.unpack()?;
+            if seek != 0 {
+                let data = &ser_it_module[seek..];
+                let pattern = ItPattern::load(data)
+                    .and_then(|itpattern| itpattern.unpack())
+                    .map_err(|e| e.at(seek))?;
                 patterns.push(pattern);
             } else {
                 patterns.push(vec![vec![]]);
@@ -252,10 +253,12 @@ impl ItModule {
                 let start = sh.sample_pointer as usize;
 
                 if ser_it_module.len() < start {
-                    return Err(DecodeError::LimitExceeded);
+                    return Err(truncated("sample data", start));
                 }
 
-                let sample = sh.get_sample_data(&ser_it_module[start..])?;
+                let sample = sh
+                    .get_sample_data(&ser_it_module[start..])
+                    .map_err(|e| e.at(start))?;
                 if sample.len() != 0 {
                     samples.push(Some(sample));
                 } else {
@@ -341,11 +344,18 @@ impl ItModule {
 
         if self.header.is_instruments_used() {
             // Prepare Instrument then add samples
-            for (index, i) in self.instruments.iter().enumerate() {
+            for i in self.instruments.iter() {
                 let mut instrument = i.prepare_instrument();
                 if let InstrumentType::Default(instrdef) = &mut instrument.instr_type {
-                    if vibratos.len() > index {
-                        instrdef.vibrato = vibratos[index];
+                    // IT vibrato is set by sample, use the first one
+                    if let Some(v) = instrdef
+                        .sample_for_pitch
+                        .iter()
+                        .flatten()
+                        .next()
+                        .and_then(|s| vibratos.get(*s))
+                    {
+                        instrdef.vibrato = *v;
                     }
                     for i2 in instrdef.sample_for_pitch {
                         if let Some(i3) = i2 {
@@ -380,4 +390,400 @@ impl ItModule {
         }
         module
     }
+
+    /// Remove the tracker version line added by `to_module`
+    fn message_from_comment(comment: &str) -> String {
+        let message = match comment.split_once("\n\n") {
+            Some((head, message))
+                if (head.starts_with("IT: ") || head.starts_with("MP: "))
+                    && head.contains("(compatibility: ") =>
+            {
+                message
+            }
+            _ => comment,
+        };
+        message.trim().replace('\n', "\r")
+    }
+
+    /// Best effort, everything which can't be saved in patterns is returned
+    pub fn from_module(module: &Module) -> Result<(Self, Vec<ModXmLost>), EncodeError> {
+        if module.pattern.len() > 254 {
+            return Err(EncodeError::Other("ItModule: more than 254 patterns"));
+        }
+        if module.instrument.len() > 255 {
+            return Err(EncodeError::Other("ItModule: more than 255 instruments"));
+        }
+
+        let mut orders = orders_helper::join_orders(&module.pattern_order);
+        if module
+            .pattern_order
+            .iter()
+            .flatten()
+            .any(|o| *o >= 254 || *o >= module.pattern.len())
+        {
+            return Err(EncodeError::Other("ItModule: bad pattern order"));
+        }
+        orders.push(255);
+        if orders.len() > 256 {
+            return Err(EncodeError::Other("ItModule: more than 256 orders"));
+        }
+
+        // IT patterns are always unpacked with 64 channels: only count used ones
+        let number_of_channels = module
+            .pattern
+            .iter()
+            .flatten()
+            .filter_map(|row| row.iter().rposition(|tu| !tu.is_empty()))
+            .max()
+            .map_or(1, |last| last + 1);
+        if number_of_channels > 64 {
+            return Err(EncodeError::Other("ItModule: more than 64 channels"));
+        }
+
+        let message = Self::message_from_comment(&module.comment);
+        if message.len() > 8000 {
+            return Err(EncodeError::Other("ItModule: message too long"));
+        }
+
+        // IT samples are global, instruments only keep an index on them
+        let mut instruments: Vec<ItInstrument> = vec![];
+        let mut samples_header: Vec<ItSampleHeader> = vec![];
+        let mut samples: Vec<Option<SampleDataType>> = vec![];
+        for instr in &module.instrument {
+            let mut sample_index: Vec<Option<usize>> = vec![];
+            if let InstrumentType::Default(id) = &instr.instr_type {
+                for s in &id.sample {
+                    if let Some(s) = s {
+                        sample_index.push(Some(samples_header.len()));
+                        samples_header.push(ItSampleHeader::from_sample(s, &id.vibrato));
+                        samples.push(s.data.clone());
+                    } else {
+                        sample_index.push(None);
+                    }
+                }
+            }
+            instruments.push(ItInstrument::Post2(ItInstrumentPost2::from_instrument(
+                instr,
+                &sample_index,
+            )));
+        }
+        if samples_header.len() > 255 {
+            return Err(EncodeError::Other("ItModule: more than 255 samples"));
+        }
+
+        let mut patterns: Vec<Vec<Vec<PatternSlot>>> = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        for (index, p) in module.pattern.iter().enumerate() {
+            let (slots, mut lost) =
+                ItEffect::it_pack_pattern(module.frequency_type, p, number_of_channels);
+            for l in &mut lost {
+                l.pattern = index;
+            }
+            patterns.push(slots);
+            all_lost.append(&mut lost);
+        }
+
+        let mut header = ItHeader::default();
+        header.song_name = module.name.clone();
+        header.order_number = orders.len() as u16;
+        header.instrument_number = instruments.len() as u16;
+        header.sample_number = samples_header.len() as u16;
+        header.pattern_number = patterns.len() as u16;
+        header.initial_speed = module.default_tempo.clamp(1, 255) as u8;
+        header.initial_bpm = module.default_bpm.clamp(32, 255) as u8;
+        header.message_length = message.len() as u16;
+        if let FrequencyType::LinearFrequencies = module.frequency_type {
+            header.flags |= 0b0000_1000;
+        }
+        if !message.is_empty() {
+            header.special_flags |= 0b0000_0001;
+        }
+        for (ch, pan) in header.initial_channel_pan.iter_mut().enumerate() {
+            if ch >= number_of_channels {
+                *pan |= 0b1000_0000; // disabled channel
+            }
+        }
+
+        Ok((
+            Self {
+                header,
+                orders,
+                edit_history: None,
+                midi_macros: None,
+                pattern_names: module.pattern_names.clone(),
+                channel_names: module.channel_names.clone(),
+                plugins: None,
+                message,
+                instruments,
+                samples_header,
+                patterns,
+                samples,
+            },
+            all_lost,
+        ))
+    }
+
+    /// Header, orders, offsets, names, message, instruments, samples header, patterns then samples data
+    pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        self.save_with(None)
+    }
+
+    /// Same as `save` but mono samples are compressed using IT214 (or IT215 if `it215`)
+    pub fn save_compressed(&mut self, it215: bool) -> Result<Vec<u8>, EncodeError> {
+        self.save_with(Some(it215))
+    }
+
+    fn save_with(&mut self, compression: Option<bool>) -> Result<Vec<u8>, EncodeError> {
+        let mut names: Vec<u8> = vec![];
+        if !self.pattern_names.is_empty() {
+            names.extend(ItXNames::save(b"PNAM", &self.pattern_names, 32));
+        }
+        if !self.channel_names.is_empty() {
+            names.extend(ItXNames::save(b"CNAM", &self.channel_names, 20));
+        }
+
+        let mut instruments: Vec<u8> = vec![];
+        let mut instrument_offsets: Vec<usize> = vec![];
+        for i in &self.instruments {
+            instrument_offsets.push(instruments.len());
+            instruments.extend(i.save()?);
+        }
+
+        let mut patterns: Vec<u8> = vec![];
+        let mut pattern_offsets: Vec<Option<usize>> = vec![];
+        for p in &self.patterns {
+            if p.is_empty() {
+                pattern_offsets.push(None);
+            } else {
+                pattern_offsets.push(Some(patterns.len()));
+                patterns.extend(ItPattern::pack(p)?.save());
+            }
+        }
+
+        let offsets_start = ItHeader::get_size() + self.orders.len();
+        let names_start = offsets_start
+            + 4 * (self.instruments.len() + self.samples_header.len() + self.patterns.len());
+        let message_start = names_start + names.len();
+        let instruments_start = message_start + self.message.len() + 1;
+        let samples_header_start = instruments_start + instruments.len();
+        let patterns_start = samples_header_start + 80 * self.samples_header.len();
+        let mut sample_data_start = patterns_start + patterns.len();
+
+        let mut samples_data: Vec<u8> = vec![];
+        for (sh, data) in self.samples_header.iter_mut().zip(&self.samples) {
+            sh.sample_pointer = (sample_data_start + samples_data.len()) as u32;
+            if let Some(d) = data {
+                match compression.and_then(|it215| sh.compress_sample_data(d, it215)) {
+                    Some(packed) => samples_data.extend(packed),
+                    None => samples_data.extend(ItSampleHeader::save_sample_data(d)),
+                }
+            }
+        }
+        sample_data_start += samples_data.len();
+        if sample_data_start > u32::MAX as usize {
+            return Err(EncodeError::Other("ItModule: file too big"));
+        }
+
+        self.header.message_offset = message_start as u32;
+
+        let mut output = self.header.save()?;
+        output.extend(&self.orders);
+        for o in &instrument_offsets {
+            output.extend(((instruments_start + o) as u32).to_le_bytes());
+        }
+        for i in 0..self.samples_header.len() {
+            output.extend(((samples_header_start + 80 * i) as u32).to_le_bytes());
+        }
+        for o in &pattern_offsets {
+            let offset = o.map_or(0, |o| patterns_start + o);
+            output.extend((offset as u32).to_le_bytes());
+        }
+        output.extend(names);
+        output.extend(self.message.as_bytes());
+        output.push(0);
+        output.extend(instruments);
+        for sh in &self.samples_header {
+            output.extend(sh.save()?);
+        }
+        output.extend(patterns);
+        output.extend(samples_data);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn it_save_load() {
+        use crate::prelude::*;
+
+        let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let tu = &mut module.pattern[0][0][0];
+        tu.effects = vec![
+            TrackEffect::Volume {
+                value: 0.5,
+                tick: 0,
+            },
+            TrackEffect::Vibrato {
+                speed: 2.0 / 256.0,
+                depth: 3.0 / 4.0,
+            },
+        ];
+        tu.global_effects = vec![];
+        let tu = tu.clone();
+        let data = module.save_it().unwrap();
+        let module2 = Module::load_it(&data).unwrap();
+        assert_eq!(data, module2.save_it().unwrap());
+        assert_eq!(module.pattern_order, module2.pattern_order);
+        assert_eq!(module.instrument.len(), module2.instrument.len());
+        let tu2 = &module2.pattern[0][0][0];
+        assert_eq!(tu2.note, tu.note);
+        // the effect column is decoded before the volume column
+        assert_eq!(tu2.effects[0], tu.effects[1]);
+        assert_eq!(tu2.effects[1], tu.effects[0]);
+
+        // vibrato can't be saved: effect column is used by speed
+        module.pattern[0][0][0].global_effects = vec![GlobalEffect::Speed(3)];
+        let (_, lost) = module.save_it_with_losses().unwrap();
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].effects, tu.effects[1..]);
+
+        // fine (EFx) and extra fine (EEx) portamentos
+        for speed in [-8.0, 3.0] {
+            let te = TrackEffect::Portamento { speed, fine: true };
+            module.pattern[0][1][0].effects = vec![te.clone()];
+            let module2 = Module::load_it(&module.save_it().unwrap()).unwrap();
+            assert_eq!(module2.pattern[0][1][0].effects, vec![te]);
+        }
+
+        // no IT effect selects the last parametric macro
+        let macro_none = GlobalEffect::MidiMacro(crate::effect::MidiMacroType::Parametric(None));
+        module.pattern[0][1][0].global_effects = vec![macro_none.clone()];
+        let (_, lost) = module.save_it_with_losses().unwrap();
+        assert_eq!(lost.last().unwrap().global_effects, vec![macro_none]);
+
+        // Lxy takes the tone portamento speed from channel memory only
+        let volume = TrackEffect::Volume {
+            value: 0.5,
+            tick: 0,
+        };
+        let tone = TrackEffect::TonePortamento(64.0);
+        let slide = TrackEffect::VolumeSlide {
+            speed: 1.0 / 64.0,
+            fine: false,
+        };
+        let row = |effects: Vec<TrackEffect>| {
+            vec![TrackUnit {
+                effects,
+                ..Default::default()
+            }]
+        };
+        let pattern = vec![
+            row(vec![volume.clone(), tone.clone(), slide.clone()]),
+            row(vec![volume, tone, slide.clone()]),
+        ];
+        let (slots, lost) = crate::import::it::it_effect::ItEffect::it_pack_pattern(
+            FrequencyType::LinearFrequencies,
+            &pattern,
+            1,
+        );
+        assert_eq!(lost.len(), 1);
+        assert_eq!((lost[0].row, &lost[0].effects), (0, &vec![slide]));
+        assert_eq!(slots[0][0].effect_type, 0x07);
+        assert_eq!(slots[1][0].effect_type, 0x0C);
+    }
+
+    #[test]
+    fn it_load() {
+        use crate::prelude::*;
+
+        // hand made IT 2.14: one instrument, one sample and one pattern
+        let mut data = vec![0u8; 0xC0];
+        data[0..4].copy_from_slice(b"IMPM");
+        data[0x20..0x28].copy_from_slice(&[2, 0, 1, 0, 1, 0, 1, 0]);
+        data[0x28..0x2C].copy_from_slice(&[0x14, 0x02, 0x14, 0x02]);
+        data[0x2C] = 0b0000_0101; // stereo, instruments
+        data[0x30..0x36].copy_from_slice(&[128, 48, 6, 125, 128, 0]);
+        data[0x40..0x80].fill(32);
+        data[0x80..0xC0].fill(64);
+        data.extend([0, 255]);
+        let offsets = data.len();
+        data.extend([0u8; 12]);
+
+        let instrument = data.len();
+        data.extend([0u8; 554]);
+        let i = &mut data[instrument..];
+        i[0..4].copy_from_slice(b"IMPI");
+        i[0x18] = 128;
+        i[0x19] = 32 | 0x80;
+        for note in 0..120 {
+            i[0x40 + 2 * note] = note as u8;
+            i[0x40 + 2 * note + 1] = 1;
+        }
+        // panning envelope: nodes are (value, tick), values are signed
+        i[0x182..0x184].copy_from_slice(&[1, 2]);
+        i[0x188..0x18E].copy_from_slice(&[-32i8 as u8, 0, 0, 32, 10, 0]);
+
+        let sample = data.len();
+        data.extend([0u8; 80]);
+        let s = &mut data[sample..];
+        s[0..4].copy_from_slice(b"IMPS");
+        s[0x11..0x14].copy_from_slice(&[64, 0b0000_0001, 64]);
+        s[0x2E] = 0b0000_0001;
+        s[0x2F] = 16 | 0x80;
+        s[0x30] = 16;
+        // C-5 speed 1.75 semitones above 16726 Hz
+        s[0x3C..0x40].copy_from_slice(&18502u32.to_le_bytes());
+
+        let pattern = data.len();
+        let packed = [
+            0x81, 0x0F, 60, 1, 32, 0x03, 0x10, // note, instrument, volume and C10
+            0x82, 0x08, 0x16, 0x40, // V40
+            0, 0,
+        ];
+        data.extend((packed.len() as u16).to_le_bytes());
+        data.extend([2, 0, 0, 0, 0, 0]);
+        data.extend(packed);
+
+        let sample_data = data.len();
+        data.extend([0u8; 16]);
+        data[sample + 0x48..sample + 0x4C].copy_from_slice(&(sample_data as u32).to_le_bytes());
+        for (n, offset) in [instrument, sample, pattern].iter().enumerate() {
+            data[offsets + 4 * n..offsets + 4 * n + 4]
+                .copy_from_slice(&(*offset as u32).to_le_bytes());
+        }
+
+        let module = Module::load_it(&data).unwrap();
+        let tu = &module.pattern[0][0][0];
+        // the volume column is the note volume
+        assert!(tu.effects.contains(&TrackEffect::Volume {
+            value: 0.5,
+            tick: 0
+        }));
+        // Cxx is not BCD encoded
+        assert_eq!(tu.global_effects, vec![GlobalEffect::PatternBreak(16)]);
+        // global volume is 0..128
+        assert_eq!(
+            module.pattern[0][0][1].global_effects,
+            vec![GlobalEffect::Volume(0.5)]
+        );
+
+        let InstrumentType::Default(id) = &module.instrument[0].instr_type else {
+            panic!("not a sample based instrument");
+        };
+        let pan: Vec<(usize, f32)> = id
+            .pan_envelope
+            .point
+            .iter()
+            .map(|p| (p.frame, p.value))
+            .collect();
+        assert_eq!(pan, vec![(0, 0.0), (10, 1.0)]);
+        let s = id.sample[0].as_ref().unwrap();
+        assert_eq!(s.panning, 0.25);
+        assert_eq!(s.relative_pitch, 2);
+        assert!((s.finetune + 0.25).abs() < 0.01, "{}", s.finetune);
+    }
 }
@@ -1,10 +1,12 @@
-use alloc::string::ToString;
 use alloc::vec;
 use alloc::vec::Vec;
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 use serde::Deserialize;
 
+use crate::error::Error;
+use crate::format::Format;
 use crate::import::patternslot::PatternSlot;
+use crate::pitch::Pitch;
 
 /// Structure representing a pattern in a musical tracker format.
 /// Note: The entire `Pattern` struct is limited to a maximum size of 0xFFFF (64 kilobytes).
@@ -31,21 +33,35 @@ pub struct ItPattern {
 }
 
 impl ItPattern {
-    pub fn load(source: &[u8]) -> Result<Self, DecodeError> {
+    pub fn load(source: &[u8]) -> Result<Self, Error> {
         let mut data = source;
+        let truncated = Error::Truncated {
+            format: Format::It,
+            structure: "pattern",
+            offset: 0,
+        };
 
         if data.len() < 8 {
-            return Err(DecodeError::LimitExceeded);
+            return Err(truncated);
         }
 
         let pattern_length: u16 = u16::from_le_bytes(data[0..2].try_into().unwrap());
         let row_count: i16 = i16::from_le_bytes(data[2..4].try_into().unwrap());
         let reserved: u32 = u32::from_le_bytes(data[4..8].try_into().unwrap());
 
+        if row_count < 0 {
+            return Err(Error::OutOfRange {
+                format: Format::It,
+                field: "row count",
+                value: row_count as i64,
+                offset: 2,
+            });
+        }
+
         data = &data[8..];
 
         if data.len() < pattern_length as usize {
-            return Err(DecodeError::LimitExceeded);
+            return Err(truncated);
         }
 
         if pattern_length == 0 {
@@ -65,71 +81,87 @@ impl ItPattern {
         });
     }
 
-    pub fn unpack(&self) -> Result<Vec<Vec<PatternSlot>>, DecodeError> {
-        let mut result = vec![vec![PatternSlot::default(); 64]; self.row_count as usize];
-        let mut last_mask_vars = vec![0u8; 64];
+    /// Errors offsets are from the pattern start
+    pub fn unpack(&self) -> Result<Vec<Vec<PatternSlot>>, Error> {
+        let truncated = || Error::Truncated {
+            format: Format::It,
+            structure: "pattern",
+            offset: 8 + self.packed_data.len(),
+        };
+        let mut result = vec![vec![Self::empty_slot(); 64]; self.row_count as usize];
+        let mut last_mask_vars = [0u8; 64];
+        let mut last_values = vec![Self::empty_slot(); 64];
         let mut data_iter = self.packed_data.iter();
 
-        for row in 0..self.row_count as usize {
+        for result_row in result.iter_mut() {
             let mut channel_mask = match data_iter.next() {
                 Some(&mask) => mask,
                 None => break,
             };
 
             while channel_mask > 0 {
-                let channel = (channel_mask - 1) & 63;
+                let channel = ((channel_mask - 1) & 63) as usize;
 
                 let mask_variable = if channel_mask & 0x80 != 0 {
-                    let var = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
-                    last_mask_vars[channel as usize] = var;
+                    let var = *data_iter.next().ok_or_else(truncated)?;
+                    last_mask_vars[channel] = var;
                     var
                 } else {
-                    last_mask_vars[channel as usize]
+                    last_mask_vars[channel]
                 };
 
-                let mut slot = PatternSlot::default();
+                let mut slot = Self::empty_slot();
+                let last = &mut last_values[channel];
 
                 if mask_variable & 0x01 != 0 {
-                    let mut n = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
+                    let mut n = *data_iter.next().ok_or_else(truncated)?;
                     if n > 120 && n != 254 && n != 255 {
                         // FIXME: Sometime n==240 et n==246, i don't know why?
                         if n == 240 || n == 246 {
                             n = 253; // None
                         }
                     }
-                    slot.note = n.try_into().map_err(|_| {
-                        DecodeError::OtherString("Failed to convert note".to_string())
+                    slot.note = n.try_into().map_err(|_| Error::OutOfRange {
+                        format: Format::It,
+                        field: "note",
+                        value: n as i64,
+                        offset: 8 + self.packed_data.len() - data_iter.len() - 1,
                     })?;
-                } else if mask_variable & 0x10 != 0 && row > 0 {
-                    slot.note = result[row - 1][channel as usize].note;
+                    last.note = slot.note;
+                } else if mask_variable & 0x10 != 0 {
+                    slot.note = last.note;
                 }
 
                 if mask_variable & 0x02 != 0 {
-                    let instr = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
+                    let instr = *data_iter.next().ok_or_else(truncated)?;
                     slot.instrument = if instr != 0 {
                         Some(instr as usize - 1)
                     } else {
                         None
                     };
-                } else if mask_variable & 0x20 != 0 && row > 0 {
-                    slot.instrument = result[row - 1][channel as usize].instrument;
+                    last.instrument = slot.instrument;
+                } else if mask_variable & 0x20 != 0 {
+                    slot.instrument = last.instrument;
                 }
 
                 if mask_variable & 0x04 != 0 {
-                    slot.volume = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
-                } else if mask_variable & 0x40 != 0 && row > 0 {
-                    slot.volume = result[row - 1][channel as usize].volume;
+                    slot.volume = *data_iter.next().ok_or_else(truncated)?;
+                    last.volume = slot.volume;
+                } else if mask_variable & 0x40 != 0 {
+                    slot.volume = last.volume;
                 }
 
                 if mask_variable & 0x08 != 0 {
-                    slot.effect_type = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
-                    slot.effect_parameter = *data_iter.next().ok_or(DecodeError::LimitExceeded)?;
-                } else if mask_variable & 0x80 != 0 && row > 0 {
-                    slot.effect_type = result[row - 1][channel as usize].effect_type;
-                    slot.effect_parameter = result[row - 1][channel as usize].effect_parameter;
+                    slot.effect_type = *data_iter.next().ok_or_else(truncated)?;
+                    slot.effect_parameter = *data_iter.next().ok_or_else(truncated)?;
+                    last.effect_type = slot.effect_type;
+                    last.effect_parameter = slot.effect_parameter;
+                } else if mask_variable & 0x80 != 0 {
+                    slot.effect_type = last.effect_type;
+                    slot.effect_parameter = last.effect_parameter;
                 }
 
-                result[row][channel as usize] = slot;
+                result_row[channel] = slot;
 
                 channel_mask = match data_iter.next() {
                     Some(&mask) => mask,
@@ -139,4 +171,117 @@ impl ItPattern {
         }
         Ok(result)
     }
+
+    /// No note, no instrument, no volume (255 is out of the volume column range), no effect
+    pub fn empty_slot() -> PatternSlot {
+        PatternSlot {
+            volume: 255,
+            ..Default::default()
+        }
+    }
+
+    /// Pack rows using mask variables and last values memory
+    pub fn pack(rows: &[Vec<PatternSlot>]) -> Result<Self, EncodeError> {
+        if rows.is_empty() || rows.len() > 200 {
+            return Err(EncodeError::Other(
+                "ItPattern: rows must be between 1 and 200",
+            ));
+        }
+
+        let mut packed_data: Vec<u8> = vec![];
+        let mut last_mask_vars = [0u8; 64];
+        let mut last_values = vec![Self::empty_slot(); 64];
+
+        for row in rows {
+            if row.len() > 64 {
+                return Err(EncodeError::Other("ItPattern: more than 64 channels"));
+            }
+            for (channel, slot) in row.iter().enumerate() {
+                let last = &mut last_values[channel];
+                let mut mask_variable = 0u8;
+                let mut data: Vec<u8> = vec![];
+
+                if slot.note != Pitch::None {
+                    if slot.note == last.note {
+                        mask_variable |= 0x10;
+                    } else {
+                        mask_variable |= 0x01;
+                        data.push(slot.note as u8);
+                        last.note = slot.note;
+                    }
+                }
+
+                if let Some(instr) = slot.instrument {
+                    if instr > 254 {
+                        return Err(EncodeError::Other("ItPattern: instrument out of range"));
+                    }
+                    if slot.instrument == last.instrument {
+                        mask_variable |= 0x20;
+                    } else {
+                        mask_variable |= 0x02;
+                        data.push(instr as u8 + 1);
+                        last.instrument = slot.instrument;
+                    }
+                }
+
+                if slot.volume != 255 {
+                    if slot.volume == last.volume {
+                        mask_variable |= 0x40;
+                    } else {
+                        mask_variable |= 0x04;
+                        data.push(slot.volume);
+                        last.volume = slot.volume;
+                    }
+                }
+
+                if slot.effect_type != 0 {
+                    if slot.effect_type == last.effect_type
+                        && slot.effect_parameter == last.effect_parameter
+                    {
+                        mask_variable |= 0x80;
+                    } else {
+                        mask_variable |= 0x08;
+                        data.push(slot.effect_type);
+                        data.push(slot.effect_parameter);
+                        last.effect_type = slot.effect_type;
+                        last.effect_parameter = slot.effect_parameter;
+                    }
+                }
+
+                if mask_variable == 0 {
+                    continue;
+                }
+
+                if mask_variable == last_mask_vars[channel] {
+                    packed_data.push(channel as u8 + 1);
+                } else {
+                    packed_data.push((channel as u8 + 1) | 0x80);
+                    packed_data.push(mask_variable);
+                    last_mask_vars[channel] = mask_variable;
+                }
+                packed_data.extend(data);
+            }
+            packed_data.push(0);
+        }
+
+        if packed_data.len() > u16::MAX as usize {
+            return Err(EncodeError::Other("ItPattern: packed data too big"));
+        }
+
+        Ok(Self {
+            pattern_length: packed_data.len() as u16,
+            row_count: rows.len() as i16,
+            reserved: 0,
+            packed_data,
+        })
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut output: Vec<u8> = vec![];
+        output.extend(self.pattern_length.to_le_bytes());
+        output.extend(self.row_count.to_le_bytes());
+        output.extend(self.reserved.to_le_bytes());
+        output.extend(&self.packed_data);
+        output
+    }
 }
@@ -1,7 +1,7 @@
-use alloc::string::ToString;
+use crate::error::Error;
+use crate::format::Format;
 use alloc::vec;
 use alloc::vec::Vec;
-use bincode::error::DecodeError;
 use serde::Deserialize;
 
 const MAX_MIXPLUGINS: usize = 64;
@@ -55,18 +55,21 @@ impl Plugins {
         Self::default()
     }
 
-    pub fn load(source: &[u8]) -> Result<(Self, usize), DecodeError> {
+    pub fn load(source: &[u8]) -> Result<(Self, usize), Error> {
         let mut data = source;
         let mut plugins = Self::new();
+        let truncated = |data: &[u8]| Error::Truncated {
+            format: Format::It,
+            structure: "plugins",
+            offset: source.len() - data.len(),
+        };
 
         while data.len() >= 8 {
             let plugin_id = u32::from_le_bytes(data[0..4].try_into().unwrap());
             let plugin_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
 
-            if data.len() < plugin_size {
-                return Err(DecodeError::OtherString(
-                    "Plugin Size too big!?".to_string(),
-                ));
+            if data.len() < 8 + plugin_size {
+                return Err(truncated(data));
             }
 
             if plugin_id == u32::from_le_bytes(*b"CHFX") {
@@ -76,28 +79,34 @@ impl Plugins {
                         plugins.channel_settings[ch] =
                             u32::from_le_bytes(data[ch * 4..(ch + 1) * 4].try_into().unwrap());
                     } else {
-                        return Err(DecodeError::LimitExceeded);
+                        return Err(truncated(data));
                     }
                 }
                 data = &data[plugin_size..];
             } else if data[0] == b'F' && data[1] == b'X' && data[2] >= b'0' && data[3] >= b'0' {
-                let plugin_index = ((data[2] - b'0') * 10 + (data[3] - b'0')) as usize;
-                data = &data[8..];
+                let plugin_index = (data[2] - b'0') as usize * 10 + (data[3] - b'0') as usize;
                 if plugin_index >= MAX_MIXPLUGINS {
-                    return Err(DecodeError::OtherString(
-                        "Plugin Index overflow!?".to_string(),
-                    ));
+                    return Err(Error::OutOfRange {
+                        format: Format::It,
+                        field: "plugin index",
+                        value: plugin_index as i64,
+                        offset: source.len() - data.len(),
+                    });
                 }
-                let info: (SndMixPluginInfo, usize) =
-                    bincode::serde::decode_from_slice::<SndMixPluginInfo, _>(
-                        data,
-                        bincode::config::legacy(),
-                    )
-                    .unwrap();
+                data = &data[8..];
+                let info: (SndMixPluginInfo, usize) = bincode::serde::decode_from_slice::<
+                    SndMixPluginInfo,
+                    _,
+                >(
+                    data, bincode::config::legacy()
+                )
+                .map_err(|e| Error::decode(Format::It, "plugins", source.len() - data.len(), e))?;
                 plugins.mix[plugin_index].info = info.0;
+                let Some(extra_size) = plugin_size.checked_sub(info.1) else {
+                    return Err(truncated(data));
+                };
                 data = &data[info.1..];
 
-                let extra_size = plugin_size - info.1;
                 if extra_size != 0 {
                     let d = &data[0..extra_size].to_vec();
                     plugins.mix[plugin_index].data = Some(d.clone());
@@ -1,15 +1,19 @@
+use crate::error::Error;
+use crate::format::Format;
 use crate::prelude::*;
 use alloc::string::String;
 
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 
 use alloc::vec;
 use alloc::vec::Vec;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::bitreader::BitReader;
+use super::bitwriter::BitWriter;
 use super::serde_helper::deserialize_string_12;
 use super::serde_helper::deserialize_string_26;
+use super::serde_helper::{serialize_string_12, serialize_string_26};
 
 #[cfg(feature = "micromath")]
 #[allow(unused_imports)]
@@ -19,14 +23,17 @@ use micromath::F32Ext;
 use num_traits::float::Float;
 
 /// Structure representing a sample header in the IMPS format.
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[repr(C)]
 pub struct ItSampleHeader {
     /// "IMPS"
     pub id: [u8; 4],
 
     /// Name of the DOS file
-    #[serde(deserialize_with = "deserialize_string_12")]
+    #[serde(
+        deserialize_with = "deserialize_string_12",
+        serialize_with = "serialize_string_12"
+    )]
     pub dos_filename: String,
 
     /// Reserved byte for future use.
@@ -55,7 +62,10 @@ pub struct ItSampleHeader {
 
     /// Name of the sample (null-terminated).
     /// Length: 26 bytes
-    #[serde(deserialize_with = "deserialize_string_26")]
+    #[serde(
+        deserialize_with = "deserialize_string_26",
+        serialize_with = "serialize_string_26"
+    )]
     pub sample_name: String,
 
     /// Convert flags for the sample.
@@ -181,10 +191,7 @@ impl ItSampleHeader {
         self.convert_flags & 0b0010_0000 != 0
     }
 
-    fn get_sample_data_from_compressed_values(
-        &self,
-        data: &[u8],
-    ) -> Result<SampleDataType, DecodeError> {
+    fn get_sample_data_from_compressed_values(&self, data: &[u8]) -> Result<SampleDataType, Error> {
         let dst = if self.is_16bits() {
             if self.is_stereo() {
                 let sample_len = 2 * self.sample_length as usize;
@@ -210,16 +217,25 @@ impl ItSampleHeader {
         Ok(dst)
     }
 
-    fn get_sample_data_from_values(&self, data: &[u8]) -> Result<SampleDataType, DecodeError> {
+    /// `len` bytes of uncompressed sample data
+    fn values(data: &[u8], len: usize) -> Result<&[u8], Error> {
+        data.get(..len).ok_or(Error::Truncated {
+            format: Format::It,
+            structure: "sample data",
+            offset: 0,
+        })
+    }
+
+    fn get_sample_data_from_values(&self, data: &[u8]) -> Result<SampleDataType, Error> {
         let dst = if self.is_16bits() {
             if self.is_stereo() {
                 let sample_len = 2 * 2 * self.sample_length as usize;
-                let src = self.convert_u8_to_i16_vec(&data[0..sample_len])?;
+                let src = self.convert_u8_to_i16_vec(Self::values(data, sample_len)?);
                 let stereo = self.convert_16bit_sample(src.as_slice());
                 SampleDataType::Stereo16(stereo)
             } else {
                 let sample_len = 2 * self.sample_length as usize;
-                let src = self.convert_u8_to_i16_vec(&data[0..sample_len])?;
+                let src = self.convert_u8_to_i16_vec(Self::values(data, sample_len)?);
                 SampleDataType::Mono16(self.convert_16bit_sample(src.as_slice()))
             }
         } else {
@@ -245,7 +261,7 @@ impl ItSampleHeader {
         Ok(dst)
     }
 
-    pub fn get_sample_data(&self, data: &[u8]) -> Result<SampleDataType, DecodeError> {
+    pub fn get_sample_data(&self, data: &[u8]) -> Result<SampleDataType, Error> {
         if self.is_compressed() {
             return self.get_sample_data_from_compressed_values(data);
         } else {
@@ -253,11 +269,48 @@ impl ItSampleHeader {
         }
     }
 
-    fn convert_u8_to_i16_vec(&self, input: &[u8]) -> Result<Vec<i16>, DecodeError> {
-        if input.len() % 2 != 0 {
-            return Err(DecodeError::Other("input is odd!"));
+    /// Header at `seek` and its data, `sample_pointer` being an offset in `source`
+    pub fn load_with_data(
+        source: &[u8],
+        seek: usize,
+    ) -> Result<(Self, Option<SampleDataType>), Error> {
+        if source.get(seek..seek + 4) != Some(b"IMPS") {
+            return Err(Error::BadMagic {
+                format: Format::Its,
+                offset: seek,
+            });
+        }
+        let sh = bincode::serde::decode_from_slice::<ItSampleHeader, _>(
+            &source[seek..],
+            bincode::config::legacy(),
+        )
+        .map_err(|e| Error::decode(Format::Its, "sample header", seek, e))?
+        .0;
+        if !sh.is_associated_sample() {
+            return Ok((sh, None));
         }
 
+        let start = sh.sample_pointer as usize;
+        if source.len() < start {
+            return Err(Error::OutOfRange {
+                format: Format::Its,
+                field: "sample pointer",
+                value: start as i64,
+                offset: seek + 0x48,
+            });
+        }
+        let data = sh
+            .get_sample_data(&source[start..])
+            .map_err(|e| e.with_format(Format::Its).at(start))?;
+        if data.len() != 0 {
+            Ok((sh, Some(data)))
+        } else {
+            Ok((sh, None))
+        }
+    }
+
+    /// A last odd byte is ignored
+    fn convert_u8_to_i16_vec(&self, input: &[u8]) -> Vec<i16> {
         let mut output = Vec::with_capacity(input.len() / 2);
 
         for chunk in input.chunks_exact(2) {
@@ -268,7 +321,7 @@ impl ItSampleHeader {
             };
             output.push(value);
         }
-        Ok(output)
+        output
     }
 
     fn convert_8bit_sample(&self, p: &[i8]) -> Vec<i8> {
@@ -325,12 +378,17 @@ impl ItSampleHeader {
         dst
     }
 
-    fn it_unpack_8bit(&self, input: &[u8], output_len: usize) -> Result<Vec<i8>, DecodeError> {
+    fn it_unpack_8bit(&self, input: &[u8], output_len: usize) -> Result<Vec<i8>, Error> {
         let mut output = Vec::new();
         let mut p_src = input;
         while output.len() < output_len {
+            let block_offset = input.len() - p_src.len();
             if p_src.len() < 2 {
-                return Err(DecodeError::LimitExceeded);
+                return Err(Error::Truncated {
+                    format: Format::It,
+                    structure: "compressed sample",
+                    offset: block_offset,
+                });
             }
 
             let block_len = u16::from_le_bytes([p_src[0], p_src[1]]) as usize;
@@ -341,8 +399,16 @@ impl ItSampleHeader {
             let mut temp2: u8 = 0;
 
             if p_src.len() < block_len {
-                return Err(DecodeError::LimitExceeded);
+                return Err(Error::Truncated {
+                    format: Format::It,
+                    structure: "compressed sample",
+                    offset: block_offset,
+                });
             }
+            let invalid = || Error::InvalidSampleCompression {
+                format: Format::It,
+                offset: block_offset,
+            };
 
             let mut bit_reader = BitReader::new(&p_src[..block_len]);
             p_src = &p_src[block_len..];
@@ -363,14 +429,12 @@ impl ItSampleHeader {
                     break;
                 }
 
-                let mut bits: u16 = bit_reader
-                    .read_bits(left)
-                    .ok_or(DecodeError::LimitExceeded)? as u16;
+                let mut bits: u16 = bit_reader.read_bits(left).ok_or_else(invalid)? as u16;
 
                 if left < 7 {
                     // Type A
                     if (1 as u16) << (left - 1) == bits {
-                        bits = bit_reader.read_bits(3).ok_or(DecodeError::LimitExceeded)? as u16;
+                        bits = bit_reader.read_bits(3).ok_or_else(invalid)? as u16;
                         left = if bits as u8 + 1 < left {
                             bits as u8 + 1
                         } else {
@@ -424,12 +488,17 @@ impl ItSampleHeader {
         Ok(output)
     }
 
-    fn it_unpack_16bit(&self, input: &[u8], output_len: usize) -> Result<Vec<i16>, DecodeError> {
+    fn it_unpack_16bit(&self, input: &[u8], output_len: usize) -> Result<Vec<i16>, Error> {
         let mut output = Vec::new();
         let mut p_src = input;
         while output.len() < output_len {
+            let block_offset = input.len() - p_src.len();
             if p_src.len() < 2 {
-                return Err(DecodeError::LimitExceeded);
+                return Err(Error::Truncated {
+                    format: Format::It,
+                    structure: "compressed sample",
+                    offset: block_offset,
+                });
             }
 
             let block_len = u16::from_le_bytes([p_src[0], p_src[1]]) as usize;
@@ -440,8 +509,16 @@ impl ItSampleHeader {
             let mut temp2: i16 = 0;
 
             if p_src.len() < block_len {
-                return Err(DecodeError::LimitExceeded);
+                return Err(Error::Truncated {
+                    format: Format::It,
+                    structure: "compressed sample",
+                    offset: block_offset,
+                });
             }
+            let invalid = || Error::InvalidSampleCompression {
+                format: Format::It,
+                offset: block_offset,
+            };
 
             let mut bit_reader = BitReader::new(&p_src[..block_len]);
             p_src = &p_src[block_len..];
@@ -462,14 +539,12 @@ impl ItSampleHeader {
                     break;
                 }
 
-                let mut bits = bit_reader
-                    .read_bits(left)
-                    .ok_or(DecodeError::LimitExceeded)?;
+                let mut bits = bit_reader.read_bits(left).ok_or_else(invalid)?;
 
                 if left < 7 {
                     // Type A
                     if (1 as u32) << (left - 1) == bits {
-                        bits = bit_reader.read_bits(4).ok_or(DecodeError::LimitExceeded)?;
+                        bits = bit_reader.read_bits(4).ok_or_else(invalid)?;
                         left = if bits as u8 + 1 < left {
                             bits as u8 + 1
                         } else {
@@ -523,11 +598,169 @@ impl ItSampleHeader {
         Ok(output)
     }
 
+    /// Inverse of `it_unpack_8bit`
+    fn it_pack_8bit(input: &[i8], double_delta: bool) -> Vec<u8> {
+        let src: Vec<i32> = input.iter().map(|&v| v as i32).collect();
+        Self::it_pack(&src, 8, double_delta)
+    }
+
+    /// Inverse of `it_unpack_16bit`
+    fn it_pack_16bit(input: &[i16], double_delta: bool) -> Vec<u8> {
+        let src: Vec<i32> = input.iter().map(|&v| v as i32).collect();
+        Self::it_pack(&src, 16, double_delta)
+    }
+
+    /// IT214/IT215 block compression, `sample_bits` is 8 or 16.
+    ///
+    /// Bit widths are chosen per block to minimize its size.
+    fn it_pack(input: &[i32], sample_bits: u8, double_delta: bool) -> Vec<u8> {
+        // max width: 9 or 17 bits
+        let max_width = sample_bits + 1;
+        // Type A: bits used to give the new width
+        let type_a_bits: u8 = if sample_bits == 8 { 3 } else { 4 };
+        // Type B: half size of the escape range
+        let type_b_half: i32 = if sample_bits == 8 { 4 } else { 8 };
+        let block_size = if sample_bits == 8 { 0x8000 } else { 0x4000 };
+        let wrap = |v: i32| -> i32 {
+            if sample_bits == 8 {
+                v as i8 as i32
+            } else {
+                v as i16 as i32
+            }
+        };
+
+        let fits = |d: i32, width: u8| -> bool {
+            if width == max_width {
+                return true;
+            }
+            let half = 1i32 << (width - 1);
+            if width < 7 {
+                // Type A: -half is the escape value
+                -half < d && d < half
+            } else {
+                // Type B: a range around half is used by escape values
+                let raw = d & ((1i32 << width) - 1);
+                -half <= d && d < half && !(half - type_b_half..half + type_b_half).contains(&raw)
+            }
+        };
+
+        let escape_cost = |width: u8| -> u32 {
+            if width < 7 {
+                (width + type_a_bits) as u32
+            } else {
+                width as u32
+            }
+        };
+
+        let mut output: Vec<u8> = vec![];
+
+        for block in input.chunks(block_size) {
+            // deltas, as the decoder will sum them
+            let mut deltas: Vec<i32> = Vec::with_capacity(block.len());
+            let mut prev = 0;
+            let mut prev_delta = 0;
+            for &v in block {
+                let delta = wrap(v - prev);
+                prev = v;
+                if double_delta {
+                    deltas.push(wrap(delta - prev_delta));
+                    prev_delta = delta;
+                } else {
+                    deltas.push(delta);
+                }
+            }
+
+            // cost[w - 1]: smallest size in bits ending with width w
+            let widths = max_width as usize;
+            let mut cost = vec![u32::MAX; widths];
+            cost[widths - 1] = 0;
+            let mut from: Vec<u8> = vec![0; deltas.len() * widths];
+
+            for (i, &d) in deltas.iter().enumerate() {
+                let (switch_width, switch_cost) = (1..=max_width)
+                    .filter(|&w| cost[w as usize - 1] != u32::MAX)
+                    .map(|w| (w, cost[w as usize - 1] + escape_cost(w)))
+                    .min_by_key(|&(_, c)| c)
+                    .unwrap_or((max_width, 0));
+
+                let mut next = vec![u32::MAX; widths];
+                for w in 1..=max_width {
+                    if !fits(d, w) {
+                        continue;
+                    }
+                    let stay = cost[w as usize - 1];
+                    let (previous, c) = if stay <= switch_cost {
+                        (w, stay)
+                    } else {
+                        (switch_width, switch_cost)
+                    };
+                    next[w as usize - 1] = c + w as u32;
+                    from[i * widths + w as usize - 1] = previous;
+                }
+                cost = next;
+            }
+
+            // find back the best width for each delta
+            let mut width = (1..=max_width)
+                .min_by_key(|&w| cost[w as usize - 1])
+                .unwrap_or(max_width);
+            let mut best_widths: Vec<u8> = vec![0; deltas.len()];
+            for i in (0..deltas.len()).rev() {
+                best_widths[i] = width;
+                width = from[i * widths + width as usize - 1];
+            }
+
+            let mut bit_writer = BitWriter::new();
+            let mut left = max_width;
+            for (&d, &width) in deltas.iter().zip(best_widths.iter()) {
+                if width != left {
+                    if left < 7 {
+                        // Type A
+                        bit_writer.write_bits(left, 1 << (left - 1));
+                        let bits = if width < left { width - 1 } else { width - 2 };
+                        bit_writer.write_bits(type_a_bits, bits as u32);
+                    } else if left < max_width {
+                        // Type B
+                        let e = if width < left { width } else { width - 1 } as i32;
+                        let bits = (1i32 << (left - 1)) - 1 - type_b_half + e;
+                        bit_writer.write_bits(left, bits as u32);
+                    } else {
+                        bit_writer.write_bits(left, (1 << sample_bits) + width as u32 - 1);
+                    }
+                    left = width;
+                }
+                let mask = (1u32 << left.min(sample_bits)) - 1;
+                bit_writer.write_bits(left, d as u32 & mask);
+            }
+
+            let data = bit_writer.into_bytes();
+            output.extend((data.len() as u16).to_le_bytes());
+            output.extend(data);
+        }
+
+        output
+    }
+
+    /// Compress `Mono8` or `Mono16` data using IT214 (or IT215 if `it215`),
+    /// flags are updated. Other data types are not compressed.
+    pub fn compress_sample_data(&mut self, data: &SampleDataType, it215: bool) -> Option<Vec<u8>> {
+        let output = match data {
+            SampleDataType::Mono8(d) => Self::it_pack_8bit(d, it215),
+            SampleDataType::Mono16(d) => Self::it_pack_16bit(d, it215),
+            _ => return None,
+        };
+        self.flags |= 0b0000_1000;
+        if it215 {
+            self.convert_flags |= 0b0000_0100;
+        }
+        Some(output)
+    }
+
     fn c5_speed_to_finetune(&self) -> (i8, f32) {
         let ratio = self.c5_speed as f32 / 16726.0;
         let semitones = 12.0 * ratio.log2();
         let relative_pitch = semitones.round() as i8;
-        let finetune = semitones.fract();
+        let finetune = semitones - relative_pitch as f32;
         (relative_pitch, finetune)
     }
 
@@ -557,7 +790,7 @@ impl ItSampleHeader {
         let (relative_pitch, finetune) = self.c5_speed_to_finetune();
 
         let panning = if self.default_pan & 0x80 != 0 {
-            self.default_pan as f32 / 64.0
+            (self.default_pan & 0x7F) as f32 / 64.0
         } else {
             0.5
         };
@@ -605,4 +838,143 @@ impl ItSampleHeader {
             sweep: self.vibrato_sweep as f32 / 64.0,
         }
     }
+
+    /// Inverse of `to_sample` and `to_vibrato`.
+    /// `sample_length` and `sample_pointer` are computed from the data, pointer must be updated later.
+    pub fn from_sample(sample: &Sample, vibrato: &Vibrato) -> Self {
+        let mut sh = Self {
+            id: *b"IMPS",
+            sample_name: sample.name.clone(),
+            global_volume: (sample.volume * 64.0).round().clamp(0.0, 64.0) as u8,
+            default_volume: 64,
+            convert_flags: 0b0000_0001, // signed samples
+            ..Default::default()
+        };
+
+        sh.flags = match &sample.data {
+            None => 0,
+            Some(SampleDataType::Mono8(_)) => 0b0000_0001,
+            Some(SampleDataType::Mono16(_)) => 0b0000_0011,
+            Some(SampleDataType::Stereo8(_)) => 0b0000_0101,
+            Some(SampleDataType::Stereo16(_)) | Some(SampleDataType::StereoFloat(_)) => 0b0000_0111,
+        };
+        sh.sample_length = sample.len() as u32;
+
+        match sample.loop_flag {
+            LoopType::No => {}
+            LoopType::Forward => sh.flags |= 0b0001_0000,
+            LoopType::PingPong => sh.flags |= 0b0101_0000,
+        }
+        sh.loop_beginning = sample.loop_start;
+        sh.loop_end = sample.loop_start + sample.loop_length;
+
+        match sample.sustain_loop_flag {
+            LoopType::No => {}
+            LoopType::Forward => sh.flags |= 0b0010_0000,
+            LoopType::PingPong => sh.flags |= 0b1010_0000,
+        }
+        sh.sustain_loop_beginning = sample.sustain_loop_start;
+        sh.sustain_loop_end = sample.sustain_loop_start + sample.sustain_loop_length;
+
+        // centered panning is the default
+        sh.default_pan = match (sample.panning * 64.0).round().clamp(0.0, 64.0) as u8 {
+            32 => 32,
+            pan => pan | 0x80,
+        };
+
+        let semitones = sample.relative_pitch as f32 + sample.finetune;
+        sh.c5_speed = (16726.0 * 2.0f32.powf(semitones / 12.0))
+            .round()
+            .clamp(0.0, 9_999_999.0) as u32;
+
+        sh.vibrato_speed = (vibrato.speed * 64.0).round().clamp(0.0, 64.0) as u8;
+        sh.vibrato_depth = (vibrato.depth * 64.0).round().clamp(0.0, 64.0) as u8;
+        sh.vibrato_sweep = (vibrato.sweep * 64.0).round().clamp(0.0, 255.0) as u8;
+        sh.vibrato_waveform = match vibrato.waveform {
+            Waveform::TranslatedRampDown => 1,
+            Waveform::TranslatedSquare => 2,
+            Waveform::Random => 3,
+            _ => 0,
+        };
+        sh
+    }
+
+    pub fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        bincode::serde::encode_to_vec(self, bincode::config::legacy())
+    }
+
+    /// Signed little endian PCM, stereo samples are saved left channel first
+    pub fn save_sample_data(data: &SampleDataType) -> Vec<u8> {
+        fn split<T: Copy>(d: &[T]) -> Vec<T> {
+            d.iter()
+                .step_by(2)
+                .chain(d.iter().skip(1).step_by(2))
+                .copied()
+                .collect()
+        }
+
+        match data {
+            SampleDataType::Mono8(d) => d.iter().map(|&v| v as u8).collect(),
+            SampleDataType::Mono16(d) => d.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            SampleDataType::Stereo8(d) => split(d).iter().map(|&v| v as u8).collect(),
+            SampleDataType::Stereo16(d) => split(d).iter().flat_map(|v| v.to_le_bytes()).collect(),
+            SampleDataType::StereoFloat(d) => split(d)
+                .iter()
+                .flat_map(|v| ((v.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16).to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    #[test]
+    fn it_save_compressed() {
+        use crate::prelude::*;
+
+        // silence, slow waves and noise, on more than one block
+        let wave = |i: usize| -> i32 {
+            match (i / 5000) % 3 {
+                0 => 0,
+                1 => ((i % 200) as i32 - 100) * (i % 7) as i32,
+                _ => (i as i32).wrapping_mul(1_103_515_245).wrapping_add(12345) >> 8,
+            }
+        };
+        let mono8: Vec<i8> = (0..40000).map(|i| wave(i) as i8).collect();
+        let mono16: Vec<i16> = (0..40000).map(|i| (wave(i) * 97) as i16).collect();
+
+        for data in [SampleDataType::Mono8(mono8), SampleDataType::Mono16(mono16)] {
+            let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+            for instr in module.instrument.iter_mut() {
+                if let InstrumentType::Default(id) = &mut instr.instr_type {
+                    for s in id.sample.iter_mut().flatten() {
+                        s.loop_flag = LoopType::No;
+                        s.data = Some(data.clone());
+                    }
+                }
+            }
+            for it215 in [false, true] {
+                let packed = module.save_it_compressed(it215).unwrap();
+                assert!(packed.len() < module.save_it().unwrap().len());
+                let module2 = Module::load_it(&packed).unwrap();
+                for instr in module2.instrument.iter() {
+                    if let InstrumentType::Default(id) = &instr.instr_type {
+                        for s in id.sample.iter().flatten() {
+                            match (&s.data, &data) {
+                                (Some(SampleDataType::Mono8(a)), SampleDataType::Mono8(b)) => {
+                                    assert_eq!(a, b)
+                                }
+                                (Some(SampleDataType::Mono16(a)), SampleDataType::Mono16(b)) => {
+                                    assert_eq!(a, b)
+                                }
+                                _ => panic!("wrong sample data type"),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
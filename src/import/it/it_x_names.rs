@@ -1,7 +1,8 @@
+use crate::error::Error;
+use crate::format::Format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use bincode::error::DecodeError;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug, Default)]
@@ -9,25 +10,25 @@ use serde::Deserialize;
 pub struct ItXNames;
 
 impl ItXNames {
-    pub fn load(source: &[u8], chunk_size: usize) -> Result<(Vec<String>, usize), DecodeError> {
+    pub fn load(source: &[u8], chunk_size: usize) -> Result<(Vec<String>, usize), Error> {
         let data = source;
+        let truncated = Error::Truncated {
+            format: Format::It,
+            structure: "names",
+            offset: 0,
+        };
 
         if data.len() < 4 {
-            return Err(DecodeError::LimitExceeded);
+            return Err(truncated);
         }
 
         let length = u32::from_le_bytes(data[0..4].try_into().unwrap());
         if length == 0 {
             return Ok((vec![], 4));
         }
-        let data = &data[4..4 + length as usize];
-
-        if data.len() < length as usize {
-            return Err(DecodeError::ArrayLengthMismatch {
-                required: length as usize,
-                found: data.len(),
-            });
-        }
+        let Some(data) = data.get(4..4 + length as usize) else {
+            return Err(truncated);
+        };
 
         let dest: Vec<String> = data
             .chunks(chunk_size)
@@ -42,11 +43,27 @@ impl ItXNames {
         Ok((dest, 4 + length as usize))
     }
 
+    /// Inverse of `load`, `id` (`PNAM` or `CNAM`) included
+    pub fn save(id: &[u8; 4], names: &[String], chunk_size: usize) -> Vec<u8> {
+        let mut output: Vec<u8> = id.to_vec();
+        output.extend(((names.len() * chunk_size) as u32).to_le_bytes());
+        for name in names {
+            let mut end = name.len().min(chunk_size);
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            let mut chunk = vec![0u8; chunk_size];
+            chunk[..end].copy_from_slice(&name.as_bytes()[..end]);
+            output.extend(chunk);
+        }
+        output
+    }
+
     pub fn is_pnam(data: &[u8]) -> bool {
-        data[0] == b'P' && data[1] == b'N' && data[2] == b'A' && data[3] == b'M'
+        data.starts_with(b"PNAM")
     }
 
     pub fn is_cnam(data: &[u8]) -> bool {
-        data[0] == b'C' && data[1] == b'N' && data[2] == b'A' && data[3] == b'M'
+        data.starts_with(b"CNAM")
     }
 }
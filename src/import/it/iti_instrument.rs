@@ -0,0 +1,161 @@
+/// Impulse Tracker Instrument file
+use bincode::error::EncodeError;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::Error;
+use crate::format::{Confidence, Format};
+use crate::instrument::{Instrument, InstrumentType};
+use crate::sample::{Sample, SampleDataType};
+use crate::vibrato::Vibrato;
+
+use super::it_instrument::{ItInstrument, ItInstrumentPost2};
+use super::it_sample_header::ItSampleHeader;
+use super::its_sample::ITS_HEADER_SIZE;
+
+/// Instrument header and envelopes
+const ITI_HEADER_SIZE: usize = 554;
+
+/// Instrument, samples header then samples data.
+/// Samples are numbered from 1 in the note-sample-keyboard table.
+pub struct ItiInstrument;
+
+impl ItiInstrument {
+    /// Header sniffing, the samples are not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        match ItInstrument::load_pre2(data) {
+            Ok(_) => Some(Confidence::High),
+            Err(e) if e.is_bad_magic() => None,
+            Err(_) => Some(Confidence::Medium),
+        }
+    }
+
+    pub fn load(data: &[u8]) -> Result<Instrument, Error> {
+        // `load_pre2` reads the post-2.0 instrument format
+        let it = ItInstrument::load_pre2(data).map_err(|e| e.with_format(Format::Iti))?;
+        let num_samples = match &it {
+            ItInstrument::Post2(i) => i.instr.num_samples as usize,
+            ItInstrument::Pre2(_) => 0,
+        };
+
+        let mut samples: Vec<Option<Sample>> = vec![];
+        let mut vibratos: Vec<Vibrato> = vec![];
+        for i in 0..num_samples {
+            let seek = ITI_HEADER_SIZE + ITS_HEADER_SIZE * i;
+            let (sh, sample_data) = ItSampleHeader::load_with_data(data, seek)
+                .map_err(|e| e.with_format(Format::Iti))?;
+            samples.push(Some(sh.to_sample(&sample_data)));
+            vibratos.push(sh.to_vibrato());
+        }
+
+        let mut instrument = it.prepare_instrument();
+        if let InstrumentType::Default(id) = &mut instrument.instr_type {
+            // IT vibrato is set by sample, use the first one
+            if let Some(v) = id
+                .sample_for_pitch
+                .iter()
+                .flatten()
+                .next()
+                .and_then(|s| vibratos.get(*s))
+            {
+                id.vibrato = *v;
+            }
+            for s in id.sample_for_pitch.iter_mut() {
+                if matches!(s, Some(index) if *index >= samples.len()) {
+                    *s = None;
+                }
+            }
+            id.sample = samples;
+        }
+        Ok(instrument)
+    }
+
+    pub fn save(instrument: &Instrument) -> Result<Vec<u8>, EncodeError> {
+        let id = match &instrument.instr_type {
+            InstrumentType::Default(id) => id,
+            _ => {
+                return Err(EncodeError::Other(
+                    "ItiInstrument: not a sample based instrument",
+                ))
+            }
+        };
+
+        let mut sample_index: Vec<Option<usize>> = vec![];
+        let mut samples_header: Vec<ItSampleHeader> = vec![];
+        let mut samples: Vec<&Option<SampleDataType>> = vec![];
+        for s in &id.sample {
+            if let Some(s) = s {
+                sample_index.push(Some(samples_header.len()));
+                samples_header.push(ItSampleHeader::from_sample(s, &id.vibrato));
+                samples.push(&s.data);
+            } else {
+                sample_index.push(None);
+            }
+        }
+        if samples_header.len() > 255 {
+            return Err(EncodeError::Other("ItiInstrument: more than 255 samples"));
+        }
+
+        let mut output = ItInstrumentPost2::from_instrument(instrument, &sample_index).save()?;
+
+        let sample_data_start = output.len() + ITS_HEADER_SIZE * samples_header.len();
+        let mut samples_data: Vec<u8> = vec![];
+        for (sh, data) in samples_header.iter_mut().zip(samples) {
+            sh.sample_pointer = (sample_data_start + samples_data.len()) as u32;
+            if let Some(d) = data {
+                samples_data.extend(ItSampleHeader::save_sample_data(d));
+            }
+        }
+        if sample_data_start + samples_data.len() > u32::MAX as usize {
+            return Err(EncodeError::Other("ItiInstrument: file too big"));
+        }
+
+        for sh in &samples_header {
+            output.extend(sh.save()?);
+        }
+        output.extend(samples_data);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn iti_its_save_load() {
+        use crate::prelude::*;
+
+        let module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let instr = &module.instrument[0];
+        let data = instr.save_iti().unwrap();
+        let instr2 = Instrument::load_iti(&data).unwrap();
+        assert_eq!(data, instr2.save_iti().unwrap());
+        assert_eq!(instr.name, instr2.name);
+        let (id, id2) = match (&instr.instr_type, &instr2.instr_type) {
+            (InstrumentType::Default(id), InstrumentType::Default(id2)) => (id, id2),
+            _ => panic!("not a sample based instrument"),
+        };
+        assert_eq!(id.sample.len(), id2.sample.len());
+        assert_eq!(id.sample_for_pitch[48], id2.sample_for_pitch[48]);
+
+        let mut sample = id.sample.iter().flatten().next().unwrap().clone();
+        sample.data = Some(SampleDataType::Stereo16(vec![1, -1, 300, -300, 0, 7]));
+        sample.loop_flag = LoopType::PingPong;
+        sample.loop_start = 1;
+        sample.loop_length = 2;
+        let data = sample.save_its().unwrap();
+        let sample2 = Sample::load_its(&data).unwrap();
+        assert_eq!(data, sample2.save_its().unwrap());
+        assert!(matches!(sample2.loop_flag, LoopType::PingPong));
+        assert_eq!(sample2.loop_length, 2);
+        match &sample2.data {
+            Some(SampleDataType::Stereo16(d)) => assert_eq!(d, &vec![1, -1, 300, -300, 0, 7]),
+            _ => panic!("wrong sample data type"),
+        }
+
+        assert!(Instrument::default().save_iti().is_err());
+        assert!(Sample::load_its(&data[0..40]).is_err());
+    }
+}
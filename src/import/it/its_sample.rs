@@ -0,0 +1,33 @@
+/// Impulse Tracker Sample file
+use bincode::error::EncodeError;
+
+use alloc::vec::Vec;
+
+use crate::error::Error;
+use crate::sample::Sample;
+use crate::vibrato::Vibrato;
+
+use super::it_sample_header::ItSampleHeader;
+
+pub const ITS_HEADER_SIZE: usize = 80;
+
+/// Sample header followed by sample data
+pub struct ItsSample;
+
+impl ItsSample {
+    /// IT vibrato is set by sample, it is lost here
+    pub fn load(data: &[u8]) -> Result<Sample, Error> {
+        let (sh, sample_data) = ItSampleHeader::load_with_data(data, 0)?;
+        Ok(sh.to_sample(&sample_data))
+    }
+
+    pub fn save(sample: &Sample) -> Result<Vec<u8>, EncodeError> {
+        let mut sh = ItSampleHeader::from_sample(sample, &Vibrato::default());
+        sh.sample_pointer = ITS_HEADER_SIZE as u32;
+        let mut output = sh.save()?;
+        if let Some(d) = &sample.data {
+            output.extend(ItSampleHeader::save_sample_data(d));
+        }
+        Ok(output)
+    }
+}
@@ -1,6 +1,7 @@
 #![forbid(unsafe_code)]
 
 mod bitreader;
+mod bitwriter;
 mod it_edit_history;
 pub(crate) mod it_effect;
 mod it_header;
@@ -11,4 +12,6 @@ mod it_pattern;
 mod it_plugins;
 mod it_sample_header;
 mod it_x_names;
+pub mod iti_instrument;
+pub mod its_sample;
 mod serde_helper;
@@ -9,6 +9,9 @@ pub(crate) mod track_import_unit;
 /// impl loader to Module. See Module documentation load* fn.
 mod import_loader;
 
+/// impl saver from Module. See Module documentation save* fn.
+mod export_saver;
+
 /// Load historical XM files
 #[cfg(feature = "import_xm")]
 pub mod xm;
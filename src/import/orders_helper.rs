@@ -24,3 +24,19 @@ pub fn parse_orders(orders: &[u8]) -> Vec<Vec<usize>> {
 
     result
 }
+
+/// Inverse of `parse_orders`: join songs with a 254 separator
+pub fn join_orders(orders: &[Vec<usize>]) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::new();
+
+    for (i, group) in orders.iter().enumerate() {
+        if i != 0 {
+            result.push(254);
+        }
+        for order in group {
+            result.push(*order as u8);
+        }
+    }
+
+    result
+}
@@ -7,8 +7,16 @@ use alloc::vec::Vec;
  *
  */
 use crate::import::patternslot::PatternSlot;
+use crate::import::xm::mod_xm_effect::{ModXmEffect, ModXmLost, ModXmMemory};
 use crate::prelude::*;
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
 #[derive(Debug)]
 pub struct S3mEffect {
     alastnfo: [u8; 32],
@@ -39,6 +47,13 @@ impl S3mEffect {
     /* ii is the channel index
      */
     fn efx_correction(&mut self, ii: usize, n: &mut PatternSlot) {
+        // S3M volume (255 if none) to XM volume column
+        n.volume = if n.volume == 255 {
+            0
+        } else {
+            0x10 + n.volume.min(64)
+        };
+
         if n.effect_parameter > 0 {
             self.alastnfo[ii] = n.effect_parameter;
             if n.effect_type == 8 || n.effect_type == 21 {
@@ -266,4 +281,130 @@ impl S3mEffect {
             }
         }
     }
+
+    //--- pack: TrackUnit to PatternSlot --------------------------------------
+
+    /// S3M pattern slot without note, instrument, volume or effect
+    pub fn empty_slot() -> PatternSlot {
+        PatternSlot {
+            volume: 255,
+            ..Default::default()
+        }
+    }
+
+    /// Inverse of `efx_correction`: XM effect to S3M effect
+    fn s3m_pack_xm_effect(fx: (u8, u8)) -> Option<(u8, u8)> {
+        let (effect_type, param) = fx;
+        let x = param & 0x0F;
+        match effect_type {
+            0x00 => Some((10, param)),                // J
+            0x01 if param < 0xE0 => Some((6, param)), // F
+            0x02 if param < 0xE0 => Some((5, param)), // E
+            0x03 => Some((7, param)),                 // G
+            0x04 => Some((8, param)),                 // H
+            0x05 => Some((12, param)),                // L
+            0x06 => Some((11, param)),                // K
+            0x07 => Some((18, param)),                // R
+            0x09 => Some((15, param)),                // O
+            0x0A => Some((4, param)),                 // D
+            0x0B => Some((2, param)),                 // B
+            0x0D => Some((3, param)),                 // C
+            0x0E => match param >> 4 {
+                0x1 if x != 0 => Some((6, 0xF0 | x)), // fine slide up
+                0x2 if x != 0 => Some((5, 0xF0 | x)), // fine slide down
+                0x3 => Some((19, 0x10 | x)),
+                0x4 => Some((19, 0x30 | x)),
+                0x5 => Some((19, 0x20 | x)),
+                0x6 => Some((19, 0xB0 | x)),
+                0x7 => Some((19, 0x40 | x)),
+                0x9 => Some((17, x)),
+                // DFF is a fine volume slide down
+                0xA if x != 0 && x != 0xF => Some((4, x << 4 | 0x0F)),
+                0xB if x != 0 => Some((4, 0xF0 | x)),
+                0xC if x != 0 => Some((19, 0xC0 | x)),
+                0xD if x != 0 => Some((19, 0xD0 | x)),
+                0xE => Some((19, 0xE0 | x)),
+                _ => None,
+            },
+            0x0F if param != 0 && param < 0x20 => Some((1, param)), // A
+            0x0F if param > 0x20 => Some((20, param)),              // T
+            0x10 if param <= 0x40 => Some((22, param)),             // V
+            0x1B => Some((17, param)),                              // Q
+            0x1D => Some((9, param)),                               // I
+            0x21 => match param >> 4 {
+                0x1 if x != 0 => Some((6, 0xE0 | x)), // extra fine slide up
+                0x2 if x != 0 => Some((5, 0xE0 | x)), // extra fine slide down
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn s3m_pack_effect(effect: &TrackEffect) -> Option<((u8, u8), (u8, u8))> {
+        let xm = ModXmEffect::mod_xm_pack_effect(FrequencyType::AmigaFrequencies, effect)?;
+        Some((xm, Self::s3m_pack_xm_effect(xm)?))
+    }
+
+    fn s3m_pack_global_effect(effect: &GlobalEffect) -> Option<((u8, u8), (u8, u8))> {
+        let xm = ModXmEffect::mod_xm_pack_global_effect(effect)?;
+        Some((xm, Self::s3m_pack_xm_effect(xm)?))
+    }
+
+    /// Best effort to save a `TrackUnit` into one volume column and one effect column.
+    /// The volume column can only set the volume, global effects have priority on the effect column.
+    /// All effects which can't be saved are returned.
+    /// `memory` is the channel memory from the previous rows of the pattern.
+    pub fn s3m_pack(memory: &mut ModXmMemory, current: &TrackUnit) -> (PatternSlot, ModXmLost) {
+        let mut ps = Self::empty_slot();
+        let mut lost = ModXmLost::default();
+        // (XM effect, S3M effect)
+        let mut fx: Option<((u8, u8), (u8, u8))> = None;
+
+        ps.note = match current.note {
+            // 254 is the only way to stop a note in S3M
+            Pitch::Cut => Pitch::Off,
+            note => note,
+        };
+        ps.instrument = current.instrument;
+
+        for ge in &current.global_effects {
+            match (fx, Self::s3m_pack_global_effect(ge)) {
+                (None, Some(e)) => fx = Some(e),
+                _ => lost.global_effects.push(ge.clone()),
+            }
+        }
+
+        for te in &current.effects {
+            if let TrackEffect::Volume { value, tick: 0 } = te {
+                if ps.volume == 255 {
+                    ps.volume = (value * 64.0).round().clamp(0.0, 64.0) as u8;
+                    continue;
+                }
+            }
+            let e = Self::s3m_pack_effect(te);
+            let merged = fx.and_then(|(xm, _)| {
+                let xm = ModXmEffect::mod_xm_pack_merge(
+                    FrequencyType::AmigaFrequencies,
+                    memory,
+                    xm,
+                    te,
+                )?;
+                Some((xm, Self::s3m_pack_xm_effect(xm)?))
+            });
+            if fx.is_none() && e.is_some() {
+                fx = e;
+            } else if merged.is_some() {
+                fx = merged;
+            } else {
+                lost.effects.push(te.clone());
+            }
+        }
+
+        memory.set_xm(fx.map(|(xm, _)| xm), None);
+        if let Some((_, (t, p))) = fx {
+            ps.effect_type = t;
+            ps.effect_parameter = p;
+        }
+        (ps, lost)
+    }
 }
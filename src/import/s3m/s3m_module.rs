@@ -1,24 +1,39 @@
 use super::serde_helper::{deserialize_string_12, deserialize_string_28, deserialize_string_4};
+use super::serde_helper::{serialize_string_12, serialize_string_28, serialize_string_4};
 use bincode;
-use bincode::error::DecodeError;
-use serde::Deserialize;
+use bincode::error::EncodeError;
+use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+use crate::format::{Confidence, Format};
 use crate::import::import_memory::ImportMemory;
 use crate::import::import_memory::MemoryType;
 use crate::import::orders_helper;
 use crate::import::patternslot::PatternSlot;
+use crate::import::xm::mod_xm_effect::ModXmMemory;
 use crate::prelude::*;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::{vec, vec::Vec};
 
 use super::s3m_effect::S3mEffect;
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
 #[repr(C)]
-#[derive(Default, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 struct S3mHeader {
-    #[serde(deserialize_with = "deserialize_string_28")]
+    #[serde(
+        deserialize_with = "deserialize_string_28",
+        serialize_with = "serialize_string_28"
+    )]
     title: String,
     /// 0x1A
     sig1: u8,
@@ -31,7 +46,10 @@ struct S3mHeader {
     version: u16,
     sample_type: u16,
     /// SCRM
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     sig2: String,
     global_volume: u8,
     speed: u8,
@@ -45,7 +63,7 @@ struct S3mHeader {
 }
 
 #[repr(C)]
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct S3mPcmInstr {
     /// offset = ((ptr_data_h << 16) | ptr_data_l) * 16
     ptr_data_h: u8,
@@ -65,10 +83,16 @@ struct S3mPcmInstr {
     /// sample rate for middle-c note (C-4)
     c2spd: u32,
     internal: [u8; 12],
-    #[serde(deserialize_with = "deserialize_string_28")]
+    #[serde(
+        deserialize_with = "deserialize_string_28",
+        serialize_with = "serialize_string_28"
+    )]
     title: String,
     /// SCRS
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     sig: String,
 }
 
@@ -89,17 +113,28 @@ impl S3mPcmInstr {
         (((self.ptr_data_h as usize) << 16) | (self.ptr_data_l as usize)) << 4
     }
 
-    fn get_sample_data(&self, data: &[u8]) -> Result<SampleDataType, DecodeError> {
+    fn get_sample_data(&self, data: &[u8]) -> Result<SampleDataType, Error> {
         let offset = self.get_sample_offset();
-        let len = if self.get_sample_offset() + self.len as usize > data.len() {
+        if offset > data.len() {
+            return Err(Error::OutOfRange {
+                format: Format::S3m,
+                field: "sample offset",
+                value: offset as i64,
+                offset: 0,
+            });
+        }
+        // `len` is given for one channel
+        let channels = if self.is_stereo() { 2 } else { 1 };
+        let bytes = if self.is_16bits() { 2 } else { 1 };
+        let len = if offset + channels * bytes * self.len as usize > data.len() {
             // fixes "miracle man.s3m" and other broken S3Ms
-            data.len() - offset
+            (data.len() - offset) / (channels * bytes) * channels
         } else {
-            self.len as usize
+            channels * self.len as usize
         };
 
         let dst = if self.is_16bits() {
-            let src = Self::convert_u8_to_u16_vec(&data[offset..(offset + len * 2)])?;
+            let src = Self::convert_u8_to_u16_vec(&data[offset..(offset + len * 2)]);
             if self.is_stereo() {
                 SampleDataType::Stereo16(self.convert_16bit_sample(src.as_slice()))
             } else {
@@ -117,18 +152,82 @@ impl S3mPcmInstr {
         Ok(dst)
     }
 
-    fn convert_u8_to_u16_vec(input: &[u8]) -> Result<Vec<u16>, DecodeError> {
-        if input.len() % 2 != 0 {
-            return Err(DecodeError::Other("input is odd!"));
+    /// Inverse of `S3mModule::to_module`, sample offset must be updated later
+    fn from_sample(title: &str, sample: &Sample) -> Self {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let mut flags = 0;
+        if !matches!(sample.loop_flag, LoopType::No) {
+            // no ping-pong loop in S3M
+            flags |= 1;
+        }
+        match &sample.data {
+            Some(SampleDataType::Stereo8(_)) => flags |= 2,
+            Some(SampleDataType::Mono16(_)) => flags |= 4,
+            Some(SampleDataType::Stereo16(_)) | Some(SampleDataType::StereoFloat(_)) => flags |= 6,
+            _ => {}
         }
+        let c2spd = ph
+            .relative_pitch_to_c4freq(sample.relative_pitch as f32, sample.finetune)
+            .unwrap_or(8363.0);
+
+        Self {
+            len: sample.len() as u32,
+            loop_start: sample.loop_start,
+            loop_end: sample.loop_start + sample.loop_length,
+            volume: (sample.volume * 64.0).round().clamp(0.0, 64.0) as u8,
+            flags,
+            c2spd: c2spd.round() as u32,
+            title: title.to_string(),
+            sig: "SCRS".to_string(),
+            ..Default::default()
+        }
+    }
 
-        let mut output = Vec::with_capacity(input.len() / 2);
+    fn set_sample_offset(&mut self, offset: usize) {
+        let paragraph = offset >> 4;
+        self.ptr_data_h = (paragraph >> 16) as u8;
+        self.ptr_data_l = paragraph as u16;
+    }
 
-        for chunk in input.chunks_exact(2) {
-            let value = u16::from_le_bytes([chunk[0], chunk[1]]);
-            output.push(value);
+    /// Unsigned PCM, stereo samples are saved left channel first
+    fn save_sample_data(data: &SampleDataType) -> Vec<u8> {
+        fn split<T: Copy>(d: &[T]) -> Vec<T> {
+            d.iter()
+                .step_by(2)
+                .chain(d.iter().skip(1).step_by(2))
+                .copied()
+                .collect()
         }
-        Ok(output)
+        fn save_8bit(d: &[i8]) -> Vec<u8> {
+            d.iter().map(|&v| v as u8 ^ 0x80).collect()
+        }
+        fn save_16bit(d: &[i16]) -> Vec<u8> {
+            d.iter()
+                .flat_map(|&v| (v as u16 ^ 0x8000).to_le_bytes())
+                .collect()
+        }
+
+        match data {
+            SampleDataType::Mono8(d) => save_8bit(d),
+            SampleDataType::Mono16(d) => save_16bit(d),
+            SampleDataType::Stereo8(d) => save_8bit(&split(d)),
+            SampleDataType::Stereo16(d) => save_16bit(&split(d)),
+            SampleDataType::StereoFloat(d) => {
+                let d: Vec<i16> = d
+                    .iter()
+                    .map(|v| (v.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)
+                    .collect();
+                save_16bit(&split(&d))
+            }
+        }
+    }
+
+    /// A last odd byte is ignored
+    fn convert_u8_to_u16_vec(input: &[u8]) -> Vec<u16> {
+        input
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect()
     }
 
     fn convert_8bit_sample(&self, p: &[u8]) -> Vec<i8> {
@@ -177,7 +276,7 @@ impl S3mPcmInstr {
 }
 
 #[repr(C)]
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 struct S3mOplInstr {
     reserved1: [u8; 3],
 
@@ -199,10 +298,16 @@ struct S3mOplInstr {
     reserved2: u16,
     c2spd: u32,
     internal: [u8; 12],
-    #[serde(deserialize_with = "deserialize_string_28")]
+    #[serde(
+        deserialize_with = "deserialize_string_28",
+        serialize_with = "serialize_string_28"
+    )]
     pub title: String,
     /// SCRI
-    #[serde(deserialize_with = "deserialize_string_4")]
+    #[serde(
+        deserialize_with = "deserialize_string_4",
+        serialize_with = "serialize_string_4"
+    )]
     sig: String,
 }
 
@@ -212,7 +317,7 @@ impl S3mOplInstr {
         let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
         let mut i_opl = InstrOpl::default();
         i_opl.element.modulator = MdiOpl {
-            ksl: ((self.mod2 & 0b0100_0000) >> 6) | (self.mod2 >> 7),
+            ksl: self.mod2 >> 6,
             multiple: self.mod0 & 0b0000_1111,
             feedback: self.mod10 >> 1,
             attack: self.mod4 >> 4,
@@ -227,7 +332,7 @@ impl S3mOplInstr {
             con: (self.mod10 & 0b0000_0001) != 0,
         };
         i_opl.element.carrier = MdiOpl {
-            ksl: ((self.car3 & 0b0100_0000) >> 6) | (self.car3 >> 7),
+            ksl: self.car3 >> 6,
             multiple: self.car1 & 0b0000_1111,
             feedback: self.mod10 >> 1, // no way for carrier
             attack: self.car5 >> 4,
@@ -250,6 +355,52 @@ impl S3mOplInstr {
 
         return i_opl;
     }
+
+    /// Inverse of `to_instr_opl`
+    pub fn from_instr_opl(title: &str, i_opl: &InstrOpl) -> Self {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let characteristic = |op: &MdiOpl| -> u8 {
+            (op.am as u8) << 7
+                | (op.vib as u8) << 6
+                | (op.eg as u8) << 5
+                | (op.ksr as u8) << 4
+                | (op.multiple & 0b0000_1111)
+        };
+        let scaling =
+            |op: &MdiOpl| -> u8 { (op.ksl & 0b0000_0011) << 6 | (op.total_level & 0b0011_1111) };
+        let attack_decay = |op: &MdiOpl| -> u8 { (op.attack & 0x0F) << 4 | (op.decay & 0x0F) };
+        let sustain_release =
+            |op: &MdiOpl| -> u8 { (op.sustain & 0x0F) << 4 | (op.release & 0x0F) };
+
+        let modulator = &i_opl.element.modulator;
+        let carrier = &i_opl.element.carrier;
+        let c2spd = ph
+            .relative_pitch_to_c4freq(i_opl.relative_pitch as f32, i_opl.finetune)
+            .unwrap_or(8363.0);
+
+        Self {
+            reserved1: [0; 3],
+            mod0: characteristic(modulator),
+            car1: characteristic(carrier),
+            mod2: scaling(modulator),
+            car3: scaling(carrier),
+            mod4: attack_decay(modulator),
+            car5: attack_decay(carrier),
+            mod6: sustain_release(modulator),
+            car7: sustain_release(carrier),
+            mod8: i_opl.element.modulator_wave_select,
+            car9: i_opl.element.carrier_wave_select,
+            mod10: (modulator.feedback & 0b0000_0111) << 1 | modulator.con as u8,
+            unused11: 0,
+            volume: i_opl.volume.min(63),
+            dsk: 0,
+            reserved2: 0,
+            c2spd: c2spd.round() as u32,
+            internal: [0; 12],
+            title: title.to_string(),
+            sig: "SCRI".to_string(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -269,7 +420,15 @@ struct S3mMetaInstrument {
 }
 
 impl S3mMetaInstrument {
-    fn new(data: &[u8], offset: usize) -> Result<Self, DecodeError> {
+    fn new(data: &[u8], offset: usize) -> Result<Self, Error> {
+        if offset + 13 > data.len() {
+            return Err(Error::Truncated {
+                format: Format::S3m,
+                structure: "instrument",
+                offset,
+            });
+        }
+        let decode_error = |e| Error::decode(Format::S3m, "instrument", offset + 13, e);
         let discriminator = data[offset];
         let filename = String::from_utf8_lossy(&data[offset + 1..offset + 13])
             .trim_end_matches('\0')
@@ -281,7 +440,8 @@ impl S3mMetaInstrument {
                 let i = bincode::serde::decode_from_slice::<S3mPcmInstr, _>(
                     &data[offset + 13..],
                     bincode::config::legacy(),
-                )?
+                )
+                .map_err(decode_error)?
                 .0;
                 S3mInstrument::PcmInstrument(i)
             }
@@ -289,22 +449,30 @@ impl S3mMetaInstrument {
                 let i = bincode::serde::decode_from_slice::<S3mPcmInstr, _>(
                     &data[offset + 13..],
                     bincode::config::legacy(),
-                )?
+                )
+                .map_err(decode_error)?
                 .0;
                 if i.sig != "SCRS" {
-                    return Err(DecodeError::Other("Not a SCRS instr?"));
+                    return Err(Error::BadMagic {
+                        format: Format::S3m,
+                        offset: offset + 0x4C,
+                    });
                 }
-                sample = Some(i.get_sample_data(data)?);
+                sample = Some(i.get_sample_data(data).map_err(|e| e.at(offset + 0x0D))?);
                 S3mInstrument::PcmInstrument(i)
             }
             2 | 3 | 4 | 5 | 6 | 7 => {
                 let i = bincode::serde::decode_from_slice::<S3mOplInstr, _>(
                     &data[offset + 13..],
                     bincode::config::legacy(),
-                )?
+                )
+                .map_err(decode_error)?
                 .0;
                 if i.sig != "SCRI" {
-                    return Err(DecodeError::Other("Not a SCRI instr?"));
+                    return Err(Error::BadMagic {
+                        format: Format::S3m,
+                        offset: offset + 0x4C,
+                    });
                 }
                 S3mInstrument::OplInstrument(i)
             }
@@ -317,6 +485,31 @@ impl S3mMetaInstrument {
             sample,
         })
     }
+
+    /// Inverse of `new`, 80 bytes
+    fn save(&self) -> Result<Vec<u8>, EncodeError> {
+        #[derive(Serialize)]
+        struct S3mInstrumentPrefix {
+            discriminator: u8,
+            #[serde(serialize_with = "serialize_string_12")]
+            filename: String,
+        }
+
+        let prefix = S3mInstrumentPrefix {
+            discriminator: self.discriminator,
+            filename: self.filename.clone(),
+        };
+        let mut output = bincode::serde::encode_to_vec(&prefix, bincode::config::legacy())?;
+        output.extend(match &self.value {
+            S3mInstrument::PcmInstrument(pcm) => {
+                bincode::serde::encode_to_vec(pcm, bincode::config::legacy())?
+            }
+            S3mInstrument::OplInstrument(opl) => {
+                bincode::serde::encode_to_vec(opl, bincode::config::legacy())?
+            }
+        });
+        Ok(output)
+    }
 }
 
 #[derive(Default, Deserialize, Debug)]
@@ -328,31 +521,61 @@ pub struct S3mModule {
 }
 
 impl S3mModule {
-    pub fn load(ser_s3m_module: &[u8]) -> Result<S3mModule, DecodeError> {
+    /// Header sniffing, the module is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        let header = bincode::serde::decode_from_slice::<S3mHeader, _>(
+            data.get(0..96)?,
+            bincode::config::legacy(),
+        )
+        .ok()?
+        .0;
+        if header.sig2 != "SCRM" {
+            None
+        } else if header.sig1 == 0x1A && header.song_type == 0x10 {
+            Some(Confidence::High)
+        } else {
+            Some(Confidence::Medium)
+        }
+    }
+
+    pub fn load(ser_s3m_module: &[u8]) -> Result<S3mModule, Error> {
         let mut s3m = S3mModule {
             ..Default::default()
         };
+        let truncated = |structure, data: &[u8]| Error::Truncated {
+            format: Format::S3m,
+            structure,
+            offset: ser_s3m_module.len() - data.len(),
+        };
 
         // === load header
 
         let s = 96;
-        if ser_s3m_module.len() < s {
-            return Result::Err(DecodeError::Other("Not an S3M module?"));
+        if ser_s3m_module.len() < s || ser_s3m_module.get(0x2C..0x30) != Some(b"SCRM") {
+            return Err(Error::BadMagic {
+                format: Format::S3m,
+                offset: 0x2C,
+            });
         }
         let data = &ser_s3m_module[0..s];
         s3m.header =
-            bincode::serde::decode_from_slice::<S3mHeader, _>(data, bincode::config::legacy())?.0;
+            bincode::serde::decode_from_slice::<S3mHeader, _>(data, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::S3m, "header", 0, e))?
+                .0;
         let data = &ser_s3m_module[s..];
 
         if s3m.header.sig1 != 0x1A || s3m.header.song_type != 0x10 || s3m.header.sig2 != "SCRM" {
-            return Result::Err(DecodeError::Other("Not an S3M module?"));
+            return Err(Error::BadMagic {
+                format: Format::S3m,
+                offset: 0x1C,
+            });
         }
 
         // === positions offsets
 
         let s = s3m.header.order_count as usize;
         if data.len() < s {
-            return Result::Err(DecodeError::Other("Not an S3M module?"));
+            return Err(truncated("orders", data));
         }
         s3m.positions = data[0..s].to_vec();
         let data = &data[s..];
@@ -371,7 +594,7 @@ impl S3mModule {
 
         let s = 2 * s3m.header.instrument_count as usize;
         if data.len() < s {
-            return Result::Err(DecodeError::Other("Not an S3M module?"));
+            return Err(truncated("instrument pointers", data));
         }
         let sample_offsets: Vec<u32> = (&data[0..s])
             .chunks(2)
@@ -383,7 +606,7 @@ impl S3mModule {
 
         let s = 2 * s3m.header.pattern_count as usize;
         if data.len() < s {
-            return Result::Err(DecodeError::Other("Not an S3M module?"));
+            return Err(truncated("pattern pointers", data));
         }
         let pattern_offsets: Vec<u32> = (&data[0..s])
             .chunks(2)
@@ -407,17 +630,21 @@ impl S3mModule {
             if offset == 0 {
                 continue;
             }
-            let data = &ser_s3m_module[offset as usize..];
+            let data = ser_s3m_module.get(offset as usize..).unwrap_or_default();
+            if data.len() < 2 {
+                return Err(truncated("pattern", data));
+            }
             let len: u16 = data[0] as u16 | (data[1] as u16) << 8;
-            let data = &data[2..len as usize];
+            let Some(data) = data.get(2..len as usize) else {
+                return Err(truncated("pattern", data));
+            };
             let mut d2 = data;
             let mut pattern: Vec<Vec<PatternSlot>> = vec![];
             while d2.len() != 0 {
-                let (pss, next) = Self::process_pattern_row(d2)?;
+                let (pss, next) = Self::process_pattern_row(d2);
                 pattern.push(pss);
                 d2 = next;
             }
-            S3mEffect::update_pattern(&mut pattern);
             s3m.patterns.push(pattern);
         }
 
@@ -425,9 +652,9 @@ impl S3mModule {
     }
 
     // load one pattern row
-    fn process_pattern_row(data: &[u8]) -> Result<(Vec<PatternSlot>, &[u8]), DecodeError> {
+    fn process_pattern_row(data: &[u8]) -> (Vec<PatternSlot>, &[u8]) {
         let mut d2 = data;
-        let mut pss: Vec<PatternSlot> = vec![PatternSlot::default(); 32];
+        let mut pss: Vec<PatternSlot> = vec![S3mEffect::empty_slot(); 32];
         while d2.len() != 0 {
             match Self::decode_pattern_slot(d2) {
                 Some((channel, ps, next)) => {
@@ -440,7 +667,7 @@ impl S3mModule {
                 }
             }
         }
-        return Ok((pss, d2));
+        return (pss, d2);
     }
 
     // return channel, PatternSlot, next data
@@ -456,7 +683,7 @@ impl S3mModule {
 
         let channel = (what & 0x1F) as usize;
 
-        let mut slot: PatternSlot = PatternSlot::default();
+        let mut slot: PatternSlot = S3mEffect::empty_slot();
 
         // Note+Instr?
         if what & 0x20 != 0 {
@@ -490,11 +717,7 @@ impl S3mModule {
         // Volume?
         if what & 0x40 != 0 {
             if k < packed_data.len() {
-                slot.volume = packed_data[k];
-                // FIXME: we ignore 255 as default instrument volume
-                if slot.volume > 64 {
-                    slot.volume = 64;
-                }
+                slot.volume = packed_data[k].min(64);
                 k += 1;
             }
         }
@@ -526,12 +749,17 @@ impl S3mModule {
         module.default_bpm = self.header.tempo as usize;
         module.pattern_order = orders_helper::parse_orders(&self.positions);
 
+        let mut patterns = self.patterns.clone();
+        for pattern in patterns.iter_mut() {
+            S3mEffect::update_pattern(pattern);
+        }
+
         let mut im = ImportMemory::default();
         module.pattern = im.unpack_patterns(
             FrequencyType::AmigaFrequencies,
             MemoryType::S3m,
             &module.pattern_order,
-            &self.patterns,
+            &patterns,
         );
 
         for s3m_meta_instr in &self.instruments {
@@ -552,7 +780,7 @@ impl S3mModule {
                             LoopType::No
                         },
                         loop_start: pcm.loop_start,
-                        loop_length: pcm.loop_end - pcm.loop_start,
+                        loop_length: pcm.loop_end.saturating_sub(pcm.loop_start),
                         sustain_loop_flag: LoopType::No,
                         sustain_loop_start: 0,
                         sustain_loop_length: 0,
@@ -562,6 +790,7 @@ impl S3mModule {
                     // Create InstrDefault
                     let mut instr_def = InstrDefault::default();
                     instr_def.sample.push(Some(sample));
+                    instr_def.change_all_sample_for_pitch(0);
 
                     // Create Instrument
                     let mut instr = Instrument::default();
@@ -585,4 +814,410 @@ impl S3mModule {
 
         module
     }
+
+    /// Inverse of `decode_pattern_slot`
+    fn encode_pattern_slot(channel: usize, slot: &PatternSlot) -> Vec<u8> {
+        let mut what = channel as u8 & 0x1F;
+        let mut data: Vec<u8> = vec![];
+
+        if slot.note != Pitch::None || slot.instrument.is_some() {
+            what |= 0x20;
+            data.push(match slot.note {
+                Pitch::None => 255,
+                Pitch::Off | Pitch::Cut => 254,
+                note => {
+                    let note = note as u8;
+                    (note / 12) << 4 | (note % 12)
+                }
+            });
+            data.push(slot.instrument.map_or(0, |i| i as u8 + 1));
+        }
+
+        if slot.volume != 255 {
+            what |= 0x40;
+            data.push(slot.volume);
+        }
+
+        if slot.effect_type != 0 {
+            what |= 0x80;
+            data.push(slot.effect_type);
+            data.push(slot.effect_parameter);
+        }
+
+        if what & 0xE0 == 0 {
+            return vec![];
+        }
+        let mut output = vec![what];
+        output.extend(data);
+        output
+    }
+
+    /// Packed pattern with its length
+    fn pack_pattern(pattern: &[Vec<PatternSlot>]) -> Result<Vec<u8>, EncodeError> {
+        let mut data: Vec<u8> = vec![];
+        for row in pattern {
+            for (channel, slot) in row.iter().enumerate() {
+                data.extend(Self::encode_pattern_slot(channel, slot));
+            }
+            data.push(0); // EOL
+        }
+        // the length includes itself
+        let len = data.len() + 2;
+        if len > u16::MAX as usize {
+            return Err(EncodeError::Other("S3mModule: pattern too big"));
+        }
+        let mut output = (len as u16).to_le_bytes().to_vec();
+        output.extend(data);
+        Ok(output)
+    }
+
+    fn from_pattern(
+        index: usize,
+        pattern: &Pattern,
+        number_of_channels: usize,
+    ) -> Result<Vec<Vec<PatternSlot>>, EncodeError> {
+        if pattern.len() > 64 {
+            return Err(EncodeError::OtherString(format!(
+                "S3mModule: pattern {} has more than 64 rows",
+                index
+            )));
+        }
+
+        let mut slots: Vec<Vec<PatternSlot>> = vec![];
+        let mut memory = vec![ModXmMemory::default(); number_of_channels];
+        for (r, row) in pattern.iter().enumerate() {
+            let mut line = vec![S3mEffect::empty_slot(); number_of_channels];
+            for (c, tu) in row.iter().enumerate().take(number_of_channels) {
+                if let Pitch::None | Pitch::Off | Pitch::Cut = tu.note {
+                } else if tu.note as u8 > 95 {
+                    return Err(EncodeError::OtherString(format!(
+                        "S3mModule: pattern {}, row {}, channel {}: note {:?} is above B-7",
+                        index, r, c, tu.note
+                    )));
+                }
+                let (ps, lost) = S3mEffect::s3m_pack(&mut memory[c], tu);
+                if !lost.is_empty() {
+                    return Err(EncodeError::OtherString(format!(
+                        "S3mModule: pattern {}, row {}, channel {}: can't save {:?} {:?}",
+                        index, r, c, lost.effects, lost.global_effects
+                    )));
+                }
+                line[c] = ps;
+            }
+            slots.push(line);
+        }
+
+        // S3M patterns have 64 rows, add a pattern break to shorter ones
+        if slots.len() < 64 {
+            if slots.is_empty() {
+                slots.push(vec![S3mEffect::empty_slot(); number_of_channels]);
+            }
+            let rows = slots.len();
+            match slots[rows - 1].iter_mut().find(|ps| ps.effect_type == 0) {
+                Some(ps) => {
+                    ps.effect_type = 3; // C
+                    ps.effect_parameter = 0;
+                }
+                None => {
+                    return Err(EncodeError::OtherString(format!(
+                        "S3mModule: pattern {}, row {}: no room for a pattern break",
+                        index,
+                        rows - 1
+                    )))
+                }
+            }
+            slots.resize(64, vec![S3mEffect::empty_slot(); number_of_channels]);
+        }
+        Ok(slots)
+    }
+
+    fn from_instrument(instr: &Instrument) -> Result<S3mMetaInstrument, EncodeError> {
+        let (discriminator, filename, value, sample) = match &instr.instr_type {
+            InstrumentType::Empty => (
+                0,
+                String::new(),
+                S3mInstrument::PcmInstrument(S3mPcmInstr {
+                    title: instr.name.clone(),
+                    sig: "SCRS".to_string(),
+                    ..Default::default()
+                }),
+                None,
+            ),
+            InstrumentType::Default(id) => {
+                // S3M instruments have only one sample
+                let index = id.sample_for_pitch.iter().flatten().next().copied();
+                match id.sample.get(index.unwrap_or(0)).and_then(|s| s.as_ref()) {
+                    Some(sample) => (
+                        if sample.data.is_some() { 1 } else { 0 },
+                        sample.name.clone(),
+                        S3mInstrument::PcmInstrument(S3mPcmInstr::from_sample(&instr.name, sample)),
+                        sample.data.clone(),
+                    ),
+                    None => (
+                        0,
+                        String::new(),
+                        S3mInstrument::PcmInstrument(S3mPcmInstr {
+                            title: instr.name.clone(),
+                            sig: "SCRS".to_string(),
+                            ..Default::default()
+                        }),
+                        None,
+                    ),
+                }
+            }
+            InstrumentType::Opl(opl) => (
+                2,
+                String::new(),
+                S3mInstrument::OplInstrument(S3mOplInstr::from_instr_opl(&instr.name, opl)),
+                None,
+            ),
+            _ => {
+                return Err(EncodeError::Other(
+                    "S3mModule: only sample and OPL instruments can be saved",
+                ))
+            }
+        };
+        Ok(S3mMetaInstrument {
+            discriminator,
+            filename,
+            value,
+            sample,
+        })
+    }
+
+    /// Inverse of `to_module`.
+    /// Fails if the module exceeds S3M limits or uses effects which can't be saved.
+    pub fn from_module(module: &Module) -> Result<Self, EncodeError> {
+        if module.pattern.len() > 100 {
+            return Err(EncodeError::Other("S3mModule: more than 100 patterns"));
+        }
+        if module.instrument.len() > 99 {
+            return Err(EncodeError::Other("S3mModule: more than 99 instruments"));
+        }
+        if module
+            .pattern_order
+            .iter()
+            .flatten()
+            .any(|&o| o >= module.pattern.len())
+        {
+            return Err(EncodeError::Other("S3mModule: bad pattern order index"));
+        }
+        let positions = orders_helper::join_orders(&module.pattern_order);
+        if positions.len() >= 256 {
+            return Err(EncodeError::Other("S3mModule: more than 255 orders"));
+        }
+
+        let number_of_channels = module
+            .pattern
+            .iter()
+            .flatten()
+            .filter_map(|row| row.iter().rposition(|tu| !tu.is_empty()))
+            .max()
+            .map_or(1, |c| c + 1);
+        if number_of_channels > 32 {
+            return Err(EncodeError::Other("S3mModule: more than 32 channels"));
+        }
+
+        let mut patterns: Vec<Vec<Vec<PatternSlot>>> = vec![];
+        for (i, p) in module.pattern.iter().enumerate() {
+            patterns.push(Self::from_pattern(i, p, number_of_channels)?);
+        }
+
+        let mut instruments: Vec<S3mMetaInstrument> = vec![];
+        for instr in &module.instrument {
+            instruments.push(Self::from_instrument(instr)?);
+        }
+
+        // Adlib instruments are played on Adlib channels only
+        let mut channel_settings = [255u8; 32];
+        let mut pcm_channels = 0;
+        let mut opl_channels = 0;
+        for (c, setting) in channel_settings
+            .iter_mut()
+            .enumerate()
+            .take(number_of_channels)
+        {
+            let kinds: Vec<bool> = module
+                .pattern
+                .iter()
+                .flatten()
+                .filter_map(|row| row.get(c)?.instrument)
+                .filter_map(|i| module.instrument.get(i))
+                .map(|i| matches!(i.instr_type, InstrumentType::Opl(_)))
+                .collect();
+            let opl = kinds.first().copied().unwrap_or(false);
+            if kinds.iter().any(|&k| k != opl) {
+                return Err(EncodeError::OtherString(format!(
+                    "S3mModule: channel {} plays both sample and OPL instruments",
+                    c
+                )));
+            }
+            if opl {
+                // A1..A9
+                *setting = 16 + opl_channels;
+                opl_channels += 1;
+            } else {
+                // L1, R1, L2, R2...
+                *setting = (pcm_channels % 2) * 8 + pcm_channels / 2;
+                pcm_channels += 1;
+            }
+        }
+        if pcm_channels > 16 {
+            return Err(EncodeError::Other(
+                "S3mModule: more than 16 sample channels",
+            ));
+        }
+        if opl_channels > 9 {
+            return Err(EncodeError::Other("S3mModule: more than 9 OPL channels"));
+        }
+
+        let header = S3mHeader {
+            title: module.name.clone(),
+            sig1: 0x1A,
+            song_type: 0x10,
+            instrument_count: instruments.len() as u16,
+            pattern_count: patterns.len() as u16,
+            version: 0x1320,
+            // unsigned samples
+            sample_type: 2,
+            sig2: "SCRM".to_string(),
+            global_volume: 64,
+            speed: module.default_tempo.clamp(1, 255) as u8,
+            tempo: module.default_bpm.clamp(33, 255) as u8,
+            // stereo
+            master_volume: 0b1000_0000 | 48,
+            channel_settings,
+            ..Default::default()
+        };
+
+        Ok(Self {
+            header,
+            positions,
+            instruments,
+            patterns,
+        })
+    }
+
+    /// Header, orders, parapointers, instruments, patterns then samples data
+    pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        fn align(offset: usize) -> usize {
+            (offset + 15) & !15
+        }
+
+        // at least one 255 terminator, with an even count
+        let mut orders = self.positions.clone();
+        orders.resize((orders.len() + 2) & !1, 255);
+        self.header.order_count = orders.len() as u16;
+
+        let instruments_start =
+            align(96 + orders.len() + 2 * (self.instruments.len() + self.patterns.len()));
+
+        let mut patterns_offsets: Vec<usize> = vec![];
+        let mut patterns: Vec<u8> = vec![];
+        let patterns_start = instruments_start + 80 * self.instruments.len();
+        for p in &self.patterns {
+            patterns.resize(align(patterns_start + patterns.len()) - patterns_start, 0);
+            patterns_offsets.push(patterns_start + patterns.len());
+            patterns.extend(Self::pack_pattern(p)?);
+        }
+
+        let samples_start = patterns_start + patterns.len();
+        let mut samples: Vec<u8> = vec![];
+        for instr in self.instruments.iter_mut() {
+            if let (S3mInstrument::PcmInstrument(pcm), Some(data)) =
+                (&mut instr.value, &instr.sample)
+            {
+                samples.resize(align(samples_start + samples.len()) - samples_start, 0);
+                pcm.set_sample_offset(samples_start + samples.len());
+                samples.extend(S3mPcmInstr::save_sample_data(data));
+            }
+        }
+        if samples_start + samples.len() > 0xFF_FFFF << 4 {
+            return Err(EncodeError::Other("S3mModule: file too big"));
+        }
+        if patterns_offsets.iter().any(|&o| o > 0xFFFF << 4) {
+            return Err(EncodeError::Other("S3mModule: patterns too big"));
+        }
+
+        let mut output = bincode::serde::encode_to_vec(&self.header, bincode::config::legacy())?;
+        output.extend(&orders);
+        for i in 0..self.instruments.len() {
+            output.extend((((instruments_start + 80 * i) >> 4) as u16).to_le_bytes());
+        }
+        for o in &patterns_offsets {
+            output.extend(((o >> 4) as u16).to_le_bytes());
+        }
+        output.resize(instruments_start, 0);
+        for instr in &self.instruments {
+            output.extend(instr.save()?);
+        }
+        output.extend(patterns);
+        output.extend(samples);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn s3m_save_load() {
+        use crate::prelude::*;
+
+        let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let tu = &mut module.pattern[0][0][0];
+        tu.effects = vec![
+            TrackEffect::Volume {
+                value: 0.5,
+                tick: 0,
+            },
+            TrackEffect::Vibrato {
+                speed: 2.0 / 64.0,
+                depth: 3.0 / 16.0,
+            },
+        ];
+        tu.global_effects = vec![];
+        let tu = tu.clone();
+
+        let mut opl = InstrOpl::default();
+        opl.element.modulator.ksl = 2;
+        opl.element.modulator.attack = 15;
+        opl.element.carrier.total_level = 42;
+        opl.element.carrier.vib = true;
+        opl.volume = 60;
+        module.instrument.push(Instrument {
+            name: "adlib".into(),
+            instr_type: InstrumentType::Opl(opl),
+            ..Default::default()
+        });
+        module.pattern[0][1][1].note = Pitch::C4;
+        module.pattern[0][1][1].instrument = Some(module.instrument.len() - 1);
+
+        let data = module.save_s3m().unwrap();
+        let module2 = Module::load_s3m(&data).unwrap();
+        assert_eq!(data, module2.save_s3m().unwrap());
+        assert_eq!(module.pattern_order, module2.pattern_order);
+        assert_eq!(module.instrument.len(), module2.instrument.len());
+        let tu2 = &module2.pattern[0][0][0];
+        assert_eq!(tu2.note, tu.note);
+        // the effect column is decoded before the volume column
+        assert_eq!(tu2.effects[0], tu.effects[1]);
+        assert_eq!(tu2.effects[1], tu.effects[0]);
+        match &module2.instrument.last().unwrap().instr_type {
+            InstrumentType::Opl(opl2) => {
+                assert_eq!(opl2.element.modulator.ksl, 2);
+                assert_eq!(opl2.element.modulator.attack, 15);
+                assert_eq!(opl2.element.carrier.total_level, 42);
+                assert!(opl2.element.carrier.vib);
+                assert_eq!(opl2.volume, 60);
+            }
+            _ => panic!("not an OPL instrument"),
+        }
+
+        // S3M limits
+        let row = module.pattern[0][0].clone();
+        module.pattern[0].resize(65, row);
+        assert!(module.save_s3m().is_err());
+    }
 }
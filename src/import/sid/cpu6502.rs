@@ -0,0 +1,603 @@
+use alloc::{vec, vec::Vec};
+
+/// Status register flags
+const CARRY: u8 = 0x01;
+const ZERO: u8 = 0x02;
+const INTERRUPT: u8 = 0x04;
+const DECIMAL: u8 = 0x08;
+const BREAK: u8 = 0x10;
+const UNUSED: u8 = 0x20;
+const OVERFLOW: u8 = 0x40;
+const NEGATIVE: u8 = 0x80;
+
+/// SID registers, mirrored every 32 bytes
+const SID_START: u16 = 0xD400;
+const SID_END: u16 = 0xD800;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Adc,
+    And,
+    Asl,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Inc,
+    Inx,
+    Iny,
+    Jmp,
+    Jsr,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rol,
+    Ror,
+    Rti,
+    Rts,
+    Sbc,
+    Sec,
+    Sed,
+    Sei,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+}
+
+/// Documented NMOS 6502 opcodes
+fn decode(opcode: u8) -> Option<(Op, Mode)> {
+    use Mode::*;
+    use Op::*;
+
+    // aaabbb01: ALU group, the addressing mode is in bbb
+    if opcode & 0x03 == 0x01 && opcode != 0x89 {
+        let op = [Ora, And, Eor, Adc, Sta, Lda, Cmp, Sbc][(opcode >> 5) as usize];
+        let mode = [
+            IndirectX, ZeroPage, Immediate, Absolute, IndirectY, ZeroPageX, AbsoluteY, AbsoluteX,
+        ][((opcode >> 2) & 0x07) as usize];
+        return Some((op, mode));
+    }
+    Some(match opcode {
+        0x00 => (Brk, Implied),
+        0x06 => (Asl, ZeroPage),
+        0x08 => (Php, Implied),
+        0x0A => (Asl, Accumulator),
+        0x0E => (Asl, Absolute),
+        0x10 => (Bpl, Relative),
+        0x16 => (Asl, ZeroPageX),
+        0x18 => (Clc, Implied),
+        0x1E => (Asl, AbsoluteX),
+        0x20 => (Jsr, Absolute),
+        0x24 => (Bit, ZeroPage),
+        0x26 => (Rol, ZeroPage),
+        0x28 => (Plp, Implied),
+        0x2A => (Rol, Accumulator),
+        0x2C => (Bit, Absolute),
+        0x2E => (Rol, Absolute),
+        0x30 => (Bmi, Relative),
+        0x36 => (Rol, ZeroPageX),
+        0x38 => (Sec, Implied),
+        0x3E => (Rol, AbsoluteX),
+        0x40 => (Rti, Implied),
+        0x46 => (Lsr, ZeroPage),
+        0x48 => (Pha, Implied),
+        0x4A => (Lsr, Accumulator),
+        0x4C => (Jmp, Absolute),
+        0x4E => (Lsr, Absolute),
+        0x50 => (Bvc, Relative),
+        0x56 => (Lsr, ZeroPageX),
+        0x58 => (Cli, Implied),
+        0x5E => (Lsr, AbsoluteX),
+        0x60 => (Rts, Implied),
+        0x66 => (Ror, ZeroPage),
+        0x68 => (Pla, Implied),
+        0x6A => (Ror, Accumulator),
+        0x6C => (Jmp, Indirect),
+        0x6E => (Ror, Absolute),
+        0x70 => (Bvs, Relative),
+        0x76 => (Ror, ZeroPageX),
+        0x78 => (Sei, Implied),
+        0x7E => (Ror, AbsoluteX),
+        0x84 => (Sty, ZeroPage),
+        0x86 => (Stx, ZeroPage),
+        0x88 => (Dey, Implied),
+        0x8A => (Txa, Implied),
+        0x8C => (Sty, Absolute),
+        0x8E => (Stx, Absolute),
+        0x90 => (Bcc, Relative),
+        0x94 => (Sty, ZeroPageX),
+        0x96 => (Stx, ZeroPageY),
+        0x98 => (Tya, Implied),
+        0x9A => (Txs, Implied),
+        0xA0 => (Ldy, Immediate),
+        0xA2 => (Ldx, Immediate),
+        0xA4 => (Ldy, ZeroPage),
+        0xA6 => (Ldx, ZeroPage),
+        0xA8 => (Tay, Implied),
+        0xAA => (Tax, Implied),
+        0xAC => (Ldy, Absolute),
+        0xAE => (Ldx, Absolute),
+        0xB0 => (Bcs, Relative),
+        0xB4 => (Ldy, ZeroPageX),
+        0xB6 => (Ldx, ZeroPageY),
+        0xB8 => (Clv, Implied),
+        0xBA => (Tsx, Implied),
+        0xBC => (Ldy, AbsoluteX),
+        0xBE => (Ldx, AbsoluteY),
+        0xC0 => (Cpy, Immediate),
+        0xC4 => (Cpy, ZeroPage),
+        0xC6 => (Dec, ZeroPage),
+        0xC8 => (Iny, Implied),
+        0xCA => (Dex, Implied),
+        0xCC => (Cpy, Absolute),
+        0xCE => (Dec, Absolute),
+        0xD0 => (Bne, Relative),
+        0xD6 => (Dec, ZeroPageX),
+        0xD8 => (Cld, Implied),
+        0xDE => (Dec, AbsoluteX),
+        0xE0 => (Cpx, Immediate),
+        0xE4 => (Cpx, ZeroPage),
+        0xE6 => (Inc, ZeroPage),
+        0xE8 => (Inx, Implied),
+        0xEA => (Nop, Implied),
+        0xEC => (Cpx, Absolute),
+        0xEE => (Inc, Absolute),
+        0xF0 => (Beq, Relative),
+        0xF6 => (Inc, ZeroPageX),
+        0xF8 => (Sed, Implied),
+        0xFE => (Inc, AbsoluteX),
+        _ => return None,
+    })
+}
+
+/// NMOS 6502 with 64 KB of RAM, enough to run C64 music routines.
+///
+/// SID writes are recorded, VIC raster and SID oscillator 3 reads return
+/// moving values so that wait loops and random generators end
+#[derive(Clone, Debug)]
+pub struct Cpu6502 {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    /// Status register
+    pub p: u8,
+    pub memory: Vec<u8>,
+    /// `(register, value)` writes to the SID, in order
+    pub sid_writes: Vec<(u8, u8)>,
+    raster: u16,
+    random: u16,
+}
+
+impl Default for Cpu6502 {
+    fn default() -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xFF,
+            pc: 0,
+            p: UNUSED | INTERRUPT,
+            memory: vec![0; 0x10000],
+            sid_writes: vec![],
+            raster: 0,
+            random: 0xACE1,
+        }
+    }
+}
+
+impl Cpu6502 {
+    /// Return address of the calls, never executed
+    const RETURN: u16 = 0xFFFF;
+
+    /// Copy `data` at `address`, truncated at the end of the memory
+    pub fn load(&mut self, address: u16, data: &[u8]) {
+        let start = address as usize;
+        let end = (start + data.len()).min(0x10000);
+        self.memory[start..end].copy_from_slice(&data[..end - start]);
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        match address {
+            0xD011 => ((self.raster >> 1) & 0x80) as u8 | (self.memory[0xD011] & 0x7F),
+            0xD012 => {
+                self.raster = (self.raster + 1) % 312;
+                self.raster as u8
+            }
+            // oscillator 3, often used as a random generator
+            0xD41B => {
+                let bit =
+                    (self.random ^ (self.random >> 2) ^ (self.random >> 3) ^ (self.random >> 5))
+                        & 1;
+                self.random = (self.random >> 1) | (bit << 15);
+                self.random as u8
+            }
+            _ => self.memory[address as usize],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if (SID_START..SID_END).contains(&address) {
+            self.sid_writes.push(((address & 0x1F) as u8, value));
+            self.memory[(SID_START | (address & 0x1F)) as usize] = value;
+        } else {
+            self.memory[address as usize] = value;
+        }
+    }
+
+    fn read_word(&mut self, address: u16) -> u16 {
+        self.read(address) as u16 | (self.read(address.wrapping_add(1)) as u16) << 8
+    }
+
+    /// Word in the zero page or in a page, the high byte does not cross the page
+    fn read_word_in_page(&mut self, address: u16) -> u16 {
+        let high = address & 0xFF00 | (address.wrapping_add(1) & 0x00FF);
+        self.read(address) as u16 | (self.read(high) as u16) << 8
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let value = self.read_word(self.pc);
+        self.pc = self.pc.wrapping_add(2);
+        value
+    }
+
+    fn push(&mut self, value: u8) {
+        self.memory[0x100 | self.sp as usize] = value;
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.memory[0x100 | self.sp as usize]
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.p |= flag;
+        } else {
+            self.p &= !flag;
+        }
+    }
+
+    fn set_nz(&mut self, value: u8) -> u8 {
+        self.set_flag(ZERO, value == 0);
+        self.set_flag(NEGATIVE, value & 0x80 != 0);
+        value
+    }
+
+    fn operand_address(&mut self, mode: Mode) -> u16 {
+        match mode {
+            Mode::Immediate => {
+                let address = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                address
+            }
+            Mode::ZeroPage => self.fetch() as u16,
+            Mode::ZeroPageX => self.fetch().wrapping_add(self.x) as u16,
+            Mode::ZeroPageY => self.fetch().wrapping_add(self.y) as u16,
+            Mode::Absolute => self.fetch_word(),
+            Mode::AbsoluteX => self.fetch_word().wrapping_add(self.x as u16),
+            Mode::AbsoluteY => self.fetch_word().wrapping_add(self.y as u16),
+            Mode::Indirect => {
+                let pointer = self.fetch_word();
+                self.read_word_in_page(pointer)
+            }
+            Mode::IndirectX => {
+                let pointer = self.fetch().wrapping_add(self.x) as u16;
+                self.read_word_in_page(pointer)
+            }
+            Mode::IndirectY => {
+                let pointer = self.fetch() as u16;
+                self.read_word_in_page(pointer).wrapping_add(self.y as u16)
+            }
+            Mode::Relative => {
+                let offset = self.fetch() as i8;
+                self.pc.wrapping_add(offset as u16)
+            }
+            Mode::Implied | Mode::Accumulator => 0,
+        }
+    }
+
+    fn adc(&mut self, value: u8) {
+        let carry = (self.p & CARRY) as u16;
+        let sum = self.a as u16 + value as u16 + carry;
+        self.set_flag(
+            OVERFLOW,
+            (!(self.a ^ value) & (self.a ^ sum as u8) & 0x80) != 0,
+        );
+        if self.p & DECIMAL != 0 {
+            let mut low = (self.a & 0x0F) as u16 + (value & 0x0F) as u16 + carry;
+            if low > 9 {
+                low += 6;
+            }
+            let mut high = (self.a >> 4) as u16 + (value >> 4) as u16 + (low > 0x0F) as u16;
+            if high > 9 {
+                high += 6;
+            }
+            self.set_flag(CARRY, high > 0x0F);
+            self.a = ((high << 4) | (low & 0x0F)) as u8;
+            self.set_flag(ZERO, sum as u8 == 0);
+            self.set_flag(NEGATIVE, self.a & 0x80 != 0);
+        } else {
+            self.set_flag(CARRY, sum > 0xFF);
+            self.a = self.set_nz(sum as u8);
+        }
+    }
+
+    fn sbc(&mut self, value: u8) {
+        if self.p & DECIMAL != 0 {
+            let borrow = (self.p & CARRY == 0) as i16;
+            let diff = self.a as i16 - value as i16 - borrow;
+            let mut low = (self.a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+            let mut high = (self.a >> 4) as i16 - (value >> 4) as i16;
+            if low < 0 {
+                low -= 6;
+                high -= 1;
+            }
+            if high < 0 {
+                high -= 6;
+            }
+            self.set_flag(
+                OVERFLOW,
+                ((self.a ^ value) & (self.a ^ diff as u8) & 0x80) != 0,
+            );
+            self.set_flag(CARRY, diff >= 0);
+            self.set_nz(diff as u8);
+            self.a = ((high << 4) | (low & 0x0F)) as u8;
+        } else {
+            self.adc(!value);
+        }
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.set_flag(CARRY, register >= value);
+        self.set_nz(register.wrapping_sub(value));
+    }
+
+    fn branch(&mut self, condition: bool, target: u16) {
+        if condition {
+            self.pc = target;
+        }
+    }
+
+    /// Read-modify-write on the accumulator or in memory
+    fn modify(&mut self, mode: Mode, address: u16, f: impl Fn(&mut Self, u8) -> u8) {
+        if mode == Mode::Accumulator {
+            self.a = f(self, self.a);
+        } else {
+            let value = self.read(address);
+            let value = f(self, value);
+            self.write(address, value);
+        }
+    }
+
+    /// Run one instruction, false on `BRK` or an unknown opcode
+    pub fn step(&mut self) -> bool {
+        let opcode = self.fetch();
+        let Some((op, mode)) = decode(opcode) else {
+            return false;
+        };
+        let address = self.operand_address(mode);
+        match op {
+            Op::Adc => {
+                let value = self.read(address);
+                self.adc(value);
+            }
+            Op::And => {
+                let value = self.read(address);
+                self.a = self.set_nz(self.a & value);
+            }
+            Op::Asl => self.modify(mode, address, |cpu, v| {
+                cpu.set_flag(CARRY, v & 0x80 != 0);
+                cpu.set_nz(v << 1)
+            }),
+            Op::Bcc => self.branch(self.p & CARRY == 0, address),
+            Op::Bcs => self.branch(self.p & CARRY != 0, address),
+            Op::Beq => self.branch(self.p & ZERO != 0, address),
+            Op::Bmi => self.branch(self.p & NEGATIVE != 0, address),
+            Op::Bne => self.branch(self.p & ZERO == 0, address),
+            Op::Bpl => self.branch(self.p & NEGATIVE == 0, address),
+            Op::Bvc => self.branch(self.p & OVERFLOW == 0, address),
+            Op::Bvs => self.branch(self.p & OVERFLOW != 0, address),
+            Op::Bit => {
+                let value = self.read(address);
+                self.set_flag(ZERO, self.a & value == 0);
+                self.set_flag(NEGATIVE, value & 0x80 != 0);
+                self.set_flag(OVERFLOW, value & 0x40 != 0);
+            }
+            Op::Brk => return false,
+            Op::Clc => self.set_flag(CARRY, false),
+            Op::Cld => self.set_flag(DECIMAL, false),
+            Op::Cli => self.set_flag(INTERRUPT, false),
+            Op::Clv => self.set_flag(OVERFLOW, false),
+            Op::Cmp => {
+                let value = self.read(address);
+                self.compare(self.a, value);
+            }
+            Op::Cpx => {
+                let value = self.read(address);
+                self.compare(self.x, value);
+            }
+            Op::Cpy => {
+                let value = self.read(address);
+                self.compare(self.y, value);
+            }
+            Op::Dec => self.modify(mode, address, |cpu, v| cpu.set_nz(v.wrapping_sub(1))),
+            Op::Dex => self.x = self.set_nz(self.x.wrapping_sub(1)),
+            Op::Dey => self.y = self.set_nz(self.y.wrapping_sub(1)),
+            Op::Eor => {
+                let value = self.read(address);
+                self.a = self.set_nz(self.a ^ value);
+            }
+            Op::Inc => self.modify(mode, address, |cpu, v| cpu.set_nz(v.wrapping_add(1))),
+            Op::Inx => self.x = self.set_nz(self.x.wrapping_add(1)),
+            Op::Iny => self.y = self.set_nz(self.y.wrapping_add(1)),
+            Op::Jmp => self.pc = address,
+            Op::Jsr => {
+                let ret = self.pc.wrapping_sub(1);
+                self.push((ret >> 8) as u8);
+                self.push(ret as u8);
+                self.pc = address;
+            }
+            Op::Lda => {
+                let value = self.read(address);
+                self.a = self.set_nz(value);
+            }
+            Op::Ldx => {
+                let value = self.read(address);
+                self.x = self.set_nz(value);
+            }
+            Op::Ldy => {
+                let value = self.read(address);
+                self.y = self.set_nz(value);
+            }
+            Op::Lsr => self.modify(mode, address, |cpu, v| {
+                cpu.set_flag(CARRY, v & 0x01 != 0);
+                cpu.set_nz(v >> 1)
+            }),
+            Op::Nop => {}
+            Op::Ora => {
+                let value = self.read(address);
+                self.a = self.set_nz(self.a | value);
+            }
+            Op::Pha => self.push(self.a),
+            Op::Php => self.push(self.p | BREAK | UNUSED),
+            Op::Pla => {
+                let value = self.pull();
+                self.a = self.set_nz(value);
+            }
+            Op::Plp => self.p = self.pull() & !BREAK | UNUSED,
+            Op::Rol => self.modify(mode, address, |cpu, v| {
+                let carry = cpu.p & CARRY;
+                cpu.set_flag(CARRY, v & 0x80 != 0);
+                cpu.set_nz(v << 1 | carry)
+            }),
+            Op::Ror => self.modify(mode, address, |cpu, v| {
+                let carry = (cpu.p & CARRY) << 7;
+                cpu.set_flag(CARRY, v & 0x01 != 0);
+                cpu.set_nz(v >> 1 | carry)
+            }),
+            Op::Rti => {
+                self.p = self.pull() & !BREAK | UNUSED;
+                self.pc = self.pull() as u16 | (self.pull() as u16) << 8;
+            }
+            Op::Rts => {
+                let ret = self.pull() as u16 | (self.pull() as u16) << 8;
+                self.pc = ret.wrapping_add(1);
+            }
+            Op::Sbc => {
+                let value = self.read(address);
+                self.sbc(value);
+            }
+            Op::Sec => self.set_flag(CARRY, true),
+            Op::Sed => self.set_flag(DECIMAL, true),
+            Op::Sei => self.set_flag(INTERRUPT, true),
+            Op::Sta => self.write(address, self.a),
+            Op::Stx => self.write(address, self.x),
+            Op::Sty => self.write(address, self.y),
+            Op::Tax => self.x = self.set_nz(self.a),
+            Op::Tay => self.y = self.set_nz(self.a),
+            Op::Tsx => self.x = self.set_nz(self.sp),
+            Op::Txa => self.a = self.set_nz(self.x),
+            Op::Txs => self.sp = self.x,
+            Op::Tya => self.a = self.set_nz(self.y),
+        }
+        true
+    }
+
+    /// Run until the return address is reached, false on `BRK`,
+    /// an unknown opcode or after `max_steps` instructions
+    fn run(&mut self, max_steps: usize) -> bool {
+        for _ in 0..max_steps {
+            if self.pc == Self::RETURN {
+                return true;
+            }
+            if !self.step() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// `JSR address` with `a` in the accumulator, until its `RTS`
+    pub fn call(&mut self, address: u16, a: u8, max_steps: usize) -> bool {
+        self.a = a;
+        self.x = 0;
+        self.y = 0;
+        let ret = Self::RETURN.wrapping_sub(1);
+        self.push((ret >> 8) as u8);
+        self.push(ret as u8);
+        self.pc = address;
+        self.run(max_steps)
+    }
+
+    /// Interrupt handler at `address`, until its `RTI`.
+    /// With `kernal`, registers are pushed like the C64 KERNAL before `JMP ($0314)`
+    pub fn interrupt(&mut self, address: u16, kernal: bool, max_steps: usize) -> bool {
+        self.push((Self::RETURN >> 8) as u8);
+        self.push(Self::RETURN as u8);
+        self.push(self.p & !BREAK | UNUSED);
+        self.set_flag(INTERRUPT, true);
+        if kernal {
+            self.push(self.a);
+            self.push(self.x);
+            self.push(self.y);
+        }
+        self.pc = address;
+        self.run(max_steps)
+    }
+}
@@ -1,4 +1,6 @@
 use crate::{instr_sid::SidVoice, prelude::*};
+
+use super::sound_fx::SoundFx;
 use alloc::format;
 use alloc::string::String;
 use alloc::{vec, vec::Vec};
@@ -222,6 +224,25 @@ impl InstrHelper {
         idst
     }
 
+    pub fn soundfxs_to_instruments(sfxs: &[SoundFx]) -> Vec<Instrument> {
+        sfxs.iter()
+            .enumerate()
+            .map(|(i, sfx)| {
+                let name = format!("SoundFx {:02}", i);
+                let mut instr = InstrDefault {
+                    sample: vec![Some(sfx.to_sample(name.clone()))],
+                    ..Default::default()
+                };
+                instr.change_all_sample_for_pitch(0);
+                Instrument {
+                    name,
+                    instr_type: InstrumentType::Default(instr),
+                    muted: false,
+                }
+            })
+            .collect()
+    }
+
     pub fn irss_to_instruments(irss: &Vec<InstrRobSid>, original: bool) -> Vec<Instrument> {
         let mut idst: Vec<Instrument> = vec![];
         for isrc in irss {
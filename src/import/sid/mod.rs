@@ -1,7 +1,10 @@
 #![forbid(unsafe_code)]
 
+pub mod cpu6502;
 pub(crate) mod instr_helper;
 pub(crate) mod one_sid;
 pub(crate) mod pattern_helper;
+pub mod psid_header;
+pub mod sid_capture;
 pub mod sid_module;
 pub(crate) mod sound_fx;
@@ -1,18 +1,23 @@
+use crate::error::Error;
+use crate::format::Format;
 use crate::{instr_robsid::RobEffects, instr_sid::SidVoice, prelude::*};
+use alloc::borrow::Cow;
 use alloc::{vec, vec::Vec};
 
 use super::pattern_helper::PatternHelper;
+use super::psid_header::PsidHeader;
 use super::sid_module::SidModule;
 use super::sound_fx::SoundFx;
 
 #[derive(Clone, Debug)]
 pub struct OneSid {
-    pub song: &'static [u8],
-    pub name: &'static str,
-    pub author: &'static str,
-    pub copyright: &'static str,
+    pub song: Cow<'static, [u8]>,
+    pub name: Cow<'static, str>,
+    pub author: Cow<'static, str>,
+    pub copyright: Cow<'static, str>,
     version: usize,
     load_adress: usize,
+    data_offset: usize, // file offset of `load_adress`
     song_track_qty: usize,
     song_list_offset: usize,
     song_list_qty: usize,
@@ -35,8 +40,8 @@ impl OneSid {
         // ********* CHANNELS
         let mut channels_ptr: Vec<usize> = vec![];
         for i in 0..self.song_list_qty {
-            let offset =
-                126 + self.song_list_offset - self.load_adress + i * 2 * self.song_track_qty;
+            let offset = self.data_offset + self.song_list_offset - self.load_adress
+                + i * 2 * self.song_track_qty;
             for j in 0..self.song_track_qty {
                 channels_ptr.push(
                     self.song[offset + j] as usize
@@ -47,7 +52,7 @@ impl OneSid {
 
         let mut channels: Vec<Vec<u8>> = vec![];
         for offset in &channels_ptr {
-            let file_offset = *offset as usize + 126 - self.load_adress;
+            let file_offset = *offset as usize + self.data_offset - self.load_adress;
             let mut tracks: Vec<u8> = vec![];
             let mut j = 0;
             while self.song[file_offset + j] & 0x80 == 0 {
@@ -84,8 +89,8 @@ impl OneSid {
         // ********* TRACKS
         let mut tracks_ptr: Vec<u16> = vec![];
         for i in 0..self.patt_qty {
-            let offset_low_file = 126 + self.patt_ptl_offset - self.load_adress;
-            let offset_high_file = 126 + self.patt_pth_offset - self.load_adress;
+            let offset_low_file = self.data_offset + self.patt_ptl_offset - self.load_adress;
+            let offset_high_file = self.data_offset + self.patt_pth_offset - self.load_adress;
             let offset: u16 = self.song[offset_low_file + i] as u16
                 | (self.song[offset_high_file + i] as u16) << 8;
             tracks_ptr.push(offset);
@@ -93,7 +98,7 @@ impl OneSid {
 
         let mut tracks: Vec<Vec<u8>> = vec![];
         for i in 0..self.patt_qty {
-            let file_offset = 126 + tracks_ptr[i] as usize - self.load_adress;
+            let file_offset = self.data_offset + tracks_ptr[i] as usize - self.load_adress;
             let mut track: Vec<u8> = vec![];
             let mut j = 0;
             loop {
@@ -110,7 +115,7 @@ impl OneSid {
 
         let mut instruments: Vec<InstrRobSid> = vec![];
         for i in 0..self.instr_qty {
-            let file_offset = 126 + self.instr_offset - self.load_adress;
+            let file_offset = self.data_offset + self.instr_offset - self.load_adress;
             let start = file_offset + i * 8;
 
             let mut voice = SidVoice::default();
@@ -162,7 +167,7 @@ impl OneSid {
             }
 
             re.skydive_config_if = self.skydive_v1_when as u8;
-            re.skydive_config_add = self.skydive_v1_add as u8;
+            re.skydive_config_add = (self.skydive_v1_add >> 8) as i8 as u8;
 
             let mut irsid = InstrRobSid::default();
             irsid.sid = isid;
@@ -174,7 +179,7 @@ impl OneSid {
         // ********* SOUNDFX
         let mut sfxs: Vec<SoundFx> = vec![];
         for i in 0..self.fx_v1_qty {
-            let file_offset = 126 + self.fx_v1_offset - self.load_adress;
+            let file_offset = self.data_offset + self.fx_v1_offset - self.load_adress;
             let start = file_offset + i * 16;
 
             // play current music freq at end of incdec_counter counter
@@ -201,7 +206,7 @@ impl OneSid {
 
             let mut voice1 = SidVoice::default();
             voice1.pw = self.song[start + 10] as u16 | (self.song[start + 11] as u16) << 8;
-            voice0.update_from_ctrl_register(self.song[start + 12]);
+            voice1.update_from_ctrl_register(self.song[start + 12]);
             voice1.ad = self.song[start + 13];
             voice1.sr = self.song[start + 14];
 
@@ -225,19 +230,243 @@ impl OneSid {
             pattern_helper: PatternHelper::new(self.version, songs, channels, tracks),
             instruments,
             soundfx: sfxs,
+            header: None,
         }
     }
 }
 
+/// Wildcard in a driver signature
+const ANY: u16 = 0x100;
+
+impl OneSid {
+    /// Positions of a 6502 code `signature` in `mem`
+    fn find_all<'a>(mem: &'a [u8], signature: &'a [u16]) -> impl Iterator<Item = usize> + 'a {
+        mem.windows(signature.len())
+            .enumerate()
+            .filter(move |(_, w)| {
+                w.iter()
+                    .zip(signature)
+                    .all(|(&b, &s)| s == ANY || b as u16 == s)
+            })
+            .map(|(i, _)| i)
+    }
+
+    fn word(mem: &[u8], i: usize) -> usize {
+        mem[i] as usize | (mem[i + 1] as usize) << 8
+    }
+
+    /// Find the Rob Hubbard driver tables in a PSID or RSID file
+    pub fn load(data: &[u8], header: &PsidHeader) -> Result<Self, Error> {
+        let (load_adress, data_offset) = header.get_load(data)?;
+        if data.len() < data_offset {
+            return Err(Error::Truncated {
+                format: Format::Sid,
+                structure: "C64 data",
+                offset: data_offset,
+            });
+        }
+        let mem = &data[data_offset..];
+        let end = load_adress + mem.len();
+        let in_mem = |addr: usize, len: usize| addr >= load_adress && addr + len <= end;
+        const NOT_FOUND: Error = Error::Unsupported {
+            format: Format::Sid,
+            what: "player, not a Rob Hubbard driver",
+            offset: 0,
+        };
+
+        // TAY, LDA ptl,Y, STA zp, LDA pth,Y, STA zp+1
+        let (patt_ptl_offset, patt_pth_offset, zp) = Self::find_all(
+            mem,
+            &[0xA8, 0xB9, ANY, ANY, 0x85, ANY, 0xB9, ANY, ANY, 0x85, ANY],
+        )
+        .find(|&i| mem[i + 10] == mem[i + 5].wrapping_add(1))
+        .map(|i| (Self::word(mem, i + 2), Self::word(mem, i + 7), mem[i + 5]))
+        .ok_or(NOT_FOUND)?;
+        if patt_pth_offset <= patt_ptl_offset {
+            return Err(NOT_FOUND);
+        }
+
+        // TAX, LDA songs,X, STA x,Y or BNE, LDA songs,X, STA zp.
+        // The song list is just before the tracks pointers.
+        let song_list_offset = Self::find_all(mem, &[0xAA, 0xBD, ANY, ANY, 0x99])
+            .map(|i| Self::word(mem, i + 2))
+            .chain(
+                Self::find_all(mem, &[0xD0, ANY, 0xBD, ANY, ANY, 0x85])
+                    .map(|i| Self::word(mem, i + 3)),
+            )
+            .filter(|&a| {
+                a < patt_ptl_offset
+                    && patt_ptl_offset - a <= 2 * 3 * 64
+                    && ((patt_ptl_offset - a) % 6 == 0 || (patt_ptl_offset - a) % 4 == 0)
+            })
+            .max()
+            .ok_or(NOT_FOUND)?;
+        let song_track_qty = if (patt_ptl_offset - song_list_offset) % 6 == 0 {
+            3
+        } else {
+            2
+        };
+
+        // ASL, ASL, ASL, TAX, LDA instr+2,X
+        let instr_offset = Self::find_all(mem, &[0x0A, 0x0A, 0x0A, 0xAA, 0xBD, ANY, ANY])
+            .find_map(|i| Self::word(mem, i + 5).checked_sub(2))
+            .ok_or(NOT_FOUND)?;
+
+        // ASL, ASL, ASL, ASL, TAY, LDA fx,Y
+        let fx_v1_offset = Self::find_all(mem, &[0x0A, 0x0A, 0x0A, 0x0A, 0xA8, 0xB9, ANY, ANY])
+            .map(|i| Self::word(mem, i + 6))
+            .next();
+        // BEQ, DEC x, LDA fx,Y, JMP
+        let fx_v2_offset = Self::find_all(mem, &[0xF0, 0x09, 0xDE, ANY, ANY, 0xB9, ANY, ANY, 0x4C])
+            .map(|i| Self::word(mem, i + 6))
+            .min();
+
+        // tables size are not in the driver: use the next table
+        let tables = [
+            song_list_offset,
+            patt_ptl_offset,
+            patt_pth_offset,
+            instr_offset,
+            fx_v1_offset.unwrap_or(0),
+            fx_v2_offset.unwrap_or(0),
+        ];
+        let next_table = |addr: usize| {
+            tables
+                .iter()
+                .copied()
+                .filter(|&t| t > addr)
+                .min()
+                .unwrap_or(end)
+                .min(end)
+        };
+        let instr_qty = next_table(instr_offset).saturating_sub(instr_offset) / 8;
+        let fx_v1_qty = fx_v1_offset.map_or(0, |a| next_table(a).saturating_sub(a) / 16);
+        let fx_v2_qty = fx_v2_offset.map_or(0, |_| instr_qty);
+        if !in_mem(instr_offset, 8 * instr_qty)
+            || !in_mem(fx_v1_offset.unwrap_or(end), 16 * fx_v1_qty)
+            || !in_mem(song_list_offset, patt_pth_offset - song_list_offset)
+            || !in_mem(patt_pth_offset, patt_pth_offset - patt_ptl_offset)
+        {
+            return Err(NOT_FOUND);
+        }
+
+        // keep valid tracks and songs only
+        let patt_qty = (0..patt_pth_offset - patt_ptl_offset)
+            .take_while(|&i| {
+                let ptr = mem[patt_ptl_offset - load_adress + i] as usize
+                    | (mem[patt_pth_offset - load_adress + i] as usize) << 8;
+                in_mem(ptr, 1) && mem[ptr - load_adress..].contains(&0xFF)
+            })
+            .count();
+        let channel_ok = |ptr: usize| {
+            in_mem(ptr, 1) && {
+                let channel = &mem[ptr - load_adress..];
+                channel[0] & 0x80 == 0 && channel.iter().any(|&b| b & 0x80 != 0)
+            }
+        };
+        let song_list_qty = (0..(patt_ptl_offset - song_list_offset) / (2 * song_track_qty))
+            .take_while(|&i| {
+                let o = song_list_offset - load_adress + i * 2 * song_track_qty;
+                (o..o + song_track_qty)
+                    .all(|j| channel_ok(mem[j] as usize | (mem[j + song_track_qty] as usize) << 8))
+            })
+            .count();
+        if patt_qty == 0 || song_list_qty == 0 {
+            return Err(Error::Unsupported {
+                format: Format::Sid,
+                what: "Rob Hubbard driver without song",
+                offset: data_offset,
+            });
+        }
+
+        let [lo5, hi5] = ((instr_offset + 5) as u16).to_le_bytes();
+        let version = if Self::find_all(mem, &[0xB9, lo5 as u16, hi5 as u16, 0x8D, ANY, ANY, 0xF0])
+            .next()
+            .is_some()
+        {
+            10 // LDA instr+5,Y, STA, BEQ: vibrato depth is the whole byte
+        } else if Self::find_all(mem, &[0x29, 0x60, 0xC9, 0x60, 0xD0])
+            .next()
+            .is_some()
+        {
+            30 // AND #$60, CMP #$60, BNE: pattern loop in channels
+        } else if Self::find_all(mem, &[0xC8, 0xB1, zp as u16, 0x10])
+            .next()
+            .is_some()
+        {
+            20 // INY, LDA (zp),Y, BPL: portamento on two bytes
+        } else {
+            15 // instrument only
+        };
+
+        // DEC speed, BPL, LDA resetspd, STA speed
+        let resetspd = Self::find_all(
+            mem,
+            &[0xCE, ANY, ANY, 0x10, ANY, 0xAD, ANY, ANY, 0x8D, ANY, ANY],
+        )
+        .find(|&i| mem[i + 1..i + 3] == mem[i + 9..i + 11])
+        .map(|i| Self::word(mem, i + 6))
+        .filter(|&a| in_mem(a, 1))
+        .map_or(1, |a| mem[a - load_adress] as usize);
+
+        // AND #$02, BEQ: skydive block, then the frequency high byte is changed
+        let mut skydive_v1_when: usize = 0;
+        let mut skydive_v1_add: isize = 0;
+        if let Some(i) = Self::find_all(mem, &[0x29, 0x02, 0xF0, ANY]).next() {
+            let block = &mem[i + 4..(i + 4 + mem[i + 3] as usize).min(mem.len())];
+            // AND #$1F, CMP #when+1, BCC
+            if let Some(j) = Self::find_all(block, &[0x29, 0x1F, 0xC9, ANY, 0x90]).next() {
+                skydive_v1_when = block[j + 3].saturating_sub(1) as usize;
+            }
+            let has = |signature: &[u16]| Self::find_all(block, signature).next().is_some();
+            skydive_v1_add = if has(&[0xF0, ANY, 0xDE]) {
+                -256
+            } else if has(&[0xF0, ANY, 0xFE, ANY, ANY, 0xFE]) {
+                512
+            } else if has(&[0xF0, ANY, 0xFE]) {
+                256
+            } else {
+                0
+            };
+        }
+
+        Ok(OneSid {
+            song: Cow::Owned(data.to_vec()),
+            name: Cow::Owned(header.name.clone()),
+            author: Cow::Owned(header.author.clone()),
+            copyright: Cow::Owned(header.copyright.clone()),
+            version,
+            load_adress,
+            data_offset,
+            song_track_qty,
+            song_list_offset,
+            song_list_qty,
+            patt_ptl_offset,
+            patt_pth_offset,
+            patt_qty,
+            instr_offset,
+            instr_qty,
+            fx_v1_offset: fx_v1_offset.unwrap_or(0),
+            fx_v1_qty,
+            fx_v2_offset: fx_v2_offset.unwrap_or(0),
+            fx_v2_qty,
+            resetspd,
+            skydive_v1_when,
+            skydive_v1_add,
+        })
+    }
+}
+
 impl OneSid {
     pub fn get_sid_commando() -> Self {
         OneSid {
-            song: include_bytes!("songs/commando.sid"),
-            name: "Commando",
-            author: "Rob Hubbard",
-            copyright: "1985 Elite",
+            song: Cow::Borrowed(include_bytes!("songs/commando.sid")),
+            name: Cow::Borrowed("Commando"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 Elite"),
             version: 10,
             load_adress: 0x5000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0x56FF,
             song_list_qty: 3,
@@ -258,12 +487,13 @@ impl OneSid {
 
     pub fn get_sid_crazy_comets() -> Self {
         OneSid {
-            song: include_bytes!("songs/crazy_comets.sid"),
-            name: "Crazy Comets",
-            author: "Rob Hubbard",
-            copyright: "1985 Martech",
+            song: Cow::Borrowed(include_bytes!("songs/crazy_comets.sid")),
+            name: Cow::Borrowed("Crazy Comets"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 Martech"),
             version: 10,
             load_adress: 0x5000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0x5732,
             song_list_qty: 2,
@@ -284,12 +514,13 @@ impl OneSid {
 
     pub fn get_sid_last_v8() -> Self {
         OneSid {
-            song: include_bytes!("songs/last_v8.sid"),
-            name: "The Last V8",
-            author: "Rob Hubbard",
-            copyright: "1985 MAD/Mastertronic",
+            song: Cow::Borrowed(include_bytes!("songs/last_v8.sid")),
+            name: Cow::Borrowed("The Last V8"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 MAD/Mastertronic"),
             version: 10,
             load_adress: 0x8010,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0x8797,
             song_list_qty: 3,
@@ -310,12 +541,13 @@ impl OneSid {
 
     pub fn get_sid_monty_on_the_run() -> Self {
         OneSid {
-            song: include_bytes!("songs/monty_on_the_run.sid"),
-            name: "Monty on the Run",
-            author: "Rob Hubbard",
-            copyright: "1985 Gremlin Graphics",
+            song: Cow::Borrowed(include_bytes!("songs/monty_on_the_run.sid")),
+            name: Cow::Borrowed("Monty on the Run"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 Gremlin Graphics"),
             version: 10,
             load_adress: 0x8000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0x856C,
             song_list_qty: 3,
@@ -336,12 +568,13 @@ impl OneSid {
 
     pub fn get_sid_thing_on_a_spring() -> Self {
         OneSid {
-            song: include_bytes!("songs/thing_on_a_spring.sid"),
-            name: "Thing on a Spring",
-            author: "Rob Hubbard",
-            copyright: "1985 Gremlin Graphics",
+            song: Cow::Borrowed(include_bytes!("songs/thing_on_a_spring.sid")),
+            name: Cow::Borrowed("Thing on a Spring"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 Gremlin Graphics"),
             version: 10,
             load_adress: 0xC000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xC509,
             song_list_qty: 1,
@@ -362,12 +595,13 @@ impl OneSid {
 
     pub fn get_sid_zoid() -> Self {
         OneSid {
-            song: include_bytes!("songs/zoids.sid"),
-            name: "Zoids",
-            author: "Rob Hubbard",
-            copyright: "1986 Martech",
+            song: Cow::Borrowed(include_bytes!("songs/zoids.sid")),
+            name: Cow::Borrowed("Zoids"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 Martech"),
             version: 10,
             load_adress: 0x1000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0x14FC,
             song_list_qty: 3,
@@ -390,12 +624,13 @@ impl OneSid {
 
     pub fn get_sid_ace_2() -> Self {
         OneSid {
-            song: include_bytes!("songs/ace_2.sid"),
-            name: "ACE II",
-            author: "Rob Hubbard",
-            copyright: "1987 Arcade",
+            song: Cow::Borrowed(include_bytes!("songs/ace_2.sid")),
+            name: Cow::Borrowed("ACE II"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1987 Arcade"),
             version: 20,
             load_adress: 0xE000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xE67C,
             song_list_qty: 1,
@@ -416,12 +651,13 @@ impl OneSid {
 
     pub fn get_sid_delta() -> Self {
         OneSid {
-            song: include_bytes!("songs/delta.sid"),
-            name: "Delta",
-            author: "Rob Hubbard",
-            copyright: "1987 Thalamus",
+            song: Cow::Borrowed(include_bytes!("songs/delta.sid")),
+            name: Cow::Borrowed("Delta"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1987 Thalamus"),
             version: 30, // Compression _and_ pattern loop in channels
             load_adress: 0xBC00,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xC4F4,
             song_list_qty: 13,
@@ -442,12 +678,13 @@ impl OneSid {
 
     pub fn get_sid_human_race() -> Self {
         OneSid {
-            song: include_bytes!("songs/human_race.sid"),
-            name: "The Human Race",
-            author: "Rob Hubbard",
-            copyright: "1985 Mastertronic",
+            song: Cow::Borrowed(include_bytes!("songs/human_race.sid")),
+            name: Cow::Borrowed("The Human Race"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1985 Mastertronic"),
             version: 20,
             load_adress: 0x0980,
+            data_offset: 126,
             song_track_qty: 2,
             song_list_offset: 0x0E9F,
             song_list_qty: 3,
@@ -468,12 +705,13 @@ impl OneSid {
 
     pub fn get_sid_international_karate() -> Self {
         OneSid {
-            song: include_bytes!("songs/international_karate.sid"),
-            name: "International Karate",
-            author: "Rob Hubbard",
-            copyright: "1986 System 3",
+            song: Cow::Borrowed(include_bytes!("songs/international_karate.sid")),
+            name: Cow::Borrowed("International Karate"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 System 3"),
             version: 20,
             load_adress: 0xAE00,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xB3B0,
             song_list_qty: 1,
@@ -494,12 +732,13 @@ impl OneSid {
 
     pub fn get_sid_lightforce() -> Self {
         OneSid {
-            song: include_bytes!("songs/lightforce.sid"),
-            name: "Lightforce",
-            author: "Rob Hubbard",
-            copyright: "1986 Faster Than Light (FTL)",
+            song: Cow::Borrowed(include_bytes!("songs/lightforce.sid")),
+            name: Cow::Borrowed("Lightforce"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 Faster Than Light (FTL)"),
             version: 20,
             load_adress: 0xF000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xF778,
             song_list_qty: 1,
@@ -520,12 +759,13 @@ impl OneSid {
 
     pub fn get_sid_sanxion_song_1() -> Self {
         OneSid {
-            song: include_bytes!("songs/sanxion.sid"),
-            name: "Sanxion Song 1",
-            author: "Rob Hubbard",
-            copyright: "1986 Thalamus",
+            song: Cow::Borrowed(include_bytes!("songs/sanxion.sid")),
+            name: Cow::Borrowed("Sanxion Song 1"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 Thalamus"),
             version: 20,
             load_adress: 0xB000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xB73C,
             song_list_qty: 1,
@@ -546,12 +786,13 @@ impl OneSid {
 
     pub fn get_sid_sanxion_song_2() -> Self {
         OneSid {
-            song: include_bytes!("songs/sanxion.sid"),
-            name: "Sanxion Song 2",
-            author: "Rob Hubbard",
-            copyright: "1986 Thalamus",
+            song: Cow::Borrowed(include_bytes!("songs/sanxion.sid")),
+            name: Cow::Borrowed("Sanxion Song 2"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 Thalamus"),
             version: 20,
             load_adress: 0xB000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xC5F5,
             song_list_qty: 1,
@@ -572,12 +813,13 @@ impl OneSid {
 
     pub fn get_sid_spellbound() -> Self {
         OneSid {
-            song: include_bytes!("songs/spellbound.sid"),
-            name: "Spellbound",
-            author: "Rob Hubbard",
-            copyright: "1986 MAD/Mastertronic",
+            song: Cow::Borrowed(include_bytes!("songs/spellbound.sid")),
+            name: Cow::Borrowed("Spellbound"),
+            author: Cow::Borrowed("Rob Hubbard"),
+            copyright: Cow::Borrowed("1986 MAD/Mastertronic"),
             version: 15, // XXX WARN: 0xE0E5 second byte & 0b1000_0000 is instrnr: not a vibrato! no vibrato. Using version 15 -- check soundfx too
             load_adress: 0xE000,
+            data_offset: 126,
             song_track_qty: 3,
             song_list_offset: 0xE6B6,
             song_list_qty: 3,
@@ -30,7 +30,7 @@ impl PatternHelper {
         let mut index: usize = 0;
         let mut last_instr: Option<usize> = None;
 
-        while source[index] != 255 {
+        while index < source.len() && source[index] != 255 {
             let mut current = PatternSlot::default();
 
             let length = source[index] & 0b0001_1111; // 0-31
@@ -40,6 +40,9 @@ impl PatternHelper {
 
             if append {
                 index += 1;
+                if index >= source.len() {
+                    break;
+                }
                 if instr_or_portamento {
                     match self.version {
                         10 => {
@@ -95,6 +98,9 @@ impl PatternHelper {
                         }
                     }
                     index += 1;
+                    if index >= source.len() {
+                        break;
+                    }
                 }
 
                 // correction of a table overflow in the original code
@@ -172,20 +178,21 @@ impl PatternHelper {
         let mut all_ok: Vec<bool> = vec![false; po_len];
         let mut i_n: [usize; 3] = [0; 3];
         let mut patterns: Vec<Vec<Vec<PatternSlot>>> = vec![];
+        // a broken song may never loop on all channels at once
+        const MAX_ROWS: usize = 256 * 256;
+        let mut rows: usize = 0;
 
         loop {
             let mut trks: Vec<&Vec<PatternSlot>> = vec![];
             for k in 0..po_len {
                 if pattern_order[k][i_n[k]] as usize >= tracks.len() {
-                    #[cfg(feature = "std")]
-                    println!("No way!? {}", pattern_order[k][i_n[k]]);
                     // special case for commando!?
                     let what_to_do: usize = match pattern_order[k][i_n[k]] {
                         111 => 3,
                         112 => 2,
                         _ => 0,
                     };
-                    trks.push(&tracks[what_to_do]);
+                    trks.push(tracks.get(what_to_do).unwrap_or(&tracks[0]));
                 } else {
                     trks.push(&tracks[pattern_order[k][i_n[k]] as usize]);
                 }
@@ -193,7 +200,7 @@ impl PatternHelper {
             let mut trks_total_len = trks.iter().map(|sublist| sublist.len()).max().unwrap_or(0);
             let mut pattern: Vec<Vec<PatternSlot>> = vec![];
             let mut j: [usize; 3] = [0; 3];
-            while trks_total_len != 0 {
+            while trks_total_len != 0 && rows < MAX_ROWS {
                 let mut line: Vec<PatternSlot> = vec![];
                 for k in 0..po_len {
                     if j[k] >= trks[k].len() {
@@ -203,15 +210,13 @@ impl PatternHelper {
                         }
                         j[k] = 0;
                         if pattern_order[k][i_n[k]] as usize >= tracks.len() {
-                            #[cfg(feature = "std")]
-                            println!("No way!? {}", pattern_order[k][i_n[k]]);
                             // special case for commando!?
                             let what_to_do: usize = match pattern_order[k][i_n[k]] {
                                 111 => 3,
                                 112 => 2,
                                 _ => 0,
                             };
-                            trks[k] = &tracks[what_to_do];
+                            trks[k] = tracks.get(what_to_do).unwrap_or(&tracks[0]);
                         } else {
                             trks[k] = &tracks[pattern_order[k][i_n[k]] as usize];
                         }
@@ -219,14 +224,18 @@ impl PatternHelper {
                             trks_total_len = trks[k].len();
                         }
                     }
-                    line.push(trks[k][j[k]]);
+                    line.push(trks[k].get(j[k]).copied().unwrap_or_default());
                     j[k] += 1;
                 }
                 trks_total_len -= 1;
+                rows += 1;
                 pattern.push(line);
             }
 
             patterns.push(pattern);
+            if rows >= MAX_ROWS {
+                return patterns;
+            }
             for k in 0..po_len {
                 i_n[k] += 1;
                 if i_n[k] >= pattern_order[k].len() {
@@ -0,0 +1,98 @@
+use crate::error::Error;
+use crate::format::{Confidence, Format};
+
+use alloc::string::String;
+
+/// PSID or RSID file header, all values are big endian
+#[derive(Clone, Debug)]
+pub struct PsidHeader {
+    /// "PSID" or "RSID"
+    pub magic: [u8; 4],
+    pub version: u16,
+    /// Offset of the C64 binary data in the file
+    pub data_offset: u16,
+    /// If 0, the load address is the first two bytes (little endian) of the data
+    pub load_address: u16,
+    pub init_address: u16,
+    /// If 0, the init routine installs its own interrupt handler
+    pub play_address: u16,
+    pub songs: u16,
+    pub start_song: u16,
+    pub speed: u32,
+    pub name: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl PsidHeader {
+    /// Header sniffing, the tune is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        match Self::load(data) {
+            Ok(header)
+                if (1..=4).contains(&header.version)
+                    && (header.data_offset == 0x76 || header.data_offset == 0x7C) =>
+            {
+                Some(Confidence::High)
+            }
+            Err(e) if e.is_bad_magic() => None,
+            _ => Some(Confidence::Medium),
+        }
+    }
+
+    /// Latin-1 string, zero padded
+    fn read_string(src: &[u8]) -> String {
+        src.iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect()
+    }
+
+    pub fn load(data: &[u8]) -> Result<Self, Error> {
+        if !data.starts_with(b"PSID") && !data.starts_with(b"RSID") {
+            return Err(Error::BadMagic {
+                format: Format::Sid,
+                offset: 0,
+            });
+        }
+        if data.len() < 0x76 {
+            return Err(Error::Truncated {
+                format: Format::Sid,
+                structure: "header",
+                offset: 0,
+            });
+        }
+        let magic = [data[0], data[1], data[2], data[3]];
+        let word = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        Ok(Self {
+            magic,
+            version: word(0x04),
+            data_offset: word(0x06),
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            songs: word(0x0E),
+            start_song: word(0x10),
+            speed: u32::from_be_bytes([data[0x12], data[0x13], data[0x14], data[0x15]]),
+            name: Self::read_string(&data[0x16..0x36]),
+            author: Self::read_string(&data[0x36..0x56]),
+            copyright: Self::read_string(&data[0x56..0x76]),
+        })
+    }
+
+    /// Load address and file offset of the byte loaded at this address
+    pub fn get_load(&self, data: &[u8]) -> Result<(usize, usize), Error> {
+        let offset = self.data_offset as usize;
+        if self.load_address != 0 {
+            return Ok((self.load_address as usize, offset));
+        }
+        if data.len() < offset + 2 {
+            return Err(Error::Truncated {
+                format: Format::Sid,
+                structure: "load address",
+                offset,
+            });
+        }
+        let load_address = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        Ok((load_address as usize, offset + 2))
+    }
+}
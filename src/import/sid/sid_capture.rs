@@ -0,0 +1,401 @@
+use alloc::format;
+use alloc::{vec, vec::Vec};
+
+use crate::error::Error;
+use crate::format::Format;
+use crate::instr_sid::{InstrSid, SidVoice};
+use crate::prelude::*;
+
+use super::cpu6502::Cpu6502;
+use super::psid_header::PsidHeader;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// PAL C64 clock, in Hz
+const CLOCK: f32 = 985_248.0;
+
+/// Instructions allowed to the init and play routines
+const INIT_STEPS: usize = 2_000_000;
+const PLAY_STEPS: usize = 200_000;
+
+/// `PLA TAY PLA TAX PLA RTI`, end of the KERNAL interrupt handler
+const KERNAL_IRQ_EXIT: [u8; 6] = [0x68, 0xA8, 0x68, 0xAA, 0x68, 0x40];
+const KERNAL_IRQ: u16 = 0xEA31;
+const KERNAL_IRQ_END: u16 = 0xEA81;
+
+const ROWS_PER_PATTERN: usize = 64;
+
+/// Legato speed, `3FF` the fastest tone portamento of XM and IT:
+/// 16 semitones a tick on linear frequencies, wider jumps take more ticks
+const LEGATO_SPEED: f32 = 4.0 * 255.0;
+
+/// SID registers at the end of a player frame
+#[derive(Clone, Debug)]
+pub struct SidFrame {
+    /// $D400 to $D418
+    pub registers: [u8; 25],
+    /// The gate of the voice was set during this frame
+    pub gate_on: [bool; 3],
+}
+
+impl SidFrame {
+    fn freq(&self, voice: usize) -> u16 {
+        u16::from_le_bytes([self.registers[7 * voice], self.registers[7 * voice + 1]])
+    }
+
+    fn gate(&self, voice: usize) -> bool {
+        self.registers[7 * voice + 4] & 0x01 != 0
+    }
+
+    /// Nearest note of the voice frequency, C-4 is `8363 / 32` Hz like the player synths
+    fn pitch(&self, voice: usize) -> Option<Pitch> {
+        let hz = self.freq(voice) as f32 * CLOCK / 16_777_216.0;
+        if hz <= 0.0 {
+            return None;
+        }
+        let note = 48.0 + (12.0 * (hz * 32.0 / 8363.0).log2()).round();
+        Pitch::try_from(note.clamp(0.0, 119.0) as u8).ok()
+    }
+
+    /// Registers of `voice` and of the filter, the instrument identity
+    fn instrument_key(&self, voice: usize) -> [u8; 10] {
+        let r = &self.registers[7 * voice..];
+        [
+            r[2],
+            r[3] & 0x0F,
+            r[4] & 0xFE,
+            r[5],
+            r[6],
+            self.registers[0x15] & 0x07,
+            self.registers[0x16],
+            self.registers[0x17] & 0xF0,
+            (self.registers[0x17] >> voice) & 0x01,
+            self.registers[0x18],
+        ]
+    }
+
+    /// The voice and the filter as an `InstrSid` playing on its voice 0
+    fn instrument(&self, voice: usize) -> InstrSid {
+        let r = &self.registers[7 * voice..];
+        let mut sid_voice = SidVoice {
+            pw: u16::from_le_bytes([r[2], r[3] & 0x0F]),
+            ad: r[5],
+            sr: r[6],
+            ..Default::default()
+        };
+        // the gate is driven by the notes
+        sid_voice.update_from_ctrl_register(r[4] & 0xFE);
+        let mode = self.registers[0x18];
+        InstrSid {
+            voice: [sid_voice, SidVoice::default(), SidVoice::default()],
+            fc: (self.registers[0x15] & 0x07) as u16 | (self.registers[0x16] as u16) << 3,
+            filter_resonance: self.registers[0x17] >> 4,
+            filter_gate: [
+                (self.registers[0x17] >> voice) & 0x01 != 0,
+                false,
+                false,
+                false,
+            ],
+            low_pass: mode & 0x10 != 0,
+            band_pass: mode & 0x20 != 0,
+            high_pass: mode & 0x40 != 0,
+            mute_voice3: mode & 0x80 != 0,
+            main_volume: mode & 0x0F,
+        }
+    }
+}
+
+/// SID register writes of any PSID or RSID tune, captured by running
+/// its init and play routines on a 6502, frame after frame
+#[derive(Clone, Debug)]
+pub struct SidCapture {
+    pub header: PsidHeader,
+    /// [1..songs]
+    pub song: u16,
+    /// Play routine calls per second
+    pub frame_rate: f32,
+    pub frames: Vec<SidFrame>,
+}
+
+impl SidCapture {
+    /// Run `frames` frames of `song` (1 is the first song, 0 the default one).
+    /// A play routine which doesn't return is an error at its program counter
+    pub fn run(data: &[u8], song: u16, frames: usize) -> Result<Self, Error> {
+        let header = PsidHeader::load(data)?;
+        let (load_address, offset) = header.get_load(data)?;
+        if offset > data.len() {
+            return Err(Error::Truncated {
+                format: Format::Sid,
+                structure: "C64 data",
+                offset,
+            });
+        }
+        let songs = header.songs.max(1);
+        let song = if song == 0 { header.start_song } else { song }.clamp(1, songs);
+
+        let mut cpu = Cpu6502::default();
+        cpu.load(KERNAL_IRQ, &KERNAL_IRQ_EXIT);
+        cpu.load(KERNAL_IRQ_END, &KERNAL_IRQ_EXIT);
+        cpu.load(0x0314, &KERNAL_IRQ.to_le_bytes());
+        cpu.memory[0x01] = 0x37;
+        cpu.load(load_address as u16, &data[offset..]);
+
+        let init_address = if header.init_address != 0 {
+            header.init_address
+        } else {
+            load_address as u16
+        };
+        let rsid = &header.magic == b"RSID";
+        // a RSID init routine may never return and play from its interrupts
+        if !cpu.call(init_address, (song - 1) as u8, INIT_STEPS) && !rsid {
+            return Err(Error::Unsupported {
+                format: Format::Sid,
+                what: "6502 init routine",
+                offset: init_address as usize,
+            });
+        }
+
+        // bit `song - 1` of speed: CIA timer instead of the vertical blank,
+        // a RSID tune without raster interrupt uses the CIA timer
+        let cia = header.speed & (1 << (song - 1).min(31)) != 0
+            || (rsid && cpu.memory[0xD01A] & 0x01 == 0);
+        let frame_rate = if cia {
+            let timer = u16::from_le_bytes([cpu.memory[0xDC04], cpu.memory[0xDC05]]);
+            if timer != 0 {
+                CLOCK / (timer as f32 + 1.0)
+            } else {
+                60.0
+            }
+        } else {
+            50.0
+        };
+
+        let play = if header.play_address != 0 {
+            Some(header.play_address)
+        } else {
+            None
+        };
+        let irq = u16::from_le_bytes([cpu.memory[0x0314], cpu.memory[0x0315]]);
+        let vector = u16::from_le_bytes([cpu.memory[0xFFFE], cpu.memory[0xFFFF]]);
+
+        let mut registers = [0u8; 25];
+        let mut capture = Self {
+            header,
+            song,
+            frame_rate,
+            frames: Vec::with_capacity(frames),
+        };
+        let mut gate_on = [false; 3];
+        Self::apply_writes(&mut cpu, &mut registers, &mut gate_on);
+
+        for _ in 0..frames {
+            gate_on = [false; 3];
+            let done = match play {
+                Some(address) => cpu.call(address, 0, PLAY_STEPS),
+                None if irq != KERNAL_IRQ => cpu.interrupt(irq, true, PLAY_STEPS),
+                None if vector != 0 => cpu.interrupt(vector, false, PLAY_STEPS),
+                None => false,
+            };
+            if !done {
+                return Err(Error::Unsupported {
+                    format: Format::Sid,
+                    what: "6502 play routine",
+                    offset: cpu.pc as usize,
+                });
+            }
+            Self::apply_writes(&mut cpu, &mut registers, &mut gate_on);
+            capture.frames.push(SidFrame { registers, gate_on });
+        }
+        Ok(capture)
+    }
+
+    /// Apply and forget the writes of the last call, rising gates are recorded
+    fn apply_writes(cpu: &mut Cpu6502, registers: &mut [u8; 25], gate_on: &mut [bool; 3]) {
+        for (register, value) in cpu.sid_writes.drain(..) {
+            let register = register as usize;
+            if register >= registers.len() {
+                continue;
+            }
+            if register % 7 == 4 && register < 21 {
+                let voice = register / 7;
+                if registers[register] & 0x01 == 0 && value & 0x01 != 0 {
+                    gate_on[voice] = true;
+                }
+            }
+            registers[register] = value;
+        }
+    }
+
+    /// Quantise the frames into patterns, `frames_per_row` frames being a tick each.
+    ///
+    /// A rising gate is a note with the instrument of the voice registers, a falling
+    /// gate a note off, and a new frequency under a held gate a legato note: the note
+    /// without instrument and a tone portamento at `LEGATO_SPEED`, so that it can be saved
+    pub fn to_module(&self, frames_per_row: usize) -> Module {
+        let frames_per_row = frames_per_row.max(1);
+        let rows = self.frames.len().div_ceil(frames_per_row);
+
+        let mut keys: Vec<[u8; 10]> = vec![];
+        let mut instrument: Vec<Instrument> = vec![];
+        let mut grid: Vec<Row> = vec![vec![TrackUnit::default(); 3]; rows];
+
+        for voice in 0..3 {
+            let mut sounding = false;
+            let mut pitch = Pitch::None;
+            for (row, frames) in grid.iter_mut().zip(self.frames.chunks(frames_per_row)) {
+                let unit = &mut row[voice];
+                let last = &frames[frames.len() - 1];
+                if let Some(frame) = frames.iter().find(|f| f.gate_on[voice]) {
+                    let Some(note) = frame.pitch(voice) else {
+                        continue;
+                    };
+                    let key = frame.instrument_key(voice);
+                    let index = match keys.iter().position(|k| *k == key) {
+                        Some(index) => index,
+                        None => {
+                            keys.push(key);
+                            instrument.push(Instrument {
+                                name: format!("Sid {:02}", instrument.len()),
+                                instr_type: InstrumentType::Sid(frame.instrument(voice)),
+                                muted: false,
+                            });
+                            instrument.len() - 1
+                        }
+                    };
+                    unit.note = note;
+                    unit.instrument = Some(index);
+                    pitch = note;
+                    sounding = true;
+                } else if sounding && !last.gate(voice) {
+                    unit.note = Pitch::Off;
+                    sounding = false;
+                } else if sounding {
+                    match frames[0].pitch(voice) {
+                        Some(note) if note != pitch => {
+                            unit.note = note;
+                            if frames_per_row >= 2 {
+                                // slides to the note from the next tick, without a new attack
+                                unit.effects.push(TrackEffect::TonePortamento(LEGATO_SPEED));
+                            }
+                            pitch = note;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let pattern: Vec<Pattern> = grid
+            .chunks(ROWS_PER_PATTERN)
+            .map(|rows| rows.to_vec())
+            .collect();
+        Module {
+            name: self.header.name.clone(),
+            comment: format!(
+                "{} - {} (song #{})",
+                self.header.copyright, self.header.author, self.song
+            ),
+            default_tempo: frames_per_row,
+            // a tick lasts 2.5 / bpm seconds
+            default_bpm: (2.5 * self.frame_rate).round() as usize,
+            pattern_order: vec![(0..pattern.len()).collect()],
+            pattern,
+            instrument,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn sid_capture() {
+        use crate::format::Format;
+        use crate::import::sid::one_sid::OneSid;
+        use crate::import::sid::sid_capture::SidCapture;
+        use crate::prelude::*;
+        use crate::Error;
+
+        // init: RTS, play: gate of a C-4 sawtooth on voice 0 every other frame
+        let mut psid = vec![0u8; 0x7C];
+        psid[0..4].copy_from_slice(b"PSID");
+        psid[0x05] = 2;
+        psid[0x07] = 0x7C;
+        psid[0x0A..0x0E].copy_from_slice(&[0x10, 0x00, 0x10, 0x01]);
+        psid[0x0F] = 1;
+        psid[0x11] = 1;
+        psid[0x16..0x1A].copy_from_slice(b"Test");
+        psid.extend_from_slice(&[
+            0x00, 0x10, // load address
+            0x60, // RTS
+            0xA5, 0xFB, 0x49, 0x01, 0x85, 0xFB, // $FB ^= 1
+            0x09, 0x20, 0x8D, 0x04, 0xD4, // ctrl
+            0xA9, 0x61, 0x8D, 0x00, 0xD4, 0xA9, 0x11, 0x8D, 0x01, 0xD4, // freq $1161
+            0x60,
+        ]);
+        let capture = SidCapture::run(&psid, 0, 8).unwrap();
+        assert_eq!(capture.frame_rate, 50.0);
+        assert_eq!(capture.frames.len(), 8);
+        assert!(capture.frames[0].gate_on[0] && !capture.frames[1].gate_on[0]);
+        let module = capture.to_module(1);
+        assert_eq!(module.name, "Test");
+        assert_eq!(module.default_bpm, 125);
+        assert_eq!(module.instrument.len(), 1);
+        let notes: Vec<Pitch> = module.pattern[0].iter().map(|row| row[0].note).collect();
+        assert_eq!(notes[0..4], [Pitch::C4, Pitch::Off, Pitch::C4, Pitch::Off]);
+        assert!(module.pattern[0]
+            .iter()
+            .all(|row| row[1].note == Pitch::None));
+
+        // gate held, the frequency goes from C-4 to B-3 every other frame
+        let mut legato = psid[..0x7C].to_vec();
+        legato.extend_from_slice(&[
+            0x00, 0x10, // load address
+            0x60, // RTS
+            0xA5, 0xFB, 0x49, 0x01, 0x85, 0xFB, // $FB ^= 1
+            0x09, 0x10, 0x8D, 0x01, 0xD4, // freq high $10 | $FB
+            0xA9, 0x61, 0x8D, 0x00, 0xD4, // freq low
+            0xA9, 0x21, 0x8D, 0x04, 0xD4, // gate on
+            0x60,
+        ]);
+        let module = SidCapture::run(&legato, 0, 9).unwrap().to_module(3);
+        let units: Vec<&TrackUnit> = module.pattern[0].iter().map(|row| &row[0]).collect();
+        assert_eq!((units[0].note, units[0].instrument), (Pitch::C4, Some(0)));
+        assert_eq!((units[1].note, units[1].instrument), (Pitch::B3, None));
+        assert_eq!(units[1].effects, [TrackEffect::TonePortamento(1020.0)]);
+        assert_eq!(units[2].note, Pitch::C4);
+
+        // the play routine stops the CPU: JAM at $1001, the PC is past it
+        psid[0x7F] = 0x02;
+        match SidCapture::run(&psid, 0, 8) {
+            Err(Error::Unsupported {
+                format: Format::Sid,
+                offset,
+                ..
+            }) => assert_eq!(offset, 0x1002),
+            e => panic!("{:?}", e.err()),
+        }
+
+        // a Rob Hubbard driver on the 6502
+        let commando = OneSid::get_sid_commando();
+        let capture = SidCapture::run(&commando.song, 1, 500).unwrap();
+        assert_eq!(capture.frames.len(), 500);
+        let module = capture.to_module(3);
+        assert!(!module.instrument.is_empty());
+        for channel in 0..3 {
+            assert!(module
+                .pattern
+                .iter()
+                .flatten()
+                .any(|row| row[channel].note.is_valid()));
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use alloc::format;
 use alloc::{vec, vec::Vec};
 
+use crate::error::Error;
 use crate::import::import_memory::{ImportMemory, MemoryType};
 use crate::prelude::*;
 
 use super::instr_helper::InstrHelper;
 use super::one_sid::OneSid;
 use super::pattern_helper::PatternHelper;
+use super::psid_header::PsidHeader;
 use super::sound_fx::SoundFx;
 
 #[derive(Debug)]
@@ -15,9 +17,18 @@ pub struct SidModule {
     pub pattern_helper: PatternHelper,
     pub instruments: Vec<InstrRobSid>,
     pub soundfx: Vec<SoundFx>,
+    pub header: Option<PsidHeader>,
 }
 
 impl SidModule {
+    /// Load a PSID or RSID file using a Rob Hubbard driver
+    pub fn load(data: &[u8]) -> Result<Self, Error> {
+        let header = PsidHeader::load(data)?;
+        let mut sid = OneSid::load(data, &header)?.to_sidmodule();
+        sid.header = Some(header);
+        Ok(sid)
+    }
+
     pub fn to_modules(&self, original_instruments: bool) -> Vec<Module> {
         let mut modules: Vec<Module> = vec![];
 
@@ -47,6 +58,10 @@ impl SidModule {
 
             let idst = InstrHelper::irss_to_instruments(&self.instruments, original_instruments);
             module.instrument = idst;
+            // game sound effects, after the music instruments
+            module
+                .instrument
+                .extend(InstrHelper::soundfxs_to_instruments(&self.soundfx));
 
             modules.push(module);
         }
@@ -129,3 +144,80 @@ impl SidModule {
         return sid.to_sidmodule();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn sid_load() {
+        use crate::import::sid::one_sid::OneSid;
+        use crate::import::sid::sid_module::SidModule;
+        // what the driver scan finds: version, songs, tracks and reset speed
+        for (builtin, version, songs, tracks, resetspd) in [
+            (OneSid::get_sid_commando(), 10, 3, 45, 2),
+            (OneSid::get_sid_crazy_comets(), 10, 2, 53, 2),
+            (OneSid::get_sid_last_v8(), 10, 3, 29, 1),
+            (OneSid::get_sid_monty_on_the_run(), 10, 3, 77, 1),
+            (OneSid::get_sid_thing_on_a_spring(), 10, 1, 36, 1),
+            (OneSid::get_sid_zoid(), 10, 3, 31, 2),
+            (OneSid::get_sid_ace_2(), 20, 1, 38, 1),
+            (OneSid::get_sid_delta(), 30, 13, 109, 1),
+            (OneSid::get_sid_human_race(), 10, 5, 79, 3),
+            (OneSid::get_sid_international_karate(), 20, 1, 53, 2),
+            (OneSid::get_sid_lightforce(), 20, 1, 31, 2),
+            (OneSid::get_sid_sanxion_song_1(), 20, 1, 27, 2),
+            (OneSid::get_sid_sanxion_song_2(), 20, 1, 27, 2),
+            (OneSid::get_sid_spellbound(), 15, 3, 42, 1),
+        ] {
+            let sid = SidModule::load(&builtin.song).unwrap();
+            let helper = &sid.pattern_helper;
+            assert_eq!(
+                (helper.version, helper.songs.len(), helper.tracks.len()),
+                (version, songs, tracks),
+                "{}",
+                builtin.name
+            );
+            assert_eq!(sid.sid.resetspd, resetspd, "{}", builtin.name);
+            assert_eq!(sid.to_modules(false).len(), songs);
+            assert!(builtin.name.starts_with(&sid.header.as_ref().unwrap().name));
+
+            let expected = builtin.to_sidmodule().pattern_helper;
+            match builtin.name.as_ref() {
+                // one more track is found after the hand-written ones
+                "The Last V8" => {
+                    assert_eq!(expected.tracks.len(), 28);
+                    assert_eq!(helper.tracks[..28], expected.tracks[..]);
+                    assert_eq!(helper.songs, expected.songs);
+                }
+                // 2 voices song list: 2 more songs than the hand-written ones,
+                // the two bytes portamento signature isn't found: taken for a v10 driver
+                "The Human Race" => {
+                    assert!(helper.songs.iter().all(|song| song.len() == 2));
+                    assert_eq!((expected.version, expected.songs.len()), (20, 3));
+                    assert_eq!(expected.tracks.len(), 58);
+                    assert_eq!(helper.songs[..3], expected.songs[..]);
+                    assert_eq!(helper.tracks[..58], expected.tracks[..]);
+                }
+                // two drivers in the same file: the first one is found
+                "Sanxion Song 2" => {
+                    let song_1 = OneSid::get_sid_sanxion_song_1().to_sidmodule();
+                    assert_eq!(helper.tracks, song_1.pattern_helper.tracks);
+                    assert_eq!(expected.tracks.len(), 56);
+                }
+                _ => {
+                    assert_eq!(helper.version, expected.version);
+                    assert_eq!(helper.songs, expected.songs);
+                    assert_eq!(helper.channels, expected.channels);
+                    assert_eq!(helper.tracks, expected.tracks);
+                }
+            }
+            // the hand-written Zoids speed is 1, the driver resets it to 2
+            if builtin.name != "Zoids" {
+                assert_eq!(sid.sid.resetspd, builtin.resetspd);
+            }
+        }
+        let mut garbage = b"PSID".to_vec();
+        garbage.resize(0x400, 0);
+        assert!(SidModule::load(&garbage).is_err());
+        assert!(SidModule::load(b"RSID").is_err());
+    }
+}
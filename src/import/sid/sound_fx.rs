@@ -1,4 +1,8 @@
 use crate::instr_sid::SidVoice;
+use crate::player::sid::chip::{SidChip, SidModel};
+use crate::prelude::*;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
 
 #[derive(Debug)]
 pub struct SoundFx {
@@ -13,3 +17,172 @@ pub struct SoundFx {
     pub voice0: SidVoice,
     pub voice1: SidVoice,
 }
+
+impl SoundFx {
+    /// Rendering rate: C-4 plays the effect at its original speed, 24 semitones up
+    pub const SAMPLE_RATE: f32 = 8363.0 * 4.0;
+    /// Driver frames per second
+    const FRAME_RATE: f32 = 50.0;
+    /// Longest sweep, in driver frames
+    const MAX_FRAMES: usize = 250;
+    /// Longest release after the sweep, in driver frames
+    const RELEASE_FRAMES: usize = 50;
+
+    /// Frequency high byte of each driver frame: from `note_start` toward `note_end`,
+    /// `note_delta` every `incdec_counter` frames.
+    /// Without delta, `note_start` is held `incdec_counter` frames
+    pub fn sweep(&self) -> Vec<u8> {
+        let every = self.incdec_counter.unsigned_abs() as usize + 1;
+        let mut sweep = vec![];
+        let mut note = self.note_start;
+        if self.note_delta == 0 {
+            return vec![note; every];
+        }
+        while sweep.len() < Self::MAX_FRAMES {
+            sweep.push(note);
+            if note == self.note_end {
+                break;
+            }
+            if sweep.len() % every == 0 {
+                note = if self.note_end > note {
+                    note.saturating_add(self.note_delta).min(self.note_end)
+                } else {
+                    note.saturating_sub(self.note_delta).max(self.note_end)
+                };
+            }
+        }
+        sweep
+    }
+
+    /// Play the effect on a SID chip: voice 0, voice 1 or both
+    pub fn render(&self, model: SidModel) -> Vec<i16> {
+        let mut chip = SidChip::new(model);
+        chip.set_sample_rate(Self::SAMPLE_RATE);
+        chip.write(0x18, 0x0F);
+
+        let mut voices: Vec<(u8, &SidVoice)> = vec![];
+        if self.voice0_ctrl || !self.voice1_ctrl {
+            voices.push((0, &self.voice0));
+        }
+        if self.voice1_ctrl {
+            voices.push((7, &self.voice1));
+        }
+        for (base, voice) in voices.iter() {
+            chip.write(base + 2, voice.pw as u8);
+            chip.write(base + 3, (voice.pw >> 8) as u8);
+            chip.write(base + 5, voice.ad);
+            chip.write(base + 6, voice.sr);
+            chip.write(base + 4, voice.generate_ctrl_register() | 1);
+        }
+
+        let frame_length = Self::SAMPLE_RATE / Self::FRAME_RATE;
+        let mut data: Vec<i16> = vec![];
+        let mut render = |chip: &mut SidChip, frame: usize| {
+            let end = ((frame + 1) as f32 * frame_length) as usize;
+            while data.len() < end {
+                data.push((chip.render_frame().clamp(-1.0, 1.0) * 32767.0) as i16);
+            }
+        };
+
+        let sweep = self.sweep();
+        for (frame, note) in sweep.iter().enumerate() {
+            for (base, voice) in voices.iter() {
+                chip.write(*base, 0);
+                chip.write(base + 1, *note);
+                // voice 1 gate goes on and off every frame
+                if *base == 7 && self.flipflop_voice1_ctrl {
+                    let gate = frame & 1 == 0;
+                    chip.write(base + 4, voice.generate_ctrl_register() & 0xFE | gate as u8);
+                }
+            }
+            render(&mut chip, frame);
+        }
+
+        for (base, voice) in voices.iter() {
+            chip.write(base + 4, voice.generate_ctrl_register() & 0xFE);
+        }
+        for frame in sweep.len()..sweep.len() + Self::RELEASE_FRAMES {
+            if chip.is_silent() {
+                break;
+            }
+            render(&mut chip, frame);
+        }
+        data
+    }
+
+    /// The effect rendered by a 6581, played with C-4
+    pub fn to_sample(&self, name: String) -> Sample {
+        Sample {
+            name,
+            relative_pitch: 24,
+            finetune: 0.0,
+            volume: 1.0,
+            panning: 0.5,
+            loop_flag: LoopType::No,
+            loop_start: 0,
+            loop_length: 0,
+            sustain_loop_flag: LoopType::No,
+            sustain_loop_start: 0,
+            sustain_loop_length: 0,
+            data: Some(SampleDataType::Mono16(self.render(SidModel::Mos6581))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn sid_soundfx() {
+        use crate::import::sid::sid_module::SidModule;
+        use crate::import::sid::sound_fx::SoundFx;
+        use crate::instr_sid::SidVoice;
+        use crate::prelude::*;
+
+        let sid = SidModule::get_sid_commando();
+        assert_eq!(sid.soundfx.len(), 16);
+        let module = &sid.to_modules(true)[0];
+        assert_eq!(
+            module.instrument.len(),
+            sid.instruments.len() + sid.soundfx.len()
+        );
+        for instr in &module.instrument[sid.instruments.len()..] {
+            let InstrumentType::Default(id) = &instr.instr_type else {
+                panic!("not a sample based instrument");
+            };
+            let sample = id.sample[0].as_ref().unwrap();
+            assert!(sample.len() > 0);
+            assert_eq!(id.sample_for_pitch[48], Some(0));
+        }
+
+        let voice0 = SidVoice {
+            ctrl_sawtooth: true,
+            sr: 0xF0,
+            ..Default::default()
+        };
+        let sfx = SoundFx {
+            incdec_start_at_end: false,
+            incdec_counter: 1,
+            note_start: 0x10,
+            note_delta: 4,
+            note_end: 0x20,
+            flipflop_voice1_ctrl: false,
+            voice0_ctrl: true,
+            voice1_ctrl: false,
+            voice0,
+            voice1: SidVoice::default(),
+        };
+        // 4 steps of 2 frames
+        assert_eq!(
+            sfx.sweep(),
+            vec![0x10, 0x10, 0x14, 0x14, 0x18, 0x18, 0x1C, 0x1C, 0x20]
+        );
+        let sample = sfx.to_sample("sweep".into());
+        assert!(sample.len() >= 9 * SoundFx::SAMPLE_RATE as usize / 50);
+        let Some(SampleDataType::Mono16(data)) = &sample.data else {
+            panic!("not a 16 bits sample");
+        };
+        assert!(data.iter().any(|v| v.unsigned_abs() > 1000));
+    }
+}
@@ -226,16 +226,16 @@ impl TrackImportEffect {
         }
     }
 
-    fn sum_portamento_speeds(effects: &[TrackImportEffect]) -> Option<TrackEffect> {
+    fn sum_portamento_speeds(effects: &[TrackImportEffect], fine: bool) -> Option<TrackEffect> {
         let s = effects
             .iter()
-            .map(|effect| match effect {
-                TrackImportEffect::PortamentoUp(speed)
-                | TrackImportEffect::PortamentoDown(speed)
-                | TrackImportEffect::PortamentoFineUp(speed)
-                | TrackImportEffect::PortamentoFineDown(speed)
-                | TrackImportEffect::PortamentoExtraFineUp(speed)
-                | TrackImportEffect::PortamentoExtraFineDown(speed) => *speed,
+            .map(|effect| match (effect, fine) {
+                (TrackImportEffect::PortamentoUp(speed), false)
+                | (TrackImportEffect::PortamentoDown(speed), false)
+                | (TrackImportEffect::PortamentoFineUp(speed), true)
+                | (TrackImportEffect::PortamentoFineDown(speed), true)
+                | (TrackImportEffect::PortamentoExtraFineUp(speed), true)
+                | (TrackImportEffect::PortamentoExtraFineDown(speed), true) => *speed,
                 _ => 0.0, // Ignore other variants
             })
             .sum();
@@ -243,7 +243,7 @@ impl TrackImportEffect {
         if s == 0.0 {
             return None;
         } else {
-            return Some(TrackEffect::Portamento(s));
+            return Some(TrackEffect::Portamento { speed: s, fine });
         }
     }
 
@@ -400,13 +400,24 @@ impl TrackImportEffect {
         }
     }
 
+    /// Fine and regular portamentos are kept apart, they play on different ticks
+    fn same_kind(a: &TrackEffect, b: &TrackEffect) -> bool {
+        match (a, b) {
+            (
+                TrackEffect::Portamento { fine: fine1, .. },
+                TrackEffect::Portamento { fine: fine2, .. },
+            ) => fine1 == fine2,
+            _ => core::mem::discriminant(a) == core::mem::discriminant(b),
+        }
+    }
+
     fn remove_duplicates_and_merge(mut effects: Vec<TrackEffect>) -> Vec<TrackEffect> {
         let mut unique_effects = Vec::new();
 
         for effect in effects.drain(..) {
             if let Some(existing_index) = unique_effects
                 .iter()
-                .position(|e| core::mem::discriminant(e) == core::mem::discriminant(&effect))
+                .position(|e| Self::same_kind(e, &effect))
             {
                 if let Some(merged_effect) = unique_effects[existing_index].merge(&effect) {
                     unique_effects[existing_index] = merged_effect;
@@ -425,9 +436,11 @@ impl TrackImportEffect {
         // This part is used to aggregate sub-effects which are subject
         // to memory management in the different trackers
 
-        // Portamento
-        if let Some(te) = Self::sum_portamento_speeds(effects) {
-            vte.push(te);
+        // Portamento, fine ones are only played at tick 0
+        for fine in [false, true] {
+            if let Some(te) = Self::sum_portamento_speeds(effects, fine) {
+                vte.push(te);
+            }
         }
 
         // TonePortamento
@@ -458,3 +471,33 @@ impl TrackImportEffect {
         return vte;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn import_fine_portamento() {
+        use crate::import::track_import_effect::TrackImportEffect;
+        use crate::prelude::*;
+
+        let effects = TrackImportEffect::to_track_effects(&vec![
+            TrackImportEffect::PortamentoUp(-8.0),
+            TrackImportEffect::PortamentoFineDown(4.0),
+            TrackImportEffect::PortamentoExtraFineDown(1.0),
+        ]);
+        assert_eq!(
+            effects,
+            vec![
+                TrackEffect::Portamento {
+                    speed: -8.0,
+                    fine: false,
+                },
+                TrackEffect::Portamento {
+                    speed: 5.0,
+                    fine: true,
+                },
+            ]
+        );
+    }
+}
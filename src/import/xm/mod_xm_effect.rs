@@ -5,6 +5,89 @@ use crate::prelude::*;
 use alloc::vec;
 use alloc::vec::Vec;
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Parts of a `TrackUnit` which can't be saved in a XM pattern slot.
+/// `note` and `instrument` are only lost on channels beyond the saved ones.
+#[derive(Default, Debug, Clone)]
+pub struct ModXmLost {
+    pub pattern: usize,
+    pub row: usize,
+    pub channel: usize,
+    pub note: Pitch,
+    pub instrument: Option<usize>,
+    pub effects: Vec<TrackEffect>,
+    pub global_effects: Vec<GlobalEffect>,
+}
+
+impl ModXmLost {
+    pub fn is_empty(&self) -> bool {
+        self.note == Pitch::None
+            && self.instrument.is_none()
+            && self.effects.is_empty()
+            && self.global_effects.is_empty()
+    }
+}
+
+/// Tone portamento and vibrato parameters a channel remembers while saving a pattern,
+/// `0` if unknown. Used to know if a parameter can be left to tracker memory.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ModXmMemory {
+    tone_portamento: u8,
+    vibrato: u8,
+}
+
+impl ModXmMemory {
+    pub(crate) fn has_tone_portamento(&self, param: u8) -> bool {
+        param == 0 || param == self.tone_portamento
+    }
+
+    /// Speed and depth nibbles are remembered apart
+    pub(crate) fn has_vibrato(&self, param: u8) -> bool {
+        [0xF0, 0x0F]
+            .iter()
+            .all(|&mask| param & mask == 0 || param & mask == self.vibrato & mask)
+    }
+
+    pub(crate) fn set_tone_portamento(&mut self, param: u8) {
+        if param != 0 {
+            self.tone_portamento = param;
+        }
+    }
+
+    pub(crate) fn set_vibrato(&mut self, param: u8) {
+        for mask in [0xF0, 0x0F] {
+            if param & mask != 0 {
+                self.vibrato = self.vibrato & !mask | param & mask;
+            }
+        }
+    }
+
+    pub(crate) fn forget_vibrato(&mut self) {
+        self.vibrato = 0;
+    }
+
+    /// Remember the parameters of a XM effect column and volume column
+    pub(crate) fn set_xm(&mut self, fx: Option<(u8, u8)>, vol: Option<u8>) {
+        match fx {
+            Some((0x03, param)) => self.set_tone_portamento(param),
+            Some((0x04, param)) => self.set_vibrato(param),
+            _ => {}
+        }
+        match vol {
+            Some(v) if v & 0xF0 == 0xA0 => self.set_vibrato((v & 0x0F) << 4),
+            Some(v) if v & 0xF0 == 0xB0 => self.set_vibrato(v & 0x0F),
+            Some(v) if v & 0xF0 == 0xF0 => self.set_tone_portamento((v & 0x0F) << 4),
+            _ => {}
+        }
+    }
+}
+
 pub struct ModXmEffect;
 
 impl ModXmEffect {
@@ -127,11 +210,7 @@ impl ModXmEffect {
                             vec![TrackImportEffect::PortamentoFineDown(4.0 * param as f32)]
                         })
                     }
-                    0x3 => {
-                        return Some(vec![TrackImportEffect::Glissando(
-                            current.effect_parameter != 0,
-                        )])
-                    }
+                    0x3 => return Some(vec![TrackImportEffect::Glissando(param != 0)]),
                     0x4 => {
                         let waveform = match param & 0b0000_0011 {
                             1 => Waveform::RampDown,
@@ -261,25 +340,25 @@ impl ModXmEffect {
             0x5 => return None,
             // - - Volume slide down (0..15)
             0x6 => {
-                return Some(TrackImportEffect::VolumeSlide0(
+                return Some(TrackImportEffect::VolumeSlideN(
                     -((current.volume & 0x0F) as f32) / 64.0,
                 ))
             }
             // + - Volume slide up (0..15)
             0x7 => {
-                return Some(TrackImportEffect::VolumeSlide0(
+                return Some(TrackImportEffect::VolumeSlideN(
                     ((current.volume & 0x0F) as f32) / 64.0,
                 ))
             }
             // D - Fine volume slide down (0..15)
             0x8 => {
-                return Some(TrackImportEffect::VolumeSlideN(
+                return Some(TrackImportEffect::VolumeSlide0(
                     -((current.volume & 0x0F) as f32) / 64.0,
                 ))
             }
             // U - Fine volume slide up (0..15)
             0x9 => {
-                return Some(TrackImportEffect::VolumeSlideN(
+                return Some(TrackImportEffect::VolumeSlide0(
                     ((current.volume & 0x0F) as f32) / 64.0,
                 ))
             }
@@ -302,17 +381,21 @@ impl ModXmEffect {
                 }
             }
             // P - Set panning
-            0xC => return Some(TrackImportEffect::Panning(((current.volume & 0x0F)as f32) / 16.0)),
+            0xC => {
+                return Some(TrackImportEffect::Panning(
+                    ((current.volume & 0x0F) as f32) / 16.0,
+                ))
+            }
             // L - Panning slide left
             0xD => {
                 return Some(TrackImportEffect::PanningSlideN(
-                    -(current.volume as f32) / 16.0,
+                    -((current.volume & 0x0F) as f32) / 16.0,
                 ))
             }
             // R - Panning slide right
             0xE => {
                 return Some(TrackImportEffect::PanningSlideN(
-                    (current.volume as f32) / 16.0,
+                    ((current.volume & 0x0F) as f32) / 16.0,
                 ))
             }
             // M - Tone portamento (0..15)
@@ -437,4 +520,503 @@ impl ModXmEffect {
             .map(|row| Self::mod_xm_unpack_row(freq_type, &row))
             .collect()
     }
+
+    //--- pack: TrackUnit to PatternSlot --------------------------------------
+
+    pub(crate) fn nibble(value: f32) -> Option<u8> {
+        let v = value.round();
+        if (0.0..=15.0).contains(&v) {
+            Some(v as u8)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn byte(value: f32) -> Option<u8> {
+        let v = value.round();
+        if (0.0..=255.0).contains(&v) {
+            Some(v as u8)
+        } else {
+            None
+        }
+    }
+
+    fn mod_xm_pack_waveform(waveform: &Waveform, retrig: bool) -> Option<u8> {
+        let w = match waveform {
+            Waveform::Sine => 0,
+            Waveform::RampDown => 1,
+            Waveform::Square => 2,
+            _ => return None,
+        };
+        Some(if retrig { w } else { w | 0b0000_0100 })
+    }
+
+    /// `x` of Rxy (XM) and Qxy (IT)
+    pub(crate) fn mod_xm_pack_retrig_operator(volume_modifier: &NoteRetrigOperator) -> Option<u8> {
+        match volume_modifier {
+            NoteRetrigOperator::None => Some(0x0),
+            NoteRetrigOperator::Sum(v) => match *v as isize {
+                -1 => Some(0x1),
+                -2 => Some(0x2),
+                -4 => Some(0x3),
+                -8 => Some(0x4),
+                -16 => Some(0x5),
+                1 => Some(0x9),
+                2 => Some(0xA),
+                4 => Some(0xB),
+                8 => Some(0xC),
+                16 => Some(0xD),
+                _ => None,
+            },
+            NoteRetrigOperator::Mul(v) => {
+                if *v == 2.0 / 3.0 {
+                    Some(0x6)
+                } else if *v == 1.0 / 2.0 {
+                    Some(0x7)
+                } else if *v == 3.0 / 2.0 {
+                    Some(0xE)
+                } else if *v == 2.0 {
+                    Some(0xF)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn mod_xm_pack_volume_slide(speed: f32) -> Option<u8> {
+        if speed >= 0.0 {
+            Some(Self::nibble(speed * 64.0)? << 4)
+        } else {
+            Self::nibble(-speed * 64.0)
+        }
+    }
+
+    fn mod_xm_pack_tone_portamento(freq_type: FrequencyType, speed: f32) -> f32 {
+        match freq_type {
+            FrequencyType::LinearFrequencies => speed / 4.0,
+            FrequencyType::AmigaFrequencies => speed,
+        }
+    }
+
+    /// Effect column (type, parameter)
+    pub(crate) fn mod_xm_pack_effect(
+        freq_type: FrequencyType,
+        effect: &TrackEffect,
+    ) -> Option<(u8, u8)> {
+        match effect {
+            TrackEffect::Arpeggio { half1, half2 } => {
+                if *half1 > 15 || *half2 > 15 {
+                    return None;
+                }
+                Some((0x00, (*half1 as u8) << 4 | *half2 as u8))
+            }
+            TrackEffect::Portamento { speed, fine } => {
+                let (fx, fx_fine, speed) = if *speed < 0.0 {
+                    (0x01, 0x10, -speed)
+                } else {
+                    (0x02, 0x20, *speed)
+                };
+                let param = speed / 4.0;
+                if param.fract() != 0.0 {
+                    if !fine {
+                        return None;
+                    }
+                    // Extra fine portamento
+                    return Some((0x21, fx_fine | Self::nibble(speed)?));
+                }
+                if *fine {
+                    return Some((0x0E, fx_fine | Self::nibble(param)?));
+                }
+                Some((fx, Self::byte(param)?))
+            }
+            TrackEffect::TonePortamento(speed) => {
+                let param = Self::mod_xm_pack_tone_portamento(freq_type, *speed);
+                Some((0x03, Self::byte(param)?))
+            }
+            TrackEffect::Vibrato { speed, depth } => {
+                let x = Self::nibble(speed * 64.0)?;
+                let y = Self::nibble(depth * 16.0)?;
+                Some((0x04, x << 4 | y))
+            }
+            TrackEffect::Tremolo { speed, depth } => {
+                let x = Self::nibble(speed * 64.0)?;
+                let y = Self::nibble(depth * 16.0)?;
+                Some((0x07, x << 4 | y))
+            }
+            TrackEffect::Panning(pos) => Some((0x08, Self::byte(pos * 255.0)?)),
+            TrackEffect::InstrumentSampleOffset(offset) => {
+                if offset % 256 != 0 || *offset > 0xFF00 {
+                    return None;
+                }
+                Some((0x09, (offset / 256) as u8))
+            }
+            TrackEffect::VolumeSlide { speed, fine: false } => {
+                Some((0x0A, Self::mod_xm_pack_volume_slide(*speed)?))
+            }
+            TrackEffect::VolumeSlide { speed, fine: true } => {
+                if *speed >= 0.0 {
+                    Some((0x0E, 0xA0 | Self::nibble(speed * 64.0)?))
+                } else {
+                    Some((0x0E, 0xB0 | Self::nibble(-speed * 64.0)?))
+                }
+            }
+            TrackEffect::Volume { value, tick: 0 } => {
+                Some((0x0C, Self::byte(value * 64.0)?.min(64)))
+            }
+            TrackEffect::Glissando(on) => Some((0x0E, 0x30 | *on as u8)),
+            TrackEffect::VibratoWaveform { waveform, retrig } => {
+                Some((0x0E, 0x40 | Self::mod_xm_pack_waveform(waveform, *retrig)?))
+            }
+            TrackEffect::InstrumentFineTune(tune) => {
+                Some((0x0E, 0x50 | Self::nibble((tune + 1.0) * 8.0)?))
+            }
+            TrackEffect::TremoloWaveform { waveform, retrig } => {
+                Some((0x0E, 0x70 | Self::mod_xm_pack_waveform(waveform, *retrig)?))
+            }
+            TrackEffect::NoteRetrig {
+                speed,
+                volume_modifier: NoteRetrigOperator::None,
+            } => Some((0x0E, 0x90 | Self::nibble(*speed as f32)?)),
+            TrackEffect::NoteRetrig {
+                speed,
+                volume_modifier,
+            } => Some((
+                0x1B,
+                Self::mod_xm_pack_retrig_operator(volume_modifier)? << 4
+                    | Self::nibble(*speed as f32)?,
+            )),
+            TrackEffect::NoteCut { tick, past: false } => {
+                Some((0x0E, 0xC0 | Self::nibble(*tick as f32)?))
+            }
+            TrackEffect::NoteDelay(tick) => Some((0x0E, 0xD0 | Self::nibble(*tick as f32)?)),
+            TrackEffect::NoteOff { tick, past: false } => Some((0x14, Self::byte(*tick as f32)?)),
+            TrackEffect::InstrumentVolumeEnvelopePosition(pos) => {
+                Some((0x15, Self::byte(*pos as f32)?))
+            }
+            TrackEffect::PanningSlide { speed, fine: false } => {
+                if *speed >= 0.0 {
+                    Some((0x19, Self::nibble(speed * 16.0)? << 4))
+                } else {
+                    Some((0x19, Self::nibble(-speed * 16.0)?))
+                }
+            }
+            TrackEffect::Tremor { on_time, off_time } => {
+                let x = Self::nibble(*on_time as f32)?;
+                let y = Self::nibble(*off_time as f32)?;
+                Some((0x1D, x << 4 | y))
+            }
+            _ => None,
+        }
+    }
+
+    /// Volume column byte
+    fn mod_xm_pack_volume(freq_type: FrequencyType, effect: &TrackEffect) -> Option<u8> {
+        match effect {
+            TrackEffect::Volume { value, tick: 0 } => {
+                Some(0x10 + Self::byte(value * 64.0)?.min(64))
+            }
+            TrackEffect::VolumeSlide { speed, fine } => {
+                let (down, up) = if *fine { (0x80, 0x90) } else { (0x60, 0x70) };
+                if *speed >= 0.0 {
+                    Some(up | Self::nibble(speed * 64.0)?)
+                } else {
+                    Some(down | Self::nibble(-speed * 64.0)?)
+                }
+            }
+            TrackEffect::VibratoSpeed(speed) => Some(0xA0 | Self::nibble(speed * 64.0)?),
+            TrackEffect::VibratoDepth(depth) => Some(0xB0 | Self::nibble(depth * 16.0)?),
+            TrackEffect::Panning(pos) => Some(0xC0 | Self::nibble(pos * 16.0)?.min(15)),
+            TrackEffect::PanningSlide { speed, fine: false } => {
+                if *speed >= 0.0 {
+                    Some(0xE0 | Self::nibble(speed * 16.0)?)
+                } else {
+                    Some(0xD0 | Self::nibble(-speed * 16.0)?)
+                }
+            }
+            TrackEffect::TonePortamento(speed) => {
+                let param = Self::mod_xm_pack_tone_portamento(freq_type, *speed) / 16.0;
+                Some(0xF0 | Self::nibble(param)?)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn mod_xm_pack_global_effect(effect: &GlobalEffect) -> Option<(u8, u8)> {
+        match effect {
+            GlobalEffect::PositionJump(pos) => Some((0x0B, Self::byte(*pos as f32)?)),
+            GlobalEffect::PatternBreak(row) => {
+                if *row > 99 {
+                    return None;
+                }
+                Some((0x0D, ((row / 10) << 4 | (row % 10)) as u8))
+            }
+            GlobalEffect::PatternLoop(count) => Some((0x0E, 0x60 | Self::nibble(*count as f32)?)),
+            GlobalEffect::PatternDelay {
+                quantity,
+                tempo: true,
+            } => Some((0x0E, 0xE0 | Self::nibble(*quantity as f32)?)),
+            GlobalEffect::Speed(speed) => {
+                if *speed == 0 || *speed >= 32 {
+                    return None;
+                }
+                Some((0x0F, *speed as u8))
+            }
+            GlobalEffect::Bpm(bpm) => {
+                if *bpm < 32 || *bpm > 255 {
+                    return None;
+                }
+                Some((0x0F, *bpm as u8))
+            }
+            GlobalEffect::Volume(value) => Some((0x10, Self::byte(value * 64.0)?.min(64))),
+            GlobalEffect::VolumeSlide { speed, fine: false } => {
+                Some((0x11, Self::mod_xm_pack_volume_slide(*speed)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Try to merge a volume slide with the effect column using `5xy` or `6xy`.
+    /// Tone portamento and vibrato parameters are then taken from tracker memory,
+    /// so only if `memory` already holds them.
+    pub(crate) fn mod_xm_pack_merge(
+        freq_type: FrequencyType,
+        memory: &ModXmMemory,
+        fx: (u8, u8),
+        effect: &TrackEffect,
+    ) -> Option<(u8, u8)> {
+        match (fx.0, effect) {
+            (0x03, TrackEffect::VolumeSlide { speed, fine: false })
+                if memory.has_tone_portamento(fx.1) =>
+            {
+                Some((0x05, Self::mod_xm_pack_volume_slide(*speed)?))
+            }
+            (0x04, TrackEffect::VolumeSlide { speed, fine: false }) if memory.has_vibrato(fx.1) => {
+                Some((0x06, Self::mod_xm_pack_volume_slide(*speed)?))
+            }
+            (0x0A, TrackEffect::TonePortamento(_)) => {
+                let (_, param) = Self::mod_xm_pack_effect(freq_type, effect)?;
+                if !memory.has_tone_portamento(param) {
+                    return None;
+                }
+                Some((0x05, fx.1))
+            }
+            (0x0A, TrackEffect::Vibrato { .. }) => {
+                let (_, param) = Self::mod_xm_pack_effect(freq_type, effect)?;
+                if !memory.has_vibrato(param) {
+                    return None;
+                }
+                Some((0x06, fx.1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Best effort to save a `TrackUnit` into one volume column and one effect column.
+    /// Global effects have priority on the effect column.
+    /// All effects which can't be saved are returned.
+    /// `memory` is the channel memory from the previous rows of the pattern.
+    pub fn mod_xm_pack(
+        freq_type: FrequencyType,
+        memory: &mut ModXmMemory,
+        current: &TrackUnit,
+    ) -> (PatternSlot, ModXmLost) {
+        Self::mod_xm_pack_slot(freq_type, memory, current, false)
+    }
+
+    /// Same as `mod_xm_pack` for MOD: no volume column and only effects `0` to `F`.
+    /// Note off is saved as a note cut.
+    pub fn mod_pack(memory: &mut ModXmMemory, current: &TrackUnit) -> (PatternSlot, ModXmLost) {
+        Self::mod_xm_pack_slot(FrequencyType::AmigaFrequencies, memory, current, true)
+    }
+
+    fn mod_xm_pack_slot(
+        freq_type: FrequencyType,
+        memory: &mut ModXmMemory,
+        current: &TrackUnit,
+        amiga: bool,
+    ) -> (PatternSlot, ModXmLost) {
+        let amiga_fx = |fx: Option<(u8, u8)>| fx.filter(|&(t, _)| !amiga || t <= 0x0F);
+        let mut ps = PatternSlot::default();
+        let mut lost = ModXmLost::default();
+        let mut fx: Option<(u8, u8)> = None;
+        let mut vol: Option<u8> = None;
+
+        ps.instrument = current.instrument;
+        let mut effects: Vec<TrackEffect> = vec![];
+        if current.note == Pitch::Cut || (amiga && current.note == Pitch::Off) {
+            // no cut note in XM
+            effects.push(TrackEffect::NoteCut {
+                tick: 0,
+                past: false,
+            });
+        } else {
+            ps.note = current.note;
+        }
+        effects.extend(current.effects.iter().cloned());
+
+        for ge in &current.global_effects {
+            match (fx, amiga_fx(Self::mod_xm_pack_global_effect(ge))) {
+                (None, Some(e)) => fx = Some(e),
+                _ => lost.global_effects.push(ge.clone()),
+            }
+        }
+
+        for te in &effects {
+            let e = amiga_fx(Self::mod_xm_pack_effect(freq_type, te));
+            let v = if amiga {
+                None
+            } else {
+                Self::mod_xm_pack_volume(freq_type, te)
+            };
+            let volume_first = matches!(
+                te,
+                TrackEffect::Volume { .. }
+                    | TrackEffect::VibratoSpeed(_)
+                    | TrackEffect::VibratoDepth(_)
+            );
+            if volume_first && vol.is_none() && v.is_some() {
+                vol = v;
+            } else if fx.is_none() && e.is_some() {
+                fx = e;
+            } else if vol.is_none() && v.is_some() {
+                vol = v;
+            } else if let Some(m) =
+                fx.and_then(|f| Self::mod_xm_pack_merge(freq_type, memory, f, te))
+            {
+                fx = Some(m);
+            } else {
+                lost.effects.push(te.clone());
+            }
+        }
+
+        memory.set_xm(fx, vol);
+        if let Some((t, p)) = fx {
+            ps.effect_type = t;
+            ps.effect_parameter = p;
+        }
+        if let Some(v) = vol {
+            ps.volume = v;
+        }
+        (ps, lost)
+    }
+
+    pub fn mod_xm_pack_pattern(
+        freq_type: FrequencyType,
+        pattern: &Pattern,
+        number_of_channels: usize,
+    ) -> (Vec<Vec<PatternSlot>>, Vec<ModXmLost>) {
+        let mut slots: Vec<Vec<PatternSlot>> = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        let channels = pattern.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut memory = vec![ModXmMemory::default(); channels];
+        for (r, row) in pattern.iter().enumerate() {
+            let mut line = vec![PatternSlot::default(); number_of_channels];
+            for (c, tu) in row.iter().enumerate() {
+                let (ps, mut lost) = Self::mod_xm_pack(freq_type, &mut memory[c], tu);
+                if c < number_of_channels {
+                    line[c] = ps;
+                } else {
+                    lost.note = tu.note;
+                    lost.instrument = tu.instrument;
+                    lost.effects = tu.effects.clone();
+                    lost.global_effects = tu.global_effects.clone();
+                }
+                if !lost.is_empty() {
+                    lost.row = r;
+                    lost.channel = c;
+                    all_lost.push(lost);
+                }
+            }
+            slots.push(line);
+        }
+        (slots, all_lost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModXmEffect;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn xm_save_effects() {
+        use crate::prelude::*;
+
+        let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let tu = &mut module.pattern[0][0][0];
+        tu.effects = vec![
+            TrackEffect::Volume {
+                value: 0.5,
+                tick: 0,
+            },
+            TrackEffect::Vibrato {
+                speed: 2.0 / 64.0,
+                depth: 3.0 / 16.0,
+            },
+        ];
+        tu.global_effects = vec![GlobalEffect::Speed(3)];
+        let tu = tu.clone();
+        let (data, lost) = module.save_xm_with_losses().unwrap();
+        let module2 = Module::load_xm(&data).unwrap();
+        let tu2 = &module2.pattern[0][0][0];
+        assert_eq!(tu2.global_effects, tu.global_effects);
+        // vibrato can't be saved: effect column is used by speed
+        assert_eq!(tu2.effects, tu.effects[0..1]);
+        assert_eq!(lost.len(), 1);
+        assert_eq!((lost[0].pattern, lost[0].row, lost[0].channel), (0, 0, 0));
+        assert_eq!(lost[0].effects, tu.effects[1..]);
+
+        // nothing of a channel beyond the saved ones is kept
+        let (_, lost) =
+            ModXmEffect::mod_xm_pack_pattern(module.frequency_type, &module.pattern[0], 0);
+        assert_eq!(lost[0].note, tu.note);
+        assert_eq!(lost[0].instrument, tu.instrument);
+        assert_eq!(lost[0].effects, tu.effects);
+
+        // fine (E1x) and extra fine (X2x) portamentos are kept apart from normal ones
+        let portamentos = vec![
+            TrackEffect::Portamento {
+                speed: -8.0,
+                fine: true,
+            },
+            TrackEffect::Portamento {
+                speed: 3.0,
+                fine: true,
+            },
+        ];
+        for te in portamentos {
+            module.pattern[0][1][0].effects = vec![te.clone()];
+            let module2 = Module::load_xm(&module.save_xm().unwrap()).unwrap();
+            assert_eq!(module2.pattern[0][1][0].effects, vec![te]);
+        }
+
+        // 5xy takes the tone portamento speed from channel memory only
+        let volume = TrackEffect::Volume {
+            value: 0.5,
+            tick: 0,
+        };
+        let tone = TrackEffect::TonePortamento(64.0);
+        let slide = TrackEffect::VolumeSlide {
+            speed: 1.0 / 64.0,
+            fine: false,
+        };
+        let row = |effects: Vec<TrackEffect>| {
+            vec![TrackUnit {
+                effects,
+                ..Default::default()
+            }]
+        };
+        let pattern = vec![
+            row(vec![volume.clone(), tone.clone(), slide.clone()]),
+            row(vec![volume, tone, slide.clone()]),
+        ];
+        let (slots, lost) =
+            ModXmEffect::mod_xm_pack_pattern(FrequencyType::LinearFrequencies, &pattern, 1);
+        assert_eq!(lost.len(), 1);
+        assert_eq!((lost[0].row, &lost[0].effects), (0, &vec![slide]));
+        assert_eq!(slots[0][0].effect_type, 0x03);
+        assert_eq!(slots[1][0].effect_type, 0x05);
+    }
 }
@@ -1,12 +1,24 @@
+use crate::error::Error;
+use crate::format::Format;
 /// Original XM Pattern Slot
 use crate::import::patternslot::PatternSlot;
 use crate::pitch::Pitch;
-use bincode::error::DecodeError;
 
 use alloc::vec::Vec;
 
 impl PatternSlot {
-    pub fn load_xm(src: &[u8]) -> Result<(&[u8], PatternSlot), DecodeError> {
+    pub fn load_xm(src: &[u8]) -> Result<(&[u8], PatternSlot), Error> {
+        let len = match src.first() {
+            Some(note) if note & 0b1000_0000 != 0 => 1 + (note & 0b0001_1111).count_ones() as usize,
+            _ => 5,
+        };
+        if src.len() < len {
+            return Err(Error::Truncated {
+                format: Format::Xm,
+                structure: "pattern slot",
+                offset: 0,
+            });
+        }
         let mut dst: [u8; 5] = [0; 5];
         let mut i = 0;
         let mut j = 0;
@@ -95,22 +107,28 @@ impl PatternSlot {
         ))
     }
 
+    /// XM note byte: 0 is no note, 1..=96 are C-0..B-7, 97 is key off
+    fn xm_note(&self) -> u8 {
+        match self.note {
+            Pitch::None => 0,
+            Pitch::Off | Pitch::Cut => 97,
+            n if n.value() < 96 => n.value() + 1,
+            _ => 0,
+        }
+    }
+
+    /// XM instrument byte: 0 is no instrument, 1..=128
+    fn xm_instrument(&self) -> u8 {
+        match self.instrument {
+            Some(instr) if instr < 128 => instr as u8 + 1,
+            _ => 0,
+        }
+    }
+
     pub fn save_xm_unpack(&self) -> Vec<u8> {
         let mut bytes: [u8; 5] = [0; 5];
-        bytes[0] = {
-            if self.note.is_keyoff() {
-                97
-            } else {
-                self.note.value() + 1
-            }
-        };
-        bytes[1] = {
-            if let Some(instr) = self.instrument {
-                instr as u8 - 1
-            } else {
-                0
-            }
-        };
+        bytes[0] = self.xm_note();
+        bytes[1] = self.xm_instrument();
         bytes[2] = self.volume;
         bytes[3] = self.effect_type;
         bytes[4] = self.effect_parameter;
@@ -119,20 +137,8 @@ impl PatternSlot {
 
     pub fn save_xm(&self) -> Vec<u8> {
         let mut bytes: [u8; 5] = [0; 5];
-        bytes[0] = {
-            if self.note.is_keyoff() {
-                97
-            } else {
-                self.note.value() + 1
-            }
-        };
-        bytes[1] = {
-            if let Some(instr) = self.instrument {
-                instr as u8 - 1
-            } else {
-                0
-            }
-        };
+        bytes[0] = self.xm_note();
+        bytes[1] = self.xm_instrument();
         bytes[2] = self.volume;
         bytes[3] = self.effect_type;
         bytes[4] = self.effect_parameter;
@@ -1,10 +1,14 @@
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 use serde::{Deserialize, Serialize};
 
 use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::Error;
+use crate::format::{Confidence, Format};
 
 use super::serde_helper::{deserialize_string_20, serialize_string_20};
 use super::serde_helper::{deserialize_string_21, serialize_string_21};
@@ -60,17 +64,34 @@ pub struct XiInstrument {
 }
 
 impl XiInstrument {
-    pub fn load(data: &[u8]) -> Result<XmInstrument, DecodeError> {
+    /// Header sniffing, the instrument is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        if !data.starts_with(b"Extended Instrument:") {
+            return None;
+        }
+        match bincode::serde::decode_from_slice::<XiInstrumentHeader, _>(
+            data,
+            bincode::config::legacy(),
+        ) {
+            Ok((xih, _)) if xih.right_arrow == 0x1A => Some(Confidence::High),
+            _ => Some(Confidence::Medium),
+        }
+    }
+
+    pub fn load(data: &[u8]) -> Result<XmInstrument, Error> {
+        if !data.starts_with(b"Extended Instrument:") {
+            return Err(Error::BadMagic {
+                format: Format::Xi,
+                offset: 0,
+            });
+        }
         let xi =
-            bincode::serde::decode_from_slice::<XiInstrument, _>(data, bincode::config::legacy())?
+            bincode::serde::decode_from_slice::<XiInstrument, _>(data, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xi, "header", 0, e))?
                 .0;
         let seek = XMINSTRUMENT_HEADER + XMINSTRDEFAULT_SIZE + 15 + 2;
         let data = &data[seek..];
 
-        if xi.header.id_text != "Extended Instrument:" {
-            return Err(DecodeError::Other("Not an Extended Instrument?"));
-        }
-
         //---
 
         let header = XmInstrumentHeader {
@@ -95,13 +116,16 @@ impl XiInstrument {
 
         let mut d3 = data;
         for _ in 0..xi.num_samples {
-            let (d, s) = XmSample::load(d3)?;
+            let (d, s) = XmSample::load(d3)
+                .map_err(|e| e.with_format(Format::Xi).at(seek + data.len() - d3.len()))?;
             sample.push(s);
             d3 = d;
         }
 
         for s in &mut sample {
-            let d = s.add_sample(d3)?;
+            let d = s
+                .add_sample(d3)
+                .map_err(|e| e.with_format(Format::Xi).at(seek + data.len() - d3.len()))?;
             d3 = d;
         }
 
@@ -115,4 +139,68 @@ impl XiInstrument {
 
         return Ok(xmi);
     }
+
+    /// Inverse of `load`
+    pub fn save(xmi: &mut XmInstrument) -> Result<Vec<u8>, EncodeError> {
+        let instr = match &xmi.instr {
+            XmInstrumentType::Default(xmid) => **xmid,
+            XmInstrumentType::Empty => {
+                return Err(EncodeError::Other(
+                    "XiInstrument: not a sample based instrument",
+                ))
+            }
+        };
+        if xmi.sample.len() > 16 {
+            return Err(EncodeError::Other("XiInstrument: more than 16 samples"));
+        }
+
+        let xi = XiInstrument {
+            header: XiInstrumentHeader {
+                name: xmi.header.name.clone(),
+                ..Default::default()
+            },
+            instr,
+            reserved: [0; 15],
+            num_samples: xmi.sample.len() as u16,
+        };
+        let mut all = bincode::serde::encode_to_vec(&xi, bincode::config::legacy())?;
+
+        // all samples headers, then data...
+        for s in &mut xmi.sample {
+            all.append(&mut s.save()?);
+        }
+        for s in &mut xmi.sample {
+            all.append(&mut s.save_sample()?);
+        }
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn xi_save_load() {
+        use crate::import::xm::xi_instrument::XiInstrument;
+        use crate::prelude::*;
+
+        let module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let instr = &module.instrument[0];
+        let data = instr.save_xi().unwrap();
+        assert_eq!(&data[0..21], b"Extended Instrument: ");
+        let instr2 = XiInstrument::load(&data).unwrap().to_instrument();
+        assert_eq!(data, instr2.save_xi().unwrap());
+        assert_eq!(instr.name, instr2.name);
+        match (&instr.instr_type, &instr2.instr_type) {
+            (InstrumentType::Default(id), InstrumentType::Default(id2)) => {
+                assert_eq!(id.sample.len(), id2.sample.len());
+                assert_eq!(
+                    id.volume_envelope.point.len(),
+                    id2.volume_envelope.point.len()
+                );
+            }
+            _ => panic!("not a sample based instrument"),
+        }
+
+        assert!(Instrument::default().save_xi().is_err());
+    }
 }
@@ -1,5 +1,5 @@
 /// Original XM Header
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +7,9 @@ use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
 
+use crate::error::Error;
+use crate::format::{Confidence, Format};
+
 use super::serde_helper::{deserialize_string_17, serialize_string_17};
 use super::serde_helper::{deserialize_string_20, serialize_string_20};
 
@@ -70,26 +73,35 @@ impl Default for XmHeader {
 }
 
 impl XmHeader {
+    /// Header sniffing, the module is not loaded
+    pub fn detect(data: &[u8]) -> Option<Confidence> {
+        if !data.starts_with(b"Extended Module:") {
+            return None;
+        }
+        match bincode::serde::decode_from_slice::<XmHeader, _>(data, bincode::config::legacy()) {
+            Ok((xmh, _)) if xmh.right_arrow == 0x1A => Some(Confidence::High),
+            _ => Some(Confidence::Medium),
+        }
+    }
+
     /* return like nom (&[u8], (XmHeader, PatternOrder) ) */
-    pub fn load(ser_xmheader: &[u8]) -> Result<(&[u8], XmHeader, Vec<u8>), DecodeError> {
-        match bincode::serde::decode_from_slice::<XmHeader, _>(
+    pub fn load(ser_xmheader: &[u8]) -> Result<(&[u8], XmHeader, Vec<u8>), Error> {
+        if !ser_xmheader.starts_with(b"Extended Module:") {
+            return Err(Error::BadMagic {
+                format: Format::Xm,
+                offset: 0,
+            });
+        }
+        let (xmh, _) = bincode::serde::decode_from_slice::<XmHeader, _>(
             ser_xmheader,
             bincode::config::legacy(),
-        ) {
-            Ok((xmh, _)) => {
-                if xmh.id_text != "Extended Module:" {
-                    return Err(DecodeError::Other("Not an Extended Module?"));
-                }
-                match xmh.get_pattern_order(&ser_xmheader[80..]) {
-                    Ok((data, pattern_order)) => Ok((data, xmh, pattern_order)),
-                    Err(e) => Err(e),
-                }
-            }
-            Err(e) => Err(e),
-        }
+        )
+        .map_err(|e| Error::decode(Format::Xm, "header", 0, e))?;
+        let (data, pattern_order) = xmh.get_pattern_order(&ser_xmheader[80..])?;
+        Ok((data, xmh, pattern_order))
     }
 
-    fn get_pattern_order<'a>(&self, data: &'a [u8]) -> Result<(&'a [u8], Vec<u8>), DecodeError> {
+    fn get_pattern_order<'a>(&self, data: &'a [u8]) -> Result<(&'a [u8], Vec<u8>), Error> {
         let pattern_order_and_maybe_more_len: usize = self.header_size as usize - 20;
         if data.len() >= pattern_order_and_maybe_more_len
             && self.song_length as usize <= pattern_order_and_maybe_more_len
@@ -97,7 +109,24 @@ impl XmHeader {
             let pattern_order: Vec<u8> = data[0..self.song_length as usize].to_vec();
             Ok((&data[pattern_order_and_maybe_more_len..], pattern_order))
         } else {
-            Err(DecodeError::Other("XmHeader.header_size too big?"))
+            Err(Error::Truncated {
+                format: Format::Xm,
+                structure: "pattern order",
+                offset: 80,
+            })
+        }
+    }
+
+    /// Save header followed by a 256 bytes pattern order table
+    pub fn save(&mut self, pattern_order: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        if pattern_order.len() > 256 {
+            return Err(EncodeError::Other("XmHeader: pattern order too long"));
         }
+        self.song_length = pattern_order.len() as u16;
+        self.header_size = 20 + 256;
+        let mut data = bincode::serde::encode_to_vec(&self, bincode::config::legacy())?;
+        data.extend_from_slice(pattern_order);
+        data.resize(data.len() + 256 - pattern_order.len(), 0);
+        Ok(data)
     }
 }
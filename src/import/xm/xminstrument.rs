@@ -1,5 +1,5 @@
 /// Original XM Instrument
-use bincode::error::{DecodeError, EncodeError};
+use bincode::error::EncodeError;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
@@ -8,12 +8,21 @@ use alloc::string::String;
 use alloc::{vec, vec::Vec};
 
 use crate::envelope::{Envelope, EnvelopePoint};
+use crate::error::Error;
+use crate::format::Format;
 use crate::instr_default::InstrDefault;
 use crate::instrument::{Instrument, InstrumentType};
 use crate::module::Module;
 use crate::sample::Sample;
 use crate::waveform::Waveform;
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
 use super::serde_helper::{deserialize_string_22, serialize_string_22};
 use super::xmsample::{XmSample, XMSAMPLE_HEADER_SIZE};
 
@@ -35,6 +44,8 @@ impl XmInstrumentType {
 }
 
 pub const XMINSTRDEFAULT_SIZE: usize = 96 + 4 * 12 + 4 * 12 + 14 + 2 + 2 + 2 + 2 + 1;
+/// FT2 instrument header is 263 bytes, the end is reserved
+const XMINSTRDEFAULT_RESERVED: usize = 15;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct XmInstrDefault {
@@ -107,9 +118,9 @@ impl XmInstrDefault {
     fn from_envelope(e: &Envelope) -> [u8; 48] {
         let mut dst: [u8; 48] = [0; 48];
         let mut i = 0;
-        for ep in &e.point {
-            let f = ep.frame.to_le_bytes();
-            let v = ((64.0 * ep.value) as u16).to_le_bytes();
+        for ep in e.point.iter().take(12) {
+            let f = (ep.frame as u16).to_le_bytes();
+            let v = ((64.0 * ep.value).round().clamp(0.0, 64.0) as u16).to_le_bytes();
             dst[i] = f[0];
             dst[i + 1] = f[1];
             dst[i + 2] = v[0];
@@ -130,7 +141,7 @@ impl XmInstrDefault {
                     };
                 }
                 xmid.volume_envelope = Self::from_envelope(&id.volume_envelope);
-                xmid.number_of_volume_points = id.volume_envelope.point.len() as u8;
+                xmid.number_of_volume_points = id.volume_envelope.point.len().min(12) as u8;
                 xmid.volume_sustain_point = id.volume_envelope.sustain_start_point as u8;
                 xmid.volume_loop_start_point = id.volume_envelope.loop_start_point as u8;
                 xmid.volume_loop_end_point = id.volume_envelope.loop_end_point as u8;
@@ -145,7 +156,7 @@ impl XmInstrDefault {
                 }
 
                 xmid.panning_envelope = Self::from_envelope(&id.pan_envelope);
-                xmid.number_of_panning_points = id.pan_envelope.point.len() as u8;
+                xmid.number_of_panning_points = id.pan_envelope.point.len().min(12) as u8;
                 xmid.panning_sustain_point = id.pan_envelope.sustain_start_point as u8;
                 xmid.panning_loop_start_point = id.pan_envelope.loop_start_point as u8;
                 xmid.panning_loop_end_point = id.pan_envelope.loop_end_point as u8;
@@ -165,11 +176,11 @@ impl XmInstrDefault {
                     Waveform::TranslatedRampDown => 3,
                     _ => 0,
                 };
-                xmid.vibrato_sweep = (id.vibrato.sweep * 255.0) as u8;
-                xmid.vibrato_depth = (id.vibrato.depth * 15.0 * 2.0) as u8;
-                xmid.vibrato_rate = (id.vibrato.speed * 63.0 * 4.0) as u8;
+                xmid.vibrato_sweep = (id.vibrato.sweep * 255.0).round() as u8;
+                xmid.vibrato_depth = (id.vibrato.depth * 15.0 * 2.0).round() as u8;
+                xmid.vibrato_rate = (id.vibrato.speed * 63.0 * 4.0).round() as u8;
 
-                xmid.volume_fadeout = (id.volume_fadeout * 4095.0 * 4.0 * 2.0) as u16;
+                xmid.volume_fadeout = (id.volume_fadeout * 4095.0 * 4.0 * 2.0).round() as u16;
 
                 xmid.midi_on = if id.midi.muted { 0 } else { 1 };
                 xmid.midi_channel = id.midi.channel;
@@ -246,7 +257,7 @@ impl Default for XmInstrument {
 }
 
 impl XmInstrument {
-    pub fn load(data: &[u8]) -> Result<(&[u8], XmInstrument), DecodeError> {
+    pub fn load(data: &[u8]) -> Result<(&[u8], XmInstrument), Error> {
         let mut sample: Vec<XmSample> = vec![];
 
         // length
@@ -262,12 +273,21 @@ impl XmInstrument {
             // no data
             return Ok((&data[4..], XmInstrument::default()));
         }
+        let truncated = |structure, offset| Error::Truncated {
+            format: Format::Xm,
+            structure,
+            offset,
+        };
+        if data.len() < xmih_len.max(4 + XMINSTRUMENT_HEADER_SIZE) {
+            return Err(truncated("instrument header", 0));
+        }
 
         // xmih
         let xmih = bincode::serde::decode_from_slice::<XmInstrumentHeader, _>(
             &data[4..],
             bincode::config::legacy(),
-        )?
+        )
+        .map_err(|e| Error::decode(Format::Xm, "instrument header", 4, e))?
         .0;
 
         if xmih.num_samples == 0 {
@@ -283,12 +303,16 @@ impl XmInstrument {
         }
 
         // samples header
-        let d2 = &data[4 + XMINSTRUMENT_HEADER_SIZE..];
+        let seek = 4 + XMINSTRUMENT_HEADER_SIZE;
+        let d2 = &data[seek..];
         let _sample_header_size: u32 =
-            bincode::serde::decode_from_slice::<u32, _>(d2, bincode::config::legacy())?.0;
+            bincode::serde::decode_from_slice::<u32, _>(d2, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xm, "instrument header", seek, e))?
+                .0;
         let d2 = &d2[4..];
         let xmid = Box::new(
-            bincode::serde::decode_from_slice::<XmInstrDefault, _>(d2, bincode::config::legacy())?
+            bincode::serde::decode_from_slice::<XmInstrDefault, _>(d2, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xm, "instrument header", seek + 4, e))?
                 .0,
         );
 
@@ -296,13 +320,13 @@ impl XmInstrument {
 
         let mut d3 = &data[xmih_len..];
         for _ in 0..xmih.num_samples {
-            let (d, s) = XmSample::load(d3)?;
+            let (d, s) = XmSample::load(d3).map_err(|e| e.at(data.len() - d3.len()))?;
             sample.push(s);
             d3 = d;
         }
 
         for s in &mut sample {
-            let d = s.add_sample(d3)?;
+            let d = s.add_sample(d3).map_err(|e| e.at(data.len() - d3.len()))?;
             d3 = d;
         }
 
@@ -318,7 +342,22 @@ impl XmInstrument {
     }
 
     pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        self.header.num_samples = self.sample.len() as u16;
+
+        // no sample: only the short header
+        if self.sample.is_empty() || matches!(self.instr, XmInstrumentType::Empty) {
+            self.header.num_samples = 0;
+            self.instrument_header_len = 4 + XMINSTRUMENT_HEADER_SIZE as u32;
+            let mut all = bincode::serde::encode_to_vec::<u32, _>(
+                self.instrument_header_len,
+                bincode::config::legacy(),
+            )?;
+            all.append(&mut self.header.save()?);
+            return Ok(all);
+        }
+
         let mut i = self.instr.save()?;
+        i.resize(i.len() + XMINSTRDEFAULT_RESERVED, 0);
         let mut vs: Vec<u8> = vec![];
 
         // all headers
@@ -339,7 +378,6 @@ impl XmInstrument {
             bincode::config::legacy(),
         )?;
 
-        self.header.num_samples = self.sample.len() as u16;
         let mut h = self.header.save()?;
 
         let sample_header_size = XMSAMPLE_HEADER_SIZE as u32;
@@ -486,18 +524,18 @@ impl XmInstrument {
         }
     }
 
+    pub fn from_instr(i: &Instrument) -> Self {
+        XmInstrument {
+            instrument_header_len: 4 + XMINSTRUMENT_HEADER_SIZE as u32,
+            header: XmInstrumentHeader::from_instr(i),
+            sample_header_size: XMSAMPLE_HEADER_SIZE as u32,
+            instr: XmInstrDefault::from_instr(i),
+            sample: XmSample::from_instr(i),
+        }
+    }
+
     // All instr
     pub fn from_module(module: &Module) -> Vec<Self> {
-        let mut all: Vec<XmInstrument> = vec![];
-        for i in &module.instrument {
-            all.push(XmInstrument {
-                instrument_header_len: 4 + XMINSTRUMENT_HEADER_SIZE as u32,
-                header: XmInstrumentHeader::from_instr(i),
-                sample_header_size: XMSAMPLE_HEADER_SIZE as u32,
-                instr: XmInstrDefault::from_instr(i),
-                sample: XmSample::from_instr(i),
-            });
-        }
-        all
+        module.instrument.iter().map(Self::from_instr).collect()
     }
 }
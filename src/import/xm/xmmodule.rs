@@ -1,14 +1,16 @@
 /// Original XM Module
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 use serde::{Deserialize, Serialize};
 
 use alloc::format;
 use alloc::{vec, vec::Vec};
 
+use super::mod_xm_effect::ModXmLost;
 use super::xmheader::{XmFlagType, XmHeader};
 use super::xminstrument::XmInstrument;
 use super::xmpattern::XmPattern;
 
+use crate::error::Error;
 use crate::import::import_memory::{ImportMemory, MemoryType};
 use crate::import::orders_helper;
 use crate::import::patternslot::PatternSlot;
@@ -24,14 +26,15 @@ pub struct XmModule {
 }
 
 impl XmModule {
-    pub fn load(data: &[u8]) -> Result<Self, DecodeError> {
-        let (data, header, pattern_order) = XmHeader::load(data)?;
+    pub fn load(source: &[u8]) -> Result<Self, Error> {
+        let (data, header, pattern_order) = XmHeader::load(source)?;
         let mut data = data;
 
         // Create patterns from xm
         let mut pattern: Vec<XmPattern> = vec![];
         for _i in 0..header.number_of_patterns {
-            let (d2, xmp) = XmPattern::load(data, header.number_of_channels)?;
+            let (d2, xmp) = XmPattern::load(data, header.number_of_channels)
+                .map_err(|e| e.at(source.len() - data.len()))?;
             data = d2;
             pattern.push(xmp);
         }
@@ -46,7 +49,8 @@ impl XmModule {
         let mut instrument: Vec<XmInstrument> = vec![];
         for _i in 0..header.number_of_instruments {
             // Create instruments form xm
-            let (d2, xmi) = XmInstrument::load(data)?;
+            let (d2, xmi) =
+                XmInstrument::load(data).map_err(|e| e.at(source.len() - data.len()))?;
             data = d2;
             instrument.push(xmi);
         }
@@ -99,4 +103,109 @@ impl XmModule {
 
         module
     }
+
+    /// Best effort, everything which can't be saved in patterns is returned
+    pub fn from_module(module: &Module) -> Result<(Self, Vec<ModXmLost>), EncodeError> {
+        if module.pattern.len() > 256 {
+            return Err(EncodeError::Other("XmModule: more than 256 patterns"));
+        }
+        if module.instrument.len() > 128 {
+            return Err(EncodeError::Other("XmModule: more than 128 instruments"));
+        }
+        if module
+            .pattern_order
+            .iter()
+            .flatten()
+            .any(|&o| o >= 254 || o >= module.pattern.len())
+        {
+            return Err(EncodeError::Other("XmModule: bad pattern order index"));
+        }
+        // no song separator in XM
+        if module.pattern_order.len() > 1 {
+            return Err(EncodeError::Other("XmModule: more than one song"));
+        }
+
+        let number_of_channels = module
+            .pattern
+            .iter()
+            .flatten()
+            .map(|row| row.len())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        if number_of_channels > 64 {
+            return Err(EncodeError::Other("XmModule: more than 64 channels"));
+        }
+
+        let mut pattern: Vec<XmPattern> = vec![];
+        let mut all_lost: Vec<ModXmLost> = vec![];
+        for (index, p) in module.pattern.iter().enumerate() {
+            let (xmp, mut lost) =
+                XmPattern::from_pattern(module.frequency_type, p, number_of_channels)?;
+            for l in &mut lost {
+                l.pattern = index;
+            }
+            pattern.push(xmp);
+            all_lost.append(&mut lost);
+        }
+
+        let mut header = XmHeader::default();
+        header.name = module.name.clone();
+        header.flags = match module.frequency_type {
+            FrequencyType::AmigaFrequencies => XmFlagType::XmAmigaFrequencies,
+            FrequencyType::LinearFrequencies => XmFlagType::XmLinearFrequencies,
+        };
+        header.restart_position = module.restart_position as u16;
+        header.number_of_channels = number_of_channels as u16;
+        header.number_of_patterns = pattern.len() as u16;
+        header.number_of_instruments = module.instrument.len() as u16;
+        header.default_tempo = module.default_tempo as u16;
+        header.default_bpm = module.default_bpm as u16;
+
+        Ok((
+            XmModule {
+                header,
+                pattern_order: module
+                    .pattern_order
+                    .first()
+                    .map_or(vec![], |song| song.iter().map(|&o| o as u8).collect()),
+                pattern,
+                instrument: XmInstrument::from_module(module),
+            },
+            all_lost,
+        ))
+    }
+
+    pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        let mut all = self.header.save(&self.pattern_order)?;
+        for p in &mut self.pattern {
+            all.append(&mut p.save()?);
+        }
+        for i in &mut self.instrument {
+            all.append(&mut i.save()?);
+        }
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn xm_save_load() {
+        use crate::prelude::*;
+
+        let module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        let data = module.save_xm().unwrap();
+        let module2 = Module::load_xm(&data).unwrap();
+        assert_eq!(data, module2.save_xm().unwrap());
+        assert_eq!(module.pattern_order, module2.pattern_order);
+        assert_eq!(module.instrument.len(), module2.instrument.len());
+
+        // XM has a single order table
+        let mut module = module;
+        module.pattern_order.push(vec![0]);
+        assert!(module.save_xm().is_err());
+    }
 }
@@ -1,10 +1,16 @@
 /// Original XM Pattern
-use bincode::error::DecodeError;
+use bincode::error::EncodeError;
 use serde::{Deserialize, Serialize};
 
 use alloc::{vec, vec::Vec};
 
+use crate::error::Error;
+use crate::format::Format;
 use crate::import::patternslot::PatternSlot;
+use crate::module::Pattern;
+use crate::period_helper::FrequencyType;
+
+use super::mod_xm_effect::{ModXmEffect, ModXmLost};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct XmPatternHeader {
@@ -32,16 +38,20 @@ impl XmPatternHeader {
         ph
     }
 
-    pub fn load(data: &[u8]) -> Result<(&[u8], XmPatternHeader), DecodeError> {
-        match bincode::serde::decode_from_slice::<XmPatternHeader, _>(
+    pub fn load(data: &[u8]) -> Result<(&[u8], XmPatternHeader), Error> {
+        let (xmph, _) = bincode::serde::decode_from_slice::<XmPatternHeader, _>(
             data,
             bincode::config::legacy(),
-        ) {
-            Ok((xmph, _)) => {
-                let hl = xmph.pattern_header_len as usize;
-                Ok((&data[hl..], xmph))
-            }
-            Err(e) => Err(e),
+        )
+        .map_err(|e| Error::decode(Format::Xm, "pattern header", 0, e))?;
+        let hl = xmph.pattern_header_len as usize;
+        match data.get(hl..) {
+            Some(data) => Ok((data, xmph)),
+            None => Err(Error::Truncated {
+                format: Format::Xm,
+                structure: "pattern header",
+                offset: 0,
+            }),
         }
     }
 }
@@ -60,15 +70,21 @@ impl XmPattern {
         }
     }
 
-    pub fn load(data: &[u8], number_of_channels: u16) -> Result<(&[u8], XmPattern), DecodeError> {
+    pub fn load(data: &[u8], number_of_channels: u16) -> Result<(&[u8], XmPattern), Error> {
+        let start = data.len();
         let (data, xmph) = XmPatternHeader::load(data)?;
-        let (_data_out, xmps) = Self::get_slots(
-            &data[0..xmph.pattern_data_size as usize],
-            number_of_channels as usize,
-            xmph.num_rows as usize,
-        )
-        .unwrap();
+        let header_len = start - data.len();
         let seek = xmph.pattern_data_size as usize;
+        let Some(slots) = data.get(0..seek) else {
+            return Err(Error::Truncated {
+                format: Format::Xm,
+                structure: "pattern data",
+                offset: header_len,
+            });
+        };
+        let (_data_out, xmps) =
+            Self::get_slots(slots, number_of_channels as usize, xmph.num_rows as usize)
+                .map_err(|e| e.at(header_len))?;
 
         let xmp = Self {
             header: xmph,
@@ -91,7 +107,7 @@ impl XmPattern {
         data: &[u8],
         number_of_channels: usize,
         number_of_rows: usize,
-    ) -> Result<(&[u8], Vec<Vec<PatternSlot>>), DecodeError> {
+    ) -> Result<(&[u8], Vec<Vec<PatternSlot>>), Error> {
         let mut lines: Vec<Vec<PatternSlot>> = vec![];
         let mut row: Vec<PatternSlot> = vec![];
 
@@ -100,7 +116,7 @@ impl XmPattern {
             if d2.is_empty() {
                 break;
             }
-            let (d3, xps) = PatternSlot::load_xm(d2)?;
+            let (d3, xps) = PatternSlot::load_xm(d2).map_err(|e| e.at(data.len() - d2.len()))?;
             d2 = d3;
             row.push(xps);
             if row.len() == number_of_channels {
@@ -115,4 +131,53 @@ impl XmPattern {
 
         Ok((d2, lines))
     }
+
+    /// Best effort, everything which can't be saved is returned, see `ModXmEffect::mod_xm_pack_pattern`
+    pub fn from_pattern(
+        freq_type: FrequencyType,
+        pattern: &Pattern,
+        number_of_channels: usize,
+    ) -> Result<(Self, Vec<ModXmLost>), EncodeError> {
+        if pattern.len() > 256 {
+            return Err(EncodeError::Other("XmPattern: more than 256 rows"));
+        }
+        let (mut slots, lost) =
+            ModXmEffect::mod_xm_pack_pattern(freq_type, pattern, number_of_channels);
+        if slots.is_empty() {
+            slots.push(Self::get_empty_line(number_of_channels));
+        }
+        Ok((
+            Self {
+                header: XmPatternHeader::new(slots.len()),
+                pattern: slots,
+            },
+            lost,
+        ))
+    }
+
+    pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        let mut data: Vec<u8> = vec![];
+        let mut empty = true;
+        for row in &self.pattern {
+            for slot in row {
+                let mut b = slot.save_xm();
+                if b.len() != 1 {
+                    empty = false;
+                }
+                data.append(&mut b);
+            }
+        }
+        // FT2 saves nothing for an empty pattern
+        if empty {
+            data.clear();
+        }
+        if data.len() > u16::MAX as usize {
+            return Err(EncodeError::Other("XmPattern: packed data too big"));
+        }
+        self.header.num_rows = self.pattern.len() as u16;
+        self.header.pattern_data_size = data.len() as u16;
+        let mut all = bincode::serde::encode_to_vec(&self.header, bincode::config::legacy())?;
+        all.append(&mut data);
+        Ok(all)
+    }
 }
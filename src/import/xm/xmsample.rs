@@ -1,5 +1,5 @@
 /// Original XM Sample
-use bincode::error::{DecodeError, EncodeError};
+use bincode::error::EncodeError;
 use serde::{Deserialize, Serialize};
 
 use alloc::string::String;
@@ -7,9 +7,18 @@ use alloc::{vec, vec::Vec};
 
 use super::helper::*;
 use super::serde_helper::{deserialize_string_22, serialize_string_22};
+use crate::error::Error;
+use crate::format::Format;
 use crate::instrument::{Instrument, InstrumentType};
 use crate::sample::{LoopType, Sample, SampleDataType};
 
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
 pub const XMSAMPLE_HEADER_SIZE: usize = 40;
 
 #[derive(Default, Serialize, Deserialize, Debug)]
@@ -47,12 +56,11 @@ impl Default for XmSample {
 }
 
 impl XmSample {
-    pub fn load(data: &[u8]) -> Result<(&[u8], XmSample), DecodeError> {
-        let sh = bincode::serde::decode_from_slice::<XmSampleHeader, _>(
-            data,
-            bincode::config::legacy(),
-        )?
-        .0;
+    pub fn load(data: &[u8]) -> Result<(&[u8], XmSample), Error> {
+        let sh =
+            bincode::serde::decode_from_slice::<XmSampleHeader, _>(data, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xm, "sample header", 0, e))?
+                .0;
         // Now create XmSample
         let xms = XmSample {
             header: sh,
@@ -61,9 +69,15 @@ impl XmSample {
         Ok((&data[XMSAMPLE_HEADER_SIZE..], xms))
     }
 
-    pub fn add_sample<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    pub fn add_sample<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8], Error> {
         let data_len: usize = self.header.length as usize;
-        let slice = &data[..data_len];
+        let Some(slice) = data.get(..data_len) else {
+            return Err(Error::Truncated {
+                format: Format::Xm,
+                structure: "sample data",
+                offset: 0,
+            });
+        };
 
         let d3 = if self.header.flags & 0b0001_0000 != 0 {
             // 16 bits data
@@ -156,39 +170,62 @@ impl XmSample {
         }
     }
 
+    /// XM only knows mono samples
+    fn to_mono(data: &SampleDataType) -> SampleDataType {
+        match data {
+            SampleDataType::Stereo8(d) => SampleDataType::Mono8(
+                d.chunks_exact(2)
+                    .map(|lr| ((lr[0] as i16 + lr[1] as i16) / 2) as i8)
+                    .collect(),
+            ),
+            SampleDataType::Stereo16(d) => SampleDataType::Mono16(
+                d.chunks_exact(2)
+                    .map(|lr| ((lr[0] as i32 + lr[1] as i32) / 2) as i16)
+                    .collect(),
+            ),
+            SampleDataType::StereoFloat(d) => SampleDataType::Mono16(
+                d.chunks_exact(2)
+                    .map(|lr| {
+                        (((lr[0] + lr[1]) / 2.0).clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+                    })
+                    .collect(),
+            ),
+            mono => mono.clone(),
+        }
+    }
+
     pub fn from_instr(i: &Instrument) -> Vec<XmSample> {
         let mut output: Vec<XmSample> = vec![];
         if let InstrumentType::Default(id) = &i.instr_type {
             for s in &id.sample {
+                let mut xms = XmSample::default();
                 if let Some(s) = s {
+                    let data = s.data.as_ref().map(Self::to_mono);
+
                     let mut loop_start = s.loop_start;
                     let mut loop_length = s.loop_length;
 
-                    if let Some(SampleDataType::Mono16(_)) = &s.data {
-                        loop_start <<= 1;
-                        loop_length <<= 1;
-                    }
-
-                    let mut xms = XmSample::default();
-                    xms.header.length = match &s.data {
-                        Some(SampleDataType::Mono8(d)) => d.len() as u32,
-                        Some(SampleDataType::Mono16(d)) => 2 * d.len() as u32,
-                        Some(SampleDataType::Stereo8(d)) => 2 * d.len() as u32,
-                        Some(SampleDataType::Stereo16(d)) => 2 * 2 * d.len() as u32,
-                        Some(SampleDataType::StereoFloat(d)) => 4 * 2 * d.len() as u32,
+                    xms.header.length = match &data {
+                        Some(SampleDataType::Mono16(d)) => {
+                            loop_start <<= 1;
+                            loop_length <<= 1;
+                            2 * d.len() as u32
+                        }
+                        Some(d) => d.len() as u32,
                         None => 0,
                     };
                     xms.header.loop_start = loop_start;
                     xms.header.loop_length = loop_length;
-                    xms.header.volume = (s.volume * 64.0) as u8;
-                    xms.header.finetune = (s.finetune * 127.0) as i8;
+                    xms.header.volume = (s.volume * 64.0).round().clamp(0.0, 64.0) as u8;
+                    xms.header.finetune = (s.finetune * 127.0).round().clamp(-128.0, 127.0) as i8;
                     xms.header.flags = s.loop_flag.into();
-                    xms.header.panning = (s.panning * 255.0) as u8;
+                    xms.header.panning = (s.panning * 255.0).round().clamp(0.0, 255.0) as u8;
                     xms.header.relative_pitch = s.relative_pitch;
                     xms.header.name = s.name.clone();
-                    xms.data = s.data.clone();
-                    output.push(xms);
+                    xms.data = data;
                 }
+                // keep sample index for `sample_for_pitch`
+                output.push(xms);
             }
         }
         output
@@ -1,9 +1,63 @@
+use crate::error::Error;
+use crate::format::Format;
+use crate::import::import_memory::{ImportMemory, MemoryType};
 use crate::import::patternslot::PatternSlot;
+use crate::module::Pattern;
+use crate::period_helper::FrequencyType;
 use alloc::{vec, vec::Vec};
 
 pub struct XpPattern;
 
 impl XpPattern {
+    /// Inverse of `save`
+    pub fn load(data: &[u8]) -> Result<Vec<Vec<PatternSlot>>, Error> {
+        let (version, nrow) =
+            bincode::serde::decode_from_slice::<(u16, u16), _>(data, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xp, "header", 0, e))?
+                .0;
+        if version != 1 {
+            return Err(Error::UnsupportedVersion {
+                format: Format::Xp,
+                version: version as u32,
+                offset: 0,
+            });
+        }
+        let data = &data[4..];
+        if data.len() < nrow as usize * 32 * 5 {
+            return Err(Error::Truncated {
+                format: Format::Xp,
+                structure: "pattern",
+                offset: 4,
+            });
+        }
+
+        let mut pattern: Vec<Vec<PatternSlot>> = vec![];
+        for row in data.chunks_exact(32 * 5).take(nrow as usize) {
+            let mut r: Vec<PatternSlot> = vec![];
+            for d in row.chunks_exact(5) {
+                r.push(
+                    PatternSlot::load_xm(d)
+                        .map_err(|e| e.with_format(Format::Xp))?
+                        .1,
+                );
+            }
+            pattern.push(r);
+        }
+        Ok(pattern)
+    }
+
+    /// Effects are decoded like in a XM module
+    pub fn to_pattern(freq_type: FrequencyType, pattern: &[Vec<PatternSlot>]) -> Pattern {
+        let mut im = ImportMemory::default();
+        im.unpack_patterns(
+            freq_type,
+            MemoryType::Xm,
+            &vec![vec![0]],
+            &vec![pattern.to_vec()],
+        )
+        .remove(0)
+    }
+
     /// XP file must have 32 tracks per row
     pub fn save(pattern: &Vec<Vec<PatternSlot>>) -> Option<Vec<u8>> {
         let mut data: Vec<u8> = vec![];
@@ -29,3 +83,43 @@ impl XpPattern {
         Some(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn xp_xt_save_load() {
+        use crate::import::xm::mod_xm_effect::ModXmEffect;
+        use crate::import::xm::xp_pattern::XpPattern;
+        use crate::import::xm::xt_track::XtTrack;
+        use crate::prelude::*;
+
+        let mut module = Module::load_xm(include_bytes!("../../../examples/note.xm")).unwrap();
+        module.pattern[0][0][0].effects = vec![TrackEffect::Volume {
+            value: 0.5,
+            tick: 0,
+        }];
+        let freq_type = module.frequency_type;
+        let (slots, _lost) = ModXmEffect::mod_xm_pack_pattern(freq_type, &module.pattern[0], 32);
+
+        let data = XpPattern::save(&slots).unwrap();
+        let slots2 = XpPattern::load(&data).unwrap();
+        assert_eq!(data, XpPattern::save(&slots2).unwrap());
+        let pattern = XpPattern::to_pattern(freq_type, &slots2);
+        assert_eq!(pattern.len(), module.pattern[0].len());
+        assert_eq!(pattern[0][0].note, module.pattern[0][0][0].note);
+        assert_eq!(pattern[0][0].effects, module.pattern[0][0][0].effects);
+
+        let track: Vec<_> = slots.iter().map(|row| row[0]).collect();
+        let data = XtTrack::save(&track);
+        let track2 = XtTrack::load(&data).unwrap();
+        assert_eq!(data, XtTrack::save(&track2));
+        let track = XtTrack::to_track(freq_type, &track2);
+        assert_eq!(track.len(), pattern.len());
+        assert_eq!(track[0].effects, pattern[0][0].effects);
+
+        assert!(XtTrack::load(&data[0..data.len() - 1]).is_err());
+    }
+}
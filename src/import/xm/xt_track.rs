@@ -1,9 +1,58 @@
+use crate::error::Error;
+use crate::format::Format;
+use crate::import::import_memory::{ImportMemory, MemoryType};
 use crate::import::patternslot::PatternSlot;
+use crate::period_helper::FrequencyType;
+use crate::track_unit::TrackUnit;
 use alloc::{vec, vec::Vec};
 
 pub struct XtTrack;
 
 impl XtTrack {
+    /// Inverse of `save`
+    pub fn load(data: &[u8]) -> Result<Vec<PatternSlot>, Error> {
+        let (version, nrow) =
+            bincode::serde::decode_from_slice::<(u16, u16), _>(data, bincode::config::legacy())
+                .map_err(|e| Error::decode(Format::Xt, "header", 0, e))?
+                .0;
+        if version != 1 {
+            return Err(Error::UnsupportedVersion {
+                format: Format::Xt,
+                version: version as u32,
+                offset: 0,
+            });
+        }
+        let data = &data[4..];
+        if data.len() < nrow as usize * 5 {
+            return Err(Error::Truncated {
+                format: Format::Xt,
+                structure: "track",
+                offset: 4,
+            });
+        }
+
+        let mut track: Vec<PatternSlot> = vec![];
+        for d in data.chunks_exact(5).take(nrow as usize) {
+            track.push(
+                PatternSlot::load_xm(d)
+                    .map_err(|e| e.with_format(Format::Xt))?
+                    .1,
+            );
+        }
+        Ok(track)
+    }
+
+    /// Effects are decoded like in a XM module
+    pub fn to_track(freq_type: FrequencyType, track: &[PatternSlot]) -> Vec<TrackUnit> {
+        let pattern: Vec<Vec<PatternSlot>> = track.iter().map(|ps| vec![*ps]).collect();
+        let mut im = ImportMemory::default();
+        im.unpack_patterns(freq_type, MemoryType::Xm, &vec![vec![0]], &vec![pattern])
+            .remove(0)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
     /// Here we use `Vec<PatternSlot>` like a track _not_ like a Pattern row!
     pub fn save(track: &Vec<PatternSlot>) -> Vec<u8> {
         let mut data: Vec<u8> = vec![];
@@ -89,6 +89,23 @@ impl Default for DuplicateCheckType {
     }
 }
 
+impl DuplicateCheckAction {
+    pub fn new_note_action(&self) -> NewNoteAction {
+        match self {
+            Self::NoteCut(nna) | Self::NoteOff(nna) | Self::NoteFadeOut(nna) => *nna,
+        }
+    }
+}
+
+impl DuplicateCheckType {
+    pub fn new_note_action(&self) -> NewNoteAction {
+        match self {
+            Self::Off(nna) => *nna,
+            Self::Note(dca) | Self::Sample(dca) | Self::Instrument(dca) => dca.new_note_action(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InstrDefault {
     // === Pitch
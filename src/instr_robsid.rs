@@ -20,8 +20,8 @@ pub struct RobEffects {
 
     // 4. Skydive
     pub skydive: bool,
-    pub skydive_config_if: u8,
-    pub skydive_config_add: u8,
+    pub skydive_config_if: u8,  // frames after the note on
+    pub skydive_config_add: u8, // i8 added to the frequency high byte, 0 => -1
 
     // 5. Arpeggio
     pub arpeggio: bool,
@@ -31,6 +31,10 @@ extern crate alloc;
 pub mod effect;
 /// Envelope with Steroid
 pub mod envelope;
+/// Loader errors
+pub mod error;
+/// Historical file formats
+pub mod format;
 /// Instrument handling samples
 pub mod instr_default;
 /// Euclidian Rythm Instrument
@@ -52,6 +56,8 @@ pub mod period_helper;
 pub(crate) mod period_helper_cache;
 /// A typical Note
 pub mod pitch;
+/// Built-in replayer
+pub mod player;
 /// Sample with Steroid
 pub mod sample;
 /// A slot
@@ -66,6 +72,8 @@ pub mod xorshift;
 /// The Xmrs Prelude
 pub mod prelude;
 
+pub use error::Error;
+
 #[cfg(any(
     feature = "import",
     feature = "import_amiga",
@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::instrument::Instrument;
 use crate::period_helper::FrequencyType;
+use crate::player::song_duration::SongDuration;
+use crate::player::subsong::Subsong;
 use crate::prelude::TrackUnit;
 
 use alloc::string::String;
@@ -66,6 +68,18 @@ impl Module {
         self.pattern_order.len()
     }
 
+    /// Duration, rows played and loop position of `song`, following jumps,
+    /// breaks, loops, delays and tempo changes without rendering audio
+    pub fn get_song_duration(&self, song: usize) -> SongDuration {
+        SongDuration::compute(self, song)
+    }
+
+    /// All songs, subsongs hidden behind position jumps included.
+    /// The first subsong of each song of `pattern_order` starts at its first order
+    pub fn get_subsongs(&self) -> Vec<Subsong> {
+        Subsong::detect(self)
+    }
+
     /// get number of channels
     pub fn get_num_channels(&self) -> usize {
         if self.pattern.len() != 0 {
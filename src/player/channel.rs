@@ -0,0 +1,1130 @@
+use crate::effect::MidiMacroType;
+use crate::instr_robsid::RobEffects;
+use crate::player::envelope_state::EnvelopeState;
+use crate::player::filter::{FilterRange, ResonantFilter};
+use crate::player::interpolation::{active_loop, loop_period, Interpolator};
+use crate::player::opl::voice::OplVoice;
+use crate::player::sid::chip::{SidChip, SidModel};
+use crate::player::sid::rob_effects::RobEffectsState;
+use crate::prelude::*;
+use crate::waveform::WaveformState;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Synth instruments play C-4 at 8363 Hz as a 32 frames cycle, near middle C
+const SYNTH_CYCLE_FRAMES: f32 = 32.0;
+
+/// Vibrato, tremolo or panbrello
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    state: WaveformState,
+    /// restart the phase on new notes
+    pub retrig: bool,
+    /// cycles per tick
+    pub speed: f32,
+    pub depth: f32,
+    /// [0..1[
+    pub phase: f32,
+}
+
+impl Oscillator {
+    fn new() -> Self {
+        Self {
+            retrig: true,
+            ..Default::default()
+        }
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform, retrig: bool) {
+        self.waveform = waveform;
+        self.state = WaveformState::new(waveform);
+        self.retrig = retrig;
+    }
+
+    fn note_on(&mut self) {
+        if self.retrig {
+            self.phase = 0.0;
+        }
+    }
+
+    /// [-1..1]
+    fn value(&mut self) -> f32 {
+        bipolar(self.waveform, self.state.value(self.phase))
+    }
+
+    fn step(&mut self) {
+        self.phase = (self.phase + self.speed) % 1.0;
+    }
+}
+
+/// Translated and random waveforms are in [0..1]
+fn bipolar(waveform: Waveform, value: f32) -> f32 {
+    match waveform {
+        Waveform::Sine | Waveform::RampDown | Waveform::Square => value,
+        _ => 2.0 * value - 1.0,
+    }
+}
+
+/// Snapshot of a channel, see `Channel::get_state()`
+#[derive(Clone, Debug)]
+pub struct ChannelState {
+    pub active: bool,
+    pub sustained: bool,
+    pub instrument: Option<usize>,
+    pub sample: Option<usize>,
+    pub note: Pitch,
+    /// Position in sample frames, loops unfolded
+    pub position: f64,
+    pub period: f32,
+    /// Tone portamento target
+    pub target_period: f32,
+    pub finetune: f32,
+    pub glissando: bool,
+    pub vibrato: Oscillator,
+    /// [0..1]
+    pub volume: f32,
+    /// [0..1]
+    pub channel_volume: f32,
+    /// [0..1]
+    pub fadeout: f32,
+    pub fading: bool,
+    pub tremolo: Oscillator,
+    /// [0..1]
+    pub panning: f32,
+    pub surround: bool,
+    pub panbrello: Oscillator,
+    pub new_note_action: Option<NewNoteAction>,
+    /// Envelope frames: volume, panning and pitch
+    pub envelope_frames: (usize, usize, usize),
+    /// [0..127]
+    pub filter_cutoff: u8,
+    /// [0..127]
+    pub filter_resonance: u8,
+}
+
+/// One channel of a pattern: current note, effects memory and sample playback
+#[derive(Clone)]
+pub struct Channel {
+    period_helper: PeriodHelper,
+
+    instrument: Option<usize>,
+    sample: Option<usize>,
+    note: Pitch,
+    /// A sample is playing
+    pub active: bool,
+    /// Key is still pressed (no note off yet)
+    pub sustained: bool,
+    /// Silenced by the user
+    pub muted: bool,
+    /// Position in sample frames, loops unfolded
+    position: f64,
+    /// Sample frames per output frame
+    step: f64,
+
+    period: f32,
+    target_period: f32,
+    finetune: f32,
+    glissando: bool,
+    arpeggio: f32,
+    vibrato: Oscillator,
+    vibrato_offset: f32,
+    autovibrato_phase: f32,
+    /// Ticks since note on, used by the autovibrato sweep
+    note_ticks: usize,
+
+    /// [0..1]
+    volume: f32,
+    channel_volume: f32,
+    fadeout: f32,
+    fading: bool,
+    tremolo: Oscillator,
+    tremolo_offset: f32,
+    tremor_counter: usize,
+    tremor_mute: bool,
+    retrig_counter: usize,
+
+    /// [0..1]
+    panning: f32,
+    surround: bool,
+    panbrello: Oscillator,
+    panbrello_offset: f32,
+
+    /// Instrument New Note Action override
+    pub new_note_action: Option<NewNoteAction>,
+
+    volume_envelope: EnvelopeState,
+    panning_envelope: EnvelopeState,
+    pitch_envelope: EnvelopeState,
+
+    filter: ResonantFilter,
+    /// [0..127]
+    filter_cutoff: u8,
+    /// [0..127]
+    filter_resonance: u8,
+    /// Parametric macro selected by `SFx`
+    macro_index: usize,
+    pub filter_range: FilterRange,
+    /// Fast Tracker II silences a released note without volume envelope,
+    /// Impulse Tracker and Scream Tracker 3 only leave the sustain loop
+    pub ft2_key_off: bool,
+
+    /// Plays SID instruments instead of samples
+    sid: Option<SidChip>,
+    pub sid_model: SidModel,
+    /// Rob Hubbard effects of `InstrRobSid` on the chip
+    rob_effects: Option<RobEffectsState>,
+    /// Plays OPL instruments instead of samples
+    opl: Option<OplVoice>,
+
+    gain_left: f32,
+    gain_right: f32,
+}
+
+impl Channel {
+    pub fn new(frequency_type: FrequencyType) -> Self {
+        Self {
+            period_helper: PeriodHelper::new(frequency_type, false),
+            instrument: None,
+            sample: None,
+            note: Pitch::None,
+            active: false,
+            sustained: false,
+            muted: false,
+            position: 0.0,
+            step: 0.0,
+            period: 0.0,
+            target_period: 0.0,
+            finetune: 0.0,
+            glissando: false,
+            arpeggio: 0.0,
+            vibrato: Oscillator::new(),
+            vibrato_offset: 0.0,
+            autovibrato_phase: 0.0,
+            note_ticks: 0,
+            volume: 0.0,
+            channel_volume: 1.0,
+            fadeout: 1.0,
+            fading: false,
+            tremolo: Oscillator::new(),
+            tremolo_offset: 0.0,
+            tremor_counter: 0,
+            tremor_mute: false,
+            retrig_counter: 0,
+            panning: 0.5,
+            surround: false,
+            panbrello: Oscillator::new(),
+            panbrello_offset: 0.0,
+            new_note_action: None,
+            volume_envelope: EnvelopeState::default(),
+            panning_envelope: EnvelopeState::default(),
+            pitch_envelope: EnvelopeState::default(),
+            filter: ResonantFilter::default(),
+            filter_cutoff: 127,
+            filter_resonance: 0,
+            macro_index: 0,
+            filter_range: FilterRange::default(),
+            ft2_key_off: true,
+            sid: None,
+            sid_model: SidModel::default(),
+            rob_effects: None,
+            opl: None,
+            gain_left: 0.0,
+            gain_right: 0.0,
+        }
+    }
+
+    fn instr<'m>(&self, module: &'m Module) -> Option<&'m InstrDefault> {
+        let instrument = module.instrument.get(self.instrument?)?;
+        match &instrument.instr_type {
+            InstrumentType::Default(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn sid_instr<'m>(&self, module: &'m Module) -> Option<&'m InstrSid> {
+        let instrument = module.instrument.get(self.instrument?)?;
+        match &instrument.instr_type {
+            InstrumentType::Sid(sid) => Some(sid),
+            InstrumentType::RobSid(robsid) => Some(&robsid.sid),
+            _ => None,
+        }
+    }
+
+    fn rob_effects(&self, module: &Module) -> Option<[RobEffects; 3]> {
+        let instrument = module.instrument.get(self.instrument?)?;
+        match &instrument.instr_type {
+            InstrumentType::RobSid(robsid) => Some(robsid.fx),
+            _ => None,
+        }
+    }
+
+    fn opl_instr<'m>(&self, module: &'m Module) -> Option<&'m InstrOpl> {
+        let instrument = module.instrument.get(self.instrument?)?;
+        match &instrument.instr_type {
+            InstrumentType::Opl(opl) => Some(opl),
+            _ => None,
+        }
+    }
+
+    pub fn get_instrument(&self) -> Option<usize> {
+        self.instrument
+    }
+
+    pub fn get_sample(&self) -> Option<usize> {
+        self.sample
+    }
+
+    pub fn get_note(&self) -> Pitch {
+        self.note
+    }
+
+    /// Action for this note when a new one is played on the channel
+    pub fn get_new_note_action(&self, module: &Module) -> NewNoteAction {
+        self.new_note_action.unwrap_or_else(|| {
+            self.instr(module).map_or(NewNoteAction::NoteCut, |instr| {
+                instr.duplicate_check.new_note_action()
+            })
+        })
+    }
+
+    pub fn get_state(&self) -> ChannelState {
+        ChannelState {
+            active: self.active,
+            sustained: self.sustained,
+            instrument: self.instrument,
+            sample: self.sample,
+            note: self.note,
+            position: self.position,
+            period: self.period,
+            target_period: self.target_period,
+            finetune: self.finetune,
+            glissando: self.glissando,
+            vibrato: self.vibrato,
+            volume: self.volume,
+            channel_volume: self.channel_volume,
+            fadeout: self.fadeout,
+            fading: self.fading,
+            tremolo: self.tremolo,
+            panning: self.panning,
+            surround: self.surround,
+            panbrello: self.panbrello,
+            new_note_action: self.new_note_action,
+            envelope_frames: (
+                self.volume_envelope.frame,
+                self.panning_envelope.frame,
+                self.pitch_envelope.frame,
+            ),
+            filter_cutoff: self.filter_cutoff,
+            filter_resonance: self.filter_resonance,
+        }
+    }
+
+    /// Current gain, used to choose which voice to steal
+    pub fn loudness(&self) -> f32 {
+        if self.active {
+            self.gain_left.abs() + self.gain_right.abs()
+        } else {
+            0.0
+        }
+    }
+
+    /// Stop the sample now
+    pub fn cut(&mut self) {
+        self.active = false;
+    }
+
+    fn current_sample<'m>(&self, module: &'m Module) -> Option<&'m Sample> {
+        self.instr(module)?.sample.get(self.sample?)?.as_ref()
+    }
+
+    /// Update the channel for the current tick.
+    ///
+    /// `tick` is the tick in the row (see `Sequencer::channel_tick()`),
+    /// notes are only triggered when `first_repeat` is true
+    pub fn tick(
+        &mut self,
+        module: &Module,
+        unit: &TrackUnit,
+        tick: usize,
+        first_repeat: bool,
+        global_volume: f32,
+        sample_rate: f32,
+    ) {
+        let trigger_tick = unit.get_delay();
+        if first_repeat && tick == trigger_tick {
+            self.trigger(module, unit);
+        }
+        self.effects(module, unit, tick, trigger_tick);
+        self.update(module, global_volume, sample_rate);
+    }
+
+    fn trigger(&mut self, module: &Module, unit: &TrackUnit) {
+        if unit.instrument.is_some() {
+            self.instrument = unit.instrument;
+        }
+        match unit.note {
+            Pitch::Off => self.key_off(module),
+            Pitch::Cut => self.volume = 0.0,
+            note if note.is_valid() => self.note_on(module, unit, note),
+            _ => {}
+        }
+        if unit.instrument.is_some() {
+            if let Some(sample) = self.current_sample(module) {
+                self.volume = sample.volume;
+                self.panning = sample.panning;
+            } else if self.sid_instr(module).is_some() {
+                self.volume = 1.0;
+            } else if let Some(opl) = self.opl_instr(module) {
+                self.volume = opl.volume.min(63) as f32 / 63.0;
+            }
+            if unit.note.is_none() && self.active {
+                self.restart_instrument(module);
+            }
+        }
+    }
+
+    fn note_on(&mut self, module: &Module, unit: &TrackUnit, note: Pitch) {
+        if let Some(sid) = self.sid_instr(module) {
+            self.sid_note_on(module, unit, note, sid);
+            return;
+        }
+        if let Some(opl) = self.opl_instr(module) {
+            self.opl_note_on(module, unit, note, opl);
+            return;
+        }
+        let Some(instr) = self.instr(module) else {
+            self.active = false;
+            return;
+        };
+        let sample_index = instr.sample_for_pitch[note.value() as usize];
+        let Some(sample) = sample_index
+            .and_then(|i| instr.sample.get(i))
+            .and_then(|s| s.as_ref())
+        else {
+            self.active = false;
+            return;
+        };
+        let period = self
+            .period_helper
+            .note_to_period(note.value() as f32 + sample.relative_pitch as f32);
+
+        if unit.has_tone_portamento() && self.active {
+            self.target_period = period;
+            return;
+        }
+
+        self.sample = sample_index;
+        self.sid = None;
+        self.rob_effects = None;
+        self.opl = None;
+        self.note = note;
+        self.new_note_action = None;
+        self.period = period;
+        self.target_period = period;
+        self.finetune = Self::unit_finetune(unit).unwrap_or(sample.finetune);
+        self.position = unit
+            .effects
+            .iter()
+            .find_map(|e| match e {
+                TrackEffect::InstrumentSampleOffset(o) => Some(*o as f64),
+                _ => None,
+            })
+            .unwrap_or(0.0);
+        self.active = (self.position as usize) < sample.len();
+        self.vibrato.note_on();
+        self.tremolo.note_on();
+        self.panbrello.note_on();
+        // bit 7 set: use the instrument value
+        if instr.initial_filter_cutoff & 0x80 != 0 {
+            self.filter_cutoff = instr.initial_filter_cutoff & 0x7F;
+        }
+        if instr.initial_filter_resonance & 0x80 != 0 {
+            self.filter_resonance = instr.initial_filter_resonance & 0x7F;
+        }
+        self.filter.reset();
+        self.restart_instrument(module);
+    }
+
+    fn unit_finetune(unit: &TrackUnit) -> Option<f32> {
+        unit.effects.iter().find_map(|e| match e {
+            TrackEffect::InstrumentFineTune(f) => Some(*f),
+            _ => None,
+        })
+    }
+
+    /// Note on with a SID instrument: voice 0 plays the note,
+    /// the other voices keep the registers of the instrument
+    fn sid_note_on(&mut self, module: &Module, unit: &TrackUnit, note: Pitch, instr: &InstrSid) {
+        let period = self.period_helper.note_to_period(note.value() as f32);
+        if unit.has_tone_portamento() && self.active {
+            self.target_period = period;
+            return;
+        }
+        self.sample = None;
+        self.opl = None;
+        self.note = note;
+        self.new_note_action = None;
+        self.period = period;
+        self.target_period = period;
+        self.finetune = Self::unit_finetune(unit).unwrap_or(0.0);
+        self.position = 0.0;
+        self.active = true;
+        self.vibrato.note_on();
+        self.tremolo.note_on();
+        self.panbrello.note_on();
+        let model = self.sid_model;
+        let chip = self.sid.get_or_insert_with(|| SidChip::new(model));
+        // no chip reset: the attack starts from the current envelope level
+        for voice in 0..3 {
+            chip.set_gate(voice, false);
+        }
+        chip.set_instrument(instr);
+        chip.set_gate(0, true);
+        self.rob_effects = self
+            .rob_effects(module)
+            .map(|fx| RobEffectsState::new(fx, instr));
+        self.restart_instrument(module);
+    }
+
+    /// Note on with an OPL instrument, the operators restart their phase and attack
+    fn opl_note_on(&mut self, module: &Module, unit: &TrackUnit, note: Pitch, instr: &InstrOpl) {
+        let period = self
+            .period_helper
+            .note_to_period(note.value() as f32 + instr.relative_pitch as f32);
+        if unit.has_tone_portamento() && self.active {
+            self.target_period = period;
+            return;
+        }
+        self.sample = None;
+        self.sid = None;
+        self.rob_effects = None;
+        self.note = note;
+        self.new_note_action = None;
+        self.period = period;
+        self.target_period = period;
+        self.finetune = Self::unit_finetune(unit).unwrap_or(instr.finetune);
+        self.position = 0.0;
+        self.active = true;
+        self.vibrato.note_on();
+        self.tremolo.note_on();
+        self.panbrello.note_on();
+        let voice = self.opl.get_or_insert_with(OplVoice::default);
+        voice.set_instrument(&instr.element);
+        voice.key_on();
+        self.restart_instrument(module);
+    }
+
+    /// Restart envelopes, fadeout and autovibrato
+    fn restart_instrument(&mut self, module: &Module) {
+        self.sustained = true;
+        self.fadeout = 1.0;
+        self.fading = false;
+        self.note_ticks = 0;
+        self.autovibrato_phase = 0.0;
+        self.tremor_counter = 0;
+        self.retrig_counter = 0;
+        if let Some(instr) = self.instr(module) {
+            self.volume_envelope.reset(&instr.volume_envelope);
+            self.panning_envelope.reset(&instr.pan_envelope);
+            self.pitch_envelope.reset(&instr.pitch_envelope);
+        }
+    }
+
+    /// Release the key: leave the sustain loop, start envelope release and fadeout
+    pub fn key_off(&mut self, module: &Module) {
+        if !self.sustained {
+            return;
+        }
+        if let Some(chip) = self.sid.as_mut() {
+            for voice in 0..3 {
+                chip.set_gate(voice, false);
+            }
+            self.sustained = false;
+            return;
+        }
+        if let Some(voice) = self.opl.as_mut() {
+            voice.key_off();
+            self.sustained = false;
+            return;
+        }
+        if let Some(sample) = self.current_sample(module) {
+            if let (LoopType::Forward | LoopType::PingPong, _, _) = active_loop(sample, true) {
+                // continue from the real position, out of the sustain loop
+                let pos = self.position as usize;
+                let fract = self.position - pos as f64;
+                self.position = sample.meta_seek(pos, true) as f64 + fract;
+            }
+        }
+        self.sustained = false;
+        let (envelope, fadeout) = self.instr(module).map_or((false, false), |instr| {
+            (self.volume_envelope.enabled, instr.volume_fadeout > 0.0)
+        });
+        if envelope || (self.ft2_key_off && fadeout) {
+            self.fading = true;
+        } else if self.ft2_key_off {
+            self.volume = 0.0;
+        }
+    }
+
+    /// Start the instrument fadeout
+    pub fn fade_out(&mut self) {
+        self.fading = true;
+    }
+
+    fn effects(&mut self, module: &Module, unit: &TrackUnit, tick: usize, trigger_tick: usize) {
+        self.arpeggio = 0.0;
+        let mut vibrato = false;
+        let mut tremolo = false;
+        let mut panbrello = false;
+        let mut tremor = false;
+
+        for effect in unit.effects.iter() {
+            match effect {
+                TrackEffect::Arpeggio { half1, half2 } => {
+                    self.arpeggio = match tick % 3 {
+                        1 => *half1 as f32,
+                        2 => *half2 as f32,
+                        _ => 0.0,
+                    };
+                }
+                TrackEffect::ChannelVolume(value) => {
+                    if tick == trigger_tick {
+                        self.channel_volume = value.clamp(0.0, 1.0);
+                    }
+                }
+                TrackEffect::ChannelVolumeSlide { speed, fine } => {
+                    if *fine == (tick == 0) {
+                        self.channel_volume = (self.channel_volume + speed).clamp(0.0, 1.0);
+                    }
+                }
+                TrackEffect::Glissando(on) => self.glissando = *on,
+                TrackEffect::InstrumentFineTune(_) => {}
+                TrackEffect::InstrumentNewNoteAction(nna) => self.new_note_action = Some(*nna),
+                TrackEffect::InstrumentPanningEnvelopePosition(pos) => {
+                    if tick == trigger_tick {
+                        self.panning_envelope.frame = *pos;
+                    }
+                }
+                TrackEffect::InstrumentPanningEnvelope(on) => self.panning_envelope.enabled = *on,
+                TrackEffect::InstrumentPitchEnvelope(on) => self.pitch_envelope.enabled = *on,
+                TrackEffect::InstrumentSampleOffset(_) => {}
+                TrackEffect::InstrumentSurround(on) => self.surround = *on,
+                TrackEffect::InstrumentVolumeEnvelopePosition(pos) => {
+                    if tick == trigger_tick {
+                        self.volume_envelope.frame = *pos;
+                    }
+                }
+                TrackEffect::InstrumentVolumeEnvelope(on) => self.volume_envelope.enabled = *on,
+                // past notes are handled by the voice pool
+                TrackEffect::NoteCut { tick: t, past } => {
+                    if !past && tick == *t {
+                        self.volume = 0.0;
+                    }
+                }
+                TrackEffect::NoteDelay(_) => {}
+                TrackEffect::NoteFadeOut { tick: t, past } => {
+                    if !past && tick == *t {
+                        self.fade_out();
+                    }
+                }
+                TrackEffect::NoteOff { tick: t, past } => {
+                    if !past && tick == *t {
+                        self.key_off(module);
+                    }
+                }
+                TrackEffect::NoteRetrig {
+                    speed,
+                    volume_modifier,
+                } => {
+                    if *speed != 0 && tick != 0 {
+                        self.retrig_counter += 1;
+                        if self.retrig_counter >= *speed {
+                            self.retrig_counter = 0;
+                            self.retrig(module, volume_modifier);
+                        }
+                    }
+                }
+                TrackEffect::Panbrello { speed, depth } => {
+                    self.panbrello.speed = *speed;
+                    self.panbrello.depth = *depth;
+                    panbrello = true;
+                }
+                TrackEffect::PanbrelloWaveform { waveform, retrig } => {
+                    self.panbrello.set_waveform(*waveform, *retrig)
+                }
+                TrackEffect::Panning(value) => {
+                    if tick == trigger_tick {
+                        self.panning = value.clamp(0.0, 1.0);
+                    }
+                }
+                TrackEffect::PanningSlide { speed, fine } => {
+                    if *fine == (tick == 0) {
+                        self.panning = (self.panning + speed / 16.0).clamp(0.0, 1.0);
+                    }
+                }
+                TrackEffect::Portamento { speed, fine } => {
+                    if *fine == (tick == 0) {
+                        self.period = (self.period + speed).max(1.0);
+                        self.target_period = self.period;
+                    }
+                }
+                TrackEffect::TonePortamento(speed) => {
+                    if tick != 0 {
+                        let speed = speed.abs();
+                        if self.period < self.target_period {
+                            self.period = (self.period + speed).min(self.target_period);
+                        } else if self.period > self.target_period {
+                            self.period = (self.period - speed).max(self.target_period);
+                        }
+                    }
+                }
+                TrackEffect::Tremolo { speed, depth } => {
+                    self.tremolo.speed = *speed;
+                    self.tremolo.depth = *depth;
+                    tremolo = true;
+                }
+                TrackEffect::TremoloWaveform { waveform, retrig } => {
+                    self.tremolo.set_waveform(*waveform, *retrig)
+                }
+                TrackEffect::Tremor { on_time, off_time } => {
+                    let length = on_time + 1 + off_time + 1;
+                    self.tremor_mute = self.tremor_counter % length > *on_time;
+                    self.tremor_counter += 1;
+                    tremor = true;
+                }
+                TrackEffect::Vibrato { speed, depth } => {
+                    self.vibrato.speed = *speed;
+                    self.vibrato.depth = *depth;
+                    vibrato = true;
+                }
+                TrackEffect::VibratoSpeed(speed) => {
+                    self.vibrato.speed = *speed;
+                    vibrato = true;
+                }
+                TrackEffect::VibratoDepth(depth) => {
+                    self.vibrato.depth = *depth;
+                    vibrato = true;
+                }
+                TrackEffect::VibratoWaveform { waveform, retrig } => {
+                    self.vibrato.set_waveform(*waveform, *retrig)
+                }
+                TrackEffect::Volume { value, tick: t } => {
+                    if tick == (*t).max(trigger_tick) {
+                        self.volume = value.clamp(0.0, 1.0);
+                    }
+                }
+                TrackEffect::VolumeSlide { speed, fine } => {
+                    if *fine == (tick == 0) {
+                        self.volume = (self.volume + speed).clamp(0.0, 1.0);
+                    }
+                }
+            }
+        }
+
+        if tick == trigger_tick {
+            for ge in unit.global_effects.iter() {
+                if let GlobalEffect::MidiMacro(midi_macro) = ge {
+                    self.midi_macro(midi_macro);
+                }
+            }
+        }
+
+        self.vibrato_offset = 0.0;
+        if vibrato {
+            if tick != 0 {
+                self.vibrato.step();
+            }
+            self.vibrato_offset = self.vibrato.value() * self.vibrato.depth * 2.0;
+        }
+        self.tremolo_offset = 0.0;
+        if tremolo {
+            if tick != 0 {
+                self.tremolo.step();
+            }
+            self.tremolo_offset = self.tremolo.value() * self.tremolo.depth;
+        }
+        self.panbrello_offset = 0.0;
+        if panbrello {
+            if tick != 0 {
+                self.panbrello.step();
+            }
+            self.panbrello_offset = self.panbrello.value() * self.panbrello.depth / 8.0;
+        }
+        if !tremor {
+            self.tremor_mute = false;
+        }
+    }
+
+    /// Default Impulse Tracker macros: `SF0` sets the filter cutoff,
+    /// `SF1` the resonance, and `Z80`-`Z8F` the resonance by steps of 8
+    fn midi_macro(&mut self, midi_macro: &MidiMacroType) {
+        match midi_macro {
+            MidiMacroType::Parametric(Some(index)) => self.macro_index = *index,
+            MidiMacroType::Parametric(None) => {}
+            MidiMacroType::ParametricValue(value) => {
+                let value = (*value).min(127) as u8;
+                match self.macro_index {
+                    0 => self.filter_cutoff = value,
+                    1 => self.filter_resonance = value,
+                    _ => {}
+                }
+            }
+            MidiMacroType::Fixed(index) => {
+                if *index < 16 {
+                    self.filter_resonance = *index as u8 * 8;
+                }
+            }
+        }
+    }
+
+    fn retrig(&mut self, module: &Module, volume_modifier: &NoteRetrigOperator) {
+        self.volume = match volume_modifier {
+            NoteRetrigOperator::None => self.volume,
+            NoteRetrigOperator::Sum(v) => self.volume + v / 64.0,
+            NoteRetrigOperator::Mul(m) => self.volume * m,
+        }
+        .clamp(0.0, 1.0);
+        self.position = 0.0;
+        self.active = self.current_sample(module).is_some_and(|s| s.len() != 0);
+    }
+
+    /// Compute gains and pitch for this tick, then move envelopes forward
+    /// Volume with tremolo and tremor, zero if the instrument is muted
+    fn effect_volume(&self, module: &Module) -> f32 {
+        let muted = self
+            .instrument
+            .and_then(|i| module.instrument.get(i))
+            .is_some_and(|i| i.muted);
+        if muted || self.tremor_mute {
+            0.0
+        } else {
+            (self.volume + self.tremolo_offset).clamp(0.0, 1.0)
+        }
+    }
+
+    fn set_gains(&mut self, volume: f32, pan: f32) {
+        self.gain_left = volume * (1.0 - pan).sqrt();
+        self.gain_right = volume * pan.sqrt();
+        if self.surround {
+            self.gain_right = -self.gain_right;
+        }
+    }
+
+    fn update(&mut self, module: &Module, global_volume: f32, sample_rate: f32) {
+        if self.sid.is_some() {
+            self.update_sid(module, global_volume, sample_rate);
+            return;
+        }
+        if self.opl.is_some() {
+            self.update_opl(module, global_volume, sample_rate);
+            return;
+        }
+        let Some(instr) = self.instr(module) else {
+            self.gain_left = 0.0;
+            self.gain_right = 0.0;
+            return;
+        };
+
+        if self.fading {
+            self.fadeout = (self.fadeout - instr.volume_fadeout).max(0.0);
+        }
+
+        // volume
+        let volume = self.effect_volume(module)
+            * self.volume_envelope.value(&instr.volume_envelope, 1.0)
+            * self.fadeout
+            * instr.global_volume
+            * self.channel_volume
+            * global_volume;
+
+        // panning
+        let pan = (self.panning + self.panbrello_offset).clamp(0.0, 1.0);
+        let pan_env = self.panning_envelope.value(&instr.pan_envelope, 0.5);
+        let pan = (pan + (pan_env - 0.5) * (0.5 - (pan - 0.5).abs()) * 2.0).clamp(0.0, 1.0);
+        self.set_gains(volume, pan);
+
+        // pitch
+        let mut semitones = self.arpeggio + self.vibrato_offset;
+        let av = &instr.vibrato;
+        if av.depth != 0.0 {
+            let sweep = if av.sweep > 0.0 {
+                (self.note_ticks as f32 / (av.sweep * 256.0)).min(1.0)
+            } else {
+                1.0
+            };
+            let wave = bipolar(
+                av.waveform,
+                WaveformState::new(av.waveform).value(self.autovibrato_phase),
+            );
+            semitones += wave * av.depth * sweep;
+            self.autovibrato_phase = (self.autovibrato_phase + av.speed) % 1.0;
+        }
+        if !instr.pitch_envelope_as_low_pass_filter {
+            semitones += (self.pitch_envelope.value(&instr.pitch_envelope, 0.5) - 0.5) * 32.0;
+        }
+        let freq = self.period_helper.all_to_frequency_cached(
+            self.period,
+            semitones,
+            self.finetune,
+            self.glissando,
+        );
+        self.step = if freq.is_finite() && freq > 0.0 && sample_rate > 0.0 {
+            freq as f64 / sample_rate as f64
+        } else {
+            0.0
+        };
+
+        // resonant filter
+        let filter_envelope =
+            instr.pitch_envelope_as_low_pass_filter && self.pitch_envelope.enabled;
+        self.filter.enabled =
+            filter_envelope || self.filter_cutoff < 127 || self.filter_resonance > 0;
+        if self.filter.enabled && sample_rate > 0.0 {
+            let modifier = if filter_envelope {
+                ((self.pitch_envelope.value(&instr.pitch_envelope, 0.5) - 0.5) * 512.0) as i32
+            } else {
+                256
+            };
+            self.filter.setup(
+                self.filter_cutoff,
+                self.filter_resonance,
+                modifier,
+                self.filter_range,
+                sample_rate,
+            );
+        }
+
+        self.note_ticks += 1;
+        self.volume_envelope
+            .tick(&instr.volume_envelope, self.sustained);
+        self.panning_envelope
+            .tick(&instr.pan_envelope, self.sustained);
+        self.pitch_envelope
+            .tick(&instr.pitch_envelope, self.sustained);
+
+        // end of the volume envelope at zero
+        if self.volume_envelope.enabled && !self.sustained {
+            if let Some(last) = instr.volume_envelope.point.last() {
+                if self.volume_envelope.frame > last.frame && last.value == 0.0 {
+                    self.active = false;
+                }
+            }
+        }
+        if self.fading && self.fadeout == 0.0 {
+            self.active = false;
+        }
+    }
+
+    /// SID instruments have their own envelopes, the chip only follows
+    /// the channel volume, panning and pitch
+    fn update_sid(&mut self, module: &Module, global_volume: f32, sample_rate: f32) {
+        let volume = self.effect_volume(module) * self.channel_volume * global_volume;
+        let pan = (self.panning + self.panbrello_offset).clamp(0.0, 1.0);
+        self.set_gains(volume, pan);
+
+        let freq = self.period_helper.all_to_frequency_cached(
+            self.period,
+            self.arpeggio + self.vibrato_offset,
+            self.finetune,
+            self.glissando,
+        );
+        let Some(chip) = self.sid.as_mut() else {
+            return;
+        };
+        if self.fading {
+            for voice in 0..3 {
+                chip.set_gate(voice, false);
+            }
+        }
+        // same tuning as the 128 frames loops of imported SID samples, 24 semitones up
+        if freq.is_finite() && freq > 0.0 {
+            chip.set_frequency(0, freq / SYNTH_CYCLE_FRAMES);
+        }
+        chip.set_sample_rate(sample_rate);
+        if let Some(rob) = self.rob_effects.as_mut() {
+            if freq.is_finite() && freq > 0.0 {
+                rob.frequency = freq / SYNTH_CYCLE_FRAMES;
+            }
+            rob.set_sample_rate(sample_rate);
+            // the frame due at the note on, then the new frequency
+            rob.advance(chip, 0);
+            rob.apply(chip);
+        }
+        self.note_ticks += 1;
+        if !self.sustained && chip.is_silent() {
+            self.active = false;
+        }
+    }
+
+    /// OPL instruments have their own envelopes, the channel volume
+    /// scales the operators total level like the OPL drivers of trackers
+    fn update_opl(&mut self, module: &Module, global_volume: f32, sample_rate: f32) {
+        let volume = self.effect_volume(module) * self.channel_volume * global_volume;
+        let pan = (self.panning + self.panbrello_offset).clamp(0.0, 1.0);
+        self.set_gains(1.0, pan);
+
+        let freq = self.period_helper.all_to_frequency_cached(
+            self.period,
+            self.arpeggio + self.vibrato_offset,
+            self.finetune,
+            self.glissando,
+        );
+        let Some(voice) = self.opl.as_mut() else {
+            return;
+        };
+        if self.fading {
+            voice.key_off();
+        }
+        voice.set_volume(volume);
+        if freq.is_finite() && freq > 0.0 {
+            voice.set_frequency(freq / SYNTH_CYCLE_FRAMES);
+        }
+        voice.set_sample_rate(sample_rate);
+        self.note_ticks += 1;
+        if !self.sustained && voice.is_silent() {
+            self.active = false;
+        }
+    }
+
+    /// Next stereo frame of the sample, gains applied
+    pub fn render_frame(&mut self, module: &Module, interpolator: &Interpolator) -> (f32, f32) {
+        if !self.active {
+            return (0.0, 0.0);
+        }
+        if let Some(chip) = self.sid.as_mut() {
+            if let Some(rob) = self.rob_effects.as_mut() {
+                rob.advance(chip, 1);
+            }
+            let out = chip.render_frame();
+            if self.muted {
+                return (0.0, 0.0);
+            }
+            return (out * self.gain_left, out * self.gain_right);
+        }
+        if let Some(voice) = self.opl.as_mut() {
+            let out = voice.render_frame();
+            if self.muted {
+                return (0.0, 0.0);
+            }
+            return (out * self.gain_left, out * self.gain_right);
+        }
+        let Some(sample) = self.current_sample(module) else {
+            self.active = false;
+            return (0.0, 0.0);
+        };
+        let (flag, start, length) = active_loop(sample, self.sustained);
+        if matches!(flag, LoopType::No) && self.position as usize >= sample.len() {
+            self.active = false;
+            return (0.0, 0.0);
+        }
+        let (l, r) = if self.muted {
+            (0.0, 0.0)
+        } else {
+            self.filter
+                .process(interpolator.frame(sample, self.position, self.sustained))
+        };
+
+        self.position += self.step;
+        self.wrap_position(flag, start, length);
+
+        (l * self.gain_left, r * self.gain_right)
+    }
+
+    /// Keep the position bounded, after the first pass of the loop
+    /// so that interpolation still sees loop frames before the loop start
+    fn wrap_position(&mut self, flag: LoopType, start: usize, length: usize) {
+        let period = loop_period(flag, length) as f64;
+        let second_pass = start as f64 + period;
+        if period > 0.0 && self.position >= second_pass + period {
+            self.position = second_pass + (self.position - second_pass) % period;
+        }
+    }
+
+    /// Move forward `frames` output frames without rendering them
+    pub fn skip_frames(&mut self, module: &Module, frames: usize) {
+        if !self.active {
+            return;
+        }
+        if let Some(chip) = self.sid.as_mut() {
+            let Some(rob) = self.rob_effects.as_mut() else {
+                chip.skip_frames(frames);
+                return;
+            };
+            let mut frames = frames;
+            while frames > 0 {
+                let n = rob.frames_to_next().min(frames);
+                rob.advance(chip, n);
+                chip.skip_frames(n);
+                frames -= n;
+            }
+            return;
+        }
+        if let Some(voice) = self.opl.as_mut() {
+            voice.skip_frames(frames);
+            return;
+        }
+        let Some(sample) = self.current_sample(module) else {
+            self.active = false;
+            return;
+        };
+        let (flag, start, length) = active_loop(sample, self.sustained);
+        self.position += self.step * frames as f64;
+        if matches!(flag, LoopType::No) && self.position as usize >= sample.len() {
+            self.active = false;
+            return;
+        }
+        self.wrap_position(flag, start, length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::square_sample;
+    use alloc::vec;
+
+    #[test]
+    fn player_key_off() {
+        use crate::prelude::*;
+
+        // like an IT instrument: no volume envelope, no fadeout
+        let mut instr = InstrDefault {
+            sample: vec![Some(square_sample(1.0))],
+            ..Default::default()
+        };
+        instr.change_all_sample_for_pitch(0);
+        let row = |note: Pitch| {
+            vec![TrackUnit {
+                note,
+                instrument: note.is_valid().then_some(0),
+                ..Default::default()
+            }]
+        };
+        let module = Module {
+            pattern_order: vec![vec![0]],
+            pattern: vec![vec![row(Pitch::C4), row(Pitch::Off), row(Pitch::None)]],
+            instrument: vec![Instrument {
+                name: "".into(),
+                instr_type: InstrumentType::Default(instr),
+                muted: false,
+            }],
+            ..Default::default()
+        };
+        let playing_at_last_row = |ft2_key_off: bool| {
+            let mut player = Player::new(&module, 8000.0, 0);
+            player.set_ft2_key_off(ft2_key_off);
+            while player.get_position().1 != 2 {
+                player.next().unwrap();
+            }
+            player.take(100).any(|(l, r)| l != 0.0 || r != 0.0)
+        };
+        // Fast Tracker II silences the note, Impulse Tracker keeps it playing
+        assert!(!playing_at_last_row(true));
+        assert!(playing_at_last_row(false));
+    }
+}
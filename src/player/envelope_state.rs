@@ -0,0 +1,54 @@
+use crate::envelope::{Envelope, EnvelopePoint};
+
+/// Current frame in an instrument `Envelope`, a frame is a tick
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EnvelopeState {
+    /// Envelope in use, effects can switch it on or off
+    pub enabled: bool,
+    pub frame: usize,
+}
+
+impl EnvelopeState {
+    /// Restart the envelope for a new note
+    pub fn reset(&mut self, env: &Envelope) {
+        self.enabled = env.enabled;
+        self.frame = 0;
+    }
+
+    /// Interpolated value at the current frame, `default` if the envelope is not used
+    pub fn value(&self, env: &Envelope, default: f32) -> f32 {
+        if !self.enabled || env.point.is_empty() {
+            return default;
+        }
+        let points = &env.point;
+        match points.iter().position(|p| p.frame > self.frame) {
+            Some(0) => points[0].value,
+            Some(i) => EnvelopePoint::lerp(&points[i - 1], &points[i], self.frame),
+            None => points[points.len() - 1].value,
+        }
+    }
+
+    /// Go to the next frame, the sustain loop is only used while the note is `sustained`
+    pub fn tick(&mut self, env: &Envelope, sustained: bool) {
+        if !self.enabled || env.point.is_empty() {
+            return;
+        }
+        let len = env.point.len();
+        let mut frame = self.frame + 1;
+        if sustained
+            && env.sustain_start_point < len
+            && env.sustain_end_point < len
+            && env.sustain_start_point <= env.sustain_end_point
+        {
+            frame = env.loop_in_sustain(frame);
+        }
+        if env.loop_start_point < len
+            && env.loop_end_point < len
+            && env.loop_start_point <= env.loop_end_point
+        {
+            frame = env.loop_in_loop(frame);
+        }
+        // no need to go further than the last point
+        self.frame = frame.min(env.point[len - 1].frame + 1);
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Cutoff frequency range of the resonant filter
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterRange {
+    /// Impulse Tracker range
+    #[default]
+    Standard,
+    /// OpenMPT extended range
+    Extended,
+}
+
+/// Impulse Tracker resonant low-pass filter, two poles
+#[derive(Default, Clone, Copy, Debug)]
+pub struct ResonantFilter {
+    pub enabled: bool,
+    gain: f32,
+    feedback0: f32,
+    feedback1: f32,
+    /// previous outputs (left, right)
+    y1: (f32, f32),
+    y2: (f32, f32),
+}
+
+impl ResonantFilter {
+    /// Cutoff frequency in Hz.
+    ///
+    /// `cutoff` is in [0..127], `modifier` comes from the pitch envelope
+    /// in filter mode, in [-256..256], 256 without envelope
+    pub fn cutoff_to_frequency(
+        cutoff: u8,
+        modifier: i32,
+        range: FilterRange,
+        sample_rate: f32,
+    ) -> f32 {
+        let computed = cutoff.min(127) as f32 * (modifier.clamp(-256, 256) + 256) as f32;
+        let fe = match range {
+            FilterRange::Standard => 24.0,
+            FilterRange::Extended => 20.0,
+        };
+        let freq = (110.0 * 2.0f32.powf(0.25 + computed / (fe * 512.0))).clamp(120.0, 20000.0);
+        freq.min(sample_rate / 2.0)
+    }
+
+    /// Compute coefficients, `resonance` is in [0..127]
+    pub fn setup(
+        &mut self,
+        cutoff: u8,
+        resonance: u8,
+        modifier: i32,
+        range: FilterRange,
+        sample_rate: f32,
+    ) {
+        let fc = Self::cutoff_to_frequency(cutoff, modifier, range, sample_rate)
+            * core::f32::consts::TAU
+            / sample_rate;
+        let dmpfac = 10.0f32.powf(-(resonance.min(127) as f32) * 24.0 / (128.0 * 20.0));
+        let d = ((1.0 - 2.0 * dmpfac) * fc).min(2.0);
+        let d = (2.0 * dmpfac - d) / fc;
+        let e = 1.0 / (fc * fc);
+        self.gain = 1.0 / (1.0 + d + e);
+        self.feedback0 = (d + e + e) / (1.0 + d + e);
+        self.feedback1 = -e / (1.0 + d + e);
+    }
+
+    /// Forget previous outputs, for a new note
+    pub fn reset(&mut self) {
+        self.y1 = (0.0, 0.0);
+        self.y2 = (0.0, 0.0);
+    }
+
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        if !self.enabled {
+            return input;
+        }
+        let out = (
+            (input.0 * self.gain + self.y1.0 * self.feedback0 + self.y2.0 * self.feedback1)
+                .clamp(-2.0, 2.0),
+            (input.1 * self.gain + self.y1.1 * self.feedback0 + self.y2.1 * self.feedback1)
+                .clamp(-2.0, 2.0),
+        );
+        self.y2 = self.y1;
+        self.y1 = out;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn player_filter() {
+        use crate::player::filter::ResonantFilter;
+        use crate::prelude::*;
+
+        let freq = |cutoff, modifier, range| {
+            ResonantFilter::cutoff_to_frequency(cutoff, modifier, range, 48000.0)
+        };
+        assert!((freq(127, 256, FilterRange::Standard) - 5124.0).abs() < 5.0);
+        assert!((freq(127, 256, FilterRange::Extended) - 10671.0).abs() < 10.0);
+        // envelope at its centre halves the cutoff value
+        let half = freq(63, 256, FilterRange::Standard) * 2.0f32.powf(0.5 / 24.0);
+        assert!((freq(127, 0, FilterRange::Standard) - half).abs() < 1.0);
+        assert!((freq(127, -256, FilterRange::Standard) - 130.8).abs() < 0.1);
+
+        // unity gain for DC, attenuation near Nyquist
+        let mut filter = ResonantFilter {
+            enabled: true,
+            ..Default::default()
+        };
+        filter.setup(40, 0, 0, FilterRange::Standard, 48000.0);
+        let mut out = (0.0, 0.0);
+        for _ in 0..48000 {
+            out = filter.process((0.5, -0.5));
+        }
+        assert!((out.0 - 0.5).abs() < 1e-3 && (out.1 + 0.5).abs() < 1e-3);
+        filter.reset();
+        let mut peak = 0.0f32;
+        for i in 0..4800 {
+            let v = if i % 2 == 0 { 1.0 } else { -1.0 };
+            let (l, _) = filter.process((v, v));
+            if i > 2400 {
+                peak = peak.max(l.abs());
+            }
+        }
+        assert!(peak < 0.01, "{peak}");
+    }
+}
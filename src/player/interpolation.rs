@@ -0,0 +1,253 @@
+use crate::prelude::*;
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// How the mixer reads a sample between two frames
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Previous frame, the historical tracker sound
+    Nearest,
+    #[default]
+    Linear,
+    /// 4 points Catmull-Rom spline
+    Cubic,
+    /// 8 points Lanczos windowed sinc
+    Sinc,
+}
+
+/// Sinc kernel half width, in frames
+const SINC_HALF_TAPS: usize = 4;
+/// Fractional positions in the sinc table
+const SINC_PHASES: usize = 256;
+
+/// Loop in use, `LoopType::No` if the loop is not playable
+pub(crate) fn active_loop(sample: &Sample, sustain: bool) -> (LoopType, usize, usize) {
+    let (flag, start, length) = if sustain && sample.sustain_loop_length != 0 {
+        (
+            sample.sustain_loop_flag,
+            sample.sustain_loop_start as usize,
+            sample.sustain_loop_length as usize,
+        )
+    } else {
+        (
+            sample.loop_flag,
+            sample.loop_start as usize,
+            sample.loop_length as usize,
+        )
+    };
+    if length == 0 || start + length > sample.len() {
+        (LoopType::No, 0, 0)
+    } else {
+        (flag, start, length)
+    }
+}
+
+/// Unfolded loop length: one pass for forward loops, back and forth for ping-pong loops
+pub(crate) fn loop_period(flag: LoopType, length: usize) -> usize {
+    match flag {
+        LoopType::No => 0,
+        LoopType::Forward => length,
+        LoopType::PingPong => 2 * length,
+    }
+}
+
+/// Read samples at fractional positions
+#[derive(Clone, Debug)]
+pub struct Interpolator {
+    mode: Interpolation,
+    /// `SINC_PHASES + 1` rows of `2 * SINC_HALF_TAPS` weights
+    sinc_table: Vec<f32>,
+}
+
+impl Default for Interpolator {
+    fn default() -> Self {
+        Self::new(Interpolation::default())
+    }
+}
+
+impl Interpolator {
+    pub fn new(mode: Interpolation) -> Self {
+        let sinc_table = if mode == Interpolation::Sinc {
+            Self::build_sinc_table()
+        } else {
+            Vec::new()
+        };
+        Self { mode, sinc_table }
+    }
+
+    pub fn get_mode(&self) -> Interpolation {
+        self.mode
+    }
+
+    fn build_sinc_table() -> Vec<f32> {
+        let taps = 2 * SINC_HALF_TAPS;
+        let a = SINC_HALF_TAPS as f32;
+        let sinc = |x: f32| {
+            if x.abs() < 1e-6 {
+                1.0
+            } else {
+                let px = core::f32::consts::PI * x;
+                px.sin() / px
+            }
+        };
+        let mut table = Vec::with_capacity((SINC_PHASES + 1) * taps);
+        for phase in 0..=SINC_PHASES {
+            let t = phase as f32 / SINC_PHASES as f32;
+            let row: Vec<f32> = (0..taps)
+                .map(|k| {
+                    // distance from the tap at index - SINC_HALF_TAPS + 1 + k
+                    let x = k as f32 - (SINC_HALF_TAPS as f32 - 1.0) - t;
+                    if x.abs() >= a {
+                        0.0
+                    } else {
+                        sinc(x) * sinc(x / a)
+                    }
+                })
+                .collect();
+            // unity gain for constant signals
+            let sum: f32 = row.iter().sum();
+            table.extend(row.iter().map(|w| w / sum));
+        }
+        table
+    }
+
+    /// Stereo frame at `position`, an unfolded position (see `Sample::meta_seek()`).
+    ///
+    /// Frames around the position follow the loop used while `sustain` is true,
+    /// outside of the sample they are silent
+    pub fn frame(&self, sample: &Sample, position: f64, sustain: bool) -> (f32, f32) {
+        let (flag, start, length) = active_loop(sample, sustain);
+        let index = position as i64;
+        let t = (position - index as f64) as f32;
+        // after the first pass, frames before the loop start are loop frames
+        let looped = !matches!(flag, LoopType::No) && index >= (start + length) as i64;
+        let at = |i: i64| Self::frame_at(sample, i, sustain, flag, start, length, looped);
+
+        match self.mode {
+            Interpolation::Nearest => at(index),
+            Interpolation::Linear => {
+                let (l0, r0) = at(index);
+                let (l1, r1) = at(index + 1);
+                (l0 + (l1 - l0) * t, r0 + (r1 - r0) * t)
+            }
+            Interpolation::Cubic => {
+                let p = [at(index - 1), at(index), at(index + 1), at(index + 2)];
+                (
+                    Self::catmull_rom(p[0].0, p[1].0, p[2].0, p[3].0, t),
+                    Self::catmull_rom(p[0].1, p[1].1, p[2].1, p[3].1, t),
+                )
+            }
+            Interpolation::Sinc => {
+                let taps = 2 * SINC_HALF_TAPS;
+                let phase = (t * SINC_PHASES as f32) as usize;
+                let Some(weights) = self.sinc_table.get(phase * taps..(phase + 1) * taps) else {
+                    return at(index);
+                };
+                let first = index - SINC_HALF_TAPS as i64 + 1;
+                weights
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(l, r), (k, w)| {
+                        let (fl, fr) = at(first + k as i64);
+                        (l + fl * w, r + fr * w)
+                    })
+            }
+        }
+    }
+
+    fn catmull_rom(y0: f32, y1: f32, y2: f32, y3: f32, t: f32) -> f32 {
+        let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+        let b = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c = -0.5 * y0 + 0.5 * y2;
+        ((a * t + b) * t + c) * t + y1
+    }
+
+    /// Frame at an unfolded index
+    fn frame_at(
+        sample: &Sample,
+        index: i64,
+        sustain: bool,
+        flag: LoopType,
+        start: usize,
+        length: usize,
+        looped: bool,
+    ) -> (f32, f32) {
+        let mut index = index;
+        if looped && index < start as i64 {
+            let period = loop_period(flag, length) as i64;
+            index = start as i64 + (index - start as i64).rem_euclid(period);
+        }
+        if index < 0 {
+            return (0.0, 0.0);
+        }
+        let index = index as usize;
+        let real = match flag {
+            LoopType::No => index,
+            _ => sample.meta_seek(index, sustain),
+        };
+        if real < sample.len() {
+            sample.at(real)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    #[test]
+    fn player_interpolation() {
+        use crate::player::interpolation::Interpolator;
+        use crate::prelude::*;
+
+        let mut sample = Sample {
+            name: "".into(),
+            relative_pitch: 0,
+            finetune: 0.0,
+            volume: 1.0,
+            panning: 0.5,
+            loop_flag: LoopType::Forward,
+            loop_start: 1,
+            loop_length: 3,
+            sustain_loop_flag: LoopType::No,
+            sustain_loop_start: 0,
+            sustain_loop_length: 0,
+            data: Some(SampleDataType::Mono16(vec![0, 8192, 16384, 24576])),
+        };
+        let at = |mode, sample: &Sample, pos| Interpolator::new(mode).frame(sample, pos, false).0;
+
+        // forward loop: frame 3 is followed by frame 1
+        assert_eq!(at(Interpolation::Nearest, &sample, 4.5), 0.25);
+        assert_eq!(at(Interpolation::Linear, &sample, 3.5), 0.5);
+        assert_eq!(at(Interpolation::Linear, &sample, 4.5), 0.375);
+        // after the first pass, frame 3 is before frame 1
+        let c = at(Interpolation::Cubic, &sample, 4.0);
+        assert!((c - 0.25).abs() < 1e-6);
+
+        // ping-pong loop: frame 3 is followed by frame 3 then frame 2
+        sample.loop_flag = LoopType::PingPong;
+        assert_eq!(at(Interpolation::Linear, &sample, 3.5), 0.75);
+        assert_eq!(at(Interpolation::Linear, &sample, 4.5), 0.625);
+
+        // no loop: silence after the end
+        sample.loop_flag = LoopType::No;
+        assert_eq!(at(Interpolation::Linear, &sample, 3.5), 0.375);
+
+        // sinc keeps a constant signal constant, loop included
+        sample.loop_flag = LoopType::Forward;
+        sample.data = Some(SampleDataType::Mono16(vec![16384; 4]));
+        for pos in [3.5, 4.75, 7.1, 100.3] {
+            let v = at(Interpolation::Sinc, &sample, pos);
+            assert!((v - 0.5).abs() < 1e-4, "{pos} {v}");
+        }
+    }
+}
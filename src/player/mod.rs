@@ -0,0 +1,38 @@
+#![forbid(unsafe_code)]
+
+/// Channel state: note, instrument, effects and sample playback
+pub mod channel;
+
+/// Instrument envelope playback
+pub mod envelope_state;
+
+/// Impulse Tracker resonant filter
+pub mod filter;
+
+/// Read samples between frames
+pub mod interpolation;
+
+/// Render a Module to PCM
+pub mod module_player;
+
+/// Yamaha OPL2/OPL3 FM synthesis
+pub mod opl;
+
+/// MOS 6581/8580 SID emulation
+pub mod sid;
+
+/// Walk `pattern_order`: rows, ticks, speed, BPM and global effects
+pub mod sequencer;
+
+/// Song duration and loop point
+pub mod song_duration;
+
+/// Songs hidden behind jumps in the order list
+pub mod subsong;
+
+/// Background voices: New Note Actions and duplicate checks
+pub mod voice_pool;
+
+/// Samples and measures shared by the player tests
+#[cfg(test)]
+pub(crate) mod test_helpers;
@@ -0,0 +1,709 @@
+use crate::player::channel::{Channel, ChannelState};
+use crate::player::filter::FilterRange;
+use crate::player::interpolation::{Interpolation, Interpolator};
+use crate::player::sequencer::Sequencer;
+use crate::player::sid::chip::SidModel;
+use crate::player::subsong::Subsong;
+use crate::player::voice_pool::VoicePool;
+use crate::prelude::*;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Playback position, global state and state of all channels
+#[derive(Clone, Debug)]
+pub struct PlaybackState {
+    pub order: usize,
+    pub row: usize,
+    /// Tick in the row, pattern delays included
+    pub tick: usize,
+    pub speed: usize,
+    pub bpm: usize,
+    /// [0..1]
+    pub global_volume: f32,
+    /// Time since the start of the song, in seconds
+    pub seconds: f64,
+    /// `(row, count)`: start row of the pattern loop and repeats left
+    pub pattern_loop: (usize, usize),
+    pub channels: Vec<ChannelState>,
+    /// Background voices, with the pattern channel which played them
+    pub voices: Vec<(usize, ChannelState)>,
+}
+
+/// How stems split the mix, see `Player::next_stems()`
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StemGrouping {
+    /// One stem per pattern channel, background voices go to the channel which played them
+    #[default]
+    Channel,
+    /// One stem per instrument
+    Instrument,
+}
+
+/// Tick accurate replayer, renders a `Module` to interleaved stereo f32 frames
+///
+/// ```ignore
+/// let mut player = Player::new(&module, 48000.0, 0);
+/// let mut buffer = [0.0f32; 2 * 1024];
+/// while player.fill_buffer(&mut buffer) != 0 {
+///     // ...
+/// }
+/// ```
+pub struct Player<'a> {
+    module: &'a Module,
+    sample_rate: f32,
+    sequencer: Sequencer,
+    channels: Vec<Channel>,
+    /// Notes moved to the background by New Note Actions
+    voices: VoicePool,
+    polyphony: usize,
+    amplification: f32,
+    interpolator: Interpolator,
+    /// Output frames left in the current tick
+    tick_frames: f32,
+    /// Used when a row is shorter than the number of channels
+    empty_unit: TrackUnit,
+}
+
+impl<'a> Player<'a> {
+    /// Impulse Tracker virtual channels
+    const DEFAULT_POLYPHONY: usize = 256;
+
+    /// Play `song` (index in `Module::pattern_order`) at `sample_rate` Hz
+    pub fn new(module: &'a Module, sample_rate: f32, song: usize) -> Self {
+        Self::with_sequencer(module, sample_rate, Sequencer::new(module, song))
+    }
+
+    /// Play a subsong, see `Module::get_subsongs()`
+    pub fn new_subsong(module: &'a Module, sample_rate: f32, subsong: &Subsong) -> Self {
+        let sequencer = Sequencer::new_at_order(module, subsong.song, subsong.start_order);
+        Self::with_sequencer(module, sample_rate, sequencer)
+    }
+
+    fn with_sequencer(module: &'a Module, sample_rate: f32, sequencer: Sequencer) -> Self {
+        let num_channels = module
+            .pattern
+            .iter()
+            .filter_map(|p| p.first())
+            .map(|row| row.len())
+            .max()
+            .unwrap_or(0);
+        Self {
+            module,
+            sample_rate,
+            sequencer,
+            channels: (0..num_channels)
+                .map(|_| Channel::new(module.frequency_type))
+                .collect(),
+            voices: VoicePool::new(Self::DEFAULT_POLYPHONY.saturating_sub(num_channels)),
+            polyphony: Self::DEFAULT_POLYPHONY,
+            amplification: 0.5,
+            interpolator: Interpolator::default(),
+            tick_frames: 0.0,
+            empty_unit: TrackUnit::default(),
+        }
+    }
+
+    pub fn get_sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn get_amplification(&self) -> f32 {
+        self.amplification
+    }
+
+    /// Master gain applied to the mix, 0.5 by default
+    pub fn set_amplification(&mut self, amplification: f32) {
+        self.amplification = amplification;
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.interpolator.get_mode()
+    }
+
+    /// Sample interpolation, `Interpolation::Linear` by default
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        if interpolation != self.interpolator.get_mode() {
+            self.interpolator = Interpolator::new(interpolation);
+        }
+    }
+
+    /// Cutoff range of the resonant filter, `FilterRange::Standard` by default.
+    /// Modules made with OpenMPT may need `FilterRange::Extended`
+    pub fn set_filter_range(&mut self, range: FilterRange) {
+        for ch in self.channels.iter_mut() {
+            ch.filter_range = range;
+        }
+    }
+
+    /// Fast Tracker II note off, on by default: a note without volume envelope is
+    /// silenced. Turn it off for IT and S3M modules, where it only leaves the sustain loop
+    pub fn set_ft2_key_off(&mut self, on: bool) {
+        for ch in self.channels.iter_mut() {
+            ch.ft2_key_off = on;
+        }
+    }
+
+    /// Chip used by SID instruments, `SidModel::Mos6581` by default
+    pub fn set_sid_model(&mut self, model: SidModel) {
+        for ch in self.channels.iter_mut() {
+            ch.sid_model = model;
+        }
+    }
+
+    pub fn get_polyphony(&self) -> usize {
+        self.polyphony
+    }
+
+    /// Maximum number of voices playing at the same time, pattern channels included.
+    /// When a note goes to the background and there is no room left, the quietest
+    /// background voice is stolen. 256 by default
+    pub fn set_polyphony(&mut self, polyphony: usize) {
+        self.polyphony = polyphony;
+        self.voices
+            .set_max_voices(polyphony.saturating_sub(self.channels.len()));
+    }
+
+    pub fn get_voice_pool(&self) -> &VoicePool {
+        &self.voices
+    }
+
+    /// Number of times the song can go back to an already played order, 0 by default
+    pub fn set_max_loop_count(&mut self, max_loop_count: usize) {
+        self.sequencer.max_loop_count = max_loop_count;
+    }
+
+    pub fn set_mute_channel(&mut self, channel: usize, mute: bool) {
+        if let Some(ch) = self.channels.get_mut(channel) {
+            ch.muted = mute;
+        }
+        self.voices.set_mute(channel, mute);
+    }
+
+    pub fn get_sequencer(&self) -> &Sequencer {
+        &self.sequencer
+    }
+
+    /// `(order, row)` currently played
+    pub fn get_position(&self) -> (usize, usize) {
+        (self.sequencer.order, self.sequencer.row)
+    }
+
+    pub fn get_num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.sequencer.ended && self.tick_frames < 1.0
+    }
+
+    /// Current position and state of all channels
+    pub fn get_state(&self) -> PlaybackState {
+        let seq = &self.sequencer;
+        PlaybackState {
+            order: seq.order,
+            row: seq.row,
+            tick: seq.tick,
+            speed: seq.speed,
+            bpm: seq.bpm,
+            global_volume: seq.global_volume,
+            seconds: seq.seconds,
+            pattern_loop: seq.get_pattern_loop(),
+            channels: self.channels.iter().map(|ch| ch.get_state()).collect(),
+            voices: self
+                .voices
+                .get_voices()
+                .iter()
+                .filter(|v| v.voice.active)
+                .map(|v| (v.host, v.voice.get_state()))
+                .collect(),
+        }
+    }
+
+    /// Seek to `ms` milliseconds from the start of the song, on a tick boundary.
+    ///
+    /// The song is replayed from the start without rendering audio, so pattern
+    /// loops, tempo changes and effects memory are the same as in a full playback.
+    /// Seeking past the end of the song stops at the end
+    pub fn seek_time(&mut self, ms: f64) -> PlaybackState {
+        self.restart();
+        while !self.sequencer.ended && self.sequencer.seconds * 1000.0 < ms {
+            self.skip_tick();
+        }
+        self.get_state()
+    }
+
+    /// Seek to the first time `(order, row)` is played, see `seek_time()`.
+    ///
+    /// Return None if the song ends before, the player is then at the end of the song
+    pub fn seek_position(&mut self, order: usize, row: usize) -> Option<PlaybackState> {
+        self.restart();
+        loop {
+            let seq = &self.sequencer;
+            if seq.order == order && seq.row == row && seq.tick == 0 {
+                return Some(self.get_state());
+            }
+            if seq.ended {
+                return None;
+            }
+            self.skip_tick();
+        }
+    }
+
+    /// Back to the start of the song, user settings are kept
+    fn restart(&mut self) {
+        let module = self.module;
+        let max_loop_count = self.sequencer.max_loop_count;
+        self.sequencer =
+            Sequencer::new_at_order(module, self.sequencer.song, self.sequencer.start_order);
+        self.sequencer.max_loop_count = max_loop_count;
+        for ch in self.channels.iter_mut() {
+            let mut fresh = Channel::new(module.frequency_type);
+            fresh.muted = ch.muted;
+            fresh.filter_range = ch.filter_range;
+            fresh.sid_model = ch.sid_model;
+            fresh.ft2_key_off = ch.ft2_key_off;
+            *ch = fresh;
+        }
+        self.voices = VoicePool::new(self.voices.get_max_voices());
+        self.tick_frames = 0.0;
+    }
+
+    /// Play one tick without rendering its frames
+    fn skip_tick(&mut self) {
+        self.tick();
+        let frames = self.tick_frames as usize;
+        for ch in self.channels.iter_mut() {
+            ch.skip_frames(self.module, frames);
+        }
+        self.voices.skip_frames(self.module, frames);
+        self.tick_frames -= frames as f32;
+    }
+
+    pub fn get_num_stems(&self, grouping: StemGrouping) -> usize {
+        match grouping {
+            StemGrouping::Channel => self.channels.len(),
+            StemGrouping::Instrument => self.module.instrument.len(),
+        }
+    }
+
+    /// Stem labels: channel or instrument names, numbered from 1 when a name is empty
+    pub fn get_stem_names(&self, grouping: StemGrouping) -> Vec<String> {
+        let module = self.module;
+        (0..self.get_num_stems(grouping))
+            .map(|i| {
+                let (name, kind) = match grouping {
+                    StemGrouping::Channel => (module.channel_names.get(i), "Channel"),
+                    StemGrouping::Instrument => (
+                        module.instrument.get(i).map(|instr| &instr.name),
+                        "Instrument",
+                    ),
+                };
+                match name {
+                    Some(name) if !name.is_empty() => name.clone(),
+                    _ => format!("{} {}", kind, i + 1),
+                }
+            })
+            .collect()
+    }
+
+    /// Next stereo frame of each stem, the sum of all stems is the mix.
+    ///
+    /// `stems` should have `get_num_stems(grouping)` items, return false at the end of the song
+    pub fn next_stems(&mut self, grouping: StemGrouping, stems: &mut [(f32, f32)]) -> bool {
+        stems.fill((0.0, 0.0));
+        if !self.next_frame() {
+            return false;
+        }
+        let module = self.module;
+        let interpolator = &self.interpolator;
+        let amplification = self.amplification;
+        let mut mix = |stem: Option<usize>, (l, r): (f32, f32)| {
+            if let Some(s) = stem.and_then(|s| stems.get_mut(s)) {
+                s.0 += l * amplification;
+                s.1 += r * amplification;
+            }
+        };
+        for (i, ch) in self.channels.iter_mut().enumerate() {
+            let frame = ch.render_frame(module, interpolator);
+            mix(
+                match grouping {
+                    StemGrouping::Channel => Some(i),
+                    StemGrouping::Instrument => ch.get_instrument(),
+                },
+                frame,
+            );
+        }
+        self.voices.render_voices(module, interpolator, |v, frame| {
+            mix(
+                match grouping {
+                    StemGrouping::Channel => Some(v.host),
+                    StemGrouping::Instrument => v.voice.get_instrument(),
+                },
+                frame,
+            )
+        });
+        true
+    }
+
+    /// Fill each buffer with a stem, interleaved stereo or mono frames.
+    /// Return the number of frames written
+    pub fn fill_stems(
+        &mut self,
+        grouping: StemGrouping,
+        buffers: &mut [&mut [f32]],
+        mono: bool,
+    ) -> usize {
+        let width = if mono { 1 } else { 2 };
+        let len = buffers.iter().map(|b| b.len() / width).min().unwrap_or(0);
+        let mut stems = vec![(0.0, 0.0); buffers.len()];
+        let mut frames = 0;
+        while frames < len && self.next_stems(grouping, &mut stems) {
+            for (buffer, (left, right)) in buffers.iter_mut().zip(stems.iter()) {
+                if mono {
+                    buffer[frames] = (left + right) * 0.5;
+                } else {
+                    buffer[2 * frames] = *left;
+                    buffer[2 * frames + 1] = *right;
+                }
+            }
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Play ticks until there is a frame to render, false at the end of the song
+    fn next_frame(&mut self) -> bool {
+        while self.tick_frames < 1.0 {
+            if self.sequencer.ended {
+                return false;
+            }
+            self.tick();
+        }
+        self.tick_frames -= 1.0;
+        true
+    }
+
+    fn tick(&mut self) {
+        let module = self.module;
+        let seq = &mut self.sequencer;
+        seq.process_tick(module);
+        let row = seq.current_row(module);
+        let tick = seq.channel_tick();
+        let first_repeat = seq.is_first_repeat();
+        for (i, ch) in self.channels.iter_mut().enumerate() {
+            let unit = row.and_then(|r| r.get(i)).unwrap_or(&self.empty_unit);
+            if first_repeat
+                && tick == unit.get_delay()
+                && unit.note.is_valid()
+                && !(unit.has_tone_portamento() && ch.active)
+            {
+                self.voices.new_note(module, i, ch, unit);
+            }
+            self.voices.past_note_effects(module, i, unit, tick);
+            ch.tick(
+                module,
+                unit,
+                tick,
+                first_repeat,
+                seq.global_volume,
+                self.sample_rate,
+            );
+        }
+        self.voices
+            .tick(module, seq.global_volume, self.sample_rate);
+        self.tick_frames += self.sample_rate * 2.5 / seq.bpm as f32;
+        seq.advance(module);
+    }
+
+    /// Fill `buffer` with interleaved stereo frames, return the number of frames written
+    pub fn fill_buffer(&mut self, buffer: &mut [f32]) -> usize {
+        let mut frames = 0;
+        for frame in buffer.chunks_exact_mut(2) {
+            match self.next() {
+                Some((left, right)) => {
+                    frame[0] = left;
+                    frame[1] = right;
+                    frames += 1;
+                }
+                None => break,
+            }
+        }
+        frames
+    }
+}
+
+impl Iterator for Player<'_> {
+    type Item = (f32, f32);
+
+    /// Next stereo frame, None at the end of the song
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.next_frame() {
+            return None;
+        }
+
+        let module = self.module;
+        let interpolator = &self.interpolator;
+        let (left, right) = self
+            .channels
+            .iter_mut()
+            .map(|ch| ch.render_frame(module, interpolator))
+            .fold((0.0, 0.0), |(l, r), (cl, cr)| (l + cl, r + cr));
+        let (vl, vr) = self.voices.render_frame(module, interpolator);
+        let (left, right) = (left + vl, right + vr);
+        Some((left * self.amplification, right * self.amplification))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::square_sample;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn player_render() {
+        use crate::prelude::*;
+
+        let mut instr = InstrDefault {
+            sample: vec![Some(square_sample(1.0))],
+            ..Default::default()
+        };
+        instr.change_all_sample_for_pitch(0);
+        let row = |note: Pitch| {
+            vec![TrackUnit {
+                note,
+                instrument: note.is_valid().then_some(0),
+                ..Default::default()
+            }]
+        };
+        let module = Module {
+            default_tempo: 6,
+            default_bpm: 125,
+            pattern_order: vec![vec![0, 1, 0]],
+            pattern: vec![
+                vec![
+                    row(Pitch::C4),
+                    row(Pitch::None),
+                    row(Pitch::E4),
+                    row(Pitch::Off),
+                ],
+                vec![row(Pitch::G4), row(Pitch::None)],
+            ],
+            instrument: vec![Instrument {
+                name: "".into(),
+                instr_type: InstrumentType::Default(instr),
+                muted: false,
+            }],
+            ..Default::default()
+        };
+        let mut player = Player::new(&module, 44100.0, 0);
+        let mut buffer = vec![0.0f32; 2 * 4096];
+        let mut frames = 0;
+        let mut peak = 0.0f32;
+        loop {
+            let n = player.fill_buffer(&mut buffer);
+            if n == 0 {
+                break;
+            }
+            peak = buffer[..2 * n].iter().fold(peak, |p, v| p.max(v.abs()));
+            frames += n;
+            assert!(frames < 44100 * 60);
+        }
+        assert!(player.is_ended());
+        assert!(peak > 0.0 && peak <= 1.0);
+
+        let rows: usize = module.pattern_order[0]
+            .iter()
+            .map(|&p| module.get_num_rows(p))
+            .sum();
+        let expected = rows * module.default_tempo * 44100 * 5 / (2 * module.default_bpm);
+        assert!(frames.abs_diff(expected) < expected / 10 + 1);
+
+        let mut player = Player::new(&module, 44100.0, 0);
+        player.set_max_loop_count(1);
+        assert!(player.count() > frames);
+    }
+
+    #[test]
+    fn player_seek() {
+        use crate::prelude::*;
+
+        let mut instr = InstrDefault {
+            sample: vec![Some(square_sample(1.0))],
+            ..Default::default()
+        };
+        instr.change_all_sample_for_pitch(0);
+        let row = |effects: Vec<TrackEffect>, global_effects: Vec<GlobalEffect>| {
+            vec![TrackUnit {
+                effects,
+                global_effects,
+                ..Default::default()
+            }]
+        };
+        let module = Module {
+            default_tempo: 6,
+            default_bpm: 125,
+            pattern_order: vec![vec![0, 1, 0]],
+            pattern: vec![
+                vec![
+                    vec![TrackUnit {
+                        note: Pitch::C4,
+                        instrument: Some(0),
+                        effects: vec![TrackEffect::Panning(0.25)],
+                        global_effects: vec![GlobalEffect::PatternLoop(0)],
+                        ..Default::default()
+                    }],
+                    row(
+                        vec![TrackEffect::Vibrato {
+                            speed: 0.125,
+                            depth: 0.5,
+                        }],
+                        vec![GlobalEffect::PatternLoop(2)],
+                    ),
+                    row(
+                        vec![TrackEffect::Volume {
+                            value: 0.5,
+                            tick: 0,
+                        }],
+                        vec![GlobalEffect::Speed(3)],
+                    ),
+                    row(vec![], vec![]),
+                ],
+                vec![
+                    row(vec![], vec![]),
+                    row(vec![], vec![GlobalEffect::PositionJump(1)]),
+                ],
+            ],
+            instrument: vec![Instrument {
+                name: "".into(),
+                instr_type: InstrumentType::Default(instr),
+                muted: false,
+            }],
+            ..Default::default()
+        };
+        let mut player = Player::new(&module, 1000.0, 0);
+
+        // first pass of the pattern loop, the loop is not set yet
+        let state = player.seek_position(0, 1).unwrap();
+        assert!((state.seconds - 0.12).abs() < 1e-9);
+        assert_eq!(state.pattern_loop, (0, 0));
+        assert_eq!(state.channels[0].instrument, Some(0));
+        assert_eq!(state.channels[0].note, Pitch::C4);
+        assert_eq!(state.channels[0].panning, 0.25);
+
+        // after the pattern loop, before the speed change
+        let state = player.seek_position(0, 2).unwrap();
+        assert!((state.seconds - 0.72).abs() < 1e-9);
+        assert_eq!(state.speed, 6);
+        assert_eq!(state.channels[0].vibrato.speed, 0.125);
+        assert_eq!(state.channels[0].vibrato.depth, 0.5);
+        assert_eq!(state.channels[0].volume, 1.0);
+
+        let state = player.seek_position(1, 1).unwrap();
+        assert!((state.seconds - 0.9).abs() < 1e-9);
+        assert_eq!(state.speed, 3);
+        assert_eq!(state.channels[0].volume, 0.5);
+        assert!(state.channels[0].active);
+
+        assert!(player.seek_position(5, 0).is_none());
+        assert!(player.is_ended());
+
+        // playing from a seek point renders the end of a full playback
+        let full: Vec<(f32, f32)> = Player::new(&module, 1000.0, 0).collect();
+        let state = player.seek_time(890.0);
+        assert_eq!((state.order, state.row, state.tick), (1, 1, 0));
+        let tail: Vec<(f32, f32)> = player.collect();
+        assert_eq!(tail.len(), 60);
+        for (a, b) in tail.iter().zip(full[900..].iter()) {
+            assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn player_stems() {
+        use crate::prelude::*;
+
+        let instrument = |name: &str, volume: f32| {
+            let mut instr = InstrDefault {
+                sample: vec![Some(square_sample(volume))],
+                duplicate_check: DuplicateCheckType::Off(NewNoteAction::Continue),
+                ..Default::default()
+            };
+            instr.change_all_sample_for_pitch(0);
+            Instrument {
+                name: name.into(),
+                instr_type: InstrumentType::Default(instr),
+                muted: false,
+            }
+        };
+        let unit = |note: Pitch, instrument: usize| TrackUnit {
+            note,
+            instrument: note.is_valid().then_some(instrument),
+            ..Default::default()
+        };
+        let module = Module {
+            pattern_order: vec![vec![0]],
+            pattern: vec![vec![
+                vec![unit(Pitch::C4, 0), unit(Pitch::C4, 1)],
+                vec![unit(Pitch::E4, 0), unit(Pitch::None, 1)],
+                vec![unit(Pitch::None, 0), unit(Pitch::None, 1)],
+            ]],
+            channel_names: vec!["Lead".into()],
+            instrument: vec![instrument("", 1.0), instrument("Bass", 0.5)],
+            ..Default::default()
+        };
+
+        let player = Player::new(&module, 8000.0, 0);
+        assert_eq!(
+            player.get_stem_names(StemGrouping::Channel),
+            vec!["Lead", "Channel 2"]
+        );
+        assert_eq!(
+            player.get_stem_names(StemGrouping::Instrument),
+            vec!["Instrument 1", "Bass"]
+        );
+
+        // each channel plays one instrument: both groupings give the same stems
+        let mix = Player::new(&module, 8000.0, 0);
+        let mut by_channel = Player::new(&module, 8000.0, 0);
+        let mut by_instrument = Player::new(&module, 8000.0, 0);
+        let mut channel_stems = [(0.0, 0.0); 2];
+        let mut instrument_stems = [(0.0, 0.0); 2];
+        let mut background = 0.0;
+        for (left, right) in mix {
+            assert!(by_channel.next_stems(StemGrouping::Channel, &mut channel_stems));
+            assert!(by_instrument.next_stems(StemGrouping::Instrument, &mut instrument_stems));
+            let (l, r) = channel_stems
+                .iter()
+                .fold((0.0, 0.0), |(l, r), s| (l + s.0, r + s.1));
+            assert!((l - left).abs() < 1e-5 && (r - right).abs() < 1e-5);
+            for (c, i) in channel_stems.iter().zip(instrument_stems.iter()) {
+                assert!((c.0 - i.0).abs() < 1e-5 && (c.1 - i.1).abs() < 1e-5);
+            }
+            if !by_channel.get_voice_pool().is_empty() {
+                background += channel_stems[0].0.abs();
+            }
+        }
+        assert!(!by_channel.next_stems(StemGrouping::Channel, &mut channel_stems));
+        // the first C-4 goes on in the background, on the first stem
+        assert!(background > 0.0);
+
+        let mut stereo = Player::new(&module, 8000.0, 0);
+        let mut mono = Player::new(&module, 8000.0, 0);
+        let (mut l0, mut l1, mut m0, mut m1) = ([0.0; 64], [0.0; 64], [0.0; 32], [0.0; 32]);
+        assert_eq!(
+            stereo.fill_stems(StemGrouping::Channel, &mut [&mut l0, &mut l1], false),
+            32
+        );
+        assert_eq!(
+            mono.fill_stems(StemGrouping::Channel, &mut [&mut m0, &mut m1], true),
+            32
+        );
+        for (i, m) in m1.iter().enumerate() {
+            assert!((m - (l1[2 * i] + l1[2 * i + 1]) * 0.5).abs() < 1e-6);
+        }
+    }
+}
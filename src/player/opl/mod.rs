@@ -0,0 +1,7 @@
+#![forbid(unsafe_code)]
+
+/// Phase generator, envelope generator and waveforms of an operator
+pub mod operator;
+
+/// Two operators voice, LFOs and F-Number
+pub mod voice;
@@ -0,0 +1,254 @@
+use crate::instr_opl::MdiOpl;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Frequency multiplier times two, `multiple` 0 is 1/2
+const MULTIPLE: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation by the 4 upper bits of the F-Number
+const KSL: [i32; 16] = [
+    0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64,
+];
+
+/// 0, 3, 1.5 and 6 dB per octave
+const KSL_SHIFT: [u32; 4] = [8, 1, 2, 0];
+
+/// From maximum attenuation to 0 dB at rate 1, in seconds
+const ATTACK_TIME: f32 = 2.826_24;
+
+/// From 0 dB to 96 dB at rate 1, in seconds
+const DECAY_TIME: f32 = 39.280_64;
+
+/// 9 bits envelope, 0.1875 dB steps
+const MAX_ATTENUATION: f32 = 511.0;
+
+/// Log-sin and exponential ROMs of the chip
+#[derive(Clone, Debug)]
+pub struct OplTables {
+    log_sin: [u16; 256],
+    exp: [u16; 256],
+}
+
+impl Default for OplTables {
+    fn default() -> Self {
+        let mut tables = Self {
+            log_sin: [0; 256],
+            exp: [0; 256],
+        };
+        for i in 0..256 {
+            let angle = (i as f32 + 0.5) * core::f32::consts::PI / 512.0;
+            tables.log_sin[i] = (-angle.sin().log2() * 256.0).round() as u16;
+            tables.exp[i] = (2.0f32.powf((255 - i) as f32 / 256.0) * 1024.0).round() as u16;
+        }
+        tables
+    }
+}
+
+impl OplTables {
+    /// Signed 13 bits output of `wave_select` (OPL3 waveforms 0 to 7)
+    /// at `phase` (10 bits), attenuated by `envelope` (9 bits)
+    pub fn wave(&self, wave_select: u8, phase: u16, envelope: u32) -> i32 {
+        const SILENT: u32 = 0x1000;
+        let phase = phase & 0x3FF;
+        let quarter = |p: u16| {
+            if p & 0x100 != 0 {
+                self.log_sin[(p & 0xFF) as usize ^ 0xFF] as u32
+            } else {
+                self.log_sin[(p & 0xFF) as usize] as u32
+            }
+        };
+        let (log, negative) = match wave_select & 0x07 {
+            // sine
+            0 => (quarter(phase), phase & 0x200 != 0),
+            // half sine
+            1 if phase & 0x200 != 0 => (SILENT, false),
+            1 => (quarter(phase), false),
+            // absolute sine
+            2 => (quarter(phase), false),
+            // pulse sine
+            3 if phase & 0x100 != 0 => (SILENT, false),
+            3 => (self.log_sin[(phase & 0xFF) as usize] as u32, false),
+            // alternating sine, camel sine
+            4 | 5 if phase & 0x200 != 0 => (SILENT, false),
+            4 => (quarter(phase << 1), phase & 0x100 != 0),
+            5 => (quarter(phase << 1), false),
+            // square
+            6 => (0, phase & 0x200 != 0),
+            // derived square
+            _ if phase & 0x200 != 0 => ((((phase & 0x1FF) ^ 0x1FF) as u32) << 3, true),
+            _ => ((phase as u32) << 3, false),
+        };
+        let level = log + (envelope << 3);
+        let value = if level > 0x1FFF {
+            0
+        } else {
+            (((self.exp[(level & 0xFF) as usize] as u32) << 1) >> (level >> 8)) as i32
+        };
+        if negative {
+            !value
+        } else {
+            value
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// One OPL operator: phase generator, envelope generator and waveform
+#[derive(Clone, Copy, Debug)]
+pub struct Operator {
+    pub registers: MdiOpl,
+    /// [0..7], waveforms 4 to 7 are OPL3 only
+    pub wave_select: u8,
+    /// 19 bits phase accumulator, 10 upper bits are the waveform phase
+    phase: u32,
+    /// [0..511], attenuation in 0.1875 dB steps
+    envelope: f32,
+    state: EnvelopeState,
+    /// Two last outputs, for feedback
+    out: [i32; 2],
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self {
+            registers: MdiOpl::default(),
+            wave_select: 0,
+            phase: 0,
+            envelope: MAX_ATTENUATION,
+            state: EnvelopeState::Release,
+            out: [0; 2],
+        }
+    }
+}
+
+impl Operator {
+    pub fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    pub fn key_off(&mut self) {
+        self.state = EnvelopeState::Release;
+    }
+
+    /// Released and at maximum attenuation
+    pub fn is_silent(&self) -> bool {
+        self.state == EnvelopeState::Release && self.envelope >= MAX_ATTENUATION
+    }
+
+    /// Phase increment by sample
+    pub fn phase_increment(&self, fnum: u16, block: u8) -> u32 {
+        ((((fnum as u32) << block) >> 1) * MULTIPLE[(self.registers.multiple & 0x0F) as usize]) >> 1
+    }
+
+    /// Key scale level and total level, in envelope steps
+    pub fn attenuation(&self, fnum: u16, block: u8) -> u32 {
+        let ksl = ((KSL[(fnum >> 6) as usize & 0x0F] << 2) - ((8 - block as i32) << 5)).max(0);
+        let ksl = ksl as u32 >> KSL_SHIFT[(self.registers.ksl & 0x03) as usize];
+        ksl + (((self.registers.total_level & 0x3F) as u32) << 2)
+    }
+
+    /// Rate with key scaling, [0..63]. `keycode` is `block << 1 | fnum bit 9`
+    fn effective_rate(&self, rate: u8, keycode: u8) -> u8 {
+        if rate == 0 {
+            return 0;
+        }
+        let ksr = if self.registers.ksr {
+            keycode
+        } else {
+            keycode >> 2
+        };
+        (rate * 4 + ksr).min(63)
+    }
+
+    /// Time for `full_time` at `rate` 1 (4 with key scaling), twice faster each rate
+    fn rate_time(full_time: f32, rate: u8) -> f32 {
+        let rate = rate.min(60);
+        full_time / (1u32 << ((rate >> 2) - 1)) as f32 * 4.0 / (4 + (rate & 3)) as f32
+    }
+
+    /// Decay and release increment by sample
+    fn decay_increment(&self, rate: u8, keycode: u8, sample_rate: f32) -> f32 {
+        let rate = self.effective_rate(rate, keycode);
+        if rate < 4 {
+            return 0.0;
+        }
+        MAX_ATTENUATION / (Self::rate_time(DECAY_TIME, rate) * sample_rate)
+    }
+
+    /// Envelope generator, once by sample
+    pub fn envelope_step(&mut self, keycode: u8, sample_rate: f32) {
+        let r = self.registers;
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = self.effective_rate(r.attack & 0x0F, keycode);
+                if rate >= 60 {
+                    self.envelope = 0.0;
+                } else if rate >= 4 {
+                    // exponential attack
+                    let k = 512.0f32.ln() / (Self::rate_time(ATTACK_TIME, rate) * sample_rate);
+                    self.envelope -= (self.envelope + 1.0) * k;
+                }
+                if self.envelope <= 0.0 {
+                    self.envelope = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                let sustain = if r.sustain & 0x0F == 0x0F {
+                    0x1F0
+                } else {
+                    ((r.sustain & 0x0F) as u32) << 4
+                } as f32;
+                self.envelope += self.decay_increment(r.decay & 0x0F, keycode, sample_rate);
+                if self.envelope >= sustain {
+                    self.envelope = sustain;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            // without the sustain flag, the release starts at the sustain level
+            EnvelopeState::Sustain if r.eg => {}
+            EnvelopeState::Sustain | EnvelopeState::Release => {
+                self.envelope += self.decay_increment(r.release & 0x0F, keycode, sample_rate);
+            }
+        }
+        self.envelope = self.envelope.min(MAX_ATTENUATION);
+    }
+
+    /// Self modulation of the operator, [0..7]
+    pub fn feedback(&self, feedback: u8) -> i32 {
+        if feedback == 0 {
+            0
+        } else {
+            (self.out[0] + self.out[1]) >> (9 - feedback.min(7))
+        }
+    }
+
+    /// Next output, `modulation` is added to the phase, `attenuation` to the envelope
+    pub fn output(
+        &mut self,
+        tables: &OplTables,
+        increment: u32,
+        modulation: i32,
+        attenuation: u32,
+    ) -> i32 {
+        let phase = ((self.phase >> 9) as i32 + modulation) as u16;
+        self.phase = (self.phase + increment) & 0x7_FFFF;
+        let envelope = (self.envelope as u32 + attenuation).min(MAX_ATTENUATION as u32);
+        let out = tables.wave(self.wave_select, phase, envelope);
+        self.out = [self.out[1], out];
+        out
+    }
+}
@@ -0,0 +1,293 @@
+use crate::instr_opl::MdiInstr;
+use crate::player::opl::operator::{Operator, OplTables};
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Yamaha OPL2/OPL3 two operators voice: modulator, carrier and LFOs.
+///
+/// The voice runs at the chip sample rate and is resampled to the output rate
+#[derive(Clone, Debug)]
+pub struct OplVoice {
+    tables: OplTables,
+    pub modulator: Operator,
+    pub carrier: Operator,
+    /// Modulator self modulation, [0..7]
+    pub feedback: u8,
+    /// Both operators are heard, instead of the modulator driving the carrier
+    pub additive: bool,
+    /// 10 bits frequency number
+    pub fnum: u16,
+    /// [0..7], octave of `fnum`
+    pub block: u8,
+    /// Tremolo of 4.8 dB instead of 1 dB
+    pub deep_tremolo: bool,
+    /// Vibrato of 14 cents instead of 7 cents
+    pub deep_vibrato: bool,
+    /// Total level of the instrument operators, before the channel volume
+    total_level: [u8; 2],
+    /// Chip samples, for the LFOs
+    counter: u32,
+    sample_rate: f32,
+    /// Position between the two last chip samples
+    position: f64,
+    previous: f32,
+    current: f32,
+}
+
+impl Default for OplVoice {
+    fn default() -> Self {
+        Self {
+            tables: OplTables::default(),
+            modulator: Operator::default(),
+            carrier: Operator::default(),
+            feedback: 0,
+            additive: false,
+            fnum: 0,
+            block: 0,
+            deep_tremolo: false,
+            deep_vibrato: false,
+            total_level: [0; 2],
+            counter: 0,
+            sample_rate: 48000.0,
+            position: 0.0,
+            previous: 0.0,
+            current: 0.0,
+        }
+    }
+}
+
+impl OplVoice {
+    /// OPL sample rate, 14.31818 MHz / 288, in Hz
+    pub const RATE: f64 = 49_715.909;
+
+    /// Output sample rate, 48000 Hz by default
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate > 0.0 {
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    /// Load the operators registers of `instr`
+    pub fn set_instrument(&mut self, instr: &MdiInstr) {
+        self.modulator.registers = instr.modulator;
+        self.modulator.wave_select = instr.modulator_wave_select;
+        self.carrier.registers = instr.carrier;
+        self.carrier.wave_select = instr.carrier_wave_select;
+        // feedback and connection are in the modulator registers, like register 0xC0
+        self.feedback = instr.modulator.feedback & 0x07;
+        self.additive = instr.modulator.con;
+        self.total_level = [
+            instr.modulator.total_level & 0x3F,
+            instr.carrier.total_level & 0x3F,
+        ];
+    }
+
+    /// F-Number and block of `freq` in Hz: the lowest block where the F-Number fits in 10 bits
+    pub fn frequency_to_fnum_block(freq: f32) -> (u16, u8) {
+        let freq = freq.max(0.0);
+        for block in 0..8u8 {
+            let fnum = (freq * (1u32 << (20 - block)) as f32 / Self::RATE as f32).round();
+            if fnum < 1024.0 {
+                return (fnum as u16, block);
+            }
+        }
+        (1023, 7)
+    }
+
+    /// Frequency of the carrier at `multiple` 1, in Hz
+    pub fn set_frequency(&mut self, freq: f32) {
+        (self.fnum, self.block) = Self::frequency_to_fnum_block(freq);
+    }
+
+    /// Channel volume in [0..1], scales the total level of the heard operators
+    pub fn set_volume(&mut self, volume: f32) {
+        let volume = (volume.clamp(0.0, 1.0) * 63.0) as u32;
+        let scale = |tl: u8| {
+            let volume = if volume > 0 { volume + 1 } else { 0 };
+            (63 - (63 - tl as u32) * volume / 64) as u8
+        };
+        self.carrier.registers.total_level = scale(self.total_level[1]);
+        self.modulator.registers.total_level = if self.additive {
+            scale(self.total_level[0])
+        } else {
+            self.total_level[0]
+        };
+    }
+
+    pub fn key_on(&mut self) {
+        self.modulator.key_on();
+        self.carrier.key_on();
+    }
+
+    pub fn key_off(&mut self) {
+        self.modulator.key_off();
+        self.carrier.key_off();
+    }
+
+    /// The heard operators are released and at maximum attenuation
+    pub fn is_silent(&self) -> bool {
+        self.carrier.is_silent() && (!self.additive || self.modulator.is_silent())
+    }
+
+    /// One chip sample, a carrier at full volume is in [-1..1]
+    fn generate(&mut self) -> f32 {
+        let counter = self.counter;
+        self.counter = self.counter.wrapping_add(1);
+
+        // vibrato: 8 steps of 1024 samples, depth from the 3 upper F-Number bits
+        let vibrato_pos = (counter >> 10) & 7;
+        let mut range = ((self.fnum >> 7) & 7) as i32;
+        if vibrato_pos & 3 == 0 {
+            range = 0;
+        } else if vibrato_pos & 1 != 0 {
+            range >>= 1;
+        }
+        if !self.deep_vibrato {
+            range >>= 1;
+        }
+        if vibrato_pos & 4 != 0 {
+            range = -range;
+        }
+        let vibrato_fnum = (self.fnum as i32 + range).clamp(0, 1023) as u16;
+
+        // tremolo: triangle of 210 steps of 64 samples
+        let tremolo_pos = (counter >> 6) % 210;
+        let tremolo = if tremolo_pos < 105 {
+            tremolo_pos
+        } else {
+            210 - tremolo_pos
+        } >> if self.deep_tremolo { 2 } else { 4 };
+
+        let keycode = self.block << 1 | ((self.fnum >> 9) & 1) as u8;
+        let operator_input = |op: &Operator| {
+            let fnum = if op.registers.vib {
+                vibrato_fnum
+            } else {
+                self.fnum
+            };
+            let mut attenuation = op.attenuation(self.fnum, self.block);
+            if op.registers.am {
+                attenuation += tremolo;
+            }
+            (op.phase_increment(fnum, self.block), attenuation)
+        };
+        let (m_increment, m_attenuation) = operator_input(&self.modulator);
+        let (c_increment, c_attenuation) = operator_input(&self.carrier);
+
+        let feedback = self.modulator.feedback(self.feedback);
+        let m = self
+            .modulator
+            .output(&self.tables, m_increment, feedback, m_attenuation);
+        let c = self.carrier.output(
+            &self.tables,
+            c_increment,
+            if self.additive { 0 } else { m },
+            c_attenuation,
+        );
+
+        let rate = Self::RATE as f32;
+        self.modulator.envelope_step(keycode, rate);
+        self.carrier.envelope_step(keycode, rate);
+
+        if self.additive {
+            (m + c) as f32 / 4096.0
+        } else {
+            c as f32 / 4096.0
+        }
+    }
+
+    /// Run the chip for one output frame and return it
+    pub fn render_frame(&mut self) -> f32 {
+        self.advance(1);
+        self.previous + (self.current - self.previous) * self.position as f32
+    }
+
+    /// Run the chip for `frames` output frames, without rendering them
+    pub fn skip_frames(&mut self, frames: usize) {
+        self.advance(frames);
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.position += Self::RATE * frames as f64 / self.sample_rate as f64;
+        while self.position >= 1.0 {
+            self.position -= 1.0;
+            self.previous = self.current;
+            self.current = self.generate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::rising_edges;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn player_opl() {
+        use crate::prelude::*;
+
+        // carrier sine, the modulator never attacks
+        let mut element = MdiInstr::default();
+        element.carrier.multiple = 1;
+        element.carrier.attack = 15;
+        element.carrier.eg = true;
+        element.carrier.release = 15;
+
+        assert_eq!(OplVoice::frequency_to_fnum_block(440.0), (580, 4));
+        let mut voice = OplVoice::default();
+        voice.set_sample_rate(48000.0);
+        voice.set_instrument(&element);
+        voice.set_frequency(440.0);
+        voice.set_volume(1.0);
+        voice.key_on();
+        voice.skip_frames(4800);
+        let frames: Vec<f32> = (0..48000).map(|_| voice.render_frame()).collect();
+        assert!((rising_edges(&frames) as i32 - 440).abs() <= 2);
+        assert!(frames.iter().any(|f| f.abs() > 0.9));
+        assert!(frames.iter().all(|f| f.abs() <= 1.0));
+        assert!(!voice.is_silent());
+        // release
+        voice.key_off();
+        voice.skip_frames(4800);
+        assert!(voice.is_silent());
+
+        let unit = |note: Pitch| TrackUnit {
+            note,
+            instrument: note.is_valid().then_some(0),
+            ..Default::default()
+        };
+        let module = Module {
+            pattern_order: vec![vec![0]],
+            pattern: vec![vec![
+                vec![unit(Pitch::C4)],
+                vec![unit(Pitch::Off)],
+                vec![unit(Pitch::None)],
+            ]],
+            instrument: vec![Instrument {
+                name: "".into(),
+                instr_type: InstrumentType::Opl(InstrOpl {
+                    element,
+                    volume: 63,
+                    relative_pitch: 12,
+                    finetune: 0.0,
+                }),
+                muted: false,
+            }],
+            ..Default::default()
+        };
+        let mut player = Player::new(&module, 48000.0, 0);
+        let row: Vec<f32> = player.by_ref().take(5760).map(|(l, _)| l).collect();
+        assert!(row.iter().any(|f| f.abs() > 0.05));
+        // C-4 one octave up
+        let e = rising_edges(&row[960..]);
+        assert!((e as i32 - 52).abs() <= 1, "{}", e);
+        let state = player.seek_position(0, 2).unwrap();
+        assert!(!state.channels[0].active);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::prelude::*;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Position in a song of `Module::pattern_order` and the global state shared by all channels
+#[derive(Clone, Debug)]
+pub struct Sequencer {
+    /// Index in `Module::pattern_order`
+    pub song: usize,
+    /// First order played
+    pub start_order: usize,
+    /// Where to go after the last order
+    pub restart_order: usize,
+    /// Index in `pattern_order[song]`
+    pub order: usize,
+    pub row: usize,
+    /// Tick in the current row, pattern delays included
+    pub tick: usize,
+    pub speed: usize,
+    pub bpm: usize,
+    /// [0..1]
+    pub global_volume: f32,
+    /// Number of times the song went back to an already played row
+    pub loop_count: usize,
+    /// The song ends when `loop_count` is greater than `max_loop_count`
+    pub max_loop_count: usize,
+    pub ended: bool,
+    /// `(order, row)` where the song loops for the first time
+    pub loop_position: Option<(usize, usize)>,
+    /// Time played before the current tick, in seconds
+    pub seconds: f64,
+
+    /// Row played again `row_repeat` times (tempo pattern delay)
+    row_repeat: usize,
+    /// Ticks added to the row (tick pattern delay)
+    row_extra_ticks: usize,
+    pattern_loop_row: usize,
+    pattern_loop_count: usize,
+    /// Where to go at the end of the current row
+    next_position: Option<(usize, usize)>,
+    /// Rows already played, by order
+    visited: Vec<Vec<bool>>,
+}
+
+impl Sequencer {
+    pub fn new(module: &Module, song: usize) -> Self {
+        Self::new_at_order(module, song, 0)
+    }
+
+    /// Start at `order`. A song which does not start at the first order is a subsong,
+    /// it restarts at `order` instead of `Module::restart_position`
+    pub fn new_at_order(module: &Module, song: usize, order: usize) -> Self {
+        let mut seq = Self {
+            song,
+            start_order: order,
+            restart_order: if order == 0 {
+                module.restart_position
+            } else {
+                order
+            },
+            order,
+            row: 0,
+            tick: 0,
+            speed: module.default_tempo.max(1),
+            bpm: module.default_bpm.max(1),
+            global_volume: 1.0,
+            loop_count: 0,
+            max_loop_count: 0,
+            ended: false,
+            row_repeat: 0,
+            row_extra_ticks: 0,
+            pattern_loop_row: 0,
+            pattern_loop_count: 0,
+            next_position: None,
+            loop_position: None,
+            seconds: 0.0,
+            visited: vec![],
+        };
+        seq.visited = (0..seq.orders(module).len())
+            .map(|order| vec![false; seq.pattern_at(module, order).map_or(0, |p| p.len())])
+            .collect();
+        seq.skip_to_playable_order(module);
+        seq.enter_row();
+        seq
+    }
+
+    fn orders<'m>(&self, module: &'m Module) -> &'m [usize] {
+        module
+            .pattern_order
+            .get(self.song)
+            .map_or(&[], |o| o.as_slice())
+    }
+
+    /// Pattern at `order`, None if the order is not playable
+    fn pattern_at<'m>(&self, module: &'m Module, order: usize) -> Option<&'m Pattern> {
+        let p = module.pattern.get(*self.orders(module).get(order)?)?;
+        if p.is_empty() {
+            None
+        } else {
+            Some(p)
+        }
+    }
+
+    pub fn current_pattern<'m>(&self, module: &'m Module) -> Option<&'m Pattern> {
+        self.pattern_at(module, self.order)
+    }
+
+    pub fn current_row<'m>(&self, module: &'m Module) -> Option<&'m Row> {
+        self.current_pattern(module)?.get(self.row)
+    }
+
+    /// Tick of the current row as seen by channel effects: it restarts at
+    /// each pattern delay repeat and goes over `speed` for extra ticks
+    pub fn channel_tick(&self) -> usize {
+        let repeats_end = self.speed * (1 + self.row_repeat);
+        if self.tick < repeats_end {
+            self.tick % self.speed
+        } else {
+            self.tick - repeats_end + self.speed
+        }
+    }
+
+    /// Notes are only triggered during the first play of a row
+    pub fn is_first_repeat(&self) -> bool {
+        self.tick < self.speed
+    }
+
+    /// Apply global effects of the current row for the current tick
+    pub fn process_tick(&mut self, module: &Module) {
+        if self.ended {
+            return;
+        }
+        if self.tick == 0 {
+            self.process_row(module);
+        } else if self.channel_tick() != 0 {
+            let Some(row) = self.current_row(module) else {
+                return;
+            };
+            for ge in row.iter().flat_map(|unit| unit.global_effects.iter()) {
+                match ge {
+                    GlobalEffect::BpmSlide(value) => {
+                        self.bpm = (self.bpm as isize + value).clamp(32, 255) as usize;
+                    }
+                    GlobalEffect::VolumeSlide { speed, fine: false } => {
+                        self.global_volume = (self.global_volume + speed).clamp(0.0, 1.0);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn process_row(&mut self, module: &Module) {
+        self.row_repeat = 0;
+        self.row_extra_ticks = 0;
+        let Some(row) = self.current_row(module) else {
+            return;
+        };
+
+        let mut pattern_break: Option<usize> = None;
+        let mut position_jump: Option<usize> = None;
+        let mut pattern_loop = false;
+        let mut row_delay: Option<usize> = None;
+        for ge in row.iter().flat_map(|unit| unit.global_effects.iter()) {
+            match ge {
+                GlobalEffect::Bpm(bpm) if *bpm != 0 => self.bpm = *bpm,
+                GlobalEffect::PatternBreak(row) => pattern_break = Some(*row),
+                // the first one wins
+                GlobalEffect::PatternDelay {
+                    quantity,
+                    tempo: true,
+                } if row_delay.is_none() => row_delay = Some(*quantity),
+                GlobalEffect::PatternDelay {
+                    quantity,
+                    tempo: false,
+                } => self.row_extra_ticks += quantity,
+                GlobalEffect::PatternLoop(0) => self.pattern_loop_row = self.row,
+                GlobalEffect::PatternLoop(count) => {
+                    if self.pattern_loop_count == 0 {
+                        self.pattern_loop_count = *count;
+                        pattern_loop = true;
+                    } else {
+                        self.pattern_loop_count -= 1;
+                        pattern_loop = self.pattern_loop_count != 0;
+                    }
+                }
+                GlobalEffect::PositionJump(order) => position_jump = Some(*order),
+                GlobalEffect::Speed(speed) if *speed != 0 => self.speed = *speed,
+                GlobalEffect::Volume(value) => self.global_volume = value.clamp(0.0, 1.0),
+                GlobalEffect::VolumeSlide { speed, fine: true } => {
+                    self.global_volume = (self.global_volume + speed).clamp(0.0, 1.0);
+                }
+                _ => {}
+            }
+        }
+        self.row_repeat = row_delay.unwrap_or(0);
+
+        self.next_position = if pattern_loop {
+            Some((self.order, self.pattern_loop_row))
+        } else {
+            match (position_jump, pattern_break) {
+                (Some(order), row) => Some((order, row.unwrap_or(0))),
+                (None, Some(row)) => Some((self.order + 1, row)),
+                (None, None) => None,
+            }
+        };
+    }
+
+    /// Go to the next tick, and to the next row at the end of the current one
+    pub fn advance(&mut self, module: &Module) {
+        if self.ended {
+            return;
+        }
+        self.seconds += 2.5 / self.bpm as f64;
+        self.tick += 1;
+        if self.tick < self.speed * (1 + self.row_repeat) + self.row_extra_ticks {
+            return;
+        }
+        self.tick = 0;
+        match self.next_position.take() {
+            Some((order, row)) => self.goto_order(module, order, row),
+            None => {
+                let len = self.current_pattern(module).map_or(0, |p| p.len());
+                if self.row + 1 < len {
+                    self.row += 1;
+                } else {
+                    self.goto_order(module, self.order + 1, 0);
+                }
+            }
+        }
+        self.enter_row();
+    }
+
+    /// Rows played again by a pattern loop are not a song loop
+    pub fn is_in_pattern_loop(&self) -> bool {
+        self.pattern_loop_count != 0
+    }
+
+    /// `(row, count)`: start row of the pattern loop and repeats left
+    pub fn get_pattern_loop(&self) -> (usize, usize) {
+        (self.pattern_loop_row, self.pattern_loop_count)
+    }
+
+    /// The song loops when a row is played again outside of a pattern loop
+    fn enter_row(&mut self) {
+        if self.ended {
+            return;
+        }
+        let Some(visited) = self
+            .visited
+            .get_mut(self.order)
+            .and_then(|rows| rows.get_mut(self.row))
+        else {
+            return;
+        };
+        if !*visited {
+            *visited = true;
+        } else if !self.is_in_pattern_loop() {
+            self.loop_position.get_or_insert((self.order, self.row));
+            self.count_loop();
+            // the next loop starts now
+            self.visited.iter_mut().for_each(|rows| rows.fill(false));
+            self.visited[self.order][self.row] = true;
+        }
+    }
+
+    fn count_loop(&mut self) {
+        self.loop_count += 1;
+        if self.loop_count > self.max_loop_count {
+            self.ended = true;
+        }
+    }
+
+    /// Go to `row` of `order`
+    pub fn goto_order(&mut self, module: &Module, order: usize, row: usize) {
+        if order != self.order {
+            self.pattern_loop_row = 0;
+            self.pattern_loop_count = 0;
+        }
+        self.order = order;
+        self.row = row;
+        self.tick = 0;
+        self.skip_to_playable_order(module);
+    }
+
+    /// Skip orders without pattern, restart the song after the last order
+    fn skip_to_playable_order(&mut self, module: &Module) {
+        let len = self.orders(module).len();
+        for _ in 0..=len {
+            if self.order >= len {
+                self.order = if self.restart_order < len {
+                    self.restart_order
+                } else {
+                    0
+                };
+                self.row = 0;
+            }
+            if let Some(p) = self.pattern_at(module, self.order) {
+                if self.row >= p.len() {
+                    self.row = 0;
+                }
+                return;
+            }
+            self.order += 1;
+            self.row = 0;
+        }
+        // nothing to play
+        self.ended = true;
+    }
+}
@@ -0,0 +1,291 @@
+use crate::instr_sid::InstrSid;
+use crate::player::sid::envelope_generator::EnvelopeGenerator;
+use crate::player::sid::filter::{ExternalFilter, SidFilter};
+use crate::player::sid::wave_generator::WaveGenerator;
+
+/// SID revision, the filter and the waveform DAC differ
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SidModel {
+    /// First revision, in the C64 breadbin
+    #[default]
+    Mos6581,
+    /// In the C64C and C128
+    Mos8580,
+}
+
+/// MOS 6581/8580 emulation: three voices, multimode filter and C64 output stage.
+///
+/// Synchronize & ring modulation sources are the same as `InstrSid`:
+/// voice0 from voice2, voice1 from voice0, voice2 from voice1
+#[derive(Clone, Debug)]
+pub struct SidChip {
+    model: SidModel,
+    wave: [WaveGenerator; 3],
+    envelope: [EnvelopeGenerator; 3],
+    filter: SidFilter,
+    external: ExternalFilter,
+    sample_rate: f32,
+    /// Chip cycles late, less than one
+    cycles: f64,
+}
+
+impl SidChip {
+    /// PAL C64 clock, in Hz
+    pub const CLOCK: f64 = 985_248.0;
+
+    pub fn new(model: SidModel) -> Self {
+        Self {
+            model,
+            wave: [WaveGenerator::default(); 3],
+            envelope: [EnvelopeGenerator::default(); 3],
+            filter: SidFilter::default(),
+            external: ExternalFilter::default(),
+            sample_rate: 48000.0,
+            cycles: 0.0,
+        }
+    }
+
+    pub fn get_model(&self) -> SidModel {
+        self.model
+    }
+
+    /// Output sample rate, 48000 Hz by default
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate > 0.0 {
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    /// Write a register, `register` is the offset from 0xD400
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x00..=0x14 => {
+                let voice = (register / 7) as usize;
+                let wave = &mut self.wave[voice];
+                let envelope = &mut self.envelope[voice];
+                match register % 7 {
+                    0 => wave.freq = wave.freq & 0xFF00 | value as u16,
+                    1 => wave.freq = wave.freq & 0x00FF | (value as u16) << 8,
+                    2 => wave.pw = wave.pw & 0x0F00 | value as u16,
+                    3 => wave.pw = wave.pw & 0x00FF | ((value & 0x0F) as u16) << 8,
+                    4 => {
+                        wave.write_control(value);
+                        envelope.set_gate(value & 1 != 0);
+                    }
+                    5 => envelope.write_attack_decay(value),
+                    _ => envelope.write_sustain_release(value),
+                }
+            }
+            0x15 => self.filter.fc = self.filter.fc & 0x7F8 | (value & 0x07) as u16,
+            0x16 => self.filter.fc = self.filter.fc & 0x007 | (value as u16) << 3,
+            0x17 => {
+                self.filter.resonance = value >> 4;
+                for (i, routing) in self.filter.routing.iter_mut().enumerate() {
+                    *routing = value & (1 << i) != 0;
+                }
+            }
+            0x18 => {
+                self.filter.mute_voice3 = value & 0x80 != 0;
+                self.filter.high_pass = value & 0x40 != 0;
+                self.filter.band_pass = value & 0x20 != 0;
+                self.filter.low_pass = value & 0x10 != 0;
+                self.filter.volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    /// Write all registers of `instr`
+    pub fn set_instrument(&mut self, instr: &InstrSid) {
+        for (i, voice) in instr.voice.iter().enumerate() {
+            let base = 7 * i as u8;
+            self.write(base, voice.freq as u8);
+            self.write(base + 1, (voice.freq >> 8) as u8);
+            self.write(base + 2, voice.pw as u8);
+            self.write(base + 3, (voice.pw >> 8) as u8);
+            self.write(base + 4, voice.generate_ctrl_register());
+            self.write(base + 5, voice.ad);
+            self.write(base + 6, voice.sr);
+        }
+        self.write(0x15, (instr.fc & 0x07) as u8);
+        self.write(0x16, (instr.fc >> 3) as u8);
+        let routing = instr
+            .filter_gate
+            .iter()
+            .enumerate()
+            .filter(|(_, gate)| **gate)
+            .fold(0, |routing, (i, _)| routing | 1 << i);
+        self.write(0x17, (instr.filter_resonance & 0x0F) << 4 | routing);
+        let mut mode = instr.main_volume & 0x0F;
+        if instr.low_pass {
+            mode |= 0x10;
+        }
+        if instr.band_pass {
+            mode |= 0x20;
+        }
+        if instr.high_pass {
+            mode |= 0x40;
+        }
+        if instr.mute_voice3 {
+            mode |= 0x80;
+        }
+        self.write(0x18, mode);
+    }
+
+    /// Frequency register of `freq` in Hz
+    pub fn frequency_register(freq: f32) -> u16 {
+        (freq as f64 * 16_777_216.0 / Self::CLOCK).clamp(0.0, 65535.0) as u16
+    }
+
+    /// Oscillator frequency of `voice`, in Hz
+    pub fn set_frequency(&mut self, voice: usize, freq: f32) {
+        if let Some(wave) = self.wave.get_mut(voice) {
+            wave.freq = Self::frequency_register(freq);
+        }
+    }
+
+    pub fn set_gate(&mut self, voice: usize, gate: bool) {
+        if let Some(envelope) = self.envelope.get_mut(voice) {
+            envelope.set_gate(gate);
+        }
+    }
+
+    pub fn get_gate(&self, voice: usize) -> bool {
+        self.envelope.get(voice).is_some_and(|e| e.get_gate())
+    }
+
+    /// All voices are released, with their envelope at zero
+    pub fn is_silent(&self) -> bool {
+        self.envelope
+            .iter()
+            .all(|e| !e.get_gate() && e.output() == 0)
+    }
+
+    /// Run the chip for `cycles` clock cycles
+    pub fn clock(&mut self, mut cycles: u32) {
+        for envelope in self.envelope.iter_mut() {
+            envelope.clock(cycles);
+        }
+        // oscillators run until the next hard sync
+        while cycles > 0 {
+            let mut delta = cycles;
+            for i in 0..3 {
+                if self.wave[i].sync {
+                    if let Some(c) = self.wave[(i + 2) % 3].cycles_to_msb_rising() {
+                        delta = delta.min(c);
+                    }
+                }
+            }
+            for wave in self.wave.iter_mut() {
+                wave.clock(delta);
+            }
+            for i in 0..3 {
+                let source = self.wave[(i + 2) % 3];
+                self.wave[i].synchronize(&source);
+            }
+            cycles -= delta;
+        }
+    }
+
+    /// Current output, a voice at full volume is in [-1..1]
+    pub fn output(&mut self) -> f32 {
+        // the 6581 waveform DAC zero level is not centered
+        let wave_zero = match self.model {
+            SidModel::Mos6581 => 0x380,
+            SidModel::Mos8580 => 0x800,
+        } as f32;
+        let mut voices = [0.0; 4];
+        for (i, voice) in voices.iter_mut().take(3).enumerate() {
+            let ring_source = self.wave[(i + 2) % 3].get_accumulator();
+            let wave = self.wave[i].output(ring_source) as f32;
+            *voice = (wave - wave_zero) / 2048.0 * self.envelope[i].output() as f32 / 255.0;
+        }
+        let mixed = self.filter.process(self.model, self.sample_rate, voices);
+        self.external.process(self.sample_rate, mixed)
+    }
+
+    /// Run the chip for one output frame and return it
+    pub fn render_frame(&mut self) -> f32 {
+        self.advance(1);
+        self.output()
+    }
+
+    /// Run the chip for `frames` output frames, without rendering them
+    pub fn skip_frames(&mut self, frames: usize) {
+        self.advance(frames);
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.cycles += Self::CLOCK * frames as f64 / self.sample_rate as f64;
+        let cycles = self.cycles as u32;
+        self.cycles -= cycles as f64;
+        self.clock(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::rising_edges;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn player_sid() {
+        use crate::prelude::*;
+
+        // 440 Hz sawtooth, full sustain
+        for model in [SidModel::Mos6581, SidModel::Mos8580] {
+            let mut chip = SidChip::new(model);
+            chip.set_sample_rate(48000.0);
+            chip.set_frequency(0, 440.0);
+            chip.write(0x06, 0xF0);
+            chip.write(0x18, 0x0F);
+            chip.write(0x04, 0x21);
+            chip.skip_frames(4800);
+            let frames: Vec<f32> = (0..48000).map(|_| chip.render_frame()).collect();
+            assert!((rising_edges(&frames) as i32 - 440).abs() <= 2);
+            assert!(frames.iter().all(|f| f.abs() <= 2.0));
+            assert!(!chip.is_silent());
+            // release
+            chip.write(0x04, 0x20);
+            chip.skip_frames(4800);
+            assert!(chip.is_silent());
+        }
+
+        let mut sid = InstrSid::default();
+        sid.voice[0].ctrl_sawtooth = true;
+        sid.voice[0].pw = 0x800;
+        sid.voice[0].sr = 0xF0;
+        let unit = |note: Pitch| TrackUnit {
+            note,
+            instrument: note.is_valid().then_some(0),
+            ..Default::default()
+        };
+        let module = Module {
+            pattern_order: vec![vec![0]],
+            pattern: vec![vec![
+                vec![unit(Pitch::C4)],
+                vec![unit(Pitch::Off)],
+                vec![unit(Pitch::None)],
+            ]],
+            instrument: vec![Instrument {
+                name: "".into(),
+                instr_type: InstrumentType::RobSid(InstrRobSid {
+                    sid,
+                    ..Default::default()
+                }),
+                muted: false,
+            }],
+            ..Default::default()
+        };
+        let mut player = Player::new(&module, 48000.0, 0);
+        player.set_sid_model(SidModel::Mos8580);
+        let row: Vec<f32> = player.by_ref().take(5760).map(|(l, _)| l).collect();
+        assert!(row.iter().any(|f| f.abs() > 0.05));
+        // C-4 is middle C
+        let e = rising_edges(&row[960..]);
+        assert!((e as i32 - 26).abs() <= 1, "{}", e);
+        let state = player.seek_position(0, 2).unwrap();
+        assert!(!state.channels[0].active);
+    }
+}
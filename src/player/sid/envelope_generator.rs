@@ -0,0 +1,152 @@
+/// Cycles between two counter steps, by attack, decay or release value
+const RATE_PERIOD: [u16; 16] = [
+    9, 32, 63, 95, 149, 220, 267, 313, 392, 977, 1954, 3126, 3907, 11720, 19532, 31251,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Attack,
+    DecaySustain,
+    Release,
+}
+
+/// ADSR of a SID voice: an 8 bits counter, linear attack,
+/// pseudo exponential decay and release
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeGenerator {
+    /// [0..15]
+    pub attack: u8,
+    /// [0..15]
+    pub decay: u8,
+    /// [0..15]
+    pub sustain: u8,
+    /// [0..15]
+    pub release: u8,
+    gate: bool,
+    state: State,
+    /// 15 bits, compared to the rate period: a lower new period waits for the
+    /// counter to wrap around (ADSR delay bug)
+    rate_counter: u16,
+    exponential_counter: u8,
+    exponential_period: u8,
+    counter: u8,
+    /// Decay and release stop at zero
+    hold_zero: bool,
+}
+
+impl Default for EnvelopeGenerator {
+    fn default() -> Self {
+        Self {
+            attack: 0,
+            decay: 0,
+            sustain: 0,
+            release: 0,
+            gate: false,
+            state: State::Release,
+            rate_counter: 0,
+            exponential_counter: 0,
+            exponential_period: 1,
+            counter: 0,
+            hold_zero: true,
+        }
+    }
+}
+
+impl EnvelopeGenerator {
+    /// A rising gate starts the attack, a falling gate starts the release
+    pub fn set_gate(&mut self, gate: bool) {
+        if !self.gate && gate {
+            self.state = State::Attack;
+            self.hold_zero = false;
+        } else if self.gate && !gate {
+            self.state = State::Release;
+        }
+        self.gate = gate;
+    }
+
+    pub fn get_gate(&self) -> bool {
+        self.gate
+    }
+
+    pub fn write_attack_decay(&mut self, value: u8) {
+        self.attack = value >> 4;
+        self.decay = value & 0x0F;
+    }
+
+    pub fn write_sustain_release(&mut self, value: u8) {
+        self.sustain = value >> 4;
+        self.release = value & 0x0F;
+    }
+
+    /// [0..255]
+    pub fn output(&self) -> u8 {
+        self.counter
+    }
+
+    fn rate_period(&self) -> u16 {
+        RATE_PERIOD[match self.state {
+            State::Attack => self.attack,
+            State::DecaySustain => self.decay,
+            State::Release => self.release,
+        } as usize
+            & 0x0F]
+    }
+
+    pub fn clock(&mut self, mut cycles: u32) {
+        loop {
+            let period = self.rate_period();
+            let to_step = if self.rate_counter < period {
+                (period - self.rate_counter) as u32
+            } else {
+                // wrap around at 0x8000, the counter restarts at 1
+                (0x8000 - self.rate_counter + period - 1) as u32
+            };
+            if cycles < to_step {
+                self.rate_counter = ((self.rate_counter as u32 + cycles) & 0x7FFF) as u16;
+                return;
+            }
+            cycles -= to_step;
+            self.rate_counter = 0;
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        if self.state == State::Attack {
+            self.exponential_counter = 0;
+            self.counter = self.counter.wrapping_add(1);
+            if self.counter == 0xFF {
+                self.state = State::DecaySustain;
+            }
+        } else {
+            self.exponential_counter += 1;
+            if self.exponential_counter < self.exponential_period {
+                return;
+            }
+            self.exponential_counter = 0;
+            if self.hold_zero {
+                return;
+            }
+            match self.state {
+                State::DecaySustain if self.counter != self.sustain * 0x11 => {
+                    self.counter = self.counter.wrapping_sub(1)
+                }
+                State::Release => self.counter = self.counter.wrapping_sub(1),
+                _ => {}
+            }
+        }
+        self.exponential_period = match self.counter {
+            0xFF => 1,
+            0x5D => 2,
+            0x36 => 4,
+            0x1A => 8,
+            0x0E => 16,
+            0x06 => 30,
+            0x00 => {
+                self.hold_zero = true;
+                1
+            }
+            _ => self.exponential_period,
+        };
+    }
+}
@@ -0,0 +1,176 @@
+use crate::player::sid::chip::SidModel;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// `(fc register, Hz)` cutoff curves, from reSID
+const CUTOFF_6581: [(u16, f32); 27] = [
+    (0, 220.0),
+    (128, 230.0),
+    (256, 250.0),
+    (384, 300.0),
+    (512, 420.0),
+    (640, 780.0),
+    (768, 1600.0),
+    (832, 2300.0),
+    (896, 3200.0),
+    (960, 4300.0),
+    (992, 5000.0),
+    (1008, 5400.0),
+    (1016, 5700.0),
+    (1023, 6000.0),
+    (1024, 4600.0),
+    (1032, 4800.0),
+    (1056, 5300.0),
+    (1088, 6000.0),
+    (1120, 6600.0),
+    (1152, 7200.0),
+    (1280, 9500.0),
+    (1408, 12000.0),
+    (1536, 14500.0),
+    (1664, 16000.0),
+    (1792, 17100.0),
+    (1920, 17700.0),
+    (2047, 18000.0),
+];
+
+const CUTOFF_8580: [(u16, f32); 17] = [
+    (0, 0.0),
+    (128, 800.0),
+    (256, 1600.0),
+    (384, 2500.0),
+    (512, 3300.0),
+    (640, 4100.0),
+    (768, 4800.0),
+    (896, 5600.0),
+    (1024, 6500.0),
+    (1152, 7500.0),
+    (1280, 8400.0),
+    (1408, 9200.0),
+    (1536, 9800.0),
+    (1664, 10500.0),
+    (1792, 11000.0),
+    (1920, 11700.0),
+    (2047, 12500.0),
+];
+
+/// SID multimode filter: two integrators state variable filter,
+/// low, band and high pass outputs can be mixed
+#[derive(Default, Clone, Copy, Debug)]
+pub struct SidFilter {
+    /// 11 bits cutoff
+    pub fc: u16,
+    /// [0..15]
+    pub resonance: u8,
+    /// Voices 1 to 3 and external input go through the filter
+    pub routing: [bool; 4],
+    pub low_pass: bool,
+    pub band_pass: bool,
+    pub high_pass: bool,
+    /// Voice 3 is not heard when it does not go through the filter
+    pub mute_voice3: bool,
+    /// [0..15]
+    pub volume: u8,
+    /// coefficients computed for these `(fc, resonance, sample_rate)`
+    setup: Option<(u16, u8, f32)>,
+    g: f32,
+    k: f32,
+    s1: f32,
+    s2: f32,
+}
+
+impl SidFilter {
+    /// Cutoff frequency in Hz
+    pub fn cutoff_to_frequency(model: SidModel, fc: u16) -> f32 {
+        let curve: &[(u16, f32)] = match model {
+            SidModel::Mos6581 => &CUTOFF_6581,
+            SidModel::Mos8580 => &CUTOFF_8580,
+        };
+        let fc = fc.min(2047);
+        let i = curve
+            .iter()
+            .rposition(|(x, _)| *x <= fc)
+            .unwrap_or(0)
+            .min(curve.len() - 2);
+        let ((x0, y0), (x1, y1)) = (curve[i], curve[i + 1]);
+        if x1 == x0 {
+            y0
+        } else {
+            y0 + (y1 - y0) * (fc - x0) as f32 / (x1 - x0) as f32
+        }
+    }
+
+    fn setup(&mut self, model: SidModel, sample_rate: f32) {
+        if self.setup == Some((self.fc, self.resonance, sample_rate)) {
+            return;
+        }
+        self.setup = Some((self.fc, self.resonance, sample_rate));
+        let freq = Self::cutoff_to_frequency(model, self.fc).clamp(30.0, sample_rate * 0.45);
+        self.g = (core::f32::consts::PI * freq / sample_rate).tan();
+        self.k = 1.0 / (0.707 + self.resonance.min(15) as f32 / 15.0);
+    }
+
+    /// Mix of the three voices and the external input, in [-1..1] each
+    pub fn process(&mut self, model: SidModel, sample_rate: f32, inputs: [f32; 4]) -> f32 {
+        self.setup(model, sample_rate);
+        let mut direct = 0.0;
+        let mut filtered = 0.0;
+        for (i, input) in inputs.iter().enumerate() {
+            if self.routing[i] {
+                filtered += input;
+            } else if !(i == 2 && self.mute_voice3) {
+                direct += input;
+            }
+        }
+
+        // topology preserving transform, stable up to Nyquist
+        let hp = (filtered - (self.k + self.g) * self.s1 - self.s2)
+            / (1.0 + self.k * self.g + self.g * self.g);
+        let bp = self.g * hp + self.s1;
+        self.s1 = self.g * hp + bp;
+        let lp = self.g * bp + self.s2;
+        self.s2 = self.g * bp + lp;
+
+        let mut out = direct;
+        if self.low_pass {
+            out += lp;
+        }
+        if self.band_pass {
+            out += bp;
+        }
+        if self.high_pass {
+            out += hp;
+        }
+        out * self.volume.min(15) as f32 / 15.0
+    }
+}
+
+/// C64 audio output stage: low pass at 16 kHz, high pass at 16 Hz
+#[derive(Default, Clone, Copy, Debug)]
+pub struct ExternalFilter {
+    /// `(sample_rate, low pass, high pass)` coefficients
+    setup: Option<(f32, f32, f32)>,
+    low: f32,
+    dc: f32,
+}
+
+impl ExternalFilter {
+    pub fn process(&mut self, sample_rate: f32, input: f32) -> f32 {
+        let (low_pass, high_pass) = match self.setup {
+            Some((sr, low_pass, high_pass)) if sr == sample_rate => (low_pass, high_pass),
+            _ => {
+                let low_pass = 1.0 - (-core::f32::consts::TAU * 16000.0 / sample_rate).exp();
+                let high_pass = 1.0 - (-core::f32::consts::TAU * 16.0 / sample_rate).exp();
+                self.setup = Some((sample_rate, low_pass, high_pass));
+                (low_pass, high_pass)
+            }
+        };
+        self.low += (input - self.low) * low_pass;
+        self.dc += (self.low - self.dc) * high_pass;
+        self.low - self.dc
+    }
+}
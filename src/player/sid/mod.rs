@@ -0,0 +1,16 @@
+#![forbid(unsafe_code)]
+
+/// SID chip: registers, clock and mixer
+pub mod chip;
+
+/// ADSR envelope generator
+pub mod envelope_generator;
+
+/// Multimode filter and output stage
+pub mod filter;
+
+/// Rob Hubbard driver effects, every frame
+pub mod rob_effects;
+
+/// Oscillator, waveforms and noise
+pub mod wave_generator;
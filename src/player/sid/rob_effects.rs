@@ -0,0 +1,232 @@
+use crate::instr_robsid::RobEffects;
+use crate::instr_sid::{InstrSid, SidVoice};
+use crate::player::sid::chip::SidChip;
+
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+#[allow(unused_imports)]
+use num_traits::float::Float;
+
+/// Rob Hubbard driver update rate, one PAL frame, in Hz
+pub const FRAME_RATE: f32 = 50.0;
+
+/// Pulse width sweep bounds, on the high nibble
+const PULSE_HIGH: u16 = 0x0E;
+const PULSE_LOW: u16 = 0x08;
+
+/// Rob Hubbard driver effects of a `InstrRobSid`, applied to the SID registers
+/// every frame like the original player routine.
+///
+/// Voice 0 plays the channel frequency, voices 1 and 2 their instrument frequency
+#[derive(Clone, Debug)]
+pub struct RobEffectsState {
+    fx: [RobEffects; 3],
+    voice: [SidVoice; 3],
+    /// Channel frequency of voice 0, in Hz
+    pub frequency: f32,
+    /// Driver frames run since the note on
+    pub frame: usize,
+    pulse: [u16; 3],
+    pulse_up: [bool; 3],
+    pulse_delay: [u8; 3],
+    /// Frequency register offset of drums and skydive
+    slide: [i32; 3],
+    sample_rate: f32,
+    /// Frames due, a frame is due at the note on
+    clock: f64,
+}
+
+impl RobEffectsState {
+    pub fn new(fx: [RobEffects; 3], instr: &InstrSid) -> Self {
+        Self {
+            fx,
+            voice: instr.voice,
+            frequency: 0.0,
+            frame: 0,
+            pulse: instr.voice.map(|v| v.pw & 0xFFF),
+            pulse_up: [true; 3],
+            pulse_delay: [0; 3],
+            slide: [0; 3],
+            sample_rate: 48000.0,
+            clock: 1.0,
+        }
+    }
+
+    /// Output sample rate, 48000 Hz by default
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate > 0.0 {
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    /// Output frames until the next driver frame, at least one
+    pub fn frames_to_next(&self) -> usize {
+        (((1.0 - self.clock) as f32 * self.sample_rate / FRAME_RATE).ceil() as usize).max(1)
+    }
+
+    /// Move forward `frames` output frames, running the driver frames due
+    pub fn advance(&mut self, chip: &mut SidChip, frames: usize) {
+        self.clock += frames as f64 * FRAME_RATE as f64 / self.sample_rate as f64;
+        while self.clock >= 1.0 {
+            self.clock -= 1.0;
+            self.step(chip);
+        }
+    }
+
+    /// Frequency register of `voice` without effects
+    fn base(&self, voice: usize) -> i32 {
+        if voice == 0 {
+            SidChip::frequency_register(self.frequency) as i32
+        } else {
+            self.voice[voice].freq as i32
+        }
+    }
+
+    /// One driver frame
+    fn step(&mut self, chip: &mut SidChip) {
+        let frame = self.frame;
+        self.frame += 1;
+        for v in 0..3 {
+            let fx = self.fx[v];
+            let register = 7 * v as u8;
+            let base = self.base(v);
+
+            // drums: noise on the first frame, then the frequency falls fast
+            if fx.drum {
+                let mut ctrl = self.voice[v].generate_ctrl_register() & 0xFE;
+                if frame == 0 {
+                    ctrl = 0x80;
+                }
+                if chip.get_gate(v) {
+                    ctrl |= 0x01;
+                }
+                chip.write(register + 4, ctrl);
+                if (base + self.slide[v]) >> 8 > 0 {
+                    self.slide[v] -= 0x100;
+                }
+            }
+
+            // skydive: the frequency high byte slides once the note is long enough
+            if fx.skydive && frame > fx.skydive_config_if as usize {
+                let add = match fx.skydive_config_add as i8 {
+                    0 => -1,
+                    add => add as i32,
+                };
+                let freq = base + self.slide[v] + add * 0x100;
+                if (0..=0xFFFF).contains(&freq) {
+                    self.slide[v] += add * 0x100;
+                }
+            }
+
+            // pulse width sweep between $0800 and $0E00
+            let speed = fx.pw_speed as u8 as u16;
+            if fx.pw || speed != 0 {
+                if self.pulse_delay[v] > 0 {
+                    self.pulse_delay[v] -= 1;
+                } else {
+                    self.pulse_delay[v] = fx.pw_delay.min(0xFF) as u8;
+                    if self.pulse_up[v] {
+                        self.pulse[v] = (self.pulse[v] + speed) & 0xFFF;
+                        if self.pulse[v] >> 8 == PULSE_HIGH {
+                            self.pulse_up[v] = false;
+                        }
+                    } else {
+                        self.pulse[v] = self.pulse[v].wrapping_sub(speed) & 0xFFF;
+                        if self.pulse[v] >> 8 == PULSE_LOW {
+                            self.pulse_up[v] = true;
+                        }
+                    }
+                    chip.write(register + 2, self.pulse[v] as u8);
+                    chip.write(register + 3, (self.pulse[v] >> 8) as u8);
+                }
+            }
+        }
+        self.apply(chip);
+    }
+
+    /// Write the frequencies of the last driver frame: vibrato, octave arpeggio and slides
+    pub fn apply(&self, chip: &mut SidChip) {
+        let frame = self.frame.saturating_sub(1);
+        for v in 0..3 {
+            let fx = self.fx[v];
+            if v != 0 && !(fx.vibrato || fx.arpeggio || fx.drum || fx.skydive) {
+                continue;
+            }
+            let mut base = self.base(v);
+            // octave up on odd frames
+            if fx.arpeggio && frame & 1 != 0 {
+                base *= 2;
+            }
+            // triangle of `vibrato_div + 1` frames, steps of `vibrato_depth / 64` semitone
+            let mut vibrato = 0;
+            if fx.vibrato && fx.vibrato_depth != 0 {
+                let period = fx.vibrato_div as usize + 1;
+                let pos = frame % period;
+                let step = pos.min(period - 1 - pos) as f32;
+                let semitone = base as f32 * (2.0f32.powf(1.0 / 12.0) - 1.0);
+                vibrato = (step * semitone * fx.vibrato_depth as f32 / 64.0) as i32;
+            }
+            let freq = (base + self.slide[v] + vibrato).clamp(0, 0xFFFF);
+            let register = 7 * v as u8;
+            chip.write(register, freq as u8);
+            chip.write(register + 1, (freq >> 8) as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::rising_edges;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn player_rob_effects() {
+        use crate::instr_robsid::RobEffects;
+        use crate::prelude::*;
+
+        let play = |fx: RobEffects| {
+            let mut sid = InstrSid::default();
+            sid.voice[0].ctrl_sawtooth = true;
+            sid.voice[0].pw = 0x800;
+            sid.voice[0].sr = 0xF0;
+            let module = Module {
+                pattern_order: vec![vec![0]],
+                pattern: vec![vec![vec![TrackUnit {
+                    note: Pitch::C4,
+                    instrument: Some(0),
+                    ..Default::default()
+                }]]],
+                instrument: vec![Instrument {
+                    name: "".into(),
+                    instr_type: InstrumentType::RobSid(InstrRobSid {
+                        sid,
+                        fx: [fx, RobEffects::default(), RobEffects::default()],
+                    }),
+                    muted: false,
+                }],
+                ..Default::default()
+            };
+            let mut player = Player::new(&module, 48000.0, 0);
+            player.set_sid_model(SidModel::Mos8580);
+            let row: Vec<f32> = player.by_ref().take(5760).map(|(l, _)| l).collect();
+            rising_edges(&row[960..])
+        };
+
+        assert!((play(RobEffects::default()) as i32 - 26).abs() <= 1);
+        // one octave up every other frame
+        let e = play(RobEffects {
+            arpeggio: true,
+            ..Default::default()
+        });
+        assert!((36..=42).contains(&e), "{}", e);
+        // the frequency high byte falls every frame
+        let e = play(RobEffects {
+            skydive: true,
+            ..Default::default()
+        });
+        assert!(e <= 23, "{}", e);
+    }
+}
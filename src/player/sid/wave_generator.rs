@@ -0,0 +1,145 @@
+/// Noise shift register value after a reset
+const SHIFT_REGISTER_RESET: u32 = 0x7F_FFF8;
+
+/// Oscillator and waveform selector of a SID voice
+#[derive(Clone, Copy, Debug)]
+pub struct WaveGenerator {
+    /// 24 bits phase accumulator
+    accumulator: u32,
+    /// 23 bits noise LFSR
+    shift_register: u32,
+    /// The accumulator msb went up during the last clock, used by hard sync
+    msb_rising: bool,
+    pub freq: u16,
+    /// 12 bits pulse width
+    pub pw: u16,
+    /// Control register bits 4 to 7: triangle, sawtooth, pulse, noise
+    pub waveform: u8,
+    pub test: bool,
+    pub ring_mod: bool,
+    pub sync: bool,
+}
+
+impl Default for WaveGenerator {
+    fn default() -> Self {
+        Self {
+            accumulator: 0,
+            shift_register: SHIFT_REGISTER_RESET,
+            msb_rising: false,
+            freq: 0,
+            pw: 0,
+            waveform: 0,
+            test: false,
+            ring_mod: false,
+            sync: false,
+        }
+    }
+}
+
+impl WaveGenerator {
+    /// Control register, the gate bit goes to the envelope generator
+    pub fn write_control(&mut self, control: u8) {
+        self.waveform = control >> 4;
+        self.test = control & 0b0000_1000 != 0;
+        self.ring_mod = control & 0b0000_0100 != 0;
+        self.sync = control & 0b0000_0010 != 0;
+        if self.test {
+            self.accumulator = 0;
+            self.shift_register = SHIFT_REGISTER_RESET;
+        }
+    }
+
+    pub fn get_accumulator(&self) -> u32 {
+        self.accumulator
+    }
+
+    /// Cycles until the accumulator msb goes up, None if it never does
+    pub fn cycles_to_msb_rising(&self) -> Option<u32> {
+        if self.test || self.freq == 0 {
+            return None;
+        }
+        let target = if self.accumulator & 0x80_0000 != 0 {
+            0x180_0000
+        } else {
+            0x80_0000
+        };
+        Some((target - self.accumulator).div_ceil(self.freq as u32))
+    }
+
+    pub fn clock(&mut self, cycles: u32) {
+        if self.test {
+            self.msb_rising = false;
+            return;
+        }
+        let old = self.accumulator as u64;
+        let sum = old + self.freq as u64 * cycles as u64;
+        self.accumulator = (sum & 0xFF_FFFF) as u32;
+        self.msb_rising = old & 0x80_0000 == 0 && self.accumulator & 0x80_0000 != 0;
+
+        // the noise LFSR is clocked when bit 19 goes up
+        let rising = |acc: u64| (acc as i64 - 0x8_0000).div_euclid(0x10_0000);
+        for _ in 0..(rising(sum) - rising(old)) {
+            let bit0 = ((self.shift_register >> 22) ^ (self.shift_register >> 17)) & 1;
+            self.shift_register = ((self.shift_register << 1) | bit0) & 0x7F_FFFF;
+        }
+    }
+
+    /// Hard sync: restart when the source msb goes up
+    pub fn synchronize(&mut self, source: &WaveGenerator) {
+        if self.sync && source.msb_rising {
+            self.accumulator = 0;
+        }
+    }
+
+    fn noise(&self) -> u16 {
+        let sr = self.shift_register;
+        (((sr & 0x10_0000) >> 9)
+            | ((sr & 0x04_0000) >> 8)
+            | ((sr & 0x00_4000) >> 5)
+            | ((sr & 0x00_0800) >> 3)
+            | ((sr & 0x00_0200) >> 2)
+            | ((sr & 0x00_0020) << 1)
+            | ((sr & 0x00_0004) << 3)
+            | ((sr & 0x00_0001) << 4)) as u16
+    }
+
+    /// 12 bits waveform output, `ring_source` is the accumulator of the ring modulation source
+    pub fn output(&self, ring_source: u32) -> u16 {
+        if self.waveform == 0 {
+            return 0;
+        }
+        // ring modulation replaces the triangle msb
+        let acc = if self.ring_mod {
+            self.accumulator ^ (ring_source & 0x80_0000)
+        } else {
+            self.accumulator
+        };
+        let pulse = if self.test || (self.accumulator >> 12) as u16 >= self.pw {
+            0xFFF
+        } else {
+            0
+        };
+        let triangle = {
+            let t = if acc & 0x80_0000 != 0 { !acc } else { acc };
+            ((t >> 11) & 0xFFF) as u16
+        };
+        let sawtooth = (self.accumulator >> 12) as u16;
+
+        // combined waveforms: the selected outputs are wired together and
+        // zero bits win, approximated by a bitwise and
+        let mut wave = 0xFFF;
+        if self.waveform & 0b0001 != 0 {
+            wave &= triangle;
+        }
+        if self.waveform & 0b0010 != 0 {
+            wave &= sawtooth;
+        }
+        if self.waveform & 0b0100 != 0 {
+            wave &= pulse;
+        }
+        if self.waveform & 0b1000 != 0 {
+            wave &= self.noise();
+        }
+        wave
+    }
+}
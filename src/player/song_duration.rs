@@ -0,0 +1,88 @@
+use crate::player::sequencer::Sequencer;
+use crate::prelude::*;
+
+/// Song timeline, computed without rendering audio
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct SongDuration {
+    /// Duration in seconds, until the song loops or ends
+    pub seconds: f64,
+    /// Number of rows played, pattern loops and jumps followed
+    pub rows: usize,
+    /// Number of ticks played
+    pub ticks: usize,
+    /// `(order, row)` where the song loops, None if the song just ends
+    pub loop_position: Option<(usize, usize)>,
+}
+
+impl SongDuration {
+    /// Stop there, whatever the song does
+    const MAX_ROWS: usize = 1 << 20;
+
+    /// Walk `song` (index in `Module::pattern_order`) row by row and tick by tick
+    pub fn compute(module: &Module, song: usize) -> Self {
+        Self::walk(module, Sequencer::new(module, song), |_| {})
+    }
+
+    /// Play `seq` until it ends, `on_row` is called at the start of each row played
+    pub fn walk(module: &Module, mut seq: Sequencer, mut on_row: impl FnMut(&Sequencer)) -> Self {
+        let mut sd = Self::default();
+        while !seq.ended && sd.rows < Self::MAX_ROWS {
+            if seq.tick == 0 {
+                on_row(&seq);
+                sd.rows += 1;
+            }
+            seq.process_tick(module);
+            sd.ticks += 1;
+            seq.advance(module);
+        }
+        sd.seconds = seq.seconds;
+        sd.loop_position = seq.loop_position;
+        sd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn song_duration() {
+        use crate::prelude::*;
+
+        let row = |global_effects: Vec<GlobalEffect>| {
+            vec![TrackUnit {
+                global_effects,
+                ..Default::default()
+            }]
+        };
+        let module = Module {
+            default_tempo: 6,
+            default_bpm: 125,
+            pattern_order: vec![vec![0, 1, 0]],
+            pattern: vec![
+                vec![
+                    row(vec![GlobalEffect::PatternLoop(0)]),
+                    row(vec![GlobalEffect::PatternLoop(2)]),
+                    row(vec![GlobalEffect::Speed(3)]),
+                    row(vec![]),
+                ],
+                vec![row(vec![]), row(vec![GlobalEffect::PositionJump(1)])],
+            ],
+            ..Default::default()
+        };
+        // rows 0 and 1 three times, rows 2 and 3, then order 1 until the jump
+        let sd = module.get_song_duration(0);
+        assert_eq!(sd.rows, 10);
+        assert_eq!(sd.ticks, 6 * 6 + 2 * 3 + 2 * 3);
+        assert!((sd.seconds - 48.0 * 2.5 / 125.0).abs() < 1e-9);
+        assert_eq!(sd.loop_position, Some((1, 0)));
+
+        let mut player = Player::new(&module, 1000.0, 0);
+        assert_eq!(player.by_ref().count(), 960);
+        assert_eq!(player.get_sequencer().loop_position, Some((1, 0)));
+
+        // no song
+        assert_eq!(module.get_song_duration(1).rows, 0);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::player::sequencer::Sequencer;
+use crate::player::song_duration::SongDuration;
+use crate::prelude::*;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A song hidden in the order list: orders never reached from the start of the song
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Subsong {
+    /// Index in `Module::pattern_order`
+    pub song: usize,
+    /// First order played
+    pub start_order: usize,
+    /// Where the subsong goes after the last order
+    pub restart_order: usize,
+    pub duration: SongDuration,
+}
+
+impl Subsong {
+    /// Play all songs of `module` from their first order, then from each
+    /// playable order not played yet, until all reachable orders are played
+    pub fn detect(module: &Module) -> Vec<Self> {
+        let mut subsongs = vec![];
+        for (song, orders) in module.pattern_order.iter().enumerate() {
+            let playable = |order: usize| {
+                module
+                    .pattern
+                    .get(orders[order])
+                    .is_some_and(|p| !p.is_empty())
+            };
+            let mut played = vec![false; orders.len()];
+            let mut start = Some(0);
+            while let Some(order) = start {
+                let seq = Sequencer::new_at_order(module, song, order);
+                if seq.ended {
+                    break;
+                }
+                let (start_order, restart_order) = (seq.order, seq.restart_order);
+                played[start_order] = true;
+                let duration = SongDuration::walk(module, seq, |seq| {
+                    if let Some(p) = played.get_mut(seq.order) {
+                        *p = true;
+                    }
+                });
+                subsongs.push(Self {
+                    song,
+                    start_order,
+                    restart_order,
+                    duration,
+                });
+                start = (0..orders.len()).find(|&o| !played[o] && playable(o));
+            }
+        }
+        subsongs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn subsongs() {
+        use crate::prelude::*;
+
+        let pattern = |rows: usize, jump: Option<usize>| {
+            (0..rows)
+                .map(|r| {
+                    vec![TrackUnit {
+                        global_effects: jump
+                            .filter(|_| r + 1 == rows)
+                            .map(GlobalEffect::PositionJump)
+                            .into_iter()
+                            .collect(),
+                        ..Default::default()
+                    }]
+                })
+                .collect()
+        };
+        let module = Module {
+            default_tempo: 6,
+            default_bpm: 125,
+            // order 3 is an empty pattern, the second song starts at order 1
+            pattern_order: vec![vec![0, 1, 2, 3], vec![3, 1]],
+            pattern: vec![
+                pattern(2, Some(0)),
+                pattern(4, Some(1)),
+                pattern(3, None),
+                vec![],
+            ],
+            ..Default::default()
+        };
+        let subsongs = module.get_subsongs();
+        let summary: Vec<_> = subsongs
+            .iter()
+            .map(|s| {
+                (
+                    s.song,
+                    s.start_order,
+                    s.restart_order,
+                    s.duration.rows,
+                    s.duration.loop_position,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 0, 0, 2, Some((0, 0))),
+                (0, 1, 1, 4, Some((1, 0))),
+                // the end of the order list goes back to the start of the subsong
+                (0, 2, 2, 3, Some((2, 0))),
+                (1, 1, 0, 4, Some((1, 0))),
+            ]
+        );
+        assert_eq!(subsongs[0].duration, module.get_song_duration(0));
+
+        let mut player = Player::new_subsong(&module, 1000.0, &subsongs[2]);
+        assert_eq!(player.get_position(), (2, 0));
+        assert_eq!(player.by_ref().count(), 3 * 6 * 20);
+    }
+}
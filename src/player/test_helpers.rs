@@ -0,0 +1,29 @@
+use crate::prelude::*;
+
+/// 32 frames forward looped square wave
+pub(crate) fn square_sample(volume: f32) -> Sample {
+    Sample {
+        name: "".into(),
+        relative_pitch: 0,
+        finetune: 0.0,
+        volume,
+        panning: 0.5,
+        loop_flag: LoopType::Forward,
+        loop_start: 0,
+        loop_length: 32,
+        sustain_loop_flag: LoopType::No,
+        sustain_loop_start: 0,
+        sustain_loop_length: 0,
+        data: Some(SampleDataType::Mono8(
+            (0..32).map(|i| if i < 16 { 64 } else { -64 }).collect(),
+        )),
+    }
+}
+
+/// Count of negative to positive crossings
+pub(crate) fn rising_edges(frames: &[f32]) -> usize {
+    frames
+        .windows(2)
+        .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+        .count()
+}
@@ -0,0 +1,330 @@
+use crate::player::channel::Channel;
+use crate::player::interpolation::Interpolator;
+use crate::prelude::*;
+
+use alloc::vec::Vec;
+
+/// A note moved to the background by a New Note Action
+#[derive(Clone)]
+pub struct BackgroundVoice {
+    /// Pattern channel which played the note
+    pub host: usize,
+    pub voice: Channel,
+}
+
+/// What to do with a playing note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NoteAction {
+    Cut,
+    Off,
+    FadeOut,
+}
+
+impl NoteAction {
+    fn apply(self, module: &Module, voice: &mut Channel) {
+        match self {
+            NoteAction::Cut => voice.cut(),
+            NoteAction::Off => voice.key_off(module),
+            NoteAction::FadeOut => voice.fade_out(),
+        }
+    }
+}
+
+impl From<&DuplicateCheckAction> for NoteAction {
+    fn from(dca: &DuplicateCheckAction) -> Self {
+        match dca {
+            DuplicateCheckAction::NoteCut(_) => NoteAction::Cut,
+            DuplicateCheckAction::NoteOff(_) => NoteAction::Off,
+            DuplicateCheckAction::NoteFadeOut(_) => NoteAction::FadeOut,
+        }
+    }
+}
+
+/// Notes still playing after a new note took their channel
+#[derive(Clone)]
+pub struct VoicePool {
+    voices: Vec<BackgroundVoice>,
+    /// Maximum number of background voices
+    max_voices: usize,
+    empty_unit: TrackUnit,
+}
+
+impl VoicePool {
+    pub fn new(max_voices: usize) -> Self {
+        Self {
+            voices: Vec::new(),
+            max_voices,
+            empty_unit: TrackUnit::default(),
+        }
+    }
+
+    pub fn get_max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    /// Background voices over the limit are stolen, quietest first
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+        self.voices.retain(|v| v.voice.active);
+        while self.voices.len() > self.max_voices {
+            self.steal();
+        }
+    }
+
+    pub fn get_voices(&self) -> &[BackgroundVoice] {
+        &self.voices
+    }
+
+    /// Number of voices playing
+    pub fn len(&self) -> usize {
+        self.voices.iter().filter(|v| v.voice.active).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn set_mute(&mut self, host: usize, mute: bool) {
+        for v in self.voices.iter_mut().filter(|v| v.host == host) {
+            v.voice.muted = mute;
+        }
+    }
+
+    fn steal(&mut self) {
+        if let Some((i, _)) = self
+            .voices
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.voice.loudness().total_cmp(&b.1.voice.loudness()))
+        {
+            self.voices.remove(i);
+        }
+    }
+
+    fn push(&mut self, host: usize, voice: Channel) {
+        self.voices.retain(|v| v.voice.active);
+        if self.max_voices == 0 {
+            return;
+        }
+        if self.voices.len() >= self.max_voices {
+            self.steal();
+        }
+        self.voices.push(BackgroundVoice { host, voice });
+    }
+
+    /// Is `voice` a duplicate of the new note, according to the instrument duplicate check?
+    fn is_duplicate(
+        dct: &DuplicateCheckType,
+        voice: &Channel,
+        instrument: Option<usize>,
+        sample: Option<usize>,
+        note: Pitch,
+    ) -> bool {
+        if !voice.active || voice.get_instrument() != instrument {
+            return false;
+        }
+        match dct {
+            DuplicateCheckType::Off(_) => false,
+            DuplicateCheckType::Note(_) => voice.get_note() == note,
+            DuplicateCheckType::Sample(_) => voice.get_sample() == sample,
+            DuplicateCheckType::Instrument(_) => true,
+        }
+    }
+
+    /// A new note is about to be played on `channel`, with `unit`:
+    /// apply the duplicate check to all notes of the channel, then the
+    /// New Note Action of the current note, which may go to the background
+    pub fn new_note(
+        &mut self,
+        module: &Module,
+        host: usize,
+        channel: &mut Channel,
+        unit: &TrackUnit,
+    ) {
+        let instrument = unit.instrument.or(channel.get_instrument());
+        let instr = instrument
+            .and_then(|i| module.instrument.get(i))
+            .and_then(|i| match &i.instr_type {
+                InstrumentType::Default(id) => Some(id),
+                _ => None,
+            });
+
+        // duplicate check
+        if let Some(instr) = instr {
+            let sample = unit
+                .note
+                .is_valid()
+                .then(|| instr.sample_for_pitch[unit.note.value() as usize])
+                .flatten();
+            let dct = &instr.duplicate_check;
+            let action = match dct {
+                DuplicateCheckType::Off(_) => None,
+                DuplicateCheckType::Note(dca)
+                | DuplicateCheckType::Sample(dca)
+                | DuplicateCheckType::Instrument(dca) => Some(NoteAction::from(dca)),
+            };
+            if let Some(action) = action {
+                for v in self.voices.iter_mut().filter(|v| v.host == host) {
+                    if Self::is_duplicate(dct, &v.voice, instrument, sample, unit.note) {
+                        action.apply(module, &mut v.voice);
+                    }
+                }
+                if Self::is_duplicate(dct, channel, instrument, sample, unit.note) {
+                    action.apply(module, channel);
+                }
+            }
+        }
+
+        // new note action
+        if channel.active {
+            let action = match channel.get_new_note_action(module) {
+                NewNoteAction::NoteCut => return,
+                NewNoteAction::Continue => None,
+                NewNoteAction::NoteOff => Some(NoteAction::Off),
+                NewNoteAction::NoteFadeOut => Some(NoteAction::FadeOut),
+            };
+            let mut voice = channel.clone();
+            if let Some(action) = action {
+                action.apply(module, &mut voice);
+            }
+            self.push(host, voice);
+        }
+    }
+
+    /// Past note effects of `unit`, for background voices of `host`
+    pub fn past_note_effects(
+        &mut self,
+        module: &Module,
+        host: usize,
+        unit: &TrackUnit,
+        tick: usize,
+    ) {
+        for effect in unit.effects.iter() {
+            let action = match effect {
+                TrackEffect::NoteCut {
+                    tick: t,
+                    past: true,
+                } if *t == tick => NoteAction::Cut,
+                TrackEffect::NoteOff {
+                    tick: t,
+                    past: true,
+                } if *t == tick => NoteAction::Off,
+                TrackEffect::NoteFadeOut {
+                    tick: t,
+                    past: true,
+                } if *t == tick => NoteAction::FadeOut,
+                _ => continue,
+            };
+            for v in self.voices.iter_mut().filter(|v| v.host == host) {
+                action.apply(module, &mut v.voice);
+            }
+        }
+    }
+
+    /// Background voices only follow their envelopes and fadeout
+    pub fn tick(&mut self, module: &Module, global_volume: f32, sample_rate: f32) {
+        self.voices.retain(|v| v.voice.active);
+        for v in self.voices.iter_mut() {
+            v.voice.tick(
+                module,
+                &self.empty_unit,
+                1,
+                false,
+                global_volume,
+                sample_rate,
+            );
+        }
+    }
+
+    /// Move all background voices forward, without rendering
+    pub fn skip_frames(&mut self, module: &Module, frames: usize) {
+        for v in self.voices.iter_mut() {
+            v.voice.skip_frames(module, frames);
+        }
+    }
+
+    /// Next stereo frame of all background voices
+    pub fn render_frame(&mut self, module: &Module, interpolator: &Interpolator) -> (f32, f32) {
+        let mut mix = (0.0, 0.0);
+        self.render_voices(module, interpolator, |_, (l, r)| {
+            mix.0 += l;
+            mix.1 += r;
+        });
+        mix
+    }
+
+    /// Next stereo frame of each background voice, given to `mix` with its voice
+    pub fn render_voices(
+        &mut self,
+        module: &Module,
+        interpolator: &Interpolator,
+        mut mix: impl FnMut(&BackgroundVoice, (f32, f32)),
+    ) {
+        for v in self.voices.iter_mut() {
+            let frame = v.voice.render_frame(module, interpolator);
+            mix(v, frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::test_helpers::square_sample;
+    use alloc::vec;
+
+    #[test]
+    fn player_new_note_action() {
+        use crate::prelude::*;
+
+        let module_with = |duplicate_check: DuplicateCheckType| {
+            let mut instr = InstrDefault {
+                sample: vec![Some(square_sample(1.0))],
+                duplicate_check,
+                ..Default::default()
+            };
+            instr.change_all_sample_for_pitch(0);
+            let row = |note: Pitch| {
+                vec![TrackUnit {
+                    note,
+                    instrument: note.is_valid().then_some(0),
+                    ..Default::default()
+                }]
+            };
+            Module {
+                pattern_order: vec![vec![0]],
+                pattern: vec![vec![
+                    row(Pitch::C4),
+                    row(Pitch::E4),
+                    row(Pitch::C4),
+                    row(Pitch::None),
+                ]],
+                instrument: vec![Instrument {
+                    name: "".into(),
+                    instr_type: InstrumentType::Default(instr),
+                    muted: false,
+                }],
+                ..Default::default()
+            }
+        };
+        let voices_at_last_row = |module: &Module, polyphony: usize| {
+            let mut player = Player::new(module, 8000.0, 0);
+            player.set_polyphony(polyphony);
+            while player.get_position().1 != 3 {
+                player.next().unwrap();
+            }
+            player.next();
+            player.get_voice_pool().len()
+        };
+
+        let module = module_with(DuplicateCheckType::Off(NewNoteAction::NoteCut));
+        assert_eq!(voices_at_last_row(&module, 256), 0);
+        let module = module_with(DuplicateCheckType::Off(NewNoteAction::Continue));
+        assert_eq!(voices_at_last_row(&module, 256), 2);
+        assert_eq!(voices_at_last_row(&module, 2), 1);
+        // the second C-4 cuts the first one
+        let module = module_with(DuplicateCheckType::Note(DuplicateCheckAction::NoteCut(
+            NewNoteAction::Continue,
+        )));
+        assert_eq!(voices_at_last_row(&module, 256), 1);
+    }
+}
@@ -20,6 +20,15 @@ pub use crate::{
     module::{Module, Pattern, Row, MAX_NUM_ROWS},
     period_helper::{FrequencyType, PeriodHelper},
     pitch::Pitch,
+    player::{
+        filter::FilterRange,
+        interpolation::Interpolation,
+        module_player::{PlaybackState, Player, StemGrouping},
+        opl::voice::OplVoice,
+        sid::chip::{SidChip, SidModel},
+        song_duration::SongDuration,
+        subsong::Subsong,
+    },
     sample::{LoopType, Sample, SampleDataType},
     track_unit::TrackUnit,
     vibrato::Vibrato,
@@ -31,6 +31,14 @@ impl Default for TrackUnit {
 }
 
 impl TrackUnit {
+    /// No note, no instrument and no effect
+    pub fn is_empty(&self) -> bool {
+        self.note == Pitch::None
+            && self.instrument.is_none()
+            && self.effects.is_empty()
+            && self.global_effects.is_empty()
+    }
+
     pub fn has_arpeggio(&self) -> bool {
         self.effects
             .iter()